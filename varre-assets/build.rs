@@ -1,18 +1,90 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use glam::{Mat3, Vec2, Vec3, Vec4};
+use import_settings::ImportSettings;
+use pack_format::{Compression, PackWriter};
+use model_format::{
+    encode_octahedral, f16_from_f32, quantize_position, Aabb, Animation, AnimationChannel, BoundingSphere, ElementFormat, Lod, Meshlet,
+    ModelWriter, Node, StreamSemantic, Submesh, NO_PARENT,
+};
 use regex::Regex;
-use russimp::scene::{PostProcess, Scene};
+use russimp::property::PropertyStore;
+use russimp::scene::Scene;
+use scene::{AlphaMode, LodData, MaterialData, MeshData, SceneData};
 use rspirv_reflect as rr;
+use textures::{load_texture, TextureKind, TEXTURE_EXTENSIONS};
+
+#[path = "build/spirv.rs"]
+mod spirv;
+#[path = "build/import_settings.rs"]
+mod import_settings;
+#[path = "build/layouts.rs"]
+mod layouts;
+#[path = "build/scene.rs"]
+mod scene;
+#[path = "build/textures.rs"]
+mod textures;
+// Shared with the runtime decoder, which uses the reading half
+#[allow(dead_code)]
+#[path = "src/model_format.rs"]
+mod model_format;
+#[allow(dead_code)]
+#[path = "src/texture_format.rs"]
+mod texture_format;
+#[allow(dead_code)]
+#[path = "src/pack_format.rs"]
+mod pack_format;
+// Model pipeline passes, compiled into the library only for their tests
+#[path = "src/meshlets.rs"]
+mod meshlets;
+#[path = "src/mesh_optimize.rs"]
+mod mesh_optimize;
+#[path = "src/mesh_simplify.rs"]
+mod mesh_simplify;
+
+/// Environment variable choosing how asset pack entries are compressed: "zstd" (the default),
+/// "lz4" or "none"
+const PACK_COMPRESSION_ENV: &str = "VARRE_PACK_COMPRESSION";
+
+/// Environment variable listing the fraction of each mesh's triangles kept by each level of detail
+/// generated with the `lods` feature, e.g. "0.5,0.25,0.125" (the default)
+const LOD_RATIOS_ENV: &str = "VARRE_LOD_RATIOS";
+
+/// Environment variable setting how far a level of detail may deviate from the full detail
+/// surface, relative to the model's bounding box diagonal. Simplification stops short of a level's
+/// ratio rather than exceed it. Defaults to `DEFAULT_LOD_MAX_ERROR`.
+const LOD_MAX_ERROR_ENV: &str = "VARRE_LOD_MAX_ERROR";
+const DEFAULT_LOD_MAX_ERROR: f32 = 0.01;
 
 fn main() {
     let out_dir = env::var("OUT_DIR").unwrap();
 
 
     process_shaders(&out_dir);
-    process_models(&out_dir);
+
+    // With the `asset-pack` feature, models and textures are written to one pack that's
+    // memory-mapped at runtime instead of being embedded in the binary
+    let mut pack = env::var_os("CARGO_FEATURE_ASSET_PACK").is_some().then(PackWriter::new);
+    println!("cargo:rerun-if-env-changed={}", PACK_COMPRESSION_ENV);
+    process_models(&out_dir, pack.as_mut());
+    process_textures(&out_dir, pack.as_mut());
+
+    if let Some(pack) = pack {
+        let pack_path = Path::new(&out_dir).join("assets.vpk");
+        fs::write(&pack_path, pack.finish()).expect("Failed to write asset pack");
+        println!("cargo:info=Wrote asset pack {} ({} bytes)", pack_path.display(), pack_path.metadata().unwrap().len());
+    }
+}
+
+fn pack_compression() -> Compression {
+    match env::var(PACK_COMPRESSION_ENV) {
+        Ok(name) => Compression::from_name(&name)
+            .unwrap_or_else(|| panic!("{} must be zstd, lz4 or none, not {:?}", PACK_COMPRESSION_ENV, name)),
+        Err(_) => Compression::Zstd,
+    }
 }
 
 fn process_shaders(out_dir: &str) {
@@ -149,7 +221,7 @@ fn reflect_descriptor_bindings(spirv_data: &[u8]) -> Result<Vec<(u32, u32, rr::D
             let count = match &descriptor_info.binding_count {
                 rr::BindingCount::One => 1u32,
                 rr::BindingCount::StaticSized(n) => *n as u32,
-                rr::BindingCount::Unbounded => 0u32, // Unbounded arrays
+                rr::BindingCount::Unbounded => 0u32, // Unbounded arrays, flagged as `unbounded`
             };
 
             all_bindings.push((*set_index, *binding_index, descriptor_info.ty, count));
@@ -159,6 +231,16 @@ fn reflect_descriptor_bindings(spirv_data: &[u8]) -> Result<Vec<(u32, u32, rr::D
     Ok(all_bindings)
 }
 
+fn reflect_push_constant_range(spirv_data: &[u8]) -> Result<Option<(u32, u32)>, String> {
+    let reflection = rr::Reflection::new_from_spirv(spirv_data)
+        .map_err(|e| format!("Failed to create reflection: {:?}", e))?;
+
+    let push_constant_range = reflection.get_push_constant_range()
+        .map_err(|e| format!("Failed to get push constant range: {:?}", e))?;
+
+    Ok(push_constant_range.map(|info| (info.offset, info.size)))
+}
+
 fn descriptor_type_to_vk_value(desc_type: rr::DescriptorType) -> u32 {
     match desc_type {
         rr::DescriptorType::SAMPLER => 0,
@@ -200,6 +282,8 @@ fn generate_shader_module(out_dir: &str, out_shader_dir: &Path, entry_point_map:
             "geometry" => "ShaderStage::Geometry",
             "tesscontrol" => "ShaderStage::TessellationControl",
             "tesseval" => "ShaderStage::TessellationEvaluation",
+            "amplification" | "task" => "ShaderStage::Task",
+            "mesh" => "ShaderStage::Mesh",
             _ => panic!("Unknown shader stage: {}", stage),
         }
     }
@@ -213,6 +297,8 @@ fn generate_shader_module(out_dir: &str, out_shader_dir: &Path, entry_point_map:
             "geometry" => 0x00000008,    // VK_SHADER_STAGE_GEOMETRY_BIT
             "tesscontrol" => 0x00000002, // VK_SHADER_STAGE_TESSELLATION_CONTROL_BIT
             "tesseval" => 0x00000004,    // VK_SHADER_STAGE_TESSELLATION_EVALUATION_BIT
+            "amplification" | "task" => 0x00000040, // VK_SHADER_STAGE_TASK_BIT_EXT
+            "mesh" => 0x00000080,        // VK_SHADER_STAGE_MESH_BIT_EXT
             _ => panic!("Unknown shader stage: {}", stage),
         }
     }
@@ -248,13 +334,16 @@ fn generate_shader_module(out_dir: &str, out_shader_dir: &Path, entry_point_map:
         generated_code.push_str(&format!("    {},\n", name));
     }
 
-    // Add ShaderID and VkDescriptorSetLayoutBinding to the imports in the shaders module
+    // Add ShaderID and the reflection types to the imports in the shaders module
     let shader_id_suffix = shader_id_suffix.replace(
         "use super::{Shader, ShaderStage};",
-        "use super::{Shader, ShaderStage, ShaderID, VkDescriptorSetLayoutBinding};"
+        "#[allow(unused_imports)]\n    use super::{Shader, ShaderStage, ShaderID, VkDescriptorSetLayoutBinding, VkPushConstantRange, ShaderInterfaceVariable, SpecializationConstant, SpecializationConstantType};"
     );
     generated_code.push_str(&shader_id_suffix);
 
+    // Block layouts, grouped by the shader file they were declared in
+    let mut block_layouts: BTreeMap<String, layouts::LayoutCollector> = BTreeMap::new();
+
     // Second pass: generate shader constants with id field
     for entry in &entries {
         let path = entry.path();
@@ -268,7 +357,7 @@ fn generate_shader_module(out_dir: &str, out_shader_dir: &Path, entry_point_map:
                 continue;
             }
 
-            let _base_name = parts[0..parts.len()-1].join("_");
+            let base_name = parts[0..parts.len()-1].join("_");
             let stage = parts[parts.len()-1];
 
             // Transform "name.stage.spv" into a valid Rust identifier "NAME_STAGE"
@@ -306,6 +395,7 @@ fn generate_shader_module(out_dir: &str, out_shader_dir: &Path, entry_point_map:
                         generated_code.push_str(&format!("            descriptor_type: {},\n", descriptor_type_value));
                         generated_code.push_str(&format!("            descriptor_count: {},\n", count));
                         generated_code.push_str(&format!("            stage_flags: {},\n", stage_to_vk_flags(stage)));
+                        generated_code.push_str(&format!("            unbounded: {},\n", *count == 0));
                         generated_code.push_str("        },\n");
                     }
 
@@ -319,6 +409,99 @@ fn generate_shader_module(out_dir: &str, out_shader_dir: &Path, entry_point_map:
                 }
             }
 
+            let module = spirv::SpirvModule::parse(&spirv_data)
+                .expect(&format!("Failed to parse SPIRV file: {}", file_name));
+
+            // Reflect uniform/storage/push constant block layouts
+            block_layouts.entry(base_name.clone())
+                .or_default()
+                .add_module(&module)
+                .unwrap_or_else(|e| panic!("Failed to reflect buffer layouts for {}: {}", file_name, e));
+
+            // Reflect specialization constants
+            let spec_constants_array_name = format!("{}_SPECIALIZATION_CONSTANTS", var_name);
+            let spec_constants = spirv::reflect_specialization_constants(&module)
+                .unwrap_or_else(|e| {
+                    println!("cargo:warning=Failed to reflect specialization constants for {}: {}", file_name, e);
+                    Vec::new()
+                });
+
+            if !spec_constants.is_empty() {
+                generated_code.push_str(&format!(
+                    "    const {}: [SpecializationConstant; {}] = [\n",
+                    spec_constants_array_name,
+                    spec_constants.len()
+                ));
+
+                for constant in &spec_constants {
+                    generated_code.push_str("        SpecializationConstant {\n");
+                    generated_code.push_str(&format!("            id: {},\n", constant.id));
+                    generated_code.push_str(&format!("            name: {:?},\n", constant.name));
+                    generated_code.push_str(&format!("            ty: SpecializationConstantType::{},\n", constant.ty));
+                    generated_code.push_str(&format!("            default_value: {:#x},\n", constant.default_value));
+                    generated_code.push_str("        },\n");
+                }
+
+                generated_code.push_str("    ];\n\n");
+            }
+
+            // Reflect stage inputs
+            let inputs_array_name = format!("{}_INPUTS", var_name);
+            let inputs = spirv::reflect_interface_variables(&module, spirv::STORAGE_CLASS_INPUT)
+                .unwrap_or_else(|e| {
+                    println!("cargo:warning=Failed to reflect stage inputs for {}: {}", file_name, e);
+                    Vec::new()
+                });
+
+            if !inputs.is_empty() {
+                generated_code.push_str(&format!(
+                    "    const {}: [ShaderInterfaceVariable; {}] = [\n",
+                    inputs_array_name,
+                    inputs.len()
+                ));
+
+                for input in &inputs {
+                    generated_code.push_str(&format!(
+                        "        ShaderInterfaceVariable {{ location: {}, format: {}, semantic: {:?} }},\n",
+                        input.location, input.format, input.semantic
+                    ));
+                }
+
+                generated_code.push_str("    ];\n\n");
+            }
+
+            // Reflect stage outputs
+            let outputs_array_name = format!("{}_OUTPUTS", var_name);
+            let outputs = spirv::reflect_interface_variables(&module, spirv::STORAGE_CLASS_OUTPUT)
+                .unwrap_or_else(|e| {
+                    println!("cargo:warning=Failed to reflect stage outputs for {}: {}", file_name, e);
+                    Vec::new()
+                });
+
+            if !outputs.is_empty() {
+                generated_code.push_str(&format!(
+                    "    const {}: [ShaderInterfaceVariable; {}] = [\n",
+                    outputs_array_name,
+                    outputs.len()
+                ));
+
+                for output in &outputs {
+                    generated_code.push_str(&format!(
+                        "        ShaderInterfaceVariable {{ location: {}, format: {}, semantic: {:?} }},\n",
+                        output.location, output.format, output.semantic
+                    ));
+                }
+
+                generated_code.push_str("    ];\n\n");
+            }
+
+            // Reflect the push constant block, if any
+            let push_constant_range = reflect_push_constant_range(&spirv_data)
+                .unwrap_or_else(|e| {
+                    println!("cargo:warning=Failed to reflect push constants for {}: {}", file_name, e);
+                    None
+                });
+
             // We use a path relative to OUT_DIR for the include_bytes! macro
             let rel_path = format!("shaders/{}", file_name);
 
@@ -358,6 +541,39 @@ fn generate_shader_module(out_dir: &str, out_shader_dir: &Path, entry_point_map:
                 generated_code.push_str("        descriptor_set_layout_bindings: &[],\n");
             }
 
+            if !spec_constants.is_empty() {
+                generated_code.push_str(&format!(
+                    "        specialization_constants: &{},\n",
+                    spec_constants_array_name
+                ));
+            } else {
+                generated_code.push_str("        specialization_constants: &[],\n");
+            }
+
+            match push_constant_range {
+                Some((offset, size)) => {
+                    generated_code.push_str(&format!(
+                        "        push_constant_range: Some(VkPushConstantRange {{ offset: {}, size: {}, stage_flags: {} }}),\n",
+                        offset, size, stage_to_vk_flags(stage)
+                    ));
+                }
+                None => {
+                    generated_code.push_str("        push_constant_range: None,\n");
+                }
+            }
+
+            if !inputs.is_empty() {
+                generated_code.push_str(&format!("        inputs: &{},\n", inputs_array_name));
+            } else {
+                generated_code.push_str("        inputs: &[],\n");
+            }
+
+            if !outputs.is_empty() {
+                generated_code.push_str(&format!("        outputs: &{},\n", outputs_array_name));
+            } else {
+                generated_code.push_str("        outputs: &[],\n");
+            }
+
             generated_code.push_str("    };\n\n");
         }
     }
@@ -375,6 +591,7 @@ fn generate_shader_module(out_dir: &str, out_shader_dir: &Path, entry_point_map:
     }
     generated_code.push_str("        ]\n");
     generated_code.push_str("    }\n\n");
+    generate_name_lookup(&mut generated_code, "ShaderID", &shader_names);
 
     generated_code.push_str("    /// Get the shader reference for this ID\n");
     generated_code.push_str("    pub const fn shader(&self) -> &'static Shader {\n");
@@ -386,10 +603,27 @@ fn generate_shader_module(out_dir: &str, out_shader_dir: &Path, entry_point_map:
     generated_code.push_str("    }\n");
     generated_code.push_str("}\n");
 
+    // Generate #[repr(C)] mirrors of the buffer blocks, one module per shader file
+    generated_code.push_str("\n/// Rust mirrors of the uniform, storage and push constant blocks declared by each shader file\n");
+    generated_code.push_str("#[allow(non_camel_case_types, non_snake_case)]\n");
+    generated_code.push_str("pub mod layouts {\n");
+    for (base_name, collector) in &block_layouts {
+        if collector.is_empty() {
+            continue;
+        }
+        generated_code.push_str(&format!("    pub mod {} {{\n", base_name));
+        generated_code.push_str(&collector.generate(8));
+        generated_code.push_str("    }\n");
+    }
+    generated_code.push_str("}\n");
+
     let dest_path = Path::new(out_dir).join("shaders.rs");
     fs::write(dest_path, generated_code).expect("Failed to write generated shaders.rs");
 }
-fn process_models(out_dir: &str) {
+/// Source formats imported through Assimp
+const MODEL_EXTENSIONS: [&str; 4] = ["obj", "fbx", "gltf", "glb"];
+
+fn process_models(out_dir: &str, mut pack: Option<&mut PackWriter>) {
     let models_dir = Path::new("models");
     let out_models_dir = Path::new(out_dir).join("models");
 
@@ -399,28 +633,41 @@ fn process_models(out_dir: &str) {
     // Tell cargo to rerun if models change
     println!("cargo:rerun-if-changed=models");
 
+    println!("cargo:rerun-if-env-changed={}", LOD_RATIOS_ENV);
+    println!("cargo:rerun-if-env-changed={}", LOD_MAX_ERROR_ENV);
+    let lods = env::var_os("CARGO_FEATURE_LODS").is_some();
+    let options = ModelOptions {
+        meshlets: env::var_os("CARGO_FEATURE_MESHLETS").is_some(),
+        optimize: env::var_os("CARGO_FEATURE_MESH_OPTIMIZATION").is_some(),
+        quantize: env::var_os("CARGO_FEATURE_MESH_QUANTIZATION").is_some(),
+        lod_ratios: if lods { lod_ratios() } else { Vec::new() },
+        lod_max_error: if lods { lod_max_error() } else { DEFAULT_LOD_MAX_ERROR },
+    };
+
     if !models_dir.exists() {
         return;
     }
 
-    // Find all .obj files recursively
-    fn find_obj_files(dir: &Path, obj_files: &mut Vec<PathBuf>) {
+    // Find all model files recursively. A .gltf file's buffers and images are found by Assimp
+    // relative to it, so they aren't imported on their own.
+    fn find_model_files(dir: &Path, model_files: &mut Vec<PathBuf>) {
         if let Ok(entries) = fs::read_dir(dir) {
             for entry in entries.filter_map(|e| e.ok()) {
                 let path = entry.path();
                 if path.is_dir() {
-                    find_obj_files(&path, obj_files);
-                } else if path.extension().map_or(false, |ext| ext == "obj") {
-                    obj_files.push(path);
+                    find_model_files(&path, model_files);
+                } else if path.extension().is_some_and(|ext| MODEL_EXTENSIONS.iter().any(|e| ext.eq_ignore_ascii_case(e))) {
+                    model_files.push(path);
                 }
             }
         }
     }
 
-    let mut obj_files = Vec::new();
-    find_obj_files(models_dir, &mut obj_files);
+    let mut model_files = Vec::new();
+    find_model_files(models_dir, &mut model_files);
+    model_files.sort();
 
-    if obj_files.is_empty() {
+    if model_files.is_empty() {
         return;
     }
 
@@ -444,12 +691,15 @@ fn process_models(out_dir: &str) {
     // Add generated cube model
     model_names.push("CUBE".to_string());
 
-    for model_path in &obj_files {
+    for model_path in &model_files {
         let base_name = model_path.file_stem().unwrap().to_str().unwrap();
         let var_name = base_name
             .replace('-', "_")
             .replace('.', "_")
             .to_uppercase();
+        if model_names.contains(&var_name) {
+            panic!("Model {} has the same ID as another model ({}); rename one of them", model_path.display(), var_name);
+        }
         model_names.push(var_name);
     }
 
@@ -462,122 +712,89 @@ fn process_models(out_dir: &str) {
     // Generate cube model data
     let (cube_vertices, cube_indices, cube_uvs) = generate_cube_model();
 
-    let mut cube_binary_data = Vec::new();
-
-    // Write vertex count
-    let vertex_count = cube_vertices.len() / 3;
-    cube_binary_data.extend_from_slice(&(vertex_count as u32).to_le_bytes());
-
-    // Write vertices
-    for v in &cube_vertices {
-        cube_binary_data.extend_from_slice(&v.to_le_bytes());
+    let cube_positions: Vec<Vec3> = cube_vertices.chunks_exact(3).map(Vec3::from_slice).collect();
+    let cube = MeshData {
+        // Corners are shared between faces, so the smooth normal points away from the center
+        normals: cube_positions.iter().map(|p| p.normalize()).collect(),
+        uv_sets: vec![cube_uvs.chunks_exact(2).map(Vec2::from_slice).collect()],
+        positions: cube_positions,
+        indices: cube_indices.clone(),
+        ..Default::default()
+    };
+    let vertex_count = cube.positions.len();
+    let mut cube_scene = SceneData::single_mesh("cube", cube);
+    if options.optimize {
+        optimize_model("cube", &mut cube_scene);
     }
+    generate_lods("cube", &mut cube_scene, &options);
+    let (cube_binary_data, _) = serialize_model(&cube_scene, &options, None);
 
-    // Write index count
-    cube_binary_data.extend_from_slice(&(cube_indices.len() as u32).to_le_bytes());
-
-    // Write indices
-    for idx in &cube_indices {
-        cube_binary_data.extend_from_slice(&idx.to_le_bytes());
-    }
-
-    // Write UV count
-    cube_binary_data.extend_from_slice(&(cube_uvs.len() as u32).to_le_bytes());
-
-    // Write UVs
-    for uv in &cube_uvs {
-        cube_binary_data.extend_from_slice(&uv.to_le_bytes());
-    }
+    // Materials of each model, indexed by `Submesh::material`
+    let mut model_materials: Vec<(String, Vec<MaterialData>)> = Vec::new();
+    model_materials.push(("CUBE".to_string(), vec![MaterialData { name: "default".to_string(), ..Default::default() }]));
 
     // Write cube binary file
     let cube_bin_path = out_models_dir.join("cube.bin");
     fs::write(&cube_bin_path, &cube_binary_data).expect("Failed to write cube model file");
 
-    models_code.push_str(&format!(
-        "    pub const CUBE_DATA: &[u8] = include_bytes!(concat!(env!(\"OUT_DIR\"), \"/models/cube.bin\"));\n\n"
-    ));
+    match pack.as_deref_mut() {
+        Some(pack) => pack.add("models/cube", &cube_binary_data, pack_compression()),
+        None => models_code.push_str(
+            "    pub const CUBE_DATA: &[u8] = ::include_bytes_aligned::include_bytes_aligned!(16, concat!(env!(\"OUT_DIR\"), \"/models/cube.bin\"));\n\n"
+        ),
+    }
 
     println!("cargo:info=Generated cube model with {} vertices, {} indices ({} bytes)",
              vertex_count, cube_indices.len(), cube_binary_data.len());
 
     // Second pass: generate model constants with id field
-    for model_path in obj_files {
+    for model_path in model_files {
         let file_name = model_path.file_name().unwrap().to_str().unwrap();
         let base_name = model_path.file_stem().unwrap().to_str().unwrap();
 
         println!("cargo:info=Loading model: {}", file_name);
 
+        // Models without a settings file are imported with the defaults
+        let settings = ImportSettings::load(&model_path).unwrap_or_else(|e| panic!("Invalid import settings: {}", e));
+        let model_options = settings.as_ref().map_or_else(|| options.clone(), |settings| options.with_settings(settings));
+        let import = settings.clone().unwrap_or_default();
+
         // Load the model using russimp
-        let scene = Scene::from_file(
-            model_path.to_str().unwrap(),
-            vec![
-                PostProcess::Triangulate,
-                PostProcess::JoinIdenticalVertices,
-                PostProcess::GenerateNormals,
-            ],
-        ).expect(&format!("Failed to load model: {}", file_name));
-
-        // Extract vertices, indices, and UVs from the first mesh
+        let mut properties = PropertyStore::default();
+        if let Some(angle) = import.smoothing_angle {
+            properties.set_float(russimp::sys::AI_CONFIG_PP_GSN_MAX_SMOOTHING_ANGLE, angle);
+        }
+        let scene = Scene::from_file_with_props(model_path.to_str().unwrap(), import.post_process_steps(), &properties)
+            .expect(&format!("Failed to load model: {}", file_name));
+
         if scene.meshes.is_empty() {
             println!("cargo:warning=No meshes found in {}", file_name);
             continue;
         }
 
-        let mesh = &scene.meshes[0];
-
-        // Flatten indices
-        let mut indices = Vec::new();
-        for face in &mesh.faces {
-            for index in &face.0 {
-                indices.push(*index);
-            }
-        }
-
-        // Flatten UVs (u, v for each vertex)
-        let mut uvs = Vec::new();
-        if !mesh.texture_coords.is_empty() && mesh.texture_coords[0].is_some() {
-            if let Some(tex_coords) = &mesh.texture_coords[0] {
-                for uv in tex_coords {
-                    uvs.push(uv.x);
-                    uvs.push(uv.y);
-                }
-            }
+        let mut scene_data = SceneData::from_russimp(&scene);
+        if import.scale != 1.0 || import.axes() != Mat3::IDENTITY {
+            scene_data.convert_space(import.axes(), import.scale);
         }
-
-        // Serialize to binary format:
-        // [vert_count: u32][vertices: f32*3*n][index_count: u32][indices: u32*n][uv_count: u32][uvs: f32*n]
-        let mut binary_data = Vec::new();
-
-        // Write vertex count
-        binary_data.extend_from_slice(&(mesh.vertices.len() as u32).to_le_bytes());
-
-        // Write vertices
-        for vertex in &mesh.vertices {
-            binary_data.extend_from_slice(&vertex.x.to_le_bytes());
-            binary_data.extend_from_slice(&vertex.y.to_le_bytes());
-            binary_data.extend_from_slice(&vertex.z.to_le_bytes());
+        if import.flip_winding {
+            scene_data.meshes.iter_mut().for_each(MeshData::reverse_winding);
         }
-
-        // Write index count
-        binary_data.extend_from_slice(&(indices.len() as u32).to_le_bytes());
-
-        // Write indices
-        for index in &indices {
-            binary_data.extend_from_slice(&index.to_le_bytes());
+        if model_options.optimize {
+            optimize_model(file_name, &mut scene_data);
         }
+        generate_lods(file_name, &mut scene_data, &model_options);
 
-        // Write UV count
-        binary_data.extend_from_slice(&(uvs.len() as u32).to_le_bytes());
-
-        // Write UVs
-        for uv in &uvs {
-            binary_data.extend_from_slice(&uv.to_le_bytes());
-        }
+        // The applied settings are recorded in the model, so it can be traced back to them
+        let record = settings.map(|settings| {
+            println!("cargo:info=Imported {} with settings from {}", file_name, ImportSettings::path(&model_path).display());
+            settings.to_toml(file_name)
+        });
+        let (binary_data, index_format) = serialize_model(&scene_data, &model_options, record.as_deref());
 
         // Write binary file to OUT_DIR/models/
         let bin_filename = format!("{}.bin", base_name.replace('-', "_"));
         let bin_path = out_models_dir.join(&bin_filename);
-        fs::write(&bin_path, binary_data).expect("Failed to write binary model file");
+        fs::write(&bin_path, &binary_data).expect("Failed to write binary model file");
 
         // Generate a valid Rust identifier
         let var_name = base_name
@@ -585,16 +802,28 @@ fn process_models(out_dir: &str) {
             .replace('.', "_")
             .to_uppercase();
 
-        // Generate constant with include_bytes!
-        models_code.push_str(&format!(
-            "    pub const {}_DATA: &[u8] = include_bytes!(concat!(env!(\"OUT_DIR\"), \"/models/{}\"));\n\n",
-            var_name, bin_filename
-        ));
+        // Pack the data, or generate a constant with include_bytes!
+        match pack.as_deref_mut() {
+            Some(pack) => pack.add(&format!("models/{}", var_name.to_lowercase()), &binary_data, pack_compression()),
+            None => models_code.push_str(&format!(
+                "    pub const {}_DATA: &[u8] = ::include_bytes_aligned::include_bytes_aligned!(16, concat!(env!(\"OUT_DIR\"), \"/models/{}\"));\n\n",
+                var_name, bin_filename
+            )),
+        }
+
+        let vertex_count = scene_data.meshes.iter().map(|mesh| mesh.positions.len()).sum::<usize>();
+        println!("cargo:info=Serialized model {} with {} meshes, {} materials, {} nodes, {} joints, {} animations, {} vertices, {} {}-bit indices ({} bytes)",
+                 file_name, scene_data.meshes.len(), scene_data.materials.len(), scene_data.nodes.len(),
+                 scene_data.joints.len(), scene_data.animations.len(), vertex_count,
+                 scene_data.meshes.iter().map(|mesh| mesh.indices.len()).sum::<usize>(),
+                 index_format.size().unwrap() * 8,
+                 bin_path.metadata().unwrap().len());
 
-        println!("cargo:info=Serialized model {} with {} vertices, {} indices ({} bytes)",
-                 file_name, mesh.vertices.len(), indices.len(), bin_path.metadata().unwrap().len());
+        model_materials.push((var_name, scene_data.materials));
     }
 
+    let material_ids = generate_material_module(out_dir, &model_materials);
+
     // Close the models module
     models_code.push_str("}\n\n");
 
@@ -608,17 +837,35 @@ fn process_models(out_dir: &str) {
     }
     models_code.push_str("        ]\n");
     models_code.push_str("    }\n\n");
+    generate_name_lookup(&mut models_code, "ModelID", &model_names);
     models_code.push_str("    /// Get the binary data for this model\n");
-    models_code.push_str("    pub fn data(&self) -> &'static [u8] {\n");
+    generate_data_accessor(&mut models_code, "ModelID", "Model", "models", &model_names, pack.is_some());
+    models_code.push_str("    /// Load and decode this model. The data was written by this build, so failing to decode\n");
+    models_code.push_str("    /// it is a bug rather than a recoverable error.\n");
+    models_code.push_str("    pub fn load(&self) -> Model {\n");
+    models_code.push_str("        Model::decode(Some(*self), self.data())\n");
+    models_code.push_str("            .unwrap_or_else(|e| panic!(\"failed to decode model {:?}: {}\", self, e))\n");
+    models_code.push_str("    }\n\n");
+    models_code.push_str("    /// Zero-copy view of this model's data\n");
+    models_code.push_str("    pub fn view(&self) -> ModelView<'static> {\n");
+    models_code.push_str("        ModelView::parse(self.data())\n");
+    models_code.push_str("            .unwrap_or_else(|e| panic!(\"failed to parse model {:?}: {}\", self, e))\n");
+    models_code.push_str("    }\n\n");
+    models_code.push_str("    /// Materials referenced by this model's submeshes, indexed by `Submesh::material`\n");
+    models_code.push_str("    pub fn materials(&self) -> &'static [MaterialID] {\n");
     models_code.push_str("        match self {\n");
     for name in &model_names {
-        models_code.push_str(&format!("            ModelID::{} => models::{}_DATA,\n", name, name));
+        let ids = material_ids.get(name).map_or(String::new(), |ids| {
+            ids.iter().map(|id| format!("MaterialID::{}", id)).collect::<Vec<_>>().join(", ")
+        });
+        models_code.push_str(&format!("            ModelID::{} => &[{}],\n", name, ids));
     }
     models_code.push_str("        }\n");
     models_code.push_str("    }\n\n");
-    models_code.push_str("    /// Load and decode this model\n");
-    models_code.push_str("    pub fn load(&self) -> Model {\n");
-    models_code.push_str("        Model::decode(*self, self.data())\n");
+    models_code.push_str("    /// Decode this model's node hierarchy\n");
+    models_code.push_str("    pub fn scene(&self) -> SceneAsset {\n");
+    models_code.push_str("        SceneAsset::decode(Some(*self), self.data())\n");
+    models_code.push_str("            .unwrap_or_else(|e| panic!(\"failed to decode the scene of model {:?}: {}\", self, e))\n");
     models_code.push_str("    }\n");
     models_code.push_str("}\n");
 
@@ -626,6 +873,462 @@ fn process_models(out_dir: &str) {
     fs::write(dest_path, models_code).expect("Failed to write generated models.rs");
 }
 
+/// Generate materials.rs from every model's materials. Returns the MaterialID variant names of
+/// each model's materials, in the model's order.
+fn generate_material_module(out_dir: &str, model_materials: &[(String, Vec<MaterialData>)]) -> HashMap<String, Vec<String>> {
+    let template = fs::read_to_string("materials_template.rs")
+        .expect("Failed to read materials_template.rs");
+
+    let (before_material_id, after_material_id) = template.split_once("    // MaterialID variants will be generated here by build.rs")
+        .expect("Template missing MaterialID placeholder comment");
+    let (material_id_suffix, after_materials) = after_material_id.split_once("    // Material constants will be generated here by build.rs")
+        .expect("Template missing Material constants placeholder comment");
+
+    // Variants are named after the model and material, e.g. UTAH_TEAPOT_DEFAULTMATERIAL
+    let mut ids_by_model = HashMap::new();
+    let mut materials = Vec::new();
+    for (model_name, model_materials) in model_materials {
+        let mut ids = Vec::new();
+        for (index, material) in model_materials.iter().enumerate() {
+            let material_name: String = material.name
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+                .collect();
+            let mut id = match material_name.trim_matches('_') {
+                "" => format!("{}_MATERIAL{}", model_name, index),
+                name => format!("{}_{}", model_name, name),
+            };
+            if materials.iter().any(|(existing, _)| *existing == id) {
+                id = format!("{}_{}", id, index);
+            }
+            ids.push(id.clone());
+            materials.push((id, material));
+        }
+        ids_by_model.insert(model_name.clone(), ids);
+    }
+
+    let texture = |path: &Option<String>| path.as_ref().map_or("None".to_string(), |path| format!("Some({:?})", path));
+
+    let mut code = String::from(before_material_id);
+    for (id, _) in &materials {
+        code.push_str(&format!("    {},\n", id));
+    }
+    code.push_str(material_id_suffix);
+
+    for (id, material) in &materials {
+        let alpha_mode = match material.alpha_mode {
+            AlphaMode::Opaque => "Opaque",
+            AlphaMode::Mask => "Mask",
+            AlphaMode::Blend => "Blend",
+        };
+        let [r, g, b, a] = material.base_color.to_array();
+        let [er, eg, eb] = material.emissive.to_array();
+
+        code.push_str(&format!("    pub const {}: Material = Material {{\n", id));
+        code.push_str(&format!("        id: MaterialID::{},\n", id));
+        code.push_str(&format!("        name: {:?},\n", material.name));
+        code.push_str(&format!("        base_color: Vec4::new({:?}, {:?}, {:?}, {:?}),\n", r, g, b, a));
+        code.push_str(&format!("        metallic: {:?},\n", material.metallic));
+        code.push_str(&format!("        roughness: {:?},\n", material.roughness));
+        code.push_str(&format!("        emissive: Vec3::new({:?}, {:?}, {:?}),\n", er, eg, eb));
+        code.push_str(&format!("        base_color_texture: {},\n", texture(&material.base_color_texture)));
+        code.push_str(&format!("        metallic_roughness_texture: {},\n", texture(&material.metallic_roughness_texture)));
+        code.push_str(&format!("        normal_texture: {},\n", texture(&material.normal_texture)));
+        code.push_str(&format!("        occlusion_texture: {},\n", texture(&material.occlusion_texture)));
+        code.push_str(&format!("        emissive_texture: {},\n", texture(&material.emissive_texture)));
+        code.push_str(&format!("        alpha_mode: AlphaMode::{},\n", alpha_mode));
+        code.push_str(&format!("        alpha_cutoff: {:?},\n", material.alpha_cutoff));
+        code.push_str(&format!("        double_sided: {},\n", material.double_sided));
+        code.push_str("    };\n\n");
+    }
+    code.push_str(after_materials);
+
+    code.push_str("\nimpl MaterialID {\n");
+    code.push_str("    /// Get all material IDs\n");
+    code.push_str("    pub const fn all() -> &'static [MaterialID] {\n");
+    code.push_str("        &[\n");
+    for (id, _) in &materials {
+        code.push_str(&format!("            MaterialID::{},\n", id));
+    }
+    code.push_str("        ]\n");
+    code.push_str("    }\n\n");
+    code.push_str("    pub const fn material(&self) -> &'static Material {\n");
+    code.push_str("        match self {\n");
+    for (id, _) in &materials {
+        code.push_str(&format!("            MaterialID::{} => &materials::{},\n", id, id));
+    }
+    code.push_str("        }\n");
+    code.push_str("    }\n");
+    code.push_str("}\n");
+
+    let dest_path = Path::new(out_dir).join("materials.rs");
+    fs::write(dest_path, code).expect("Failed to write generated materials.rs");
+
+    ids_by_model
+}
+
+/// How models are processed on import, chosen by the crate's features and overridable per model
+/// by its import settings
+#[derive(Clone)]
+struct ModelOptions {
+    /// Build meshlets with culling bounds (`meshlets`)
+    meshlets: bool,
+    /// Reorder triangles and vertices for the vertex cache, overdraw and vertex fetch (`mesh-optimization`)
+    optimize: bool,
+    /// Store 16-bit positions, octahedral normals and half float texture coordinates (`mesh-quantization`)
+    quantize: bool,
+    /// Fraction of the triangles kept by each level of detail; empty without the `lods` feature
+    lod_ratios: Vec<f32>,
+    /// Largest error of a level of detail, relative to the model's bounding box diagonal
+    lod_max_error: f32,
+}
+
+impl ModelOptions {
+    fn with_settings(&self, settings: &ImportSettings) -> Self {
+        Self {
+            meshlets: settings.meshlets.unwrap_or(self.meshlets),
+            optimize: settings.optimize.unwrap_or(self.optimize),
+            quantize: settings.quantize.unwrap_or(self.quantize),
+            lod_ratios: settings.lod_ratios.clone().unwrap_or_else(|| self.lod_ratios.clone()),
+            lod_max_error: settings.lod_max_error.unwrap_or(self.lod_max_error),
+        }
+    }
+}
+
+fn lod_ratios() -> Vec<f32> {
+    let Ok(ratios) = env::var(LOD_RATIOS_ENV) else {
+        return vec![0.5, 0.25, 0.125];
+    };
+
+    ratios.split(',')
+        .map(|ratio| match ratio.trim().parse::<f32>() {
+            Ok(value) if value > 0.0 && value < 1.0 => value,
+            _ => panic!("{} must be a comma-separated list of ratios between 0 and 1, not {:?}", LOD_RATIOS_ENV, ratios),
+        })
+        .collect()
+}
+
+fn lod_max_error() -> f32 {
+    match env::var(LOD_MAX_ERROR_ENV) {
+        Ok(error) => error.trim().parse::<f32>().ok().filter(|error| *error >= 0.0)
+            .unwrap_or_else(|| panic!("{} must be a non-negative number, not {:?}", LOD_MAX_ERROR_ENV, error)),
+        Err(_) => DEFAULT_LOD_MAX_ERROR,
+    }
+}
+
+/// Simplify every mesh of a model to each of the configured ratios of its triangles. Levels that
+/// the error limit keeps from getting any simpler than the previous one are dropped.
+fn generate_lods(name: &str, scene: &mut SceneData, options: &ModelOptions) {
+    if options.lod_ratios.is_empty() {
+        return;
+    }
+
+    let bounds = Aabb::from_points(scene.meshes.iter().flat_map(|mesh| mesh.positions.iter().copied()));
+    let max_error = options.lod_max_error * (bounds.max - bounds.min).length();
+
+    for mesh in &mut scene.meshes {
+        let mut previous_count = mesh.indices.len();
+        for ratio in &options.lod_ratios {
+            let target = (mesh.indices.len() as f32 * ratio) as usize / 3 * 3;
+            let simplified = mesh_simplify::simplify(&mesh.indices, &mesh.positions, target, max_error);
+            if simplified.indices.len() >= previous_count {
+                break;
+            }
+            previous_count = simplified.indices.len();
+
+            let indices = if options.optimize {
+                mesh_optimize::optimize_vertex_cache(&simplified.indices, mesh.positions.len())
+            } else {
+                simplified.indices
+            };
+            mesh.lods.push(LodData { indices, error: simplified.error });
+        }
+    }
+
+    let level_count = scene.meshes.iter().map(|mesh| mesh.lods.len()).max().unwrap_or(0);
+    let levels: Vec<String> = (0..level_count)
+        .map(|level| {
+            let triangles: usize = scene.meshes.iter().map(|mesh| mesh_lod(mesh, level).0.len() / 3).sum();
+            let error = scene.meshes.iter().map(|mesh| mesh_lod(mesh, level).1).fold(0.0, f32::max);
+            format!("{} triangles (error {:.4})", triangles, error)
+        })
+        .collect();
+    println!("cargo:info=Generated {} levels of detail for {}: {}", level_count, name, levels.join(", "));
+}
+
+/// Indices and error of a mesh at a level of detail. Meshes that didn't simplify as far as others
+/// stay at their last level.
+fn mesh_lod(mesh: &MeshData, level: usize) -> (&[u32], f32) {
+    match mesh.lods.get(level).or(mesh.lods.last()) {
+        Some(lod) => (&lod.indices, lod.error),
+        None => (&mesh.indices, 0.0),
+    }
+}
+
+/// Run the optimization passes over every mesh of a model, reporting the average cache miss ratio
+/// before and after
+fn optimize_model(name: &str, scene: &mut SceneData) {
+    let triangle_count = scene.meshes.iter().map(|mesh| mesh.indices.len() / 3).sum::<usize>();
+    let acmr = |scene: &SceneData| {
+        let misses: usize = scene.meshes.iter().map(|mesh| mesh_optimize::cache_misses(&mesh.indices, mesh_optimize::CACHE_SIZE)).sum();
+        misses as f32 / triangle_count.max(1) as f32
+    };
+
+    let before = acmr(scene);
+    for mesh in &mut scene.meshes {
+        optimize_mesh(mesh);
+    }
+    println!("cargo:info=Optimized {}: ACMR {:.3} -> {:.3} over {} triangles", name, before, acmr(scene), triangle_count);
+}
+
+/// Reorder a mesh's triangles for the vertex cache and then overdraw, and its vertices, in every
+/// stream, for fetch locality
+fn optimize_mesh(mesh: &mut MeshData) {
+    let vertex_count = mesh.positions.len();
+    mesh.indices = mesh_optimize::optimize_vertex_cache(&mesh.indices, vertex_count);
+    mesh.indices = mesh_optimize::optimize_overdraw(&mesh.indices, &mesh.positions, mesh_optimize::OVERDRAW_THRESHOLD);
+
+    let remap = mesh_optimize::vertex_fetch_remap(&mesh.indices, vertex_count);
+    for index in &mut mesh.indices {
+        *index = remap[*index as usize];
+    }
+    mesh_optimize::remap_stream(&mut mesh.positions, &remap);
+    mesh_optimize::remap_stream(&mut mesh.normals, &remap);
+    mesh_optimize::remap_stream(&mut mesh.tangents, &remap);
+    for uvs in &mut mesh.uv_sets {
+        mesh_optimize::remap_stream(uvs, &remap);
+    }
+    for colors in &mut mesh.color_sets {
+        mesh_optimize::remap_stream(colors, &remap);
+    }
+    mesh_optimize::remap_stream(&mut mesh.joints, &remap);
+    mesh_optimize::remap_stream(&mut mesh.weights, &remap);
+}
+
+/// Serialize a scene in the format described in src/model_format.rs. Meshes become submeshes of
+/// one model; attributes only some meshes have are filled with defaults in the others. Returns the
+/// model file and the format its indices were stored in.
+fn serialize_model(scene: &SceneData, options: &ModelOptions, import_settings: Option<&str>) -> (Vec<u8>, ElementFormat) {
+    let has_normals = scene.meshes.iter().any(|mesh| !mesh.normals.is_empty());
+    let has_tangents = scene.meshes.iter().any(|mesh| !mesh.tangents.is_empty());
+    let has_skin = scene.meshes.iter().any(|mesh| !mesh.joints.is_empty());
+    let uv_set_count = scene.meshes.iter().map(|mesh| mesh.uv_sets.len()).max().unwrap_or(0);
+    let color_set_count = scene.meshes.iter().map(|mesh| mesh.color_sets.len()).max().unwrap_or(0);
+
+    let mut merged = MeshData {
+        uv_sets: vec![Vec::new(); uv_set_count],
+        color_sets: vec![Vec::new(); color_set_count],
+        ..Default::default()
+    };
+    let mut submeshes = Vec::with_capacity(scene.meshes.len());
+
+    for mesh in &scene.meshes {
+        let vertex_offset = merged.positions.len() as u32;
+        let vertex_count = mesh.positions.len();
+
+        submeshes.push(Submesh {
+            index_offset: merged.indices.len() as u32,
+            index_count: mesh.indices.len() as u32,
+            vertex_offset,
+            vertex_count: vertex_count as u32,
+            material: mesh.material,
+            bounds: Aabb::from_points(mesh.positions.iter().copied()),
+            bounding_sphere: BoundingSphere::from_points(&mesh.positions),
+        });
+
+        merged.positions.extend_from_slice(&mesh.positions);
+        merged.indices.extend(mesh.indices.iter().map(|index| index + vertex_offset));
+
+        if has_normals {
+            extend_or_fill(&mut merged.normals, &mesh.normals, vertex_count, Vec3::ZERO);
+        }
+        if has_tangents {
+            extend_or_fill(&mut merged.tangents, &mesh.tangents, vertex_count, Vec4::new(0.0, 0.0, 0.0, 1.0));
+        }
+        for (set, uvs) in merged.uv_sets.iter_mut().enumerate() {
+            extend_or_fill(uvs, mesh.uv_sets.get(set).map_or(&[], Vec::as_slice), vertex_count, Vec2::ZERO);
+        }
+        for (set, colors) in merged.color_sets.iter_mut().enumerate() {
+            extend_or_fill(colors, mesh.color_sets.get(set).map_or(&[], Vec::as_slice), vertex_count, [255; 4]);
+        }
+        // Vertices of unskinned meshes get no joint influences
+        if has_skin {
+            extend_or_fill(&mut merged.joints, &mesh.joints, vertex_count, [0; 4]);
+            extend_or_fill(&mut merged.weights, &mesh.weights, vertex_count, [0; 4]);
+        }
+    }
+
+    let mut names = String::new();
+    let mut node_meshes = Vec::new();
+    let nodes: Vec<Node> = scene.nodes.iter()
+        .map(|node| {
+            let record = Node {
+                parent: node.parent.map_or(NO_PARENT, |parent| parent as u32),
+                mesh_offset: node_meshes.len() as u32,
+                mesh_count: node.meshes.len() as u32,
+                name_offset: names.len() as u32,
+                name_length: node.name.len() as u32,
+                transform: node.transform.to_cols_array(),
+            };
+            node_meshes.extend_from_slice(&node.meshes);
+            names.push_str(&node.name);
+            record
+        })
+        .collect();
+
+    let mut channels = Vec::new();
+    let mut translations = Vec::new();
+    let mut rotations = Vec::new();
+    let mut scales = Vec::new();
+    let animations: Vec<Animation> = scene.animations.iter()
+        .map(|animation| {
+            let record = Animation {
+                name_offset: names.len() as u32,
+                name_length: animation.name.len() as u32,
+                duration: animation.duration,
+                channel_offset: channels.len() as u32,
+                channel_count: animation.channels.len() as u32,
+            };
+            for channel in &animation.channels {
+                channels.push(AnimationChannel {
+                    node: channel.node,
+                    translation_offset: translations.len() as u32,
+                    translation_count: channel.translations.len() as u32,
+                    rotation_offset: rotations.len() as u32,
+                    rotation_count: channel.rotations.len() as u32,
+                    scale_offset: scales.len() as u32,
+                    scale_count: channel.scales.len() as u32,
+                });
+                translations.extend_from_slice(&channel.translations);
+                rotations.extend_from_slice(&channel.rotations);
+                scales.extend_from_slice(&channel.scales);
+            }
+            names.push_str(&animation.name);
+            record
+        })
+        .collect();
+
+    let positions = &merged.positions;
+    let indices = &merged.indices;
+    let bounds = Aabb::from_points(positions.iter().copied());
+
+    let mut writer = ModelWriter::new();
+    if options.quantize {
+        let quantized: Vec<[u16; 4]> = positions.iter().map(|position| quantize_position(*position, &bounds)).collect();
+        writer.vertex_stream(StreamSemantic::Position, 0, ElementFormat::Unorm16x4, &quantized);
+    } else {
+        writer.vertex_stream(StreamSemantic::Position, 0, ElementFormat::Float32x3, positions);
+    }
+    writer
+        .indices(indices)
+        .submeshes(&submeshes)
+        .bounds(bounds)
+        .bounding_sphere(BoundingSphere::from_points(positions))
+        .nodes(&nodes, &node_meshes)
+        .names(&names);
+
+    if let Some(settings) = import_settings {
+        writer.import_settings(settings);
+    }
+    if !scene.cameras.is_empty() {
+        writer.cameras(&scene.cameras);
+    }
+    if !scene.lights.is_empty() {
+        writer.lights(&scene.lights);
+    }
+    if !scene.joints.is_empty() {
+        writer.skin(&scene.joints);
+    }
+    if !animations.is_empty() {
+        writer.animations(&animations, &channels, &translations, &rotations, &scales);
+    }
+
+    if !merged.normals.is_empty() {
+        if options.quantize {
+            let encoded: Vec<[i16; 2]> = merged.normals.iter().map(|normal| encode_octahedral(*normal)).collect();
+            writer.vertex_stream(StreamSemantic::Normal, 0, ElementFormat::Snorm16x2, &encoded);
+        } else {
+            writer.vertex_stream(StreamSemantic::Normal, 0, ElementFormat::Float32x3, &merged.normals);
+        }
+    }
+    if !merged.tangents.is_empty() {
+        writer.vertex_stream(StreamSemantic::Tangent, 0, ElementFormat::Float32x4, &merged.tangents);
+    }
+    for (set, uvs) in merged.uv_sets.iter().enumerate() {
+        if options.quantize {
+            let halves: Vec<[u16; 2]> = uvs.iter().map(|uv| [f16_from_f32(uv.x), f16_from_f32(uv.y)]).collect();
+            writer.vertex_stream(StreamSemantic::TexCoord, set as u16, ElementFormat::Float16x2, &halves);
+        } else {
+            writer.vertex_stream(StreamSemantic::TexCoord, set as u16, ElementFormat::Float32x2, uvs);
+        }
+    }
+    if options.quantize {
+        let normal_streams = usize::from(has_normals);
+        let float_size = positions.len() * (12 + 12 * normal_streams + 8 * merged.uv_sets.len());
+        let quantized_size = positions.len() * (8 + 4 * normal_streams + 4 * merged.uv_sets.len());
+        println!("cargo:info=Quantized positions, normals and texture coordinates from {} to {} bytes", float_size, quantized_size);
+    }
+    for (set, colors) in merged.color_sets.iter().enumerate() {
+        writer.vertex_stream(StreamSemantic::Color, set as u16, ElementFormat::Unorm8x4, colors);
+    }
+    if !merged.joints.is_empty() {
+        writer.vertex_stream(StreamSemantic::Joints, 0, ElementFormat::Uint16x4, &merged.joints);
+        writer.vertex_stream(StreamSemantic::Weights, 0, ElementFormat::Unorm16x4, &merged.weights);
+    }
+
+    let level_count = scene.meshes.iter().map(|mesh| mesh.lods.len()).max().unwrap_or(0);
+    if level_count > 0 {
+        let mut lods = Vec::with_capacity(level_count);
+        let mut lod_submeshes = Vec::with_capacity(level_count * submeshes.len());
+        let mut lod_indices = Vec::new();
+        for level in 0..level_count {
+            let submesh_offset = lod_submeshes.len() as u32;
+            let mut error: f32 = 0.0;
+            for (mesh, submesh) in scene.meshes.iter().zip(&submeshes) {
+                let (indices, mesh_error) = mesh_lod(mesh, level);
+                lod_submeshes.push(Submesh { index_offset: lod_indices.len() as u32, index_count: indices.len() as u32, ..*submesh });
+                lod_indices.extend(indices.iter().map(|index| index + submesh.vertex_offset));
+                error = error.max(mesh_error);
+            }
+            lods.push(Lod { submesh_offset, submesh_count: submeshes.len() as u32, error });
+        }
+        writer.lods(&lods, &lod_submeshes, &lod_indices);
+    }
+
+    if options.meshlets {
+        // Built per submesh so that no meshlet spans two materials
+        let points: Vec<[f32; 3]> = positions.iter().map(|p| p.to_array()).collect();
+        let mut all = meshlets::Meshlets::default();
+        for submesh in &submeshes {
+            let range = submesh.index_offset as usize..(submesh.index_offset + submesh.index_count) as usize;
+            let result = meshlets::build_meshlets(&points, &indices[range], meshlets::MAX_VERTICES, meshlets::MAX_TRIANGLES);
+
+            let vertex_base = all.vertices.len() as u32;
+            let triangle_base = (all.triangles.len() / 3) as u32;
+            all.meshlets.extend(result.meshlets.iter().map(|meshlet| Meshlet {
+                vertex_offset: meshlet.vertex_offset + vertex_base,
+                triangle_offset: meshlet.triangle_offset + triangle_base,
+                ..*meshlet
+            }));
+            all.vertices.extend(result.vertices);
+            all.triangles.extend(result.triangles);
+        }
+        println!("cargo:info=Built {} meshlets for {} triangles", all.meshlets.len(), indices.len() / 3);
+        writer.meshlets(&all.meshlets, &all.vertices, &all.triangles);
+    }
+
+    (writer.finish(), writer.index_format().expect("model written without indices"))
+}
+
+/// Append `values` to a merged stream, or `count` defaults if the mesh doesn't have the attribute
+fn extend_or_fill<T: Copy>(stream: &mut Vec<T>, values: &[T], count: usize, default: T) {
+    if values.len() == count {
+        stream.extend_from_slice(values);
+    } else {
+        stream.resize(stream.len() + count, default);
+    }
+}
+
 fn generate_cube_model() -> (Vec<f32>, Vec<u32>, Vec<f32>) {
     // Cube vertices: 8 unique positions
     #[rustfmt::skip]
@@ -673,4 +1376,150 @@ fn generate_cube_model() -> (Vec<f32>, Vec<u32>, Vec<f32>) {
     ];
 
     (vertices, indices, uvs)
-}
\ No newline at end of file
+}
+/// Generate `name()` and `from_name()` for an ID enum, naming each variant in lowercase so assets
+/// can be looked up by string, e.g. `ModelID::from_name("utah_teapot")`
+fn generate_name_lookup(code: &mut String, enum_name: &str, variants: &[String]) {
+    code.push_str("    /// Name of the asset, for looking it up at runtime\n");
+    code.push_str("    pub const fn name(&self) -> &'static str {\n");
+    code.push_str("        match *self {\n");
+    for variant in variants {
+        code.push_str(&format!("            {}::{} => {:?},\n", enum_name, variant, variant.to_lowercase()));
+    }
+    code.push_str("        }\n");
+    code.push_str("    }\n\n");
+    code.push_str("    /// Find the ID whose `name()` is `name`\n");
+    code.push_str(&format!("    pub fn from_name(name: &str) -> Option<{}> {{\n", enum_name));
+    code.push_str("        Self::all().iter().copied().find(|id| id.name() == name)\n");
+    code.push_str("    }\n\n");
+}
+
+/// Generate `data()`, which returns an asset's data from its embedded constant, or from the
+/// bundled pack when the data was packed
+fn generate_data_accessor(code: &mut String, enum_name: &str, kind: &str, module: &str, variants: &[String], packed: bool) {
+    code.push_str("    pub fn data(&self) -> &'static [u8] {\n");
+    if packed {
+        code.push_str(&format!("        crate::asset_source::bundled_pack().get(crate::AssetKind::{}, self.name())\n", kind));
+        code.push_str("            .unwrap_or_else(|e| panic!(\"{}\", e))\n");
+    } else {
+        code.push_str("        match *self {\n");
+        for variant in variants {
+            code.push_str(&format!("            {}::{} => {}::{}_DATA,\n", enum_name, variant, module, variant));
+        }
+        code.push_str("        }\n");
+    }
+    code.push_str("    }\n\n");
+}
+
+fn process_textures(out_dir: &str, mut pack: Option<&mut PackWriter>) {
+    let textures_dir = Path::new("textures");
+    let out_textures_dir = Path::new(out_dir).join("textures");
+
+    fs::create_dir_all(&out_textures_dir).expect("Failed to create output textures directory");
+
+    println!("cargo:rerun-if-changed=textures");
+
+    // Textures are stored block compressed (BC7, BC5 for normal maps, BC6H for HDR) with the
+    // `texture-compression` feature, and as RGBA8/RGBA16F otherwise
+    let compress = env::var_os("CARGO_FEATURE_TEXTURE_COMPRESSION").is_some();
+
+    fn find_texture_files(dir: &Path, texture_files: &mut Vec<PathBuf>) {
+        if let Ok(entries) = fs::read_dir(dir) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                let path = entry.path();
+                if path.is_dir() {
+                    find_texture_files(&path, texture_files);
+                } else if path.extension().is_some_and(|ext| TEXTURE_EXTENSIONS.iter().any(|e| ext.eq_ignore_ascii_case(e))) {
+                    texture_files.push(path);
+                }
+            }
+        }
+    }
+
+    // The module is generated even without textures, so TextureID always exists
+    let mut texture_files = Vec::new();
+    find_texture_files(textures_dir, &mut texture_files);
+    texture_files.sort();
+
+    let template = fs::read_to_string("textures_template.rs")
+        .expect("Failed to read textures_template.rs");
+
+    let (before_texture_id, after_texture_id) = template.split_once("    // TextureID variants will be generated here by build.rs")
+        .expect("Template missing TextureID placeholder comment");
+    let (texture_id_suffix, after_textures) = after_texture_id.split_once("    // Texture constants will be generated here by build.rs")
+        .expect("Template missing Texture constants placeholder comment");
+
+    let mut textures = Vec::new();
+    for texture_path in &texture_files {
+        let id = texture_path.file_stem().unwrap().to_str().unwrap()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+            .collect::<String>();
+        if textures.iter().any(|(existing, _, _, _)| *existing == id) {
+            panic!("Texture {} has the same ID as another texture ({}); rename one of them", texture_path.display(), id);
+        }
+
+        let kind = TextureKind::from_path(texture_path);
+        let texture = load_texture(texture_path, kind, compress).unwrap_or_else(|e| panic!("{}", e));
+
+        let file_name = format!("{}.ktx2", id.to_lowercase());
+        fs::write(out_textures_dir.join(&file_name), &texture.ktx2).expect("Failed to write texture file");
+        if let Some(pack) = pack.as_deref_mut() {
+            pack.add(&format!("textures/{}", id.to_lowercase()), &texture.ktx2, pack_compression());
+        }
+
+        let name = texture_path.strip_prefix(textures_dir).unwrap().to_str().unwrap().replace('\\', "/");
+        textures.push((id, name, texture, file_name));
+    }
+
+    let mut code = String::from(before_texture_id);
+    for (id, _, _, _) in &textures {
+        code.push_str(&format!("    {},\n", id));
+    }
+    code.push_str(texture_id_suffix);
+
+    for (id, name, texture, file_name) in &textures {
+        code.push_str(&format!("    pub const {}: Texture = Texture {{\n", id));
+        code.push_str(&format!("        id: TextureID::{},\n", id));
+        code.push_str(&format!("        name: {:?},\n", name));
+        code.push_str(&format!("        kind: TextureKind::{},\n", texture.kind.name()));
+        code.push_str(&format!("        width: {},\n", texture.width));
+        code.push_str(&format!("        height: {},\n", texture.height));
+        code.push_str(&format!("        mip_levels: {},\n", texture.mip_levels));
+        code.push_str(&format!("        vk_format: {},\n", texture.vk_format));
+        code.push_str("    };\n");
+        if pack.is_none() {
+            code.push_str(&format!(
+                "    pub const {}_DATA: &[u8] = ::include_bytes_aligned::include_bytes_aligned!(16, concat!(env!(\"OUT_DIR\"), \"/textures/{}\"));\n",
+                id, file_name
+            ));
+        }
+        code.push('\n');
+    }
+    code.push_str(after_textures);
+
+    code.push_str("\nimpl TextureID {\n");
+    code.push_str("    /// Get all texture IDs\n");
+    code.push_str("    pub const fn all() -> &'static [TextureID] {\n");
+    code.push_str("        &[\n");
+    for (id, _, _, _) in &textures {
+        code.push_str(&format!("            TextureID::{},\n", id));
+    }
+    code.push_str("        ]\n");
+    code.push_str("    }\n\n");
+    let texture_ids: Vec<String> = textures.iter().map(|(id, _, _, _)| id.clone()).collect();
+    generate_name_lookup(&mut code, "TextureID", &texture_ids);
+    code.push_str("    /// KTX2 container holding every mip level\n");
+    generate_data_accessor(&mut code, "TextureID", "Texture", "textures", &texture_ids, pack.is_some());
+    code.push_str("    pub const fn texture(&self) -> &'static Texture {\n");
+    code.push_str("        match *self {\n");
+    for (id, _, _, _) in &textures {
+        code.push_str(&format!("            TextureID::{} => &textures::{},\n", id, id));
+    }
+    code.push_str("        }\n");
+    code.push_str("    }\n");
+    code.push_str("}\n");
+
+    let dest_path = Path::new(out_dir).join("textures.rs");
+    fs::write(dest_path, code).expect("Failed to write generated textures.rs");
+}
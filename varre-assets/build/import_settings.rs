@@ -0,0 +1,302 @@
+// Per-model import settings, read from a `<model>.import.toml` file next to the model. Every key is
+// optional; a model without a settings file is imported with the defaults.
+//
+//   scale = 0.01                  uniform scale, e.g. to convert centimetres to metres
+//   up_axis = "z"                 the source's up axis, "x", "y" (the default) or "z", rotated onto +Y
+//   flip_handedness = true        mirror the X axis to convert between left and right handed
+//   flip_winding = true           reverse the winding of every triangle
+//   post_process = ["generate_smooth_normals", "flip_uvs"]
+//                                 Assimp post-processing steps, replacing the default ones
+//   smoothing_angle = 60.0        largest angle in degrees between normals that get smoothed
+//
+//   [optimization]                overrides of the crate's features for this model
+//   optimize = false              `mesh-optimization`
+//   quantize = true               `mesh-quantization`
+//   meshlets = false              `meshlets`
+//
+//   [lods]                        overrides of the level of detail environment variables
+//   ratios = [0.5, 0.25]          `VARRE_LOD_RATIOS`, or [] for no levels of detail
+//   max_error = 0.02              `VARRE_LOD_MAX_ERROR`
+
+use glam::{Mat3, Vec3};
+use russimp::scene::PostProcess;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// Steps run when the settings don't list any
+const DEFAULT_POST_PROCESS: [&str; 4] = ["triangulate", "join_identical_vertices", "generate_normals", "calc_tangent_space"];
+
+/// An Assimp post-processing step by its settings name. Steps that would undo or duplicate the
+/// settings above, such as Assimp's own scaling and handedness conversion, aren't offered.
+fn post_process_step(name: &str) -> Option<PostProcess> {
+    Some(match name {
+        "triangulate" => PostProcess::Triangulate,
+        "join_identical_vertices" => PostProcess::JoinIdenticalVertices,
+        "generate_normals" => PostProcess::GenerateNormals,
+        "generate_smooth_normals" => PostProcess::GenerateSmoothNormals,
+        "force_generate_normals" => PostProcess::ForceGenerateNormals,
+        "calc_tangent_space" => PostProcess::CalcTangentSpace,
+        "fix_infacing_normals" => PostProcess::FixInfacingNormals,
+        "find_degenerates" => PostProcess::FindDegenerates,
+        "find_invalid_data" => PostProcess::FindInvalidData,
+        "find_instances" => PostProcess::FindInstances,
+        "remove_redundant_materials" => PostProcess::RemoveRedundantMaterials,
+        "optimize_meshes" => PostProcess::OptimizeMeshes,
+        "optimize_graph" => PostProcess::OptimizeGraph,
+        "pre_transform_vertices" => PostProcess::PreTransformVertices,
+        "flip_uvs" => PostProcess::FlipUVs,
+        "generate_uv_coords" => PostProcess::GenerateUVCoords,
+        "transform_uv_coords" => PostProcess::TransformUVCoords,
+        "limit_bone_weights" => PostProcess::LimitBoneWeights,
+        "split_large_meshes" => PostProcess::SplitLargeMeshes,
+        "sort_by_primitive_type" => PostProcess::SortByPrimitiveType,
+        "validate_data_structure" => PostProcess::ValidateDataStructure,
+        _ => return None,
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpAxis {
+    X,
+    Y,
+    Z,
+}
+
+impl UpAxis {
+    fn name(self) -> &'static str {
+        match self {
+            UpAxis::X => "x",
+            UpAxis::Y => "y",
+            UpAxis::Z => "z",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ImportSettings {
+    pub scale: f32,
+    pub up_axis: UpAxis,
+    pub flip_handedness: bool,
+    pub flip_winding: bool,
+    /// Names of the Assimp steps to run, as accepted by `post_process_step`
+    pub post_process: Vec<String>,
+    /// Largest angle in degrees between smoothed normals, or None for Assimp's default
+    pub smoothing_angle: Option<f32>,
+    pub optimize: Option<bool>,
+    pub quantize: Option<bool>,
+    pub meshlets: Option<bool>,
+    pub lod_ratios: Option<Vec<f32>>,
+    pub lod_max_error: Option<f32>,
+}
+
+impl Default for ImportSettings {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            up_axis: UpAxis::Y,
+            flip_handedness: false,
+            flip_winding: false,
+            post_process: DEFAULT_POST_PROCESS.iter().map(|step| step.to_string()).collect(),
+            smoothing_angle: None,
+            optimize: None,
+            quantize: None,
+            meshlets: None,
+            lod_ratios: None,
+            lod_max_error: None,
+        }
+    }
+}
+
+impl ImportSettings {
+    /// Path of the settings file of a model
+    pub fn path(model_path: &Path) -> PathBuf {
+        let stem = model_path.file_stem().unwrap().to_str().unwrap();
+        model_path.with_file_name(format!("{}.import.toml", stem))
+    }
+
+    /// Read the settings file of a model. Returns None if it has none.
+    pub fn load(model_path: &Path) -> Result<Option<Self>, String> {
+        let path = Self::path(model_path);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let text = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(&text).map(Some).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut table: Table = text.parse().map_err(|e: toml::de::Error| e.to_string())?;
+        let mut settings = Self::default();
+
+        if let Some(scale) = take_float(&mut table, "scale")? {
+            if !(scale > 0.0 && scale.is_finite()) {
+                return Err(format!("scale must be a positive number, not {}", scale));
+            }
+            settings.scale = scale;
+        }
+        if let Some(axis) = take(&mut table, "up_axis", |value| value.as_str().map(str::to_string))? {
+            settings.up_axis = match axis.as_str() {
+                "x" => UpAxis::X,
+                "y" => UpAxis::Y,
+                "z" => UpAxis::Z,
+                _ => return Err(format!("up_axis must be \"x\", \"y\" or \"z\", not {:?}", axis)),
+            };
+        }
+        settings.flip_handedness = take(&mut table, "flip_handedness", Value::as_bool)?.unwrap_or(false);
+        settings.flip_winding = take(&mut table, "flip_winding", Value::as_bool)?.unwrap_or(false);
+
+        if let Some(steps) = take(&mut table, "post_process", |value| value.as_array().cloned())? {
+            settings.post_process = steps.iter()
+                .map(|step| match step.as_str() {
+                    Some(name) if post_process_step(name).is_some() => Ok(name.to_string()),
+                    _ => Err(format!("post_process has unknown step {}; see build/import_settings.rs for the steps", step)),
+                })
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some(angle) = take_float(&mut table, "smoothing_angle")? {
+            if !(0.0..=175.0).contains(&angle) {
+                return Err(format!("smoothing_angle must be between 0 and 175 degrees, not {}", angle));
+            }
+            settings.smoothing_angle = Some(angle);
+        }
+
+        if let Some(mut optimization) = take(&mut table, "optimization", |value| value.as_table().cloned())? {
+            settings.optimize = take(&mut optimization, "optimize", Value::as_bool)?;
+            settings.quantize = take(&mut optimization, "quantize", Value::as_bool)?;
+            settings.meshlets = take(&mut optimization, "meshlets", Value::as_bool)?;
+            reject_unknown(&optimization, "optimization.")?;
+        }
+
+        if let Some(mut lods) = take(&mut table, "lods", |value| value.as_table().cloned())? {
+            if let Some(ratios) = take(&mut lods, "ratios", |value| value.as_array().cloned())? {
+                settings.lod_ratios = Some(ratios.iter()
+                    .map(|ratio| match float(ratio) {
+                        Some(value) if value > 0.0 && value < 1.0 => Ok(value),
+                        _ => Err(format!("lods.ratios must be ratios between 0 and 1, not {}", ratio)),
+                    })
+                    .collect::<Result<_, _>>()?);
+            }
+            if let Some(error) = take_float(&mut lods, "max_error")? {
+                if error < 0.0 {
+                    return Err(format!("lods.max_error must be non-negative, not {}", error));
+                }
+                settings.lod_max_error = Some(error);
+            }
+            reject_unknown(&lods, "lods.")?;
+        }
+
+        reject_unknown(&table, "")?;
+        Ok(settings)
+    }
+
+    /// Assimp steps to run. Meshes are always triangulated, as the rest of the pipeline needs
+    /// triangles, and a smoothing angle takes effect through `GenerateSmoothNormals`, which Assimp
+    /// won't run together with `GenerateNormals`.
+    pub fn post_process_steps(&self) -> Vec<PostProcess> {
+        let mut names: Vec<&str> = self.post_process.iter().map(String::as_str).collect();
+        if !names.contains(&"triangulate") {
+            names.insert(0, "triangulate");
+        }
+        if self.smoothing_angle.is_some() {
+            names.retain(|name| *name != "generate_normals");
+            if !names.contains(&"generate_smooth_normals") {
+                names.push("generate_smooth_normals");
+            }
+        }
+
+        names.iter()
+            .map(|name| post_process_step(name).unwrap())
+            .collect()
+    }
+
+    /// Rotation onto +Y up, followed by the handedness mirror. The scale is applied separately.
+    pub fn axes(&self) -> Mat3 {
+        let rotation = match self.up_axis {
+            UpAxis::X => Mat3::from_cols(Vec3::Y, -Vec3::X, Vec3::Z),
+            UpAxis::Y => Mat3::IDENTITY,
+            UpAxis::Z => Mat3::from_cols(Vec3::X, -Vec3::Z, Vec3::Y),
+        };
+        let mirror = if self.flip_handedness { Mat3::from_diagonal(Vec3::new(-1.0, 1.0, 1.0)) } else { Mat3::IDENTITY };
+        mirror * rotation
+    }
+
+    /// The settings as TOML, with every key spelled out, for recording in the model they were
+    /// applied to
+    pub fn to_toml(&self, source: &str) -> String {
+        let mut table = Table::new();
+        table.insert("source".to_string(), source.into());
+        table.insert("scale".to_string(), float_value(self.scale));
+        table.insert("up_axis".to_string(), self.up_axis.name().into());
+        table.insert("flip_handedness".to_string(), self.flip_handedness.into());
+        table.insert("flip_winding".to_string(), self.flip_winding.into());
+        let steps: Vec<Value> = self.post_process.iter().map(|step| step.as_str().into()).collect();
+        table.insert("post_process".to_string(), steps.into());
+        if let Some(angle) = self.smoothing_angle {
+            table.insert("smoothing_angle".to_string(), float_value(angle));
+        }
+
+        let mut optimization = Table::new();
+        for (key, value) in [("optimize", self.optimize), ("quantize", self.quantize), ("meshlets", self.meshlets)] {
+            if let Some(value) = value {
+                optimization.insert(key.to_string(), value.into());
+            }
+        }
+        if !optimization.is_empty() {
+            table.insert("optimization".to_string(), optimization.into());
+        }
+
+        let mut lods = Table::new();
+        if let Some(ratios) = &self.lod_ratios {
+            let ratios: Vec<Value> = ratios.iter().map(|ratio| float_value(*ratio)).collect();
+            lods.insert("ratios".to_string(), ratios.into());
+        }
+        if let Some(error) = self.lod_max_error {
+            lods.insert("max_error".to_string(), float_value(error));
+        }
+        if !lods.is_empty() {
+            table.insert("lods".to_string(), lods.into());
+        }
+
+        table.to_string()
+    }
+}
+
+/// Remove `key` from `table`, converting it with `convert`
+fn take<T>(table: &mut Table, key: &str, convert: impl FnOnce(&Value) -> Option<T>) -> Result<Option<T>, String> {
+    match table.remove(key) {
+        Some(value) => convert(&value).map(Some).ok_or_else(|| format!("{} has the wrong type: {}", key, value)),
+        None => Ok(None),
+    }
+}
+
+/// Remove `key` from `table` as a float, accepting integers too
+fn take_float(table: &mut Table, key: &str) -> Result<Option<f32>, String> {
+    match table.remove(key) {
+        Some(value) => float(&value).map(Some).ok_or_else(|| format!("{} must be a number, not {}", key, value)),
+        None => Ok(None),
+    }
+}
+
+fn float(value: &Value) -> Option<f32> {
+    match value {
+        Value::Float(value) => Some(*value as f32),
+        Value::Integer(value) => Some(*value as f32),
+        _ => None,
+    }
+}
+
+/// A float value written as the shortest decimal that reads back as the same f32, rather than the
+/// f32's exact value
+fn float_value(value: f32) -> Value {
+    Value::Float(value.to_string().parse().unwrap())
+}
+
+/// Fail on keys left over after every known one was taken, which are most likely typos
+fn reject_unknown(table: &Table, prefix: &str) -> Result<(), String> {
+    match table.keys().next() {
+        Some(key) => Err(format!("unknown setting {}{}", prefix, key)),
+        None => Ok(()),
+    }
+}
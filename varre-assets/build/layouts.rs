@@ -0,0 +1,371 @@
+// Rust type generation for the uniform, storage and push constant blocks reflected from SPIR-V.
+//
+// Every block struct becomes a #[repr(C)] Rust struct with its fields at the offsets given by the
+// shader's Offset decorations, explicit padding in between, and compile-time assertions on the
+// struct size and each field offset. A shader change that breaks the layout fails the build
+// instead of silently corrupting uniform data.
+
+use crate::spirv::{self, SpirvModule, SpirvType};
+
+/// A Rust type along with the size and alignment it was generated for.
+struct RustType {
+    name: String,
+    size: usize,
+    align: usize,
+    /// Scalar type and size for scalars, vectors and matrices; used to spell padded array elements
+    scalar: Option<(&'static str, usize)>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Field {
+    name: String,
+    ty: String,
+    offset: usize,
+    size: usize,
+}
+
+/// A trailing runtime-sized array member, which can't be expressed in a sized Rust struct.
+#[derive(Debug, PartialEq)]
+struct RuntimeArray {
+    name: String,
+    offset: usize,
+    stride: usize,
+}
+
+#[derive(Debug, PartialEq)]
+struct StructLayout {
+    name: String,
+    fields: Vec<Field>,
+    /// Total size including tail padding
+    size: usize,
+    align: usize,
+    runtime_array: Option<RuntimeArray>,
+}
+
+/// Collects the block layouts of all entry points compiled from one shader file.
+#[derive(Default)]
+pub struct LayoutCollector {
+    structs: Vec<StructLayout>,
+}
+
+impl LayoutCollector {
+    pub fn add_module(&mut self, module: &SpirvModule) -> Result<(), String> {
+        for variable in &module.variables {
+            if !matches!(
+                variable.storage_class,
+                spirv::STORAGE_CLASS_UNIFORM | spirv::STORAGE_CLASS_STORAGE_BUFFER | spirv::STORAGE_CLASS_PUSH_CONSTANT
+            ) {
+                continue;
+            }
+
+            let Some(SpirvType::Pointer { pointee, .. }) = module.types.get(&variable.type_id) else {
+                continue;
+            };
+
+            // Descriptor arrays of blocks (`ConstantBuffer<T> buffers[4]`) share the element's layout
+            let mut block = *pointee;
+            while let Some(SpirvType::Array { element, .. } | SpirvType::RuntimeArray { element }) = module.types.get(&block) {
+                block = *element;
+            }
+
+            let is_block = module.decoration(block, spirv::DECORATION_BLOCK).is_some()
+                || module.decoration(block, spirv::DECORATION_BUFFER_BLOCK).is_some();
+            if is_block {
+                self.add_struct(module, block, 0)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.structs.is_empty()
+    }
+
+    /// Generate the struct definitions, indenting every line by `indent` spaces.
+    pub fn generate(&self, indent: usize) -> String {
+        let pad = " ".repeat(indent);
+        let mut code = String::new();
+
+        for layout in &self.structs {
+            // Blocks that only hold a runtime array (e.g. StructuredBuffer<T>) have no sized part;
+            // their element type is generated on its own
+            if layout.fields.is_empty() && layout.runtime_array.is_some() {
+                continue;
+            }
+
+            if layout.align > 4 {
+                code.push_str(&format!("{}#[repr(C, align({}))]\n", pad, layout.align));
+            } else {
+                code.push_str(&format!("{}#[repr(C)]\n", pad));
+            }
+            code.push_str(&format!("{}#[derive(Debug, Clone, Copy)]\n", pad));
+            code.push_str(&format!("{}pub struct {} {{\n", pad, layout.name));
+
+            let mut cursor = 0;
+            let mut padding_count = 0;
+            let mut push_padding = |code: &mut String, bytes: usize| {
+                code.push_str(&format!("{}    pub _pad{}: [u8; {}],\n", pad, padding_count, bytes));
+                padding_count += 1;
+            };
+
+            for field in &layout.fields {
+                if field.offset > cursor {
+                    push_padding(&mut code, field.offset - cursor);
+                }
+                code.push_str(&format!("{}    pub {}: {},\n", pad, field.name, field.ty));
+                cursor = field.offset + field.size;
+            }
+            if layout.size > cursor {
+                push_padding(&mut code, layout.size - cursor);
+            }
+            code.push_str(&format!("{}}}\n\n", pad));
+
+            code.push_str(&format!("{}impl {} {{\n", pad, layout.name));
+            code.push_str(&format!("{}    /// All-zero value, padding included\n", pad));
+            code.push_str(&format!("{}    pub const fn zeroed() -> Self {{\n", pad));
+            code.push_str(&format!("{}        unsafe {{ ::core::mem::zeroed() }}\n", pad));
+            code.push_str(&format!("{}    }}\n", pad));
+            if let Some(array) = &layout.runtime_array {
+                let constant = array.name.trim_start_matches("r#").to_uppercase();
+                code.push_str(&format!("\n{}    /// Byte offset of the runtime-sized `{}` array\n", pad, array.name));
+                code.push_str(&format!("{}    pub const {}_OFFSET: usize = {};\n", pad, constant, array.offset));
+                code.push_str(&format!("{}    /// Byte stride between elements of the runtime-sized `{}` array\n", pad, array.name));
+                code.push_str(&format!("{}    pub const {}_STRIDE: usize = {};\n", pad, constant, array.stride));
+            }
+            code.push_str(&format!("{}}}\n\n", pad));
+
+            code.push_str(&format!(
+                "{}const _: () = assert!(::core::mem::size_of::<{}>() == {});\n",
+                pad, layout.name, layout.size
+            ));
+            for field in &layout.fields {
+                code.push_str(&format!(
+                    "{}const _: () = assert!(::core::mem::offset_of!({}, {}) == {});\n",
+                    pad, layout.name, field.name, field.offset
+                ));
+            }
+            code.push('\n');
+        }
+
+        code
+    }
+
+    /// Generate the layout for a struct type, padding it to at least `min_size` bytes (the array
+    /// stride when the struct is used as an array element).
+    fn add_struct(&mut self, module: &SpirvModule, type_id: u32, min_size: usize) -> Result<RustType, String> {
+        let Some(SpirvType::Struct { members }) = module.types.get(&type_id) else {
+            return Err(format!("Type {} is not a struct", type_id));
+        };
+
+        let name = struct_name(module, type_id);
+        let mut fields = Vec::new();
+        let mut runtime_array = None;
+        let mut align = 1;
+
+        for (index, &member_type) in members.iter().enumerate() {
+            let index = index as u32;
+            let field_name = module.member_names.get(&(type_id, index))
+                .filter(|n| !n.is_empty())
+                .map(|n| rust_identifier(n))
+                .unwrap_or_else(|| format!("member{}", index));
+
+            let offset = module.member_decoration(type_id, index, spirv::DECORATION_OFFSET)
+                .and_then(|d| d.first())
+                .ok_or_else(|| format!("Member `{}` of `{}` has no Offset decoration", field_name, name))?;
+            let offset = *offset as usize;
+
+            let matrix_stride = module.member_decoration(type_id, index, spirv::DECORATION_MATRIX_STRIDE)
+                .and_then(|d| d.first())
+                .map(|s| *s as usize);
+            let row_major = module.member_decoration(type_id, index, spirv::DECORATION_ROW_MAJOR).is_some();
+
+            if let Some(SpirvType::RuntimeArray { element }) = module.types.get(&member_type) {
+                let stride = array_stride(module, member_type)?;
+                // Generate the element type so it's available to index the array with
+                self.rust_type(module, *element, matrix_stride, row_major, stride)?;
+                runtime_array = Some(RuntimeArray { name: field_name, offset, stride });
+                continue;
+            }
+
+            let ty = self.rust_type(module, member_type, matrix_stride, row_major, 0)?;
+            align = align.max(ty.align);
+            fields.push(Field { name: field_name, ty: ty.name, offset, size: ty.size });
+        }
+
+        fields.sort_by_key(|f| f.offset);
+        for pair in fields.windows(2) {
+            if pair[0].offset + pair[0].size > pair[1].offset {
+                return Err(format!("Members `{}` and `{}` of `{}` overlap", pair[0].name, pair[1].name, name));
+            }
+        }
+
+        let end = fields.last().map_or(0, |f| f.offset + f.size);
+        let size = end.next_multiple_of(align).max(min_size);
+        if size % align != 0 {
+            return Err(format!("Array stride {} of `{}` is not a multiple of its alignment {}", min_size, name, align));
+        }
+
+        let layout = StructLayout { name: name.clone(), fields, size, align, runtime_array };
+
+        match self.structs.iter().find(|s| s.name == name) {
+            Some(existing) if *existing != layout => {
+                return Err(format!("Conflicting layouts for struct `{}`", name));
+            }
+            Some(_) => {}
+            None => self.structs.push(layout),
+        }
+
+        Ok(RustType { name, size, align, scalar: None })
+    }
+
+    /// Map a SPIR-V type to a Rust type. `matrix_stride`/`row_major` come from the decorations
+    /// of the member holding the type; `min_size` is the enclosing array stride, if any.
+    fn rust_type(
+        &mut self,
+        module: &SpirvModule,
+        type_id: u32,
+        matrix_stride: Option<usize>,
+        row_major: bool,
+        min_size: usize,
+    ) -> Result<RustType, String> {
+        match module.types.get(&type_id) {
+            Some(SpirvType::Int { .. } | SpirvType::Float { .. }) => {
+                let (scalar, size) = scalar_type(module, type_id)?;
+                Ok(RustType { name: scalar.to_string(), size, align: size, scalar: Some((scalar, size)) })
+            }
+            Some(SpirvType::Vector { component, count }) => vector_type(module, *component, *count),
+            Some(SpirvType::Matrix { column, count }) => {
+                let Some(SpirvType::Vector { component, count: rows }) = module.types.get(column) else {
+                    return Err(format!("Matrix column type {} is not a vector", column));
+                };
+                let stride = matrix_stride.ok_or("Matrix member has no MatrixStride decoration")?;
+                let (scalar, scalar_size) = scalar_type(module, *component)?;
+
+                // Row-major matrices are stored as consecutive rows rather than columns
+                let (vectors, length) = if row_major { (*rows, *count) } else { (*count, *rows) };
+
+                // Use the glam matrix types where their memory layout matches
+                match (scalar, vectors, length, stride) {
+                    ("f32", 4, 4, 16) => return Ok(RustType { name: "glam::Mat4".into(), size: 64, align: 16, scalar: Some((scalar, scalar_size)) }),
+                    ("f32", 3, 3, 16) => return Ok(RustType { name: "glam::Mat3A".into(), size: 48, align: 16, scalar: Some((scalar, scalar_size)) }),
+                    _ => {}
+                }
+
+                let vector = vector_type(module, *component, length)?;
+                let element = if vector.size == stride {
+                    vector.name
+                } else {
+                    format!("[{}; {}]", scalar, stride / scalar_size)
+                };
+
+                Ok(RustType {
+                    name: format!("[{}; {}]", element, vectors),
+                    size: stride * vectors as usize,
+                    align: vector.align,
+                    scalar: Some((scalar, scalar_size)),
+                })
+            }
+            Some(SpirvType::Array { element, length }) => {
+                let length = module.constants.get(length)
+                    .and_then(|v| v.first())
+                    .ok_or_else(|| format!("Array type {} has a non-constant length", type_id))?;
+                let stride = array_stride(module, type_id)?;
+                let element = self.rust_type(module, *element, matrix_stride, row_major, stride)?;
+
+                let element_name = if element.size == stride {
+                    element.name
+                } else if let Some((scalar, scalar_size)) = element.scalar {
+                    // std140 pads scalar and vector array elements out to 16 bytes
+                    format!("[{}; {}]", scalar, stride / scalar_size)
+                } else {
+                    return Err(format!("Array element `{}` is {} bytes but the array stride is {}", element.name, element.size, stride));
+                };
+
+                Ok(RustType {
+                    name: format!("[{}; {}]", element_name, length),
+                    size: stride * *length as usize,
+                    align: element.align,
+                    scalar: None,
+                })
+            }
+            Some(SpirvType::Struct { .. }) => self.add_struct(module, type_id, min_size),
+            other => Err(format!("Unsupported type in buffer block: {:?}", other)),
+        }
+    }
+}
+
+fn scalar_type(module: &SpirvModule, type_id: u32) -> Result<(&'static str, usize), String> {
+    match module.types.get(&type_id) {
+        Some(SpirvType::Float { width: 32 }) => Ok(("f32", 4)),
+        Some(SpirvType::Float { width: 64 }) => Ok(("f64", 8)),
+        Some(SpirvType::Int { width: 32, signed: true }) => Ok(("i32", 4)),
+        Some(SpirvType::Int { width: 32, signed: false }) => Ok(("u32", 4)),
+        Some(SpirvType::Int { width: 64, signed: true }) => Ok(("i64", 8)),
+        Some(SpirvType::Int { width: 64, signed: false }) => Ok(("u64", 8)),
+        other => Err(format!("Unsupported scalar type in buffer block: {:?}", other)),
+    }
+}
+
+fn vector_type(module: &SpirvModule, component: u32, count: u32) -> Result<RustType, String> {
+    let (scalar, scalar_size) = scalar_type(module, component)?;
+    let size = scalar_size * count as usize;
+
+    let prefix = match scalar {
+        "f32" => Some(""),
+        "f64" => Some("D"),
+        "i32" => Some("I"),
+        "u32" => Some("U"),
+        _ => None,
+    };
+
+    let (name, align) = match (prefix, count) {
+        // glam::Vec4 is 16-byte aligned, which matches its std140/std430 alignment
+        (Some(""), 4) => ("glam::Vec4".to_string(), 16),
+        (Some(prefix), 2..=4) => (format!("glam::{}Vec{}", prefix, count), scalar_size),
+        _ => (format!("[{}; {}]", scalar, count), scalar_size),
+    };
+
+    Ok(RustType { name, size, align, scalar: Some((scalar, scalar_size)) })
+}
+
+fn array_stride(module: &SpirvModule, type_id: u32) -> Result<usize, String> {
+    module.decoration(type_id, spirv::DECORATION_ARRAY_STRIDE)
+        .and_then(|d| d.first())
+        .map(|s| *s as usize)
+        .ok_or_else(|| format!("Array type {} has no ArrayStride decoration", type_id))
+}
+
+/// Name of the generated struct, with the layout suffix slang appends to block types stripped
+/// (e.g. "UBO_std140" -> "UBO").
+fn struct_name(module: &SpirvModule, type_id: u32) -> String {
+    let name = module.names.get(&type_id).map(|n| n.as_str()).unwrap_or("");
+    let name = ["_std140", "_std430", "_scalar", "_natural"]
+        .iter()
+        .fold(name, |n, suffix| n.strip_suffix(suffix).unwrap_or(n));
+
+    let name: String = name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("Block{}", type_id)
+    } else {
+        name
+    }
+}
+
+fn rust_identifier(name: &str) -> String {
+    const KEYWORDS: [&str; 38] = [
+        "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern",
+        "false", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+        "pub", "ref", "return", "static", "struct", "trait", "true", "type", "unsafe", "use",
+        "where", "while", "abstract", "final", "box",
+    ];
+
+    let name: String = name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' }).collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", name)
+    } else if KEYWORDS.contains(&name.as_str()) {
+        format!("r#{}", name)
+    } else {
+        name
+    }
+}
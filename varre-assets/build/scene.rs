@@ -0,0 +1,493 @@
+// Conversion of scenes imported by Assimp (OBJ, FBX, glTF 2.0, ...) into the data the model
+// serializer writes: every mesh, the node hierarchy, and the cameras, lights, skin and animations
+// attached to its nodes. Materials are converted to the metallic-roughness model, approximating it
+// from the diffuse color and specular exponent for formats such as OBJ that don't use it.
+
+use crate::model_format::{Camera, Joint, Light, LightKind, QuatKey, Vec3Key};
+use glam::{Mat3, Mat4, Quat, Vec2, Vec3, Vec4};
+use russimp::animation::Animation;
+use russimp::bone::Bone;
+use russimp::light::LightSourceType;
+use russimp::material::{Material, PropertyTypeInfo, TextureType};
+use russimp::mesh::Mesh;
+use russimp::scene::Scene;
+use russimp::{Matrix4x4, Vector3D};
+use std::collections::HashMap;
+
+/// Vertex and index data of one mesh, ready to serialize. Optional attributes are empty when the
+/// source has none.
+#[derive(Default)]
+pub struct MeshData {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    /// xyz tangent, with the bitangent's handedness in w
+    pub tangents: Vec<Vec4>,
+    pub uv_sets: Vec<Vec<Vec2>>,
+    pub color_sets: Vec<Vec<[u8; 4]>>,
+    /// Up to four indices into the scene's joints per vertex
+    pub joints: Vec<[u16; 4]>,
+    /// Weights of `joints`, as unorm16 summing to one
+    pub weights: Vec<[u16; 4]>,
+    pub indices: Vec<u32>,
+    pub material: u32,
+    /// Simplified levels of detail, from most to least detailed
+    pub lods: Vec<LodData>,
+}
+
+/// A level of detail of a mesh, drawing a subset of its vertices
+pub struct LodData {
+    pub indices: Vec<u32>,
+    /// Largest distance from the full detail surface, in model units
+    pub error: f32,
+}
+
+/// A node of the source file's hierarchy
+pub struct NodeData {
+    pub name: String,
+    pub parent: Option<usize>,
+    /// Relative to the parent
+    pub transform: Mat4,
+    /// Indices into the scene's meshes
+    pub meshes: Vec<u32>,
+}
+
+pub struct ChannelData {
+    pub node: u32,
+    pub translations: Vec<Vec3Key>,
+    pub rotations: Vec<QuatKey>,
+    pub scales: Vec<Vec3Key>,
+}
+
+pub struct AnimationData {
+    pub name: String,
+    /// In seconds
+    pub duration: f32,
+    pub channels: Vec<ChannelData>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaMode {
+    Opaque,
+    Mask,
+    Blend,
+}
+
+/// A material in the metallic-roughness model. Texture paths are as given by the source file.
+#[derive(Debug, Clone)]
+pub struct MaterialData {
+    pub name: String,
+    pub base_color: Vec4,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive: Vec3,
+    pub base_color_texture: Option<String>,
+    pub metallic_roughness_texture: Option<String>,
+    pub normal_texture: Option<String>,
+    pub occlusion_texture: Option<String>,
+    pub emissive_texture: Option<String>,
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    pub double_sided: bool,
+}
+
+impl Default for MaterialData {
+    /// The glTF default material: white, fully metallic and rough
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            base_color: Vec4::ONE,
+            metallic: 1.0,
+            roughness: 1.0,
+            emissive: Vec3::ZERO,
+            base_color_texture: None,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            occlusion_texture: None,
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            double_sided: false,
+        }
+    }
+}
+
+/// Every mesh of a source file along with its node hierarchy, with parents before children.
+/// Cameras, lights, joints and animation channels reference nodes by index, and meshes reference
+/// materials by index.
+#[derive(Default)]
+pub struct SceneData {
+    pub meshes: Vec<MeshData>,
+    pub materials: Vec<MaterialData>,
+    pub nodes: Vec<NodeData>,
+    pub cameras: Vec<Camera>,
+    pub lights: Vec<Light>,
+    pub joints: Vec<Joint>,
+    pub animations: Vec<AnimationData>,
+}
+
+impl SceneData {
+    /// One mesh instanced by a single root node
+    pub fn single_mesh(name: &str, mesh: MeshData) -> Self {
+        let root = NodeData { name: name.to_string(), parent: None, transform: Mat4::IDENTITY, meshes: vec![0] };
+        Self { meshes: vec![mesh], nodes: vec![root], ..Default::default() }
+    }
+
+    pub fn from_russimp(scene: &Scene) -> Self {
+        let mut nodes = Vec::new();
+        match &scene.root {
+            Some(root) => flatten_nodes(root, None, &mut nodes),
+            // Without a hierarchy every mesh is instanced once, untransformed
+            None => nodes.push(NodeData {
+                name: String::new(),
+                parent: None,
+                transform: Mat4::IDENTITY,
+                meshes: (0..scene.meshes.len() as u32).collect(),
+            }),
+        }
+
+        // Assimp attaches cameras, lights, bones and animation channels to nodes by name
+        let node_index: HashMap<String, u32> = nodes.iter()
+            .enumerate()
+            .map(|(index, node)| (node.name.clone(), index as u32))
+            .collect();
+        let find_node = |kind: &str, name: &str| {
+            let node = node_index.get(name).copied();
+            if node.is_none() {
+                println!("cargo:warning=Skipping {} {}, which has no matching node", kind, name);
+            }
+            node
+        };
+
+        let mut joints = Vec::new();
+        let meshes = scene.meshes.iter()
+            .map(|mesh| {
+                let mut data = MeshData::from_russimp(mesh);
+                if !mesh.bones.is_empty() {
+                    data.skin(&mesh.bones, &find_node, &mut joints);
+                }
+                data
+            })
+            .collect();
+
+        let cameras = scene.cameras.iter()
+            .filter_map(|camera| Some(Camera {
+                node: find_node("camera", &camera.name)?,
+                // Assimp stores half the angle
+                horizontal_fov: camera.horizontal_fov * 2.0,
+                aspect: camera.aspect,
+                near: camera.clip_plane_near,
+                far: camera.clip_plane_far,
+                position: vec3(&camera.position),
+                up: vec3(&camera.up),
+                look_at: vec3(&camera.look_at),
+            }))
+            .collect();
+
+        let lights = scene.lights.iter()
+            .filter_map(|light| {
+                let kind = match light.light_source_type {
+                    LightSourceType::Directional => LightKind::Directional,
+                    LightSourceType::Point => LightKind::Point,
+                    LightSourceType::Spot => LightKind::Spot,
+                    LightSourceType::Ambient => LightKind::Ambient,
+                    LightSourceType::Area => LightKind::Area,
+                    _ => {
+                        println!("cargo:warning=Skipping light {} of unknown type", light.name);
+                        return None;
+                    }
+                };
+
+                Some(Light {
+                    node: find_node("light", &light.name)?,
+                    kind: kind as u32,
+                    color: Vec3::new(light.color_diffuse.r, light.color_diffuse.g, light.color_diffuse.b),
+                    position: vec3(&light.pos),
+                    direction: vec3(&light.direction),
+                    attenuation: [light.attenuation_constant, light.attenuation_linear, light.attenuation_quadratic],
+                    inner_cone: light.angle_inner_cone,
+                    outer_cone: light.angle_outer_cone,
+                })
+            })
+            .collect();
+
+        let animations = scene.animations.iter()
+            .map(|animation| AnimationData::from_russimp(animation, &find_node))
+            .collect();
+
+        let materials = scene.materials.iter().map(MaterialData::from_russimp).collect();
+
+        Self { meshes, materials, nodes, cameras, lights, joints, animations }
+    }
+
+    /// Move the scene into another coordinate system, given by `axes`, a signed permutation of the
+    /// coordinate axes, and a uniform `scale`. Mesh data is converted and every transform is
+    /// conjugated, so that the hierarchy, skin and animations still fit together. A reflection
+    /// also reverses the winding of every triangle, so that front faces stay in front.
+    pub fn convert_space(&mut self, axes: Mat3, scale: f32) {
+        let point = |p: Vec3| axes * p * scale;
+        let conversion = Mat4::from_mat3(axes * scale);
+        let inverse = conversion.inverse();
+        let conjugate = |transform: Mat4| conversion * transform * inverse;
+        let mirrored = axes.determinant() < 0.0;
+
+        for mesh in &mut self.meshes {
+            mesh.positions.iter_mut().for_each(|position| *position = point(*position));
+            mesh.normals.iter_mut().for_each(|normal| *normal = axes * *normal);
+            // The bitangent is the cross product of normal and tangent, which a reflection turns over
+            let handedness = if mirrored { -1.0 } else { 1.0 };
+            mesh.tangents.iter_mut().for_each(|tangent| *tangent = (axes * tangent.truncate()).extend(tangent.w * handedness));
+            if mirrored {
+                mesh.reverse_winding();
+            }
+        }
+
+        for node in &mut self.nodes {
+            node.transform = conjugate(node.transform);
+        }
+        for camera in &mut self.cameras {
+            camera.position = point(camera.position);
+            camera.look_at = point(camera.look_at);
+            camera.up = axes * camera.up;
+            camera.near *= scale;
+            camera.far *= scale;
+        }
+        for light in &mut self.lights {
+            light.position = point(light.position);
+            light.direction = axes * light.direction;
+            // Keep the same falloff over the scaled distances
+            let [constant, linear, quadratic] = light.attenuation;
+            light.attenuation = [constant, linear / scale, quadratic / (scale * scale)];
+        }
+        for joint in &mut self.joints {
+            joint.inverse_bind = conjugate(Mat4::from_cols_array(&joint.inverse_bind)).to_cols_array();
+        }
+        for channel in self.animations.iter_mut().flat_map(|animation| &mut animation.channels) {
+            channel.translations.iter_mut().for_each(|key| key.value = point(key.value));
+            for key in &mut channel.rotations {
+                let rotation = Mat3::from_quat(Quat::from_array(key.value));
+                key.value = Quat::from_mat3(&(axes * rotation * axes.transpose())).to_array();
+            }
+            // Scales along the permuted axes, which the reflection's signs don't affect
+            channel.scales.iter_mut().for_each(|key| key.value = (axes * key.value).abs());
+        }
+    }
+}
+
+/// Append `node` and its descendants to `nodes` in depth-first order
+fn flatten_nodes(node: &russimp::node::Node, parent: Option<usize>, nodes: &mut Vec<NodeData>) {
+    let index = nodes.len();
+    nodes.push(NodeData {
+        name: node.name.clone(),
+        parent,
+        transform: mat4(&node.transformation),
+        meshes: node.meshes.clone(),
+    });
+
+    for child in node.children.borrow().iter() {
+        flatten_nodes(child, Some(index), nodes);
+    }
+}
+
+impl MeshData {
+    pub fn from_russimp(mesh: &Mesh) -> Self {
+        let positions: Vec<Vec3> = mesh.vertices.iter().map(vec3).collect();
+        let normals: Vec<Vec3> = mesh.normals.iter().map(vec3).collect();
+
+        // Store the bitangent as a sign so shaders can rebuild it from the normal and tangent
+        let tangents = if normals.is_empty() || mesh.tangents.len() != positions.len() {
+            Vec::new()
+        } else {
+            mesh.tangents.iter()
+                .zip(&mesh.bitangents)
+                .zip(&normals)
+                .map(|((t, b), n)| {
+                    let tangent = vec3(t);
+                    let handedness = if n.cross(tangent).dot(vec3(b)) < 0.0 { -1.0 } else { 1.0 };
+                    tangent.extend(handedness)
+                })
+                .collect()
+        };
+
+        // Assimp keeps a fixed number of channels, with unused ones empty; sets stay consecutive
+        let uv_sets = mesh.texture_coords.iter()
+            .map_while(|channel| channel.as_ref())
+            .map(|uvs| uvs.iter().map(|uv| Vec2::new(uv.x, uv.y)).collect())
+            .collect();
+
+        let color_sets = mesh.colors.iter()
+            .map_while(|channel| channel.as_ref())
+            .map(|colors| colors.iter()
+                .map(|c| [c.r, c.g, c.b, c.a].map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8))
+                .collect())
+            .collect();
+
+        let indices = mesh.faces.iter().flat_map(|face| face.0.iter().copied()).collect();
+
+        Self {
+            positions,
+            normals,
+            tangents,
+            uv_sets,
+            color_sets,
+            indices,
+            material: mesh.material_index,
+            ..Default::default()
+        }
+    }
+
+    /// Fill the joint and weight streams from the mesh's bones, adding joints not seen in earlier
+    /// meshes to `joints`. Each vertex keeps its four strongest influences.
+    fn skin(&mut self, bones: &[Bone], find_node: &impl Fn(&str, &str) -> Option<u32>, joints: &mut Vec<Joint>) {
+        let mut influences: Vec<Vec<(u16, f32)>> = vec![Vec::new(); self.positions.len()];
+
+        for bone in bones {
+            let Some(node) = find_node("bone", &bone.name) else {
+                continue;
+            };
+            let joint = joints.iter().position(|joint| joint.node == node).unwrap_or_else(|| {
+                joints.push(Joint { node, inverse_bind: mat4(&bone.offset_matrix).to_cols_array() });
+                joints.len() - 1
+            });
+
+            for weight in &bone.weights {
+                if let Some(vertex) = influences.get_mut(weight.vertex_id as usize) {
+                    vertex.push((joint as u16, weight.weight));
+                }
+            }
+        }
+
+        (self.joints, self.weights) = influences.into_iter()
+            .map(|mut vertex| {
+                vertex.sort_by(|a, b| b.1.total_cmp(&a.1));
+                vertex.truncate(4);
+
+                let mut joints = [0u16; 4];
+                let mut weights = [0u16; 4];
+                let total: f32 = vertex.iter().map(|(_, weight)| weight).sum();
+                if total > 0.0 {
+                    for (i, (joint, weight)) in vertex.iter().enumerate() {
+                        joints[i] = *joint;
+                        weights[i] = (weight / total * u16::MAX as f32).round() as u16;
+                    }
+                    // Give the rounding error to the strongest influence so the weights sum to one
+                    let sum: i32 = weights.iter().map(|weight| *weight as i32).sum();
+                    weights[0] = (weights[0] as i32 + u16::MAX as i32 - sum) as u16;
+                }
+
+                (joints, weights)
+            })
+            .unzip();
+    }
+
+    /// Turn every triangle, including those of the levels of detail, to the opposite winding
+    pub fn reverse_winding(&mut self) {
+        for triangle in self.indices.chunks_exact_mut(3).chain(self.lods.iter_mut().flat_map(|lod| lod.indices.chunks_exact_mut(3))) {
+            triangle.swap(1, 2);
+        }
+    }
+}
+
+impl AnimationData {
+    fn from_russimp(animation: &Animation, find_node: &impl Fn(&str, &str) -> Option<u32>) -> Self {
+        // Key times are in ticks; files that don't specify a rate use Assimp's default of 25
+        let ticks_per_second = if animation.ticks_per_second > 0.0 { animation.ticks_per_second } else { 25.0 };
+        let seconds = |ticks: f64| (ticks / ticks_per_second) as f32;
+
+        let channels = animation.channels.iter()
+            .filter_map(|channel| Some(ChannelData {
+                node: find_node("animation channel", &channel.name)?,
+                translations: channel.position_keys.iter()
+                    .map(|key| Vec3Key { time: seconds(key.time), value: vec3(&key.value) })
+                    .collect(),
+                rotations: channel.rotation_keys.iter()
+                    .map(|key| QuatKey { time: seconds(key.time), value: [key.value.x, key.value.y, key.value.z, key.value.w] })
+                    .collect(),
+                scales: channel.scaling_keys.iter()
+                    .map(|key| Vec3Key { time: seconds(key.time), value: vec3(&key.value) })
+                    .collect(),
+            }))
+            .collect();
+
+        Self { name: animation.name.clone(), duration: seconds(animation.duration), channels }
+    }
+}
+
+impl MaterialData {
+    pub fn from_russimp(material: &Material) -> Self {
+        let property = |key: &str| material.properties.iter()
+            .find(|property| property.key == key && property.semantic == TextureType::None)
+            .map(|property| &property.data);
+        let floats = |key: &str| match property(key) {
+            Some(PropertyTypeInfo::FloatArray(values)) => Some(values.as_slice()),
+            _ => None,
+        };
+        let float = |key: &str| floats(key).and_then(|values| values.first().copied());
+        let string = |key: &str| match property(key) {
+            Some(PropertyTypeInfo::String(value)) => Some(value.as_str()),
+            _ => None,
+        };
+        let texture = |types: &[TextureType]| material.properties.iter()
+            .filter(|property| property.key == "$tex.file" && property.index == 0)
+            .find(|property| types.contains(&property.semantic))
+            .and_then(|property| match &property.data {
+                PropertyTypeInfo::String(path) => Some(path.clone()),
+                _ => None,
+            });
+
+        let mut data = Self { name: string("?mat.name").unwrap_or_default().to_string(), ..Default::default() };
+
+        // glTF sets the base color and metallic-roughness factors directly; other formats only
+        // have a diffuse color and a specular exponent
+        if let Some(color) = floats("$clr.base").or_else(|| floats("$clr.diffuse")) {
+            data.base_color = Vec4::new(color[0], color[1], color[2], color.get(3).copied().unwrap_or(1.0));
+        }
+        match (float("$mat.metallicFactor"), float("$mat.roughnessFactor")) {
+            (None, None) => {
+                data.metallic = 0.0;
+                data.roughness = float("$mat.shininess").map_or(1.0, |shininess| (2.0 / (shininess.max(0.0) + 2.0)).sqrt());
+            }
+            (metallic, roughness) => {
+                data.metallic = metallic.unwrap_or(1.0);
+                data.roughness = roughness.unwrap_or(1.0);
+            }
+        }
+        if let Some(emissive) = floats("$clr.emissive") {
+            data.emissive = Vec3::new(emissive[0], emissive[1], emissive[2]);
+        }
+
+        data.base_color_texture = texture(&[TextureType::BaseColor, TextureType::Diffuse]);
+        // Assimp reports glTF's combined metallic-roughness texture as unknown
+        data.metallic_roughness_texture = texture(&[TextureType::Unknown, TextureType::Metalness, TextureType::Roughness]);
+        data.normal_texture = texture(&[TextureType::Normals, TextureType::NormalCamera, TextureType::Height]);
+        data.occlusion_texture = texture(&[TextureType::AmbientOcclusion, TextureType::LightMap]);
+        data.emissive_texture = texture(&[TextureType::EmissionColor, TextureType::Emissive]);
+
+        let opacity = float("$mat.opacity").unwrap_or(1.0);
+        data.base_color.w *= opacity;
+        data.alpha_mode = match string("$mat.gltf.alphaMode") {
+            Some("MASK") => AlphaMode::Mask,
+            Some("BLEND") => AlphaMode::Blend,
+            Some(_) => AlphaMode::Opaque,
+            None if opacity < 1.0 => AlphaMode::Blend,
+            None => AlphaMode::Opaque,
+        };
+        data.alpha_cutoff = float("$mat.gltf.alphaCutoff").unwrap_or(0.5);
+        data.double_sided = matches!(property("$mat.twosided"), Some(PropertyTypeInfo::IntegerArray(values)) if values.first() == Some(&1));
+
+        data
+    }
+}
+
+fn vec3(v: &Vector3D) -> Vec3 {
+    Vec3::new(v.x, v.y, v.z)
+}
+
+/// Assimp matrices are row-major
+fn mat4(m: &Matrix4x4) -> Mat4 {
+    Mat4::from_cols_array(&[
+        m.a1, m.b1, m.c1, m.d1,
+        m.a2, m.b2, m.c2, m.d2,
+        m.a3, m.b3, m.c3, m.d3,
+        m.a4, m.b4, m.c4, m.d4,
+    ])
+}
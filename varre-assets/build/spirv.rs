@@ -0,0 +1,340 @@
+// Minimal SPIR-V module walker used by build.rs for the reflection data that rspirv-reflect
+// doesn't expose.
+//
+// Only the instructions needed for reflection are decoded; everything else is skipped.
+
+use std::collections::HashMap;
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+// Opcodes
+const OP_NAME: u32 = 5;
+const OP_MEMBER_NAME: u32 = 6;
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_SPEC_CONSTANT_TRUE: u32 = 48;
+const OP_SPEC_CONSTANT_FALSE: u32 = 49;
+const OP_SPEC_CONSTANT: u32 = 50;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+const OP_DECORATE_STRING: u32 = 5632;
+
+// Decorations
+pub const DECORATION_SPEC_ID: u32 = 1;
+pub const DECORATION_BLOCK: u32 = 2;
+pub const DECORATION_BUFFER_BLOCK: u32 = 3;
+pub const DECORATION_ROW_MAJOR: u32 = 4;
+pub const DECORATION_ARRAY_STRIDE: u32 = 6;
+pub const DECORATION_MATRIX_STRIDE: u32 = 7;
+pub const DECORATION_BUILTIN: u32 = 11;
+pub const DECORATION_LOCATION: u32 = 30;
+pub const DECORATION_OFFSET: u32 = 35;
+pub const DECORATION_USER_SEMANTIC: u32 = 5635;
+
+// Storage classes
+pub const STORAGE_CLASS_INPUT: u32 = 1;
+pub const STORAGE_CLASS_OUTPUT: u32 = 3;
+pub const STORAGE_CLASS_UNIFORM: u32 = 2;
+pub const STORAGE_CLASS_PUSH_CONSTANT: u32 = 9;
+pub const STORAGE_CLASS_STORAGE_BUFFER: u32 = 12;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpirvType {
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Struct { members: Vec<u32> },
+    Pointer { storage_class: u32, pointee: u32 },
+}
+
+#[derive(Debug, Clone)]
+pub struct SpecConstantDecl {
+    pub result_id: u32,
+    pub type_id: u32,
+    /// Literal default value words (low-order word first); booleans are stored as 0 or 1.
+    pub value: Vec<u32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub id: u32,
+    pub type_id: u32,
+    pub storage_class: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct SpirvModule {
+    pub names: HashMap<u32, String>,
+    /// Struct member names keyed by (struct type id, member index).
+    pub member_names: HashMap<(u32, u32), String>,
+    /// Decoration arguments keyed by (target id, decoration).
+    pub decorations: HashMap<(u32, u32), Vec<u32>>,
+    /// Member decoration arguments keyed by (struct type id, member index, decoration).
+    pub member_decorations: HashMap<(u32, u32, u32), Vec<u32>>,
+    pub types: HashMap<u32, SpirvType>,
+    /// Literal value words of non-specialization constants, keyed by result id.
+    pub constants: HashMap<u32, Vec<u32>>,
+    pub spec_constants: Vec<SpecConstantDecl>,
+    pub variables: Vec<Variable>,
+}
+
+impl SpirvModule {
+    pub fn parse(spirv_data: &[u8]) -> Result<Self, String> {
+        if spirv_data.len() % 4 != 0 || spirv_data.len() < 20 {
+            return Err(format!("Invalid SPIR-V size: {} bytes", spirv_data.len()));
+        }
+
+        let words: Vec<u32> = spirv_data
+            .chunks_exact(4)
+            .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect();
+
+        if words[0] != SPIRV_MAGIC {
+            return Err(format!("Invalid SPIR-V magic number: {:#010x}", words[0]));
+        }
+
+        let mut module = SpirvModule::default();
+
+        // Skip the 5 word header
+        let mut offset = 5;
+        while offset < words.len() {
+            let word_count = (words[offset] >> 16) as usize;
+            let opcode = words[offset] & 0xffff;
+
+            if word_count == 0 || offset + word_count > words.len() {
+                return Err(format!("Malformed SPIR-V instruction at word {}", offset));
+            }
+
+            let operands = &words[offset + 1..offset + word_count];
+            module.record_instruction(opcode, operands);
+
+            offset += word_count;
+        }
+
+        Ok(module)
+    }
+
+    fn record_instruction(&mut self, opcode: u32, ops: &[u32]) {
+        match opcode {
+            OP_NAME if ops.len() >= 2 => {
+                self.names.insert(ops[0], decode_string(&ops[1..]));
+            }
+            OP_MEMBER_NAME if ops.len() >= 3 => {
+                self.member_names.insert((ops[0], ops[1]), decode_string(&ops[2..]));
+            }
+            OP_TYPE_BOOL if !ops.is_empty() => {
+                self.types.insert(ops[0], SpirvType::Bool);
+            }
+            OP_TYPE_INT if ops.len() >= 3 => {
+                self.types.insert(ops[0], SpirvType::Int { width: ops[1], signed: ops[2] != 0 });
+            }
+            OP_TYPE_FLOAT if ops.len() >= 2 => {
+                self.types.insert(ops[0], SpirvType::Float { width: ops[1] });
+            }
+            OP_TYPE_VECTOR if ops.len() >= 3 => {
+                self.types.insert(ops[0], SpirvType::Vector { component: ops[1], count: ops[2] });
+            }
+            OP_TYPE_MATRIX if ops.len() >= 3 => {
+                self.types.insert(ops[0], SpirvType::Matrix { column: ops[1], count: ops[2] });
+            }
+            OP_TYPE_ARRAY if ops.len() >= 3 => {
+                self.types.insert(ops[0], SpirvType::Array { element: ops[1], length: ops[2] });
+            }
+            OP_TYPE_RUNTIME_ARRAY if ops.len() >= 2 => {
+                self.types.insert(ops[0], SpirvType::RuntimeArray { element: ops[1] });
+            }
+            OP_TYPE_STRUCT if !ops.is_empty() => {
+                self.types.insert(ops[0], SpirvType::Struct { members: ops[1..].to_vec() });
+            }
+            OP_CONSTANT if ops.len() >= 3 => {
+                self.constants.insert(ops[1], ops[2..].to_vec());
+            }
+            OP_TYPE_POINTER if ops.len() >= 3 => {
+                self.types.insert(ops[0], SpirvType::Pointer { storage_class: ops[1], pointee: ops[2] });
+            }
+            OP_SPEC_CONSTANT_TRUE | OP_SPEC_CONSTANT_FALSE if ops.len() >= 2 => {
+                self.spec_constants.push(SpecConstantDecl {
+                    result_id: ops[1],
+                    type_id: ops[0],
+                    value: vec![(opcode == OP_SPEC_CONSTANT_TRUE) as u32],
+                });
+            }
+            OP_SPEC_CONSTANT if ops.len() >= 3 => {
+                self.spec_constants.push(SpecConstantDecl {
+                    result_id: ops[1],
+                    type_id: ops[0],
+                    value: ops[2..].to_vec(),
+                });
+            }
+            OP_VARIABLE if ops.len() >= 3 => {
+                self.variables.push(Variable { id: ops[1], type_id: ops[0], storage_class: ops[2] });
+            }
+            OP_DECORATE | OP_DECORATE_STRING if ops.len() >= 2 => {
+                self.decorations.insert((ops[0], ops[1]), ops[2..].to_vec());
+            }
+            OP_MEMBER_DECORATE if ops.len() >= 3 => {
+                self.member_decorations.insert((ops[0], ops[1], ops[2]), ops[3..].to_vec());
+            }
+            _ => {}
+        }
+    }
+
+    pub fn decoration(&self, id: u32, decoration: u32) -> Option<&[u32]> {
+        self.decorations.get(&(id, decoration)).map(|v| v.as_slice())
+    }
+
+    pub fn member_decoration(&self, struct_id: u32, member: u32, decoration: u32) -> Option<&[u32]> {
+        self.member_decorations.get(&(struct_id, member, decoration)).map(|v| v.as_slice())
+    }
+}
+
+/// Decode a nul-terminated UTF-8 literal string packed into little-endian words.
+pub fn decode_string(words: &[u32]) -> String {
+    let bytes: Vec<u8> = words
+        .iter()
+        .flat_map(|w| w.to_le_bytes())
+        .take_while(|b| *b != 0)
+        .collect();
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Specialization constant reflected from an `OpSpecConstant*` declaration with a `SpecId`.
+pub struct SpecializationConstantInfo {
+    pub id: u32,
+    pub name: String,
+    /// Name of the `SpecializationConstantType` variant in the generated code
+    pub ty: &'static str,
+    pub default_value: u64,
+}
+
+pub fn reflect_specialization_constants(module: &SpirvModule) -> Result<Vec<SpecializationConstantInfo>, String> {
+    let mut constants = Vec::new();
+
+    for decl in &module.spec_constants {
+        // Spec constants without a SpecId are composite/op results and can't be set by the host
+        let Some(spec_id) = module.decoration(decl.result_id, DECORATION_SPEC_ID).and_then(|d| d.first()) else {
+            continue;
+        };
+
+        let ty = match module.types.get(&decl.type_id) {
+            Some(SpirvType::Bool) => "Bool",
+            Some(SpirvType::Int { width: 32, signed: true }) => "Int32",
+            Some(SpirvType::Int { width: 32, signed: false }) => "UInt32",
+            Some(SpirvType::Int { width: 64, signed: true }) => "Int64",
+            Some(SpirvType::Int { width: 64, signed: false }) => "UInt64",
+            Some(SpirvType::Float { width: 32 }) => "Float32",
+            Some(SpirvType::Float { width: 64 }) => "Float64",
+            other => {
+                return Err(format!("Unsupported type for specialization constant {}: {:?}", spec_id, other));
+            }
+        };
+
+        let default_value = decl.value.iter()
+            .take(2)
+            .enumerate()
+            .fold(0u64, |acc, (i, w)| acc | ((*w as u64) << (32 * i)));
+
+        constants.push(SpecializationConstantInfo {
+            id: *spec_id,
+            name: module.names.get(&decl.result_id).cloned().unwrap_or_default(),
+            ty,
+            default_value,
+        });
+    }
+
+    constants.sort_by_key(|c| c.id);
+
+    Ok(constants)
+}
+
+/// Stage input/output variable reflected from an `OpVariable` with a `Location` decoration.
+pub struct InterfaceVariableInfo {
+    pub location: u32,
+    /// Raw VkFormat value matching the variable's type
+    pub format: u32,
+    pub semantic: String,
+}
+
+pub fn reflect_interface_variables(module: &SpirvModule, storage_class: u32) -> Result<Vec<InterfaceVariableInfo>, String> {
+    let mut variables = Vec::new();
+
+    for variable in module.variables.iter().filter(|v| v.storage_class == storage_class) {
+        // Built-ins (SV_Position, SV_VertexID, ...) are not part of the user interface
+        if module.decoration(variable.id, DECORATION_BUILTIN).is_some() {
+            continue;
+        }
+
+        let Some(&location) = module.decoration(variable.id, DECORATION_LOCATION).and_then(|d| d.first()) else {
+            continue;
+        };
+
+        let mut pointee = match module.types.get(&variable.type_id) {
+            Some(SpirvType::Pointer { pointee, .. }) => *pointee,
+            _ => return Err(format!("Interface variable at location {} is not a pointer", location)),
+        };
+
+        // Mesh shader outputs are arrayed per vertex/primitive; the interface type is the element
+        if let Some(SpirvType::Array { element, .. }) = module.types.get(&pointee) {
+            pointee = *element;
+        }
+
+        let format = vk_format_for_type(module, pointee)
+            .ok_or_else(|| format!("Unsupported type for interface variable at location {}: {:?}", location, module.types.get(&pointee)))?;
+
+        // Prefer an explicit semantic decoration, otherwise use the last component of the
+        // variable's debug name (e.g. "input.position" -> "POSITION")
+        let semantic = module.decoration(variable.id, DECORATION_USER_SEMANTIC)
+            .map(decode_string)
+            .or_else(|| module.names.get(&variable.id)
+                .and_then(|name| name.rsplit('.').next())
+                .filter(|name| !name.is_empty())
+                .map(|name| name.to_string()))
+            .unwrap_or_else(|| format!("LOCATION{}", location))
+            .to_uppercase();
+
+        variables.push(InterfaceVariableInfo { location, format, semantic });
+    }
+
+    variables.sort_by_key(|v| v.location);
+
+    Ok(variables)
+}
+
+/// Map a scalar or vector type to the raw value of the matching 32/64/16-bit VkFormat.
+fn vk_format_for_type(module: &SpirvModule, type_id: u32) -> Option<u32> {
+    let (component, count) = match module.types.get(&type_id)? {
+        SpirvType::Vector { component, count } => (*component, *count),
+        _ => (type_id, 1),
+    };
+
+    if !(1..=4).contains(&count) {
+        return None;
+    }
+
+    // Formats of the same kind are laid out in the VkFormat enum with a fixed stride per
+    // component count, e.g. R32_SFLOAT = 100, R32G32_SFLOAT = 103, ...
+    let (first, stride) = match module.types.get(&component)? {
+        SpirvType::Float { width: 32 } => (100, 3),        // VK_FORMAT_R32_SFLOAT
+        SpirvType::Int { width: 32, signed: true } => (99, 3),  // VK_FORMAT_R32_SINT
+        SpirvType::Int { width: 32, signed: false } => (98, 3), // VK_FORMAT_R32_UINT
+        SpirvType::Float { width: 64 } => (112, 3),        // VK_FORMAT_R64_SFLOAT
+        SpirvType::Float { width: 16 } => (76, 7),         // VK_FORMAT_R16_SFLOAT
+        _ => return None,
+    };
+
+    Some(first + stride * (count - 1))
+}
@@ -0,0 +1,260 @@
+// Texture import for the build script: decodes source images, generates mip chains and encodes
+// them into KTX2 containers, block compressed when the `texture-compression` feature is enabled.
+
+use std::path::Path;
+use crate::texture_format::{vk_format, write_ktx2, TextureDesc};
+
+pub const TEXTURE_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "hdr"];
+
+/// How a texture's values are interpreted, which decides its format and how mips are filtered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureKind {
+    /// sRGB encoded color, such as base color or emissive maps
+    Color,
+    /// Linear data, such as occlusion/roughness/metalness maps
+    Linear,
+    /// Tangent-space normals in RG(B); the shader reconstructs z
+    Normal,
+    /// Linear floating point color from .hdr files
+    Hdr,
+}
+
+impl TextureKind {
+    /// Guess the kind from the file name: `.hdr` files are HDR, `*_n`/`*_normal` files are normal
+    /// maps and `*_orm`, `*_roughness` etc. are linear data. Everything else is color.
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_ascii_lowercase();
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default().to_ascii_lowercase();
+        let has_suffix = |suffixes: &[&str]| suffixes.iter().any(|suffix| stem.ends_with(&format!("_{}", suffix)));
+
+        if extension == "hdr" {
+            TextureKind::Hdr
+        } else if has_suffix(&["n", "nrm", "normal"]) {
+            TextureKind::Normal
+        } else if has_suffix(&["orm", "mr", "metallic_roughness", "metallic", "roughness", "ao", "occlusion", "height", "mask"]) {
+            TextureKind::Linear
+        } else {
+            TextureKind::Color
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TextureKind::Color => "Color",
+            TextureKind::Linear => "Linear",
+            TextureKind::Normal => "Normal",
+            TextureKind::Hdr => "Hdr",
+        }
+    }
+
+    /// VkFormat the texture is stored in
+    pub fn vk_format(&self, compress: bool) -> u32 {
+        match (self, compress) {
+            (TextureKind::Color, true) => vk_format::BC7_SRGB_BLOCK,
+            (TextureKind::Linear, true) => vk_format::BC7_UNORM_BLOCK,
+            (TextureKind::Normal, true) => vk_format::BC5_UNORM_BLOCK,
+            (TextureKind::Hdr, true) => vk_format::BC6H_UFLOAT_BLOCK,
+            (TextureKind::Color, false) => vk_format::R8G8B8A8_SRGB,
+            (TextureKind::Linear | TextureKind::Normal, false) => vk_format::R8G8B8A8_UNORM,
+            (TextureKind::Hdr, false) => vk_format::R16G16B16A16_SFLOAT,
+        }
+    }
+}
+
+pub struct TextureData {
+    pub kind: TextureKind,
+    pub width: u32,
+    pub height: u32,
+    pub vk_format: u32,
+    pub mip_levels: u32,
+    pub ktx2: Vec<u8>,
+}
+
+/// One mip level of linear RGBA values
+struct Level {
+    width: u32,
+    height: u32,
+    pixels: Vec<[f32; 4]>,
+}
+
+/// Import an image file as a KTX2 container with a full mip chain
+pub fn load_texture(path: &Path, kind: TextureKind, compress: bool) -> Result<TextureData, String> {
+    let image = image::open(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let image = image.to_rgba32f();
+    let (width, height) = image.dimensions();
+
+    let mut pixels: Vec<[f32; 4]> = image.into_raw().chunks_exact(4).map(|p| [p[0], p[1], p[2], p[3]]).collect();
+    // Filter in linear space; normal maps are filtered as unit vectors
+    match kind {
+        TextureKind::Color => {
+            for pixel in &mut pixels {
+                for channel in &mut pixel[..3] {
+                    *channel = srgb_to_linear(*channel);
+                }
+            }
+        }
+        TextureKind::Normal => {
+            for pixel in &mut pixels {
+                *pixel = decode_normal(*pixel);
+            }
+        }
+        TextureKind::Linear | TextureKind::Hdr => {}
+    }
+
+    let mut levels = vec![Level { width, height, pixels }];
+    while let Some(last) = levels.last().filter(|level| level.width > 1 || level.height > 1) {
+        let mut next = downsample(last);
+        if kind == TextureKind::Normal {
+            for pixel in &mut next.pixels {
+                *pixel = normalize(*pixel);
+            }
+        }
+        levels.push(next);
+    }
+
+    let vk_format = kind.vk_format(compress);
+    let encoded: Vec<Vec<u8>> = levels.iter().map(|level| encode_level(level, kind, compress)).collect();
+    let ktx2 = write_ktx2(&TextureDesc::new_2d(vk_format, width, height), &encoded);
+
+    Ok(TextureData { kind, width, height, vk_format, mip_levels: levels.len() as u32, ktx2 })
+}
+
+/// Halve a level with a box filter. Odd edges reuse their last row or column.
+fn downsample(level: &Level) -> Level {
+    let width = (level.width / 2).max(1);
+    let height = (level.height / 2).max(1);
+    let source = |x: u32, y: u32| level.pixels[(y.min(level.height - 1) * level.width + x.min(level.width - 1)) as usize];
+
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let samples = [source(2 * x, 2 * y), source(2 * x + 1, 2 * y), source(2 * x, 2 * y + 1), source(2 * x + 1, 2 * y + 1)];
+            let mut sum = [0.0; 4];
+            for sample in samples {
+                for (total, value) in sum.iter_mut().zip(sample) {
+                    *total += value * 0.25;
+                }
+            }
+            pixels.push(sum);
+        }
+    }
+    Level { width, height, pixels }
+}
+
+fn encode_level(level: &Level, kind: TextureKind, compress: bool) -> Vec<u8> {
+    if kind == TextureKind::Hdr {
+        let halves: Vec<u8> = level.pixels.iter()
+            .flat_map(|pixel| pixel.map(|value| f32_to_f16(value.max(0.0))))
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        return if compress { compress_level(level, kind, &halves, 8) } else { halves };
+    }
+
+    let rgba8: Vec<u8> = level.pixels.iter()
+        .flat_map(|pixel| {
+            let [r, g, b, a] = match kind {
+                TextureKind::Color => [linear_to_srgb(pixel[0]), linear_to_srgb(pixel[1]), linear_to_srgb(pixel[2]), pixel[3]],
+                TextureKind::Normal => [pixel[0] * 0.5 + 0.5, pixel[1] * 0.5 + 0.5, pixel[2] * 0.5 + 0.5, 1.0],
+                _ => *pixel,
+            };
+            [r, g, b, a].map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8)
+        })
+        .collect();
+    if compress { compress_level(level, kind, &rgba8, 4) } else { rgba8 }
+}
+
+/// Block compress a level's encoded texels, `texel_size` bytes each
+#[cfg(feature = "texture-compression")]
+fn compress_level(level: &Level, kind: TextureKind, texels: &[u8], texel_size: usize) -> Vec<u8> {
+    use intel_tex_2::{bc5, bc6h, bc7, RgSurface, RgbaSurface};
+
+    // The encoders work on whole 4x4 blocks, so pad the level by repeating its edges
+    let width = level.width.next_multiple_of(4);
+    let height = level.height.next_multiple_of(4);
+    let mut padded = Vec::with_capacity(width as usize * height as usize * texel_size);
+    for y in 0..height {
+        for x in 0..width {
+            let index = (y.min(level.height - 1) * level.width + x.min(level.width - 1)) as usize * texel_size;
+            padded.extend_from_slice(&texels[index..index + texel_size]);
+        }
+    }
+
+    match kind {
+        TextureKind::Normal => {
+            let rg: Vec<u8> = padded.chunks_exact(4).flat_map(|texel| [texel[0], texel[1]]).collect();
+            bc5::compress_blocks(&RgSurface { data: &rg, width, height, stride: width * 2 })
+        }
+        TextureKind::Hdr => {
+            let surface = RgbaSurface { data: &padded, width, height, stride: width * 8 };
+            bc6h::compress_blocks(&bc6h::basic_settings(), &surface)
+        }
+        TextureKind::Color | TextureKind::Linear => {
+            let surface = RgbaSurface { data: &padded, width, height, stride: width * 4 };
+            let settings = if padded.chunks_exact(4).all(|texel| texel[3] == 255) {
+                bc7::opaque_basic_settings()
+            } else {
+                bc7::alpha_basic_settings()
+            };
+            bc7::compress_blocks(&settings, &surface)
+        }
+    }
+}
+
+#[cfg(not(feature = "texture-compression"))]
+fn compress_level(_level: &Level, _kind: TextureKind, _texels: &[u8], _texel_size: usize) -> Vec<u8> {
+    unreachable!("textures are only compressed with the texture-compression feature")
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 { value * 12.92 } else { 1.055 * value.powf(1.0 / 2.4) - 0.055 }
+}
+
+fn decode_normal(pixel: [f32; 4]) -> [f32; 4] {
+    normalize([pixel[0] * 2.0 - 1.0, pixel[1] * 2.0 - 1.0, pixel[2] * 2.0 - 1.0, 0.0])
+}
+
+fn normalize(pixel: [f32; 4]) -> [f32; 4] {
+    let length = (pixel[0] * pixel[0] + pixel[1] * pixel[1] + pixel[2] * pixel[2]).sqrt();
+    if length > 0.0 { [pixel[0] / length, pixel[1] / length, pixel[2] / length, 0.0] } else { [0.0, 0.0, 1.0, 0.0] }
+}
+
+/// Convert to IEEE 754 half precision, rounding to nearest even
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xFF) as i32;
+    let mantissa = bits & 0x007F_FFFF;
+
+    if exponent == 0xFF {
+        // Infinity, or a quiet NaN
+        return sign | 0x7C00 | if mantissa != 0 { 0x0200 } else { 0 };
+    }
+
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1F {
+        return sign | 0x7C00;
+    }
+    if half_exponent <= 0 {
+        // Subnormal, or too small and flushed to zero
+        if half_exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x0080_0000;
+        let shift = (14 - half_exponent) as u32;
+        let half_mantissa = mantissa >> shift;
+        let remainder = mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round = remainder > halfway || (remainder == halfway && half_mantissa & 1 != 0);
+        return sign | (half_mantissa + round as u32) as u16;
+    }
+
+    let half = ((half_exponent as u32) << 10) | (mantissa >> 13);
+    let remainder = mantissa & 0x1FFF;
+    let round = remainder > 0x1000 || (remainder == 0x1000 && half & 1 != 0);
+    // Rounding may carry into the exponent, which correctly rounds up to infinity
+    sign | (half + round as u32) as u16
+}
@@ -0,0 +1,47 @@
+// Template for generated materials.rs
+// This file is read by build.rs and prepended to the generated material constants
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MaterialID {
+    // MaterialID variants will be generated here by build.rs
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaMode {
+    Opaque,
+    /// Fragments with alpha below `Material::alpha_cutoff` are discarded
+    Mask,
+    Blend,
+}
+
+/// A PBR metallic-roughness material imported alongside a model. Texture references are paths
+/// relative to the model file, or `*<index>` for textures embedded in it.
+#[derive(Debug, Clone, Copy)]
+pub struct Material {
+    pub id: MaterialID,
+    pub name: &'static str,
+    /// Linear RGBA, multiplied with the base color texture
+    pub base_color: glam::Vec4,
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Linear RGB
+    pub emissive: glam::Vec3,
+    pub base_color_texture: Option<&'static str>,
+    /// Roughness in green, metalness in blue
+    pub metallic_roughness_texture: Option<&'static str>,
+    pub normal_texture: Option<&'static str>,
+    pub occlusion_texture: Option<&'static str>,
+    pub emissive_texture: Option<&'static str>,
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    pub double_sided: bool,
+}
+
+pub mod materials {
+    #[allow(unused_imports)]
+    use super::{AlphaMode, Material, MaterialID};
+    #[allow(unused_imports)]
+    use glam::{Vec3, Vec4};
+
+    // Material constants will be generated here by build.rs
+}
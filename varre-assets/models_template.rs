@@ -1,7 +1,11 @@
 // Template for generated models.rs
 // This file is read by build.rs and prepended to the generated model constants
 
-use glam::Vec3;
+use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
+use crate::model_format::{StreamSemantic, NO_PARENT};
+pub use crate::model_format::{
+    Aabb, BoundingSphere, Camera, Joint, Light, LightKind, Meshlet, ModelError, ModelView, QuatKey, Submesh, Vec3Key,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ModelID {
@@ -10,56 +14,344 @@ pub enum ModelID {
 
 #[derive(Debug, Clone)]
 pub struct Model {
-    pub id: ModelID,
+    /// None for models loaded at runtime that weren't compiled in
+    pub id: Option<ModelID>,
     pub verts: Vec<Vec3>,
     pub indices: Vec<u32>,
-    pub uvs: Vec<f32>,
+    /// Per-vertex normals; empty if the model has none
+    pub normals: Vec<Vec3>,
+    /// Per-vertex tangents in xyz, with the bitangent's handedness in w:
+    /// `bitangent = cross(normal, tangent.xyz) * tangent.w`. Empty without normals and UVs.
+    pub tangents: Vec<Vec4>,
+    /// Texture coordinate sets, one value per vertex each
+    pub uvs: Vec<Vec<Vec2>>,
+    /// Vertex color sets as RGBA8, one value per vertex each
+    pub colors: Vec<Vec<[u8; 4]>>,
+    /// Up to four indices into the scene's skin joints per vertex; empty if the model isn't skinned
+    pub joints: Vec<[u16; 4]>,
+    /// Weights of `joints` as unorm16, summing to one for skinned vertices and zero otherwise
+    pub weights: Vec<[u16; 4]>,
+    /// Index ranges drawn with one material each, with their own bounds
+    pub submeshes: Vec<Submesh>,
+    /// Bounds of every vertex, for framing or culling the model as a whole
+    pub bounds: Aabb,
+    pub bounding_sphere: BoundingSphere,
+    /// Empty when the `meshlets` feature is disabled
+    pub meshlets: Vec<Meshlet>,
+    /// Indices into `verts` referenced by each meshlet's local vertices
+    pub meshlet_vertices: Vec<u32>,
+    /// Three meshlet-local vertex indices per triangle
+    pub meshlet_triangles: Vec<u8>,
+    /// Simplified levels of detail, from most to least detailed. Empty unless the model was built
+    /// with the `lods` feature.
+    pub lods: Vec<ModelLod>,
+    /// Indices of every level in `lods`, into `verts`
+    pub lod_indices: Vec<u32>,
+}
+
+/// A simplified level of detail of a model, drawn with the model's vertices
+#[derive(Debug, Clone)]
+pub struct ModelLod {
+    /// The model's submeshes at this level, in the same order, with index ranges into
+    /// `Model::lod_indices`. A level's indices are contiguous.
+    pub submeshes: Vec<Submesh>,
+    /// Largest distance of the simplified surface from the full detail one, in model units
+    pub error: f32,
 }
 
 impl Model {
+    /// The material a submesh is drawn with. Materials are compiled in, so models loaded at
+    /// runtime without an ID have none.
+    pub fn material(&self, submesh: &Submesh) -> Option<MaterialID> {
+        self.id.map(|id| id.materials()[submesh.material as usize])
+    }
+
     /// Decode binary model data into runtime structures
-    pub fn decode(id: ModelID, data: &[u8]) -> Self {
-        let mut offset = 0;
+    pub fn decode(id: Option<ModelID>, data: &[u8]) -> Result<Self, ModelError> {
+        let view = ModelView::parse(data)?;
+
+        // Quantized positions, normals and texture coordinates are expanded back to floats
+        let verts = view.positions()?.into_owned();
+
+        let normals = view.normals()?
+            .map_or(Vec::new(), |normals| normals.into_owned());
+        let tangents = view.vertex_attribute::<Vec4>(StreamSemantic::Tangent, 0)?
+            .map_or(Vec::new(), |tangents| tangents.into_owned());
+
+        let uvs = (0..view.set_count(StreamSemantic::TexCoord))
+            .map(|set| view.tex_coords(set).map(|uvs| uvs.unwrap().into_owned()))
+            .collect::<Result<_, _>>()?;
+        let colors = (0..view.set_count(StreamSemantic::Color))
+            .map(|set| view.vertex_attribute::<[u8; 4]>(StreamSemantic::Color, set).map(|colors| colors.unwrap().into_owned()))
+            .collect::<Result<_, _>>()?;
+
+        let joints = view.vertex_attribute::<[u16; 4]>(StreamSemantic::Joints, 0)?
+            .map_or(Vec::new(), |joints| joints.into_owned());
+        let weights = view.vertex_attribute::<[u16; 4]>(StreamSemantic::Weights, 0)?
+            .map_or(Vec::new(), |weights| weights.into_owned());
+        if joints.len() != weights.len() {
+            return Err(ModelError::InvalidChunk("joint and weight streams don't match".to_string()));
+        }
+
+        let indices = view.indices()?.to_u32();
+        if let Some(index) = indices.iter().find(|index| **index as usize >= verts.len()) {
+            return Err(ModelError::InvalidChunk(format!("index {} is out of range for {} vertices", index, verts.len())));
+        }
+
+        let submeshes = view.submeshes().into_owned();
+        if let Some(id) = id
+            && let Some(submesh) = submeshes.iter().find(|submesh| submesh.material as usize >= id.materials().len())
+        {
+            return Err(ModelError::InvalidChunk(format!(
+                "submesh uses material {} of {}", submesh.material, id.materials().len()
+            )));
+        }
+
+        let lod_indices = view.lod_indices()?.to_u32();
+        if let Some(index) = lod_indices.iter().find(|index| **index as usize >= verts.len()) {
+            return Err(ModelError::InvalidChunk(format!("LOD index {} is out of range for {} vertices", index, verts.len())));
+        }
+        let mut lods = Vec::new();
+        for lod in view.lods().iter() {
+            let lod_submeshes = view.lod_submeshes(lod)?.into_owned();
+            if lod_submeshes.len() != submeshes.len() {
+                return Err(ModelError::InvalidChunk(format!("LOD has {} submeshes for {}", lod_submeshes.len(), submeshes.len())));
+            }
+            lods.push(ModelLod { submeshes: lod_submeshes, error: lod.error });
+        }
+
+        let meshlets = view.meshlets().into_owned();
+        let meshlet_vertices = view.meshlet_vertices().into_owned();
+        let meshlet_triangles = view.meshlet_triangles().to_vec();
+        if let Some(vertex) = meshlet_vertices.iter().find(|vertex| **vertex as usize >= verts.len()) {
+            return Err(ModelError::InvalidChunk(format!("meshlet vertex {} is out of range for {} vertices", vertex, verts.len())));
+        }
+        for meshlet in meshlets.iter() {
+            // Ranges were checked by `ModelView::parse`
+            let triangles = &meshlet_triangles[meshlet.triangle_offset as usize * 3..][..meshlet.triangle_count as usize * 3];
+            if let Some(vertex) = triangles.iter().find(|vertex| **vertex as u32 >= meshlet.vertex_count) {
+                return Err(ModelError::InvalidChunk(format!(
+                    "meshlet triangle vertex {} is out of range for {} vertices", vertex, meshlet.vertex_count
+                )));
+            }
+        }
+
+        Ok(Self {
+            id,
+            verts,
+            indices,
+            normals,
+            tangents,
+            uvs,
+            colors,
+            joints,
+            weights,
+            submeshes,
+            bounds: view.bounds().ok_or(ModelError::MissingChunk("bounds"))?,
+            bounding_sphere: view.bounding_sphere().ok_or(ModelError::MissingChunk("bounding sphere"))?,
+            meshlets,
+            meshlet_vertices,
+            meshlet_triangles,
+            lods,
+            lod_indices,
+        })
+    }
+}
+
+/// A node of a model's hierarchy
+#[derive(Debug, Clone)]
+pub struct SceneNode {
+    pub name: String,
+    pub parent: Option<usize>,
+    /// Relative to the parent
+    pub transform: Mat4,
+    /// Indices into the model's submeshes drawn at this node
+    pub meshes: Vec<u32>,
+}
+
+/// One node's keyframes in an animation clip
+#[derive(Debug, Clone)]
+pub struct AnimationChannel {
+    pub node: usize,
+    pub translations: Vec<Vec3Key>,
+    pub rotations: Vec<QuatKey>,
+    pub scales: Vec<Vec3Key>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimationClip {
+    pub name: String,
+    /// In seconds
+    pub duration: f32,
+    pub channels: Vec<AnimationChannel>,
+}
+
+/// The node hierarchy of a model's source file. Each mesh of the file is a submesh of the model;
+/// nodes instance them, so a submesh may be drawn several times with different transforms.
+/// Cameras, lights, skin joints and animation channels refer to nodes by index.
+#[derive(Debug, Clone)]
+pub struct SceneAsset {
+    /// None for models loaded at runtime that weren't compiled in
+    pub model: Option<ModelID>,
+    /// Parents always come before their children
+    pub nodes: Vec<SceneNode>,
+    pub cameras: Vec<Camera>,
+    pub lights: Vec<Light>,
+    /// Joints indexed by the model's `joints` stream
+    pub skin: Vec<Joint>,
+    pub animations: Vec<AnimationClip>,
+}
+
+impl SceneAsset {
+    /// Decode the hierarchy of binary model data. Models written without one get a single root
+    /// node instancing every submesh.
+    pub fn decode(id: Option<ModelID>, data: &[u8]) -> Result<Self, ModelError> {
+        let view = ModelView::parse(data)?;
+        let submesh_count = view.submeshes().len();
+
+        let records = view.nodes();
+        if records.is_empty() {
+            let root = SceneNode {
+                name: String::new(),
+                parent: None,
+                transform: Mat4::IDENTITY,
+                meshes: (0..submesh_count as u32).collect(),
+            };
+            return Ok(Self {
+                model: id,
+                nodes: vec![root],
+                cameras: Vec::new(),
+                lights: Vec::new(),
+                skin: Vec::new(),
+                animations: Vec::new(),
+            });
+        }
+
+        let mut nodes = Vec::with_capacity(records.len());
+        for (index, record) in records.iter().enumerate() {
+            let parent = match record.parent {
+                NO_PARENT => None,
+                parent if (parent as usize) < index => Some(parent as usize),
+                parent => return Err(ModelError::InvalidChunk(format!("node {} has parent {}, which doesn't precede it", index, parent))),
+            };
 
-        // Read vertex count (u32)
-        let vert_count = u32::from_le_bytes([data[offset], data[offset+1], data[offset+2], data[offset+3]]) as usize;
-        offset += 4;
+            let meshes = view.node_meshes(record)?.into_owned();
+            if let Some(mesh) = meshes.iter().find(|mesh| **mesh as usize >= submesh_count) {
+                return Err(ModelError::InvalidChunk(format!("node {} references submesh {} of {}", index, mesh, submesh_count)));
+            }
 
-        // Read vertices (3 f32s per vertex)
-        let mut verts = Vec::with_capacity(vert_count);
-        for _ in 0..vert_count {
-            let x = f32::from_le_bytes([data[offset], data[offset+1], data[offset+2], data[offset+3]]);
-            let y = f32::from_le_bytes([data[offset+4], data[offset+5], data[offset+6], data[offset+7]]);
-            let z = f32::from_le_bytes([data[offset+8], data[offset+9], data[offset+10], data[offset+11]]);
-            verts.push(Vec3::new(x, y, z));
-            offset += 12;
+            nodes.push(SceneNode {
+                name: view.node_name(record)?.to_string(),
+                parent,
+                transform: Mat4::from_cols_array(&record.transform),
+                meshes,
+            });
         }
 
-        // Read index count (u32)
-        let index_count = u32::from_le_bytes([data[offset], data[offset+1], data[offset+2], data[offset+3]]) as usize;
-        offset += 4;
+        let check_node = |kind: &str, node: u32| {
+            if node as usize >= nodes.len() {
+                return Err(ModelError::InvalidChunk(format!("{} references node {} of {}", kind, node, nodes.len())));
+            }
+            Ok(node as usize)
+        };
 
-        // Read indices (u32s)
-        let mut indices = Vec::with_capacity(index_count);
-        for _ in 0..index_count {
-            let idx = u32::from_le_bytes([data[offset], data[offset+1], data[offset+2], data[offset+3]]);
-            indices.push(idx);
-            offset += 4;
+        let cameras = view.cameras().into_owned();
+        let lights = view.lights().into_owned();
+        let skin = view.skin().into_owned();
+        for camera in &cameras {
+            check_node("camera", camera.node)?;
+        }
+        for light in &lights {
+            check_node("light", light.node)?;
+            light.kind().ok_or_else(|| ModelError::InvalidChunk(format!("unknown light kind {}", light.kind)))?;
+        }
+        for joint in &skin {
+            check_node("joint", joint.node)?;
         }
 
-        // Read UV count (u32)
-        let uv_count = u32::from_le_bytes([data[offset], data[offset+1], data[offset+2], data[offset+3]]) as usize;
-        offset += 4;
+        let mut animations = Vec::new();
+        for animation in view.animations().iter() {
+            let mut channels = Vec::new();
+            for channel in view.animation_channels(animation)?.iter() {
+                channels.push(AnimationChannel {
+                    node: check_node("animation channel", channel.node)?,
+                    translations: view.translation_keys(channel)?.into_owned(),
+                    rotations: view.rotation_keys(channel)?.into_owned(),
+                    scales: view.scale_keys(channel)?.into_owned(),
+                });
+            }
 
-        // Read UVs (f32s)
-        let mut uvs = Vec::with_capacity(uv_count);
-        for _ in 0..uv_count {
-            let uv = f32::from_le_bytes([data[offset], data[offset+1], data[offset+2], data[offset+3]]);
-            uvs.push(uv);
-            offset += 4;
+            animations.push(AnimationClip {
+                name: view.animation_name(animation)?.to_string(),
+                duration: animation.duration,
+                channels,
+            });
         }
 
-        Self { id, verts, indices, uvs }
+        Ok(Self { model: id, nodes, cameras, lights, skin, animations })
+    }
+
+    pub fn roots(&self) -> impl Iterator<Item = usize> + '_ {
+        self.nodes.iter().enumerate().filter(|(_, node)| node.parent.is_none()).map(|(index, _)| index)
+    }
+
+    pub fn children(&self, parent: usize) -> impl Iterator<Item = usize> + '_ {
+        self.nodes.iter().enumerate().filter(move |(_, node)| node.parent == Some(parent)).map(|(index, _)| index)
+    }
+
+    /// Each node's transform relative to the scene root, indexed like `nodes`
+    pub fn world_transforms(&self) -> Vec<Mat4> {
+        self.to_world(self.nodes.iter().map(|node| node.transform).collect())
+    }
+
+    /// World transforms with `animation` applied at `time` seconds. Channels hold their first and
+    /// last keys outside the keyed range.
+    pub fn animated_world_transforms(&self, animation: &AnimationClip, time: f32) -> Vec<Mat4> {
+        let mut local: Vec<Mat4> = self.nodes.iter().map(|node| node.transform).collect();
+
+        for channel in &animation.channels {
+            let (scale, rotation, translation) = local[channel.node].to_scale_rotation_translation();
+            let translation = sample_keys(&channel.translations, time, |key| key.time, |key| key.value, Vec3::lerp)
+                .unwrap_or(translation);
+            let rotation = sample_keys(&channel.rotations, time, |key| key.time, |key| Quat::from_array(key.value), Quat::slerp)
+                .unwrap_or(rotation);
+            let scale = sample_keys(&channel.scales, time, |key| key.time, |key| key.value, Vec3::lerp)
+                .unwrap_or(scale);
+
+            local[channel.node] = Mat4::from_scale_rotation_translation(scale, rotation, translation);
+        }
+
+        self.to_world(local)
+    }
+
+    fn to_world(&self, mut transforms: Vec<Mat4>) -> Vec<Mat4> {
+        for index in 0..transforms.len() {
+            if let Some(parent) = self.nodes[index].parent {
+                transforms[index] = transforms[parent] * transforms[index];
+            }
+        }
+        transforms
+    }
+
+    /// Every submesh to draw with the world transform to draw it with
+    pub fn instances(&self) -> Vec<(u32, Mat4)> {
+        self.nodes.iter()
+            .zip(self.world_transforms())
+            .flat_map(|(node, transform)| node.meshes.iter().map(move |mesh| (*mesh, transform)))
+            .collect()
+    }
+}
+
+/// Interpolate between the keys surrounding `time`, or None if there are no keys
+fn sample_keys<K, T: Copy>(keys: &[K], time: f32, key_time: impl Fn(&K) -> f32, value: impl Fn(&K) -> T, interpolate: impl Fn(T, T, f32) -> T) -> Option<T> {
+    let next = keys.partition_point(|key| key_time(key) <= time);
+    match (next.checked_sub(1).map(|index| &keys[index]), keys.get(next)) {
+        (Some(a), Some(b)) => {
+            let t = (time - key_time(a)) / (key_time(b) - key_time(a));
+            Some(interpolate(value(a), value(b), t))
+        }
+        (Some(key), None) | (None, Some(key)) => Some(value(key)),
+        (None, None) => None,
     }
 }
 
@@ -25,8 +25,47 @@ pub struct VkDescriptorSetLayoutBinding {
     pub set: u32,
     pub binding: u32,
     pub descriptor_type: u32,
+    /// Array size; 0 when `unbounded` is set
     pub descriptor_count: u32,
     pub stage_flags: u32,
+    /// The shader declares a runtime-sized array at this binding, which needs descriptor indexing
+    pub unbounded: bool,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct VkPushConstantRange {
+    pub offset: u32,
+    pub size: u32,
+    pub stage_flags: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ShaderInterfaceVariable {
+    pub location: u32,
+    /// Raw VkFormat value matching the variable's type
+    pub format: u32,
+    pub semantic: &'static str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecializationConstantType {
+    Bool,
+    Int32,
+    UInt32,
+    Int64,
+    UInt64,
+    Float32,
+    Float64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SpecializationConstant {
+    pub id: u32,
+    pub name: &'static str,
+    pub ty: SpecializationConstantType,
+    /// Default value as raw little-endian bits, zero-extended to 64 bits
+    pub default_value: u64,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -36,6 +75,12 @@ pub struct Shader {
     pub stage: ShaderStage,
     pub entry_point: &'static str,
     pub descriptor_set_layout_bindings: &'static [VkDescriptorSetLayoutBinding],
+    pub specialization_constants: &'static [SpecializationConstant],
+    pub push_constant_range: Option<VkPushConstantRange>,
+    /// Stage input variables; for vertex shaders these are the vertex attributes
+    pub inputs: &'static [ShaderInterfaceVariable],
+    /// Stage output variables, matched against the next stage's inputs when stages are linked
+    pub outputs: &'static [ShaderInterfaceVariable],
 }
 
 pub mod shaders {
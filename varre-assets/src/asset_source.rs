@@ -0,0 +1,463 @@
+// Where asset data comes from: compiled into the binary by the build script, read from disk at
+// runtime in the same binary formats, or memory-mapped from an asset pack. Assets are looked up
+// by kind and name, where names match the generated enums' `name()`, e.g. "utah_teapot" for
+// `ModelID::UTAH_TEAPOT`.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use memmap2::Mmap;
+use crate::pack_format::{PackEntry, PackError, PackIndex};
+use crate::{Model, ModelError, ModelID, SceneAsset, ShaderID, TextureID};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AssetKind {
+    /// Model data in the format of `model_format`
+    Model,
+    /// KTX2 or DDS files
+    Texture,
+    /// SPIR-V modules
+    Shader,
+}
+
+impl AssetKind {
+    pub const ALL: [AssetKind; 3] = [AssetKind::Model, AssetKind::Texture, AssetKind::Shader];
+
+    /// Subdirectory holding this kind of asset, matching the build script's output layout
+    pub const fn directory(&self) -> &'static str {
+        match self {
+            AssetKind::Model => "models",
+            AssetKind::Texture => "textures",
+            AssetKind::Shader => "shaders",
+        }
+    }
+
+    /// File extensions of this kind of asset, in lookup order
+    pub const fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            AssetKind::Model => &["bin"],
+            AssetKind::Texture => &["ktx2", "dds"],
+            AssetKind::Shader => &["spv"],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum AssetError {
+    NotFound { kind: AssetKind, name: String },
+    Io { path: PathBuf, error: std::io::Error },
+    Model { name: String, error: ModelError },
+    /// A pack failed to open, or one of its entries failed to read
+    Pack { path: PathBuf, error: PackError },
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetError::NotFound { kind, name } => write!(f, "no {:?} asset named {:?}", kind, name),
+            AssetError::Io { path, error } => write!(f, "failed to read {}: {}", path.display(), error),
+            AssetError::Model { name, error } => write!(f, "failed to decode model {:?}: {}", name, error),
+            AssetError::Pack { path, error } => write!(f, "failed to read asset pack {}: {}", path.display(), error),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AssetError::NotFound { .. } => None,
+            AssetError::Io { error, .. } => Some(error),
+            AssetError::Model { error, .. } => Some(error),
+            AssetError::Pack { error, .. } => Some(error),
+        }
+    }
+}
+
+/// A place assets can be read from
+pub trait AssetSource: Send + Sync {
+    /// Read the data of the asset named `name`, or `AssetError::NotFound` if the source doesn't
+    /// have it
+    fn read(&self, kind: AssetKind, name: &str) -> Result<Cow<'_, [u8]>, AssetError>;
+
+    /// Names of every asset of `kind` in the source
+    fn names(&self, kind: AssetKind) -> Vec<String>;
+}
+
+/// Assets compiled into the binary: `ModelID::data()`, `TextureID::data()` and
+/// `ShaderID::shader()`. With the `asset-pack` feature, models and textures come from the
+/// `bundled_pack()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EmbeddedSource;
+
+impl AssetSource for EmbeddedSource {
+    fn read(&self, kind: AssetKind, name: &str) -> Result<Cow<'_, [u8]>, AssetError> {
+        let data = match kind {
+            AssetKind::Model => ModelID::from_name(name).map(|id| id.data()),
+            AssetKind::Texture => TextureID::from_name(name).map(|id| id.data()),
+            AssetKind::Shader => ShaderID::from_name(name).map(|id| id.shader().spv),
+        };
+        data.map(Cow::Borrowed).ok_or_else(|| AssetError::NotFound { kind, name: name.to_string() })
+    }
+
+    fn names(&self, kind: AssetKind) -> Vec<String> {
+        match kind {
+            AssetKind::Model => ModelID::all().iter().map(|id| id.name().to_string()).collect(),
+            AssetKind::Texture => TextureID::all().iter().map(|id| id.name().to_string()).collect(),
+            AssetKind::Shader => ShaderID::all().iter().map(|id| id.name().to_string()).collect(),
+        }
+    }
+}
+
+/// Assets read from a directory laid out like the build script's output:
+/// `models/<name>.bin`, `textures/<name>.ktx2` (or `.dds`) and `shaders/<source>.<stage>.spv`.
+/// Shaders are named like `ShaderID::name()`, e.g. "basic_model_vertex" for
+/// `shaders/basic_model.vertex.spv`, and can also be stored as `shaders/<name>.spv`.
+#[derive(Debug, Clone)]
+pub struct DirectorySource {
+    root: PathBuf,
+}
+
+impl DirectorySource {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Path of an existing asset file
+    fn path(&self, kind: AssetKind, name: &str) -> Option<PathBuf> {
+        // Names can't reach outside the root
+        if name.is_empty() || name.contains(['/', '\\']) {
+            return None;
+        }
+        kind.extensions()
+            .iter()
+            .map(|extension| self.root.join(kind.directory()).join(format!("{}.{}", name, extension)))
+            .find(|path| path.is_file())
+            .or_else(|| {
+                let mut files = self.files(kind);
+                files.sort();
+                files.into_iter().find(|path| asset_name(kind, path).as_deref() == Some(name))
+            })
+    }
+
+    /// Every file with one of `kind`'s extensions in its subdirectory
+    fn files(&self, kind: AssetKind) -> Vec<PathBuf> {
+        let Ok(entries) = fs::read_dir(self.root.join(kind.directory())) else {
+            return Vec::new();
+        };
+        entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .filter(|path| {
+                path.extension().and_then(|e| e.to_str()).is_some_and(|e| kind.extensions().iter().any(|k| e.eq_ignore_ascii_case(k)))
+            })
+            .collect()
+    }
+}
+
+/// Name of the asset in the file at `path`. The build script names shaders
+/// `<source>.<stage>.spv` but their IDs `<source>_<stage>`, in lowercase with hyphens replaced.
+fn asset_name(kind: AssetKind, path: &Path) -> Option<String> {
+    let stem = path.file_stem()?.to_str()?;
+    Some(match kind {
+        AssetKind::Shader => stem.replace(['.', '-'], "_").to_lowercase(),
+        AssetKind::Model | AssetKind::Texture => stem.to_string(),
+    })
+}
+
+impl AssetSource for DirectorySource {
+    fn read(&self, kind: AssetKind, name: &str) -> Result<Cow<'_, [u8]>, AssetError> {
+        let path = self.path(kind, name).ok_or_else(|| AssetError::NotFound { kind, name: name.to_string() })?;
+        fs::read(&path).map(Cow::Owned).map_err(|error| AssetError::Io { path, error })
+    }
+
+    fn names(&self, kind: AssetKind) -> Vec<String> {
+        let mut names: Vec<String> = self.files(kind).iter().filter_map(|path| asset_name(kind, path)).collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+/// Environment variable overriding where the `bundled_pack()` is opened from
+pub const ASSET_PACK_ENV: &str = "VARRE_ASSET_PACK";
+/// File name of the pack the build script writes with the `asset-pack` feature
+pub const BUNDLED_PACK_NAME: &str = "assets.vpk";
+
+/// Assets memory-mapped from a pack written by `pack_format::PackWriter`. Entries are read on
+/// demand; uncompressed ones are borrowed straight from the mapping.
+pub struct PackSource {
+    path: PathBuf,
+    map: Mmap,
+    index: PackIndex,
+    /// Decompressed entries kept alive by `get`
+    decoded: Vec<OnceLock<Box<[u8]>>>,
+}
+
+impl PackSource {
+    /// Map the pack at `path` and read its table of contents. The file must not be modified while
+    /// it's open.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, AssetError> {
+        let path = path.into();
+        let file = fs::File::open(&path).map_err(|error| AssetError::Io { path: path.clone(), error })?;
+        // Safety: packs are written once by the build and only ever read afterwards
+        let map = unsafe { Mmap::map(&file) }.map_err(|error| AssetError::Io { path: path.clone(), error })?;
+        let index = PackIndex::parse(&map).map_err(|error| AssetError::Pack { path: path.clone(), error })?;
+        let decoded = index.entries().iter().map(|_| OnceLock::new()).collect();
+        Ok(Self { path, map, index, decoded })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn index(&self) -> &PackIndex {
+        &self.index
+    }
+
+    /// The whole mapped pack, which `PackEntry::stored`, `read` and `read_into` take
+    pub fn data(&self) -> &[u8] {
+        &self.map
+    }
+
+    pub fn entry(&self, kind: AssetKind, name: &str) -> Option<&PackEntry> {
+        self.index.find(&pack_path(kind, name))
+    }
+
+    /// Read an asset, keeping compressed entries decompressed for as long as the pack is open, so
+    /// every asset can be borrowed for the pack's lifetime
+    pub fn get(&self, kind: AssetKind, name: &str) -> Result<&[u8], AssetError> {
+        let position = self.index.position(&pack_path(kind, name)).ok_or_else(|| AssetError::NotFound { kind, name: name.to_string() })?;
+        if let Some(data) = self.decoded[position].get() {
+            return Ok(data);
+        }
+        match self.read_entry(&self.index.entries()[position])? {
+            Cow::Borrowed(data) => Ok(data),
+            Cow::Owned(data) => Ok(self.decoded[position].get_or_init(|| data.into_boxed_slice())),
+        }
+    }
+
+    fn read_entry(&self, entry: &PackEntry) -> Result<Cow<'_, [u8]>, AssetError> {
+        entry.read(&self.map).map_err(|error| AssetError::Pack { path: self.path.clone(), error })
+    }
+}
+
+impl AssetSource for PackSource {
+    fn read(&self, kind: AssetKind, name: &str) -> Result<Cow<'_, [u8]>, AssetError> {
+        let entry = self.entry(kind, name).ok_or_else(|| AssetError::NotFound { kind, name: name.to_string() })?;
+        self.read_entry(entry)
+    }
+
+    fn names(&self, kind: AssetKind) -> Vec<String> {
+        let prefix = format!("{}/", kind.directory());
+        self.index.entries().iter().filter_map(|entry| entry.path.strip_prefix(&prefix)).map(str::to_string).collect()
+    }
+}
+
+/// Path of an asset within a pack, e.g. "models/cube"
+pub fn pack_path(kind: AssetKind, name: &str) -> String {
+    format!("{}/{}", kind.directory(), name)
+}
+
+/// The pack the build script wrote with the `asset-pack` feature, opened on first use from
+/// `ASSET_PACK_ENV` if it's set, then next to the executable, then from the build's output
+/// directory. Panics if it can't be opened, since compiled-in IDs have no other data.
+#[cfg(feature = "asset-pack")]
+pub fn bundled_pack() -> &'static PackSource {
+    static PACK: OnceLock<PackSource> = OnceLock::new();
+    PACK.get_or_init(|| {
+        let path = match std::env::var_os(ASSET_PACK_ENV) {
+            Some(path) => PathBuf::from(path),
+            None => std::env::current_exe()
+                .ok()
+                .and_then(|exe| exe.parent().map(|dir| dir.join(BUNDLED_PACK_NAME)))
+                .filter(|path| path.is_file())
+                .unwrap_or_else(|| Path::new(env!("OUT_DIR")).join(BUNDLED_PACK_NAME)),
+        };
+        PackSource::open(path).unwrap_or_else(|e| panic!("{}", e))
+    })
+}
+
+/// Asset sources searched in priority order, so that files on disk can add to or replace the
+/// compiled-in assets without recompiling
+pub struct Assets {
+    sources: Vec<Box<dyn AssetSource>>,
+}
+
+impl Default for Assets {
+    /// Only the compiled-in assets
+    fn default() -> Self {
+        Self { sources: vec![Box::new(EmbeddedSource)] }
+    }
+}
+
+impl Assets {
+    /// No sources at all
+    pub fn empty() -> Self {
+        Self { sources: Vec::new() }
+    }
+
+    /// Add a source that takes priority over the ones added before it
+    pub fn with_source(mut self, source: impl AssetSource + 'static) -> Self {
+        self.sources.insert(0, Box::new(source));
+        self
+    }
+
+    /// Read an asset from the first source that has it
+    pub fn read(&self, kind: AssetKind, name: &str) -> Result<Cow<'_, [u8]>, AssetError> {
+        for source in &self.sources {
+            match source.read(kind, name) {
+                Err(AssetError::NotFound { .. }) => continue,
+                result => return result,
+            }
+        }
+        Err(AssetError::NotFound { kind, name: name.to_string() })
+    }
+
+    /// Names of every asset of `kind` in any source, sorted
+    pub fn names(&self, kind: AssetKind) -> Vec<String> {
+        let mut names: Vec<String> = self.sources.iter().flat_map(|source| source.names(kind)).collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Load and decode a model. Models sharing a name with a compiled-in model keep its ID and
+    /// materials.
+    pub fn load_model(&self, name: &str) -> Result<Model, AssetError> {
+        let data = self.read(AssetKind::Model, name)?;
+        Model::decode(ModelID::from_name(name), &data).map_err(|error| AssetError::Model { name: name.to_string(), error })
+    }
+
+    /// Load and decode a model's node hierarchy
+    pub fn load_scene(&self, name: &str) -> Result<SceneAsset, AssetError> {
+        let data = self.read(AssetKind::Model, name)?;
+        SceneAsset::decode(ModelID::from_name(name), &data).map_err(|error| AssetError::Model { name: name.to_string(), error })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model_format::test_triangle_model;
+    use crate::pack_format::{Compression, PackWriter};
+
+    /// An empty directory under the system temp directory, unique to the test
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("varre-assets-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("models")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_embedded_source() {
+        let source = EmbeddedSource;
+        let name = ModelID::CUBE.name();
+
+        assert!(source.names(AssetKind::Model).iter().any(|n| n == name));
+        assert!(matches!(source.read(AssetKind::Model, name), Ok(Cow::Borrowed(data)) if data.as_ptr() == ModelID::CUBE.data().as_ptr()));
+        assert!(matches!(source.read(AssetKind::Model, "missing"), Err(AssetError::NotFound { .. })));
+        assert_eq!(ModelID::from_name(name), Some(ModelID::CUBE));
+    }
+
+    #[test]
+    fn test_directory_overrides_embedded() {
+        let dir = temp_dir("directory");
+        fs::write(dir.join("models/custom.bin"), test_triangle_model()).unwrap();
+        fs::write(dir.join(format!("models/{}.bin", ModelID::CUBE.name())), test_triangle_model()).unwrap();
+        fs::write(dir.join("models/notes.txt"), "not a model").unwrap();
+
+        let assets = Assets::default().with_source(DirectorySource::new(&dir));
+
+        let names = assets.names(AssetKind::Model);
+        assert!(names.contains(&"custom".to_string()));
+        assert_eq!(names.iter().filter(|n| *n == ModelID::CUBE.name()).count(), 1);
+        assert!(!names.contains(&"notes".to_string()));
+
+        let custom = assets.load_model("custom").expect("model on disk should load");
+        assert_eq!(custom.id, None);
+        assert_eq!(custom.verts.len(), 3);
+        assert_eq!(custom.material(&custom.submeshes[0]), None);
+
+        // The file replaces the embedded cube but keeps its ID
+        let cube = assets.load_model(ModelID::CUBE.name()).unwrap();
+        assert_eq!(cube.id, Some(ModelID::CUBE));
+        assert_eq!(cube.verts.len(), 3);
+
+        assert!(matches!(assets.read(AssetKind::Model, "../models/custom"), Err(AssetError::NotFound { .. })));
+        assert!(matches!(assets.load_model("missing"), Err(AssetError::NotFound { .. })));
+
+        fs::write(dir.join("models/broken.bin"), b"VRMD").unwrap();
+        assert!(matches!(assets.load_model("broken"), Err(AssetError::Model { .. })));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_directory_shader_names() {
+        let dir = temp_dir("shaders");
+        fs::create_dir_all(dir.join("shaders")).unwrap();
+
+        // Written as the build script names it, e.g. basic_model.vertex.spv for basic_model_vertex
+        let id = ShaderID::all()[0];
+        let (source, stage) = id.name().rsplit_once('_').unwrap();
+        fs::write(dir.join(format!("shaders/{}.{}.spv", source, stage)), [1, 2, 3, 4]).unwrap();
+        fs::write(dir.join("shaders/custom.spv"), [5, 6, 7, 8]).unwrap();
+
+        let directory = DirectorySource::new(&dir);
+        assert_eq!(directory.read(AssetKind::Shader, id.name()).unwrap().as_ref(), [1, 2, 3, 4]);
+        let mut names = vec![id.name().to_string(), "custom".to_string()];
+        names.sort();
+        assert_eq!(directory.names(AssetKind::Shader), names);
+
+        // The file replaces the embedded shader of the same ID
+        let assets = Assets::default().with_source(directory);
+        assert_eq!(assets.read(AssetKind::Shader, id.name()).unwrap().as_ref(), [1, 2, 3, 4]);
+        assert_eq!(assets.names(AssetKind::Shader).iter().filter(|name| *name == id.name()).count(), 1);
+        assert_eq!(assets.read(AssetKind::Shader, "custom").unwrap().as_ref(), [5, 6, 7, 8]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_pack_source() {
+        let dir = temp_dir("pack");
+        let mut writer = PackWriter::new();
+        writer.add("models/zstd", &test_triangle_model(), Compression::Zstd);
+        writer.add("models/lz4", &test_triangle_model(), Compression::Lz4);
+        writer.add("models/stored", &test_triangle_model(), Compression::None);
+        writer.add("textures/checker", &[0; 64], Compression::Zstd);
+        fs::write(dir.join("assets.vpk"), writer.finish()).unwrap();
+
+        let pack = PackSource::open(dir.join("assets.vpk")).expect("pack should open");
+        assert_eq!(pack.names(AssetKind::Model), ["lz4", "stored", "zstd"]);
+        assert_eq!(pack.names(AssetKind::Texture), ["checker"]);
+        assert!(pack.names(AssetKind::Shader).is_empty());
+
+        // Uncompressed entries are borrowed from the mapping, aligned so models can be read in
+        // place
+        assert!(matches!(pack.read(AssetKind::Model, "stored"), Ok(Cow::Borrowed(data)) if (data.as_ptr() as usize).is_multiple_of(16)));
+        assert!(matches!(pack.read(AssetKind::Model, "zstd"), Ok(Cow::Owned(_))));
+        // `get` decompresses once and keeps the result
+        assert_eq!(pack.get(AssetKind::Model, "lz4").unwrap().as_ptr(), pack.get(AssetKind::Model, "lz4").unwrap().as_ptr());
+
+        let assets = Assets::empty().with_source(pack);
+        for name in ["zstd", "lz4", "stored"] {
+            assert_eq!(assets.load_model(name).expect("packed model should load").verts.len(), 3);
+        }
+        assert!(matches!(assets.read(AssetKind::Texture, "missing"), Err(AssetError::NotFound { .. })));
+
+        fs::write(dir.join("broken.vpk"), b"VRPK").unwrap();
+        assert!(matches!(PackSource::open(dir.join("broken.vpk")), Err(AssetError::Pack { .. })));
+        assert!(matches!(PackSource::open(dir.join("missing.vpk")), Err(AssetError::Io { .. })));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
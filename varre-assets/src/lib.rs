@@ -2,5 +2,25 @@
 // Example usage:
 // pub const VERTEX_SHADER: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/shaders/shader.vert.spv"));
 
+pub mod asset_source;
+pub mod model_format;
+pub mod pack_format;
+pub mod texture_format;
+// Model pipeline passes of the build script, compiled here only for their tests
+#[cfg(test)]
+mod meshlets;
+#[cfg(test)]
+mod mesh_optimize;
+#[cfg(test)]
+mod mesh_simplify;
+
+pub use asset_source::{
+    AssetError, AssetKind, AssetSource, Assets, DirectorySource, EmbeddedSource, PackSource, ASSET_PACK_ENV, BUNDLED_PACK_NAME,
+};
+#[cfg(feature = "asset-pack")]
+pub use asset_source::bundled_pack;
+
 include!(concat!(env!("OUT_DIR"), "/shaders.rs"));
-include!(concat!(env!("OUT_DIR"), "/models.rs"));
\ No newline at end of file
+include!(concat!(env!("OUT_DIR"), "/models.rs"));
+include!(concat!(env!("OUT_DIR"), "/materials.rs"));
+include!(concat!(env!("OUT_DIR"), "/textures.rs"));
@@ -0,0 +1,369 @@
+// Mesh optimization passes, run by the build script's model pipeline. Triangles are reordered for
+// the GPU's post-transform vertex cache and then, in cache-friendly clusters, for less overdraw;
+// vertices are then reordered so the vertex fetch walks memory forward.
+
+use glam::Vec3;
+use std::collections::VecDeque;
+
+/// Entries of the post-transform cache modelled by the vertex cache pass
+pub const CACHE_SIZE: usize = 32;
+
+/// How much worse than the vertex cache order a cluster may get in exchange for drawing it in a
+/// better order for overdraw, as a factor of its average cache miss ratio
+pub const OVERDRAW_THRESHOLD: f32 = 1.05;
+
+/// Smaller FIFO cache used to find where the vertex cache order restarts, as clusters are split there
+const CLUSTER_CACHE_SIZE: usize = 16;
+
+// Vertex scoring from Tom Forsyth's "Linear-Speed Vertex Cache Optimisation"
+const CACHE_DECAY_POWER: f32 = 1.5;
+const LAST_TRIANGLE_SCORE: f32 = 0.75;
+const VALENCE_BOOST_SCALE: f32 = 2.0;
+const VALENCE_BOOST_POWER: f32 = 0.5;
+
+/// A FIFO post-transform cache, as found in most GPUs
+struct FifoCache {
+    entries: VecDeque<u32>,
+    size: usize,
+}
+
+impl FifoCache {
+    fn new(size: usize) -> Self {
+        Self { entries: VecDeque::with_capacity(size), size }
+    }
+
+    /// Look up a vertex, returning whether it missed and had to be transformed
+    fn access(&mut self, vertex: u32) -> bool {
+        if self.entries.contains(&vertex) {
+            return false;
+        }
+        if self.entries.len() == self.size {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(vertex);
+        true
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Number of the triangle's vertices that missed
+    fn triangle(&mut self, triangle: &[u32]) -> usize {
+        triangle.iter().filter(|vertex| self.access(**vertex)).count()
+    }
+}
+
+/// Vertices transformed when drawing `indices` with a FIFO cache of `cache_size` entries. Divided
+/// by the triangle count this is the average cache miss ratio (ACMR), which is 0.5 at best for
+/// large regular meshes and 3 at worst.
+pub fn cache_misses(indices: &[u32], cache_size: usize) -> usize {
+    let mut cache = FifoCache::new(cache_size);
+    indices.chunks_exact(3).map(|triangle| cache.triangle(triangle)).sum()
+}
+
+fn vertex_score(cache_position: Option<usize>, live_triangles: u32) -> f32 {
+    if live_triangles == 0 {
+        return -1.0;
+    }
+
+    let cache_score = match cache_position {
+        None => 0.0,
+        // The last triangle's vertices get a fixed score, so the next triangle doesn't simply
+        // reuse its edge and strip along
+        Some(position) if position < 3 => LAST_TRIANGLE_SCORE,
+        Some(position) => (1.0 - (position - 3) as f32 / (CACHE_SIZE - 3) as f32).powf(CACHE_DECAY_POWER),
+    };
+
+    // Vertices with few triangles left are finished first, so they don't need to be reloaded later
+    cache_score + VALENCE_BOOST_SCALE * (live_triangles as f32).powf(-VALENCE_BOOST_POWER)
+}
+
+/// Reorder triangles for the post-transform cache. Each step emits the triangle with the best
+/// vertex scores, favouring vertices that are in the modelled LRU cache and vertices with few
+/// triangles left.
+pub fn optimize_vertex_cache(indices: &[u32], vertex_count: usize) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+    let triangle = |t: usize| [indices[t * 3], indices[t * 3 + 1], indices[t * 3 + 2]];
+
+    // Triangles using each vertex, as ranges of `adjacency`. Emitted triangles are swapped past
+    // the end of their vertices' live ranges.
+    let mut live_triangles = vec![0u32; vertex_count];
+    for index in indices {
+        live_triangles[*index as usize] += 1;
+    }
+    let mut adjacency_offsets = Vec::with_capacity(vertex_count);
+    let mut offset = 0;
+    for count in &live_triangles {
+        adjacency_offsets.push(offset);
+        offset += *count as usize;
+    }
+    let mut adjacency = vec![0usize; indices.len()];
+    let mut filled = vec![0usize; vertex_count];
+    for t in 0..triangle_count {
+        for vertex in triangle(t) {
+            let vertex = vertex as usize;
+            adjacency[adjacency_offsets[vertex] + filled[vertex]] = t;
+            filled[vertex] += 1;
+        }
+    }
+    let mut cache_positions: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut vertex_scores: Vec<f32> = live_triangles.iter().map(|live| vertex_score(None, *live)).collect();
+    let triangle_score = |vertex_scores: &[f32], t: usize| -> f32 {
+        triangle(t).iter().map(|vertex| vertex_scores[*vertex as usize]).sum()
+    };
+    let mut emitted = vec![false; triangle_count];
+    let mut next_unemitted = 0;
+
+    let mut cache: Vec<u32> = Vec::with_capacity(CACHE_SIZE + 3);
+    let mut result = Vec::with_capacity(indices.len());
+    let mut best = (0..triangle_count)
+        .max_by(|a, b| triangle_score(&vertex_scores, *a).total_cmp(&triangle_score(&vertex_scores, *b)));
+
+    while let Some(t) = best {
+        emitted[t] = true;
+        let vertices = triangle(t);
+        result.extend_from_slice(&vertices);
+
+        for vertex in vertices {
+            let vertex = vertex as usize;
+            let range = adjacency_offsets[vertex]..adjacency_offsets[vertex] + live_triangles[vertex] as usize;
+            let position = adjacency[range.clone()].iter().position(|other| *other == t).unwrap();
+            adjacency.swap(range.start + position, range.end - 1);
+            live_triangles[vertex] -= 1;
+        }
+
+        // The triangle's vertices move to the front of the cache, pushing the rest back
+        let mut touched: Vec<u32> = Vec::with_capacity(cache.len() + 3);
+        for vertex in vertices.iter().chain(&cache) {
+            if !touched.contains(vertex) {
+                touched.push(*vertex);
+            }
+        }
+        cache = touched.iter().take(CACHE_SIZE).copied().collect();
+        for (position, vertex) in touched.iter().enumerate() {
+            let vertex = *vertex as usize;
+            cache_positions[vertex] = (position < CACHE_SIZE).then_some(position);
+            vertex_scores[vertex] = vertex_score(cache_positions[vertex], live_triangles[vertex]);
+        }
+
+        // Rescore the live triangles of every vertex whose score changed, picking the best of them
+        best = None;
+        let mut best_score = f32::NEG_INFINITY;
+        for vertex in &touched {
+            let vertex = *vertex as usize;
+            for other in &adjacency[adjacency_offsets[vertex]..][..live_triangles[vertex] as usize] {
+                let score = triangle_score(&vertex_scores, *other);
+                if score > best_score {
+                    best = Some(*other);
+                    best_score = score;
+                }
+            }
+        }
+
+        // Nothing in the cache has triangles left, so continue with the next unemitted one
+        if best.is_none() {
+            while next_unemitted < triangle_count && emitted[next_unemitted] {
+                next_unemitted += 1;
+            }
+            best = (next_unemitted < triangle_count).then_some(next_unemitted);
+        }
+    }
+
+    result
+}
+
+/// Reorder clusters of triangles so that those facing away from the mesh's center are drawn first,
+/// as they are the most likely to occlude the rest. `indices` should already be in vertex cache
+/// order: clusters are split where that order restarts and, within that, wherever their cache miss
+/// ratio is within `threshold` of the whole run's, so the cache efficiency is mostly kept.
+pub fn optimize_overdraw(indices: &[u32], positions: &[Vec3], threshold: f32) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+    if triangle_count < 2 {
+        return indices.to_vec();
+    }
+
+    // Hard boundaries: triangles whose vertices all miss, where the cache order started afresh
+    let mut cache = FifoCache::new(CLUSTER_CACHE_SIZE);
+    let mut hard_boundaries: Vec<usize> = indices.chunks_exact(3)
+        .enumerate()
+        .filter(|(t, triangle)| cache.triangle(triangle) == 3 || *t == 0)
+        .map(|(t, _)| t)
+        .collect();
+    hard_boundaries.push(triangle_count);
+
+    // Soft boundaries: split each run as soon as the part so far is as cache efficient as the run
+    let mut clusters = vec![0];
+    for run in hard_boundaries.windows(2) {
+        let (start, end) = (run[0], run[1]);
+        let run_misses = cache_misses(&indices[start * 3..end * 3], CLUSTER_CACHE_SIZE);
+        let cluster_threshold = threshold * run_misses as f32 / (end - start) as f32;
+
+        cache.clear();
+        let mut cluster_start = start;
+        let mut misses = 0;
+        for t in start..end {
+            misses += cache.triangle(&indices[t * 3..t * 3 + 3]);
+            if t + 1 < end && misses as f32 / (t + 1 - cluster_start) as f32 <= cluster_threshold {
+                clusters.push(t + 1);
+                cluster_start = t + 1;
+                misses = 0;
+                cache.clear();
+            }
+        }
+        clusters.push(end);
+    }
+    clusters.dedup();
+
+    // Area-weighted centroid and normal of each cluster and the whole mesh
+    let triangle_geometry = |t: usize| {
+        let [a, b, c] = [0, 1, 2].map(|corner| positions[indices[t * 3 + corner] as usize]);
+        let normal = (b - a).cross(c - a);
+        (normal, (a + b + c) / 3.0, normal.length())
+    };
+    let mut mesh_centroid = Vec3::ZERO;
+    let mut mesh_area = 0.0;
+    let cluster_geometry: Vec<(Vec3, Vec3)> = clusters.windows(2)
+        .map(|cluster| {
+            let (mut normal, mut centroid, mut area) = (Vec3::ZERO, Vec3::ZERO, 0.0);
+            for t in cluster[0]..cluster[1] {
+                let (triangle_normal, triangle_centroid, triangle_area) = triangle_geometry(t);
+                normal += triangle_normal;
+                centroid += triangle_centroid * triangle_area;
+                area += triangle_area;
+            }
+            mesh_centroid += centroid;
+            mesh_area += area;
+            (normal.normalize_or_zero(), if area > 0.0 { centroid / area } else { centroid })
+        })
+        .collect();
+    if mesh_area > 0.0 {
+        mesh_centroid /= mesh_area;
+    }
+
+    let mut order: Vec<usize> = (0..cluster_geometry.len()).collect();
+    let sort_key = |cluster: usize| {
+        let (normal, centroid) = cluster_geometry[cluster];
+        (centroid - mesh_centroid).dot(normal)
+    };
+    order.sort_by(|a, b| sort_key(*b).total_cmp(&sort_key(*a)));
+
+    order.iter()
+        .flat_map(|cluster| &indices[clusters[*cluster] * 3..clusters[*cluster + 1] * 3])
+        .copied()
+        .collect()
+}
+
+/// The new index of each vertex when vertices are ordered by first use in `indices`, so that the
+/// vertex fetch walks memory forward. Unreferenced vertices go last, in their original order.
+pub fn vertex_fetch_remap(indices: &[u32], vertex_count: usize) -> Vec<u32> {
+    const UNASSIGNED: u32 = u32::MAX;
+
+    let mut remap = vec![UNASSIGNED; vertex_count];
+    let mut next = 0;
+    for index in indices {
+        if remap[*index as usize] == UNASSIGNED {
+            remap[*index as usize] = next;
+            next += 1;
+        }
+    }
+    for new_index in &mut remap {
+        if *new_index == UNASSIGNED {
+            *new_index = next;
+            next += 1;
+        }
+    }
+
+    remap
+}
+
+/// Move each vertex of a stream to its new index. Empty streams are left as they are.
+pub fn remap_stream<T: Copy + Default>(stream: &mut Vec<T>, remap: &[u32]) {
+    if stream.len() != remap.len() {
+        return;
+    }
+
+    let mut remapped = vec![T::default(); stream.len()];
+    for (value, new_index) in stream.iter().zip(remap) {
+        remapped[*new_index as usize] = *value;
+    }
+    *stream = remapped;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model_format::test_grid;
+
+    /// The triangles of `indices`, each rotated to start at its smallest index, in sorted order
+    fn sorted_triangles(indices: &[u32]) -> Vec<[u32; 3]> {
+        let mut triangles: Vec<[u32; 3]> = indices.chunks_exact(3)
+            .map(|t| {
+                let first = (0..3).min_by_key(|corner| t[*corner]).unwrap();
+                [t[first], t[(first + 1) % 3], t[(first + 2) % 3]]
+            })
+            .collect();
+        triangles.sort();
+        triangles
+    }
+
+    /// A grid with its triangles scattered, as some exporters leave them
+    fn scattered_grid() -> (Vec<Vec3>, Vec<u32>) {
+        let (positions, indices) = test_grid(32, |x, y| (x * 0.3).sin() + (y * 0.2).cos());
+        let triangle_count = indices.len() / 3;
+        // 701 is coprime to the triangle count, so this visits every triangle once
+        let scattered = (0..triangle_count)
+            .flat_map(|t| {
+                let t = t * 701 % triangle_count;
+                [indices[t * 3], indices[t * 3 + 1], indices[t * 3 + 2]]
+            })
+            .collect();
+        (positions, scattered)
+    }
+
+    fn acmr(indices: &[u32]) -> f32 {
+        cache_misses(indices, CACHE_SIZE) as f32 / (indices.len() / 3) as f32
+    }
+
+    #[test]
+    fn test_optimize_vertex_cache() {
+        let (positions, scattered) = scattered_grid();
+        let optimized = optimize_vertex_cache(&scattered, positions.len());
+        assert_eq!(sorted_triangles(&optimized), sorted_triangles(&scattered));
+        assert_eq!(acmr(&scattered), 3.0);
+        assert!(acmr(&optimized) < 0.7, "ACMR {}", acmr(&optimized));
+
+        // Rows of a grid are already fairly cache friendly
+        let (_, rows) = test_grid(32, |_, _| 0.0);
+        assert!(acmr(&optimize_vertex_cache(&rows, positions.len())) <= acmr(&rows));
+    }
+
+    #[test]
+    fn test_optimize_overdraw() {
+        let (positions, scattered) = scattered_grid();
+        let (_, rows) = test_grid(32, |_, _| 0.0);
+
+        for indices in [scattered, rows] {
+            let optimized = optimize_overdraw(&optimize_vertex_cache(&indices, positions.len()), &positions, OVERDRAW_THRESHOLD);
+            assert_eq!(sorted_triangles(&optimized), sorted_triangles(&indices));
+            assert!(acmr(&optimized) <= acmr(&indices), "ACMR {} -> {}", acmr(&indices), acmr(&optimized));
+        }
+
+        assert_eq!(optimize_overdraw(&[0, 1, 2], &positions, OVERDRAW_THRESHOLD), [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_vertex_fetch_remap() {
+        // Vertices 1 and 4 are unreferenced
+        let remap = vertex_fetch_remap(&[2, 0, 3, 3, 0, 2], 5);
+        assert_eq!(remap, [1, 3, 0, 2, 4]);
+
+        let mut stream = vec!['a', 'b', 'c', 'd', 'e'];
+        remap_stream(&mut stream, &remap);
+        assert_eq!(stream, ['c', 'a', 'd', 'b', 'e']);
+
+        let mut empty: Vec<char> = Vec::new();
+        remap_stream(&mut empty, &remap);
+        assert!(empty.is_empty());
+    }
+}
@@ -0,0 +1,288 @@
+// Mesh simplification for the model pipeline's levels of detail. Edges are collapsed in order of
+// their quadric error (Garland and Heckbert, "Surface Simplification Using Quadric Error
+// Metrics"), always onto one of their existing vertices so that every level shares the mesh's
+// vertex buffer.
+
+use glam::{DVec3, Vec3};
+use std::collections::HashMap;
+
+/// A simplified index list
+pub struct Simplified {
+    pub indices: Vec<u32>,
+    /// Largest distance of the simplified surface from the original, in the positions' units
+    pub error: f32,
+}
+
+/// Sum of squared distances to a set of planes, weighted by the area of the triangles they came
+/// from. Stored as the upper triangle of the symmetric 4x4 matrix.
+#[derive(Clone, Copy, Default)]
+struct Quadric {
+    xx: f64,
+    xy: f64,
+    xz: f64,
+    xw: f64,
+    yy: f64,
+    yz: f64,
+    yw: f64,
+    zz: f64,
+    zw: f64,
+    ww: f64,
+    weight: f64,
+}
+
+impl Quadric {
+    fn from_plane(normal: DVec3, distance: f64, weight: f64) -> Self {
+        let DVec3 { x, y, z } = normal;
+        let w = distance;
+        Self {
+            xx: x * x * weight,
+            xy: x * y * weight,
+            xz: x * z * weight,
+            xw: x * w * weight,
+            yy: y * y * weight,
+            yz: y * z * weight,
+            yw: y * w * weight,
+            zz: z * z * weight,
+            zw: z * w * weight,
+            ww: w * w * weight,
+            weight,
+        }
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        self.xx += other.xx;
+        self.xy += other.xy;
+        self.xz += other.xz;
+        self.xw += other.xw;
+        self.yy += other.yy;
+        self.yz += other.yz;
+        self.yw += other.yw;
+        self.zz += other.zz;
+        self.zw += other.zw;
+        self.ww += other.ww;
+        self.weight += other.weight;
+    }
+
+    /// Weighted mean squared distance of `point` from the planes
+    fn error(&self, point: Vec3) -> f64 {
+        if self.weight == 0.0 {
+            return 0.0;
+        }
+
+        let DVec3 { x, y, z } = point.as_dvec3();
+        let error = x * x * self.xx + y * y * self.yy + z * z * self.zz + self.ww
+            + 2.0 * (x * y * self.xy + x * z * self.xz + y * z * self.yz + x * self.xw + y * self.yw + z * self.zw);
+        error.max(0.0) / self.weight
+    }
+}
+
+/// Simplify `indices` until at most `target_index_count` remain, or until any further collapse
+/// would move the surface by more than `max_error`.
+///
+/// Vertices on open borders, and vertices whose position is shared with another vertex (UV or
+/// normal seams), are never moved, so the simplified mesh keeps its outline and attribute seams.
+pub fn simplify(indices: &[u32], positions: &[Vec3], target_index_count: usize, max_error: f32) -> Simplified {
+    let vertex_count = positions.len();
+    let mut triangles: Vec<[u32; 3]> = indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect();
+
+    // Vertices sharing a position with another referenced vertex are on a seam
+    let mut canonical = vec![u32::MAX; vertex_count];
+    let mut seam = vec![false; vertex_count];
+    let mut by_position: HashMap<[u32; 3], u32> = HashMap::new();
+    for &index in indices {
+        if canonical[index as usize] != u32::MAX {
+            continue;
+        }
+        let first = *by_position.entry(positions[index as usize].to_array().map(f32::to_bits)).or_insert(index);
+        canonical[index as usize] = first;
+        if first != index {
+            seam[index as usize] = true;
+            seam[first as usize] = true;
+        }
+    }
+
+    // Edges used by only one triangle, compared by position so seams don't count, are on a border
+    let mut edge_uses: HashMap<(u32, u32), u32> = HashMap::new();
+    for triangle in &triangles {
+        for corner in 0..3 {
+            let (a, b) = (canonical[triangle[corner] as usize], canonical[triangle[(corner + 1) % 3] as usize]);
+            *edge_uses.entry((a.min(b), a.max(b))).or_default() += 1;
+        }
+    }
+    let mut locked = seam.clone();
+    for triangle in &triangles {
+        for corner in 0..3 {
+            let (a, b) = (triangle[corner], triangle[(corner + 1) % 3]);
+            let (ca, cb) = (canonical[a as usize], canonical[b as usize]);
+            if edge_uses[&(ca.min(cb), ca.max(cb))] == 1 {
+                locked[a as usize] = true;
+                locked[b as usize] = true;
+            }
+        }
+    }
+
+    let mut quadrics = vec![Quadric::default(); vertex_count];
+    for triangle in &triangles {
+        let [a, b, c] = triangle.map(|index| positions[index as usize].as_dvec3());
+        let cross = (b - a).cross(c - a);
+        let area = cross.length() * 0.5;
+        if area == 0.0 {
+            continue;
+        }
+        let normal = cross.normalize();
+        let quadric = Quadric::from_plane(normal, -normal.dot(a), area);
+        for index in triangle {
+            quadrics[*index as usize].add(&quadric);
+        }
+    }
+
+    let max_error_squared = (max_error as f64) * (max_error as f64);
+    let mut error_squared: f64 = 0.0;
+
+    // Each pass collapses the cheapest edges whose neighbourhoods don't overlap, so that the
+    // collapses are independent, then rebuilds the triangle list
+    while triangles.len() * 3 > target_index_count {
+        let mut vertex_triangles: Vec<Vec<usize>> = vec![Vec::new(); vertex_count];
+        for (t, triangle) in triangles.iter().enumerate() {
+            for index in triangle {
+                vertex_triangles[*index as usize].push(t);
+            }
+        }
+
+        // Collapsing `from` onto `to` removes `from`; a seam vertex can't be the target either,
+        // as the triangles of `from` would have to pick one of its attribute sets
+        let mut collapses: Vec<(f64, u32, u32)> = Vec::new();
+        for triangle in &triangles {
+            for corner in 0..3 {
+                let (a, b) = (triangle[corner], triangle[(corner + 1) % 3]);
+                for (from, to) in [(a, b), (b, a)] {
+                    if !locked[from as usize] && !seam[to as usize] {
+                        let mut quadric = quadrics[from as usize];
+                        quadric.add(&quadrics[to as usize]);
+                        collapses.push((quadric.error(positions[to as usize]), from, to));
+                    }
+                }
+            }
+        }
+        collapses.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut remap: Vec<u32> = (0..vertex_count as u32).collect();
+        let mut touched = vec![false; vertex_count];
+        let mut removed = 0;
+        let excess = triangles.len() - target_index_count / 3;
+        for (cost, from, to) in collapses {
+            if cost > max_error_squared || removed >= excess {
+                break;
+            }
+            if touched[from as usize] || touched[to as usize] || flips(&triangles, &vertex_triangles[from as usize], from, to, positions) {
+                continue;
+            }
+
+            remap[from as usize] = to;
+            let quadric = quadrics[from as usize];
+            quadrics[to as usize].add(&quadric);
+            error_squared = error_squared.max(cost);
+
+            for t in &vertex_triangles[from as usize] {
+                let triangle = triangles[*t];
+                for index in triangle {
+                    touched[index as usize] = true;
+                }
+                if triangle.contains(&to) {
+                    removed += 1;
+                }
+            }
+        }
+
+        if removed == 0 {
+            break;
+        }
+
+        triangles = triangles.iter()
+            .map(|triangle| triangle.map(|index| remap[index as usize]))
+            .filter(|[a, b, c]| a != b && b != c && c != a)
+            .collect();
+    }
+
+    Simplified {
+        indices: triangles.into_iter().flatten().collect(),
+        error: error_squared.sqrt() as f32,
+    }
+}
+
+/// Whether moving `from` onto `to` would turn any of `from`'s remaining triangles over, or
+/// close to it
+fn flips(triangles: &[[u32; 3]], from_triangles: &[usize], from: u32, to: u32, positions: &[Vec3]) -> bool {
+    from_triangles.iter().any(|t| {
+        let triangle = triangles[*t];
+        if triangle.contains(&to) {
+            return false;
+        }
+
+        let normal = |triangle: [u32; 3]| {
+            let [a, b, c] = triangle.map(|index| positions[index as usize]);
+            (b - a).cross(c - a)
+        };
+        let before = normal(triangle);
+        let after = normal(triangle.map(|index| if index == from { to } else { index }));
+        // Also rejects collapses that rotate a triangle by more than ~75 degrees, so that no triangle
+        // turns over across a few passes either
+        before.dot(after) <= 0.25 * before.length() * after.length()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model_format::test_grid;
+
+    /// Triangles of a mesh facing +z that are degenerate or have turned over. Triangles between
+    /// border vertices of a curved grid may stand vertical, as the border is kept.
+    fn turned_over(indices: &[u32], positions: &[Vec3]) -> usize {
+        indices.chunks_exact(3)
+            .filter(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|corner| positions[triangle[corner] as usize]);
+                let normal = (b - a).cross(c - a);
+                normal == Vec3::ZERO || normal.z < 0.0
+            })
+            .count()
+    }
+
+    #[test]
+    fn test_simplify_to_ratio() {
+        // Interior vertices of a plane collapse at no cost, while its border is kept
+        let (positions, indices) = test_grid(16, |_, _| 0.0);
+        let target = indices.len() / 4 / 3 * 3;
+        let simplified = simplify(&indices, &positions, target, 0.01);
+
+        assert!(simplified.indices.len() <= target, "{} indices for a target of {}", simplified.indices.len(), target);
+        assert!(simplified.error < 1e-4);
+        assert_eq!(turned_over(&simplified.indices, &positions), 0);
+
+        let on_border = |index: &u32| {
+            let Vec3 { x, y, .. } = positions[*index as usize];
+            x == 0.0 || y == 0.0 || x == 16.0 || y == 16.0
+        };
+        let mut border: Vec<u32> = simplified.indices.iter().copied().filter(on_border).collect();
+        border.sort();
+        border.dedup();
+        assert_eq!(border.len(), 16 * 4);
+    }
+
+    #[test]
+    fn test_simplify_error_limit() {
+        let (positions, indices) = test_grid(16, |x, y| (x * 0.7).sin() * (y * 0.5).cos());
+
+        // Without a target, the error limit alone decides how far the mesh is simplified
+        let mut previous_count = indices.len();
+        for max_error in [0.0, 0.05, 0.1, 0.5] {
+            let simplified = simplify(&indices, &positions, 0, max_error);
+            assert!(simplified.error <= max_error, "error {} over the limit of {}", simplified.error, max_error);
+            assert!(simplified.indices.len() <= previous_count);
+            assert_eq!(turned_over(&simplified.indices, &positions), 0);
+            previous_count = simplified.indices.len();
+        }
+        assert_eq!(simplify(&indices, &positions, 0, 0.0).indices.len(), indices.len());
+        assert!(previous_count < indices.len() / 4);
+    }
+}
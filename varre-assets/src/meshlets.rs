@@ -0,0 +1,286 @@
+// Meshlet generation for the model pipeline. Splits an indexed triangle list into clusters with
+// bounded vertex and triangle counts and computes per-meshlet culling bounds.
+
+use crate::model_format::Meshlet;
+use glam::Vec3;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+/// Vertex and triangle limits, matching `MAX_VERTICES` and `MAX_PRIMITIVES` in meshlet.slang
+pub const MAX_VERTICES: usize = 64;
+pub const MAX_TRIANGLES: usize = 124;
+
+#[derive(Default)]
+pub struct Meshlets {
+    pub meshlets: Vec<Meshlet>,
+    /// Mesh vertex indices referenced by each meshlet's local vertices
+    pub vertices: Vec<u32>,
+    /// Three meshlet-local vertex indices per triangle
+    pub triangles: Vec<u8>,
+}
+
+/// Build meshlets over `indices`. Triangles are added to the current meshlet preferring those
+/// that share the most vertices with it, so meshlets stay spatially compact and reuse vertices;
+/// when no neighbouring triangle is left, the closest remaining triangle starts a new region.
+pub fn build_meshlets(positions: &[[f32; 3]], indices: &[u32], max_vertices: usize, max_triangles: usize) -> Meshlets {
+    assert!((3..=256).contains(&max_vertices), "meshlet vertex limit must fit 8-bit local indices");
+    assert!(max_triangles > 0, "meshlet triangle limit must be non-zero");
+
+    let triangle_count = indices.len() / 3;
+    let triangle = |t: usize| [indices[t * 3], indices[t * 3 + 1], indices[t * 3 + 2]];
+    let centroid = |t: usize| {
+        let [a, b, c] = triangle(t).map(|i| positions[i as usize]);
+        [0, 1, 2].map(|axis| (a[axis] + b[axis] + c[axis]) / 3.0)
+    };
+
+    // Triangles using each vertex
+    let mut adjacency: HashMap<u32, Vec<usize>> = HashMap::new();
+    for t in 0..triangle_count {
+        for index in triangle(t) {
+            adjacency.entry(index).or_default().push(t);
+        }
+    }
+
+    let mut result = Meshlets::default();
+    let mut emitted = vec![false; triangle_count];
+    let mut remaining = triangle_count;
+    let mut next_unused = 0;
+
+    // Current meshlet: global -> local vertex index, and its triangles
+    let mut local: HashMap<u32, u8> = HashMap::new();
+    let mut local_vertices: Vec<u32> = Vec::new();
+    let mut local_triangles: Vec<usize> = Vec::new();
+    let mut center_sum = [0.0f32; 3];
+    // Triangle that didn't fit the previous meshlet, used to start the next one next to it
+    let mut seed = None;
+
+    while remaining > 0 {
+        // Neighbours of the current meshlet, scored by how many new vertices they'd add
+        let candidate = local_vertices.iter()
+            .flat_map(|v| &adjacency[v])
+            .filter(|t| !emitted[**t])
+            .map(|t| (triangle(*t).iter().filter(|i| !local.contains_key(i)).count(), *t))
+            .min();
+
+        let (new_vertices, t) = match candidate {
+            Some(candidate) => candidate,
+            None => {
+                while emitted[next_unused] {
+                    next_unused += 1;
+                }
+                let t = if local_triangles.is_empty() {
+                    seed.unwrap_or(next_unused)
+                } else {
+                    // Jump to the closest unconnected triangle to keep the meshlet compact
+                    let count = local_triangles.len() as f32;
+                    let center = center_sum.map(|c| c / count);
+                    (next_unused..triangle_count)
+                        .filter(|t| !emitted[*t])
+                        .min_by(|a, b| distance_squared(centroid(*a), center).total_cmp(&distance_squared(centroid(*b), center)))
+                        .unwrap()
+                };
+                (triangle(t).iter().filter(|i| !local.contains_key(i)).count(), t)
+            }
+        };
+
+        if local_vertices.len() + new_vertices > max_vertices || local_triangles.len() == max_triangles {
+            flush_meshlet(&mut result, positions, indices, &mut local, &mut local_vertices, &mut local_triangles);
+            center_sum = [0.0; 3];
+            seed = Some(t);
+            continue;
+        }
+
+        for index in triangle(t) {
+            if let Entry::Vacant(entry) = local.entry(index) {
+                entry.insert(local_vertices.len() as u8);
+                local_vertices.push(index);
+            }
+        }
+        let c = centroid(t);
+        for axis in 0..3 {
+            center_sum[axis] += c[axis];
+        }
+        local_triangles.push(t);
+        emitted[t] = true;
+        remaining -= 1;
+    }
+
+    if !local_triangles.is_empty() {
+        flush_meshlet(&mut result, positions, indices, &mut local, &mut local_vertices, &mut local_triangles);
+    }
+
+    result
+}
+
+fn flush_meshlet(
+    result: &mut Meshlets,
+    positions: &[[f32; 3]],
+    indices: &[u32],
+    local: &mut HashMap<u32, u8>,
+    local_vertices: &mut Vec<u32>,
+    local_triangles: &mut Vec<usize>,
+) {
+    let points: Vec<[f32; 3]> = local_vertices.iter().map(|v| positions[*v as usize]).collect();
+    let (center, radius) = bounding_sphere(&points);
+
+    let corners: Vec<[[f32; 3]; 3]> = local_triangles.iter()
+        .map(|t| [0, 1, 2].map(|corner| positions[indices[t * 3 + corner] as usize]))
+        .collect();
+    let (cone_apex, cone_axis, cone_cutoff) = normal_cone(&corners, center);
+
+    let meshlet = Meshlet {
+        vertex_offset: result.vertices.len() as u32,
+        vertex_count: local_vertices.len() as u32,
+        triangle_offset: (result.triangles.len() / 3) as u32,
+        triangle_count: local_triangles.len() as u32,
+        center: Vec3::from(center),
+        radius,
+        cone_apex: Vec3::from(cone_apex),
+        cone_axis: Vec3::from(cone_axis),
+        cone_cutoff,
+    };
+
+    result.vertices.append(local_vertices);
+    for t in local_triangles.drain(..) {
+        result.triangles.extend((0..3).map(|corner| local[&indices[t * 3 + corner]]));
+    }
+    local.clear();
+
+    result.meshlets.push(meshlet);
+}
+
+/// Ritter's bounding sphere: start from the two most distant points along the spread of the set,
+/// then grow to enclose every point
+fn bounding_sphere(points: &[[f32; 3]]) -> ([f32; 3], f32) {
+    let farthest = |from: [f32; 3]| *points.iter()
+        .max_by(|a, b| distance_squared(**a, from).total_cmp(&distance_squared(**b, from)))
+        .unwrap();
+
+    let a = farthest(points[0]);
+    let b = farthest(a);
+    let mut center = [0, 1, 2].map(|axis| (a[axis] + b[axis]) * 0.5);
+    let mut radius = distance_squared(a, b).sqrt() * 0.5;
+
+    for point in points {
+        let distance = distance_squared(*point, center).sqrt();
+        if distance > radius {
+            let new_radius = (radius + distance) * 0.5;
+            let k = (new_radius - radius) / distance;
+            center = [0, 1, 2].map(|axis| center[axis] + (point[axis] - center[axis]) * k);
+            radius = new_radius;
+        }
+    }
+
+    (center, radius)
+}
+
+/// Cone containing every triangle normal, for backface culling whole meshlets. A meshlet can be
+/// culled when `dot(normalize(cone_apex - camera_position), cone_axis) >= cone_cutoff`. Meshlets
+/// whose normals span a half-space or more get a cutoff of 1, which never culls.
+fn normal_cone(triangles: &[[[f32; 3]; 3]], center: [f32; 3]) -> ([f32; 3], [f32; 3], f32) {
+    let normals: Vec<Option<[f32; 3]>> = triangles.iter()
+        .map(|[a, b, c]| normalize(cross(sub(*b, *a), sub(*c, *a))))
+        .collect();
+
+    let sum = normals.iter().flatten().fold([0.0; 3], |sum, n| [sum[0] + n[0], sum[1] + n[1], sum[2] + n[2]]);
+    let Some(axis) = normalize(sum) else {
+        return (center, [0.0; 3], 1.0);
+    };
+
+    let min_dot = normals.iter().flatten().map(|n| dot(*n, axis)).fold(1.0f32, f32::min);
+    if min_dot <= 0.0 {
+        return (center, axis, 1.0);
+    }
+
+    // Move the apex back along the axis until every triangle's plane is in front of it
+    let max_t = triangles.iter().zip(&normals)
+        .filter_map(|(corners, normal)| normal.map(|n| dot(sub(center, corners[0]), n) / dot(axis, n)))
+        .fold(0.0f32, f32::max);
+
+    let apex = [0, 1, 2].map(|i| center[i] - axis[i] * max_t);
+    let cutoff = (1.0 - min_dot * min_dot).sqrt();
+
+    (apex, axis, cutoff)
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn normalize(v: [f32; 3]) -> Option<[f32; 3]> {
+    let length = dot(v, v).sqrt();
+    (length > 1e-12).then(|| v.map(|c| c / length))
+}
+
+fn distance_squared(a: [f32; 3], b: [f32; 3]) -> f32 {
+    let d = sub(a, b);
+    dot(d, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model_format::test_grid;
+
+    #[test]
+    fn test_build_meshlets() {
+        let (positions, indices) = test_grid(24, |x, y| (x * 0.3).sin() * (y * 0.4).cos());
+        let positions: Vec<[f32; 3]> = positions.iter().map(|position| position.to_array()).collect();
+        let result = build_meshlets(&positions, &indices, MAX_VERTICES, MAX_TRIANGLES);
+        assert!(result.meshlets.len() > 1);
+
+        let mut covered = Vec::new();
+        for meshlet in &result.meshlets {
+            assert!((1..=MAX_VERTICES).contains(&(meshlet.vertex_count as usize)));
+            assert!((1..=MAX_TRIANGLES).contains(&(meshlet.triangle_count as usize)));
+
+            let vertices = &result.vertices[meshlet.vertex_offset as usize..][..meshlet.vertex_count as usize];
+            let triangles = &result.triangles[meshlet.triangle_offset as usize * 3..][..meshlet.triangle_count as usize * 3];
+            for triangle in triangles.chunks_exact(3) {
+                covered.push(triangle.iter().map(|local| vertices[*local as usize]).collect::<Vec<u32>>());
+            }
+
+            for vertex in vertices {
+                let distance = distance_squared(positions[*vertex as usize], meshlet.center.to_array()).sqrt();
+                assert!(distance <= meshlet.radius * 1.0001, "vertex {} is outside its meshlet's bounds", vertex);
+            }
+        }
+
+        // Every triangle is in exactly one meshlet, with its winding
+        let mut triangles: Vec<Vec<u32>> = indices.chunks_exact(3).map(|triangle| triangle.to_vec()).collect();
+        triangles.sort();
+        covered.sort();
+        assert_eq!(covered, triangles);
+        assert_eq!(result.vertices.len(), result.meshlets.iter().map(|meshlet| meshlet.vertex_count as usize).sum::<usize>());
+
+        // Tight limits give a meshlet per triangle
+        let single = build_meshlets(&positions, &indices, 3, 1);
+        assert_eq!(single.meshlets.len(), indices.len() / 3);
+    }
+
+    #[test]
+    fn test_normal_cone() {
+        let (positions, indices) = test_grid(4, |_, _| 0.0);
+        let positions: Vec<[f32; 3]> = positions.iter().map(|position| position.to_array()).collect();
+        let meshlet = build_meshlets(&positions, &indices, MAX_VERTICES, MAX_TRIANGLES).meshlets[0];
+        assert_eq!(meshlet.cone_axis, Vec3::Z);
+
+        // The plane faces +z, so it's culled from below and drawn from above
+        let culled = |camera: Vec3| (meshlet.cone_apex - camera).normalize().dot(meshlet.cone_axis) >= meshlet.cone_cutoff;
+        assert!(culled(Vec3::new(2.0, 2.0, -5.0)));
+        assert!(!culled(Vec3::new(2.0, 2.0, 5.0)));
+        assert!(!culled(Vec3::new(20.0, 2.0, 0.5)));
+
+        // Normals spanning more than a half-space never cull
+        let folded = [[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]], [[0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]]];
+        assert_eq!(normal_cone(&folded, [0.0; 3]).2, 1.0);
+    }
+}
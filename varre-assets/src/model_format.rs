@@ -0,0 +1,1544 @@
+// Binary model format, shared by the build script (which writes it) and `Model::decode` (which
+// reads it).
+//
+// All values are little-endian. A file is laid out as:
+//
+//   header       magic "VRMD", version: u32, chunk_count: u32, reserved: u32
+//   chunk table  chunk_count * ChunkHeader (24 bytes each)
+//   chunk data   one block per chunk, each starting on a 16-byte boundary
+//
+// Vertex streams are stored as separate chunks, tagged with their semantic, set index and element
+// format, so new attributes can be added without changing the layout of the others.
+//
+// A model holds every mesh of its source file as one submesh each, sharing the vertex streams and
+// index buffer. The source's node hierarchy is kept in the nodes chunk, with each node listing the
+// submeshes it instances in the node meshes chunk and naming itself in the names chunk. Cameras,
+// lights, skin joints and animation channels are attached to nodes by index.
+//
+// The model as a whole, in the bounds and bounding sphere chunks, and each submesh have an
+// axis-aligned bounding box and a bounding sphere.
+//
+// Simplified levels of detail share the vertex streams. Each level has its own copy of the
+// submeshes in the LOD submeshes chunk, indexing the LOD indices chunk.
+//
+// Models imported with a sidecar settings file record the settings they were built with, as TOML
+// text, in the import settings chunk.
+
+use glam::{Vec2, Vec3, Vec4};
+use std::borrow::Cow;
+use std::fmt;
+
+pub const MAGIC: [u8; 4] = *b"VRMD";
+pub const VERSION: u32 = 2;
+
+const HEADER_SIZE: usize = 16;
+const CHUNK_HEADER_SIZE: usize = 24;
+const CHUNK_ALIGNMENT: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModelError {
+    BadMagic,
+    UnsupportedVersion(u32),
+    Truncated { needed: usize, available: usize },
+    InvalidChunk(String),
+    MissingChunk(&'static str),
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelError::BadMagic => write!(f, "not a model file (bad magic)"),
+            ModelError::UnsupportedVersion(version) => {
+                write!(f, "unsupported model format version {} (expected {})", version, VERSION)
+            }
+            ModelError::Truncated { needed, available } => {
+                write!(f, "model data is truncated: needed {} bytes, got {}", needed, available)
+            }
+            ModelError::InvalidChunk(message) => write!(f, "invalid chunk: {}", message),
+            ModelError::MissingChunk(name) => write!(f, "model has no {} chunk", name),
+        }
+    }
+}
+
+impl std::error::Error for ModelError {}
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkKind {
+    VertexStream = 1,
+    Indices = 2,
+    Submeshes = 3,
+    Bounds = 4,
+    Meshlets = 5,
+    MeshletVertices = 6,
+    MeshletTriangles = 7,
+    Nodes = 8,
+    NodeMeshes = 9,
+    Names = 10,
+    Cameras = 11,
+    Lights = 12,
+    SkinJoints = 13,
+    Animations = 14,
+    AnimationChannels = 15,
+    TranslationKeys = 16,
+    RotationKeys = 17,
+    ScaleKeys = 18,
+    Lods = 19,
+    LodSubmeshes = 20,
+    LodIndices = 21,
+    ImportSettings = 22,
+    BoundingSphere = 23,
+}
+
+impl ChunkKind {
+    fn from_raw(value: u32) -> Option<Self> {
+        Some(match value {
+            1 => ChunkKind::VertexStream,
+            2 => ChunkKind::Indices,
+            3 => ChunkKind::Submeshes,
+            4 => ChunkKind::Bounds,
+            5 => ChunkKind::Meshlets,
+            6 => ChunkKind::MeshletVertices,
+            7 => ChunkKind::MeshletTriangles,
+            8 => ChunkKind::Nodes,
+            9 => ChunkKind::NodeMeshes,
+            10 => ChunkKind::Names,
+            11 => ChunkKind::Cameras,
+            12 => ChunkKind::Lights,
+            13 => ChunkKind::SkinJoints,
+            14 => ChunkKind::Animations,
+            15 => ChunkKind::AnimationChannels,
+            16 => ChunkKind::TranslationKeys,
+            17 => ChunkKind::RotationKeys,
+            18 => ChunkKind::ScaleKeys,
+            19 => ChunkKind::Lods,
+            20 => ChunkKind::LodSubmeshes,
+            21 => ChunkKind::LodIndices,
+            22 => ChunkKind::ImportSettings,
+            23 => ChunkKind::BoundingSphere,
+            _ => return None,
+        })
+    }
+}
+
+/// What a vertex stream holds. Streams with the same semantic are distinguished by their set
+/// index, e.g. TEXCOORD0 and TEXCOORD1.
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StreamSemantic {
+    /// Not a vertex stream
+    None = 0,
+    Position = 1,
+    Normal = 2,
+    Tangent = 3,
+    TexCoord = 4,
+    Color = 5,
+    Joints = 6,
+    Weights = 7,
+}
+
+impl StreamSemantic {
+    fn from_raw(value: u16) -> Option<Self> {
+        Some(match value {
+            0 => StreamSemantic::None,
+            1 => StreamSemantic::Position,
+            2 => StreamSemantic::Normal,
+            3 => StreamSemantic::Tangent,
+            4 => StreamSemantic::TexCoord,
+            5 => StreamSemantic::Color,
+            6 => StreamSemantic::Joints,
+            7 => StreamSemantic::Weights,
+            _ => return None,
+        })
+    }
+}
+
+/// Element format of a chunk. Vertex stream formats mirror the VkFormat they are uploaded as.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ElementFormat {
+    /// Fixed-size records (submeshes, meshlets, bounds) whose layout is given by the chunk kind
+    Record = 0,
+    Uint8 = 1,
+    Uint16 = 2,
+    Uint32 = 3,
+    Float32x2 = 4,
+    Float32x3 = 5,
+    Float32x4 = 6,
+    Unorm8x4 = 7,
+    Uint8x4 = 8,
+    Uint16x4 = 9,
+    Unorm16x4 = 10,
+    /// Octahedral-encoded unit vectors
+    Snorm16x2 = 11,
+    Float16x2 = 12,
+}
+
+impl ElementFormat {
+    fn from_raw(value: u32) -> Option<Self> {
+        Some(match value {
+            0 => ElementFormat::Record,
+            1 => ElementFormat::Uint8,
+            2 => ElementFormat::Uint16,
+            3 => ElementFormat::Uint32,
+            4 => ElementFormat::Float32x2,
+            5 => ElementFormat::Float32x3,
+            6 => ElementFormat::Float32x4,
+            7 => ElementFormat::Unorm8x4,
+            8 => ElementFormat::Uint8x4,
+            9 => ElementFormat::Uint16x4,
+            10 => ElementFormat::Unorm16x4,
+            11 => ElementFormat::Snorm16x2,
+            12 => ElementFormat::Float16x2,
+            _ => return None,
+        })
+    }
+
+    /// Size of one element in bytes, or None for records
+    pub const fn size(&self) -> Option<usize> {
+        match self {
+            ElementFormat::Record => None,
+            ElementFormat::Uint8 => Some(1),
+            ElementFormat::Uint16 => Some(2),
+            ElementFormat::Uint32 | ElementFormat::Unorm8x4 | ElementFormat::Uint8x4
+                | ElementFormat::Snorm16x2 | ElementFormat::Float16x2 => Some(4),
+            ElementFormat::Float32x2 | ElementFormat::Uint16x4 | ElementFormat::Unorm16x4 => Some(8),
+            ElementFormat::Float32x3 => Some(12),
+            ElementFormat::Float32x4 => Some(16),
+        }
+    }
+}
+
+/// Axis-aligned bounding box
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Bounds of `points`, or a zero-sized box at the origin if there are none
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        let mut points = points.into_iter();
+        let Some(first) = points.next() else {
+            return Self { min: Vec3::ZERO, max: Vec3::ZERO };
+        };
+
+        points.fold(Self { min: first, max: first }, |aabb, point| Self {
+            min: aabb.min.min(point),
+            max: aabb.max.max(point),
+        })
+    }
+}
+
+/// Bounding sphere
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// A sphere enclosing `points`, the smaller of Ritter's approximation and the sphere centered on
+    /// their bounding box. A zero-sized sphere at the origin if there are none.
+    pub fn from_points(points: &[Vec3]) -> Self {
+        let Some(&first) = points.first() else {
+            return Self { center: Vec3::ZERO, radius: 0.0 };
+        };
+
+        // Start from the two points farthest apart along a rough diameter, then grow to take in
+        // every point outside
+        let farthest = |from: Vec3| {
+            points.iter().copied().max_by(|a, b| a.distance_squared(from).total_cmp(&b.distance_squared(from))).unwrap()
+        };
+        let a = farthest(first);
+        let b = farthest(a);
+        let mut center = (a + b) * 0.5;
+        let mut radius = a.distance(b) * 0.5;
+        for &point in points {
+            let distance = point.distance(center);
+            if distance > radius {
+                let grown = (radius + distance) * 0.5;
+                center += (point - center) * ((grown - radius) / distance);
+                radius = grown;
+            }
+        }
+        // Growing moves the center, which can leave earlier points outside by a rounding error
+        let ritter = Self { center, radius: points.iter().map(|point| point.distance(center)).fold(radius, f32::max) };
+
+        let bounds = Aabb::from_points(points.iter().copied());
+        let center = (bounds.min + bounds.max) * 0.5;
+        let boxed = Self { center, radius: points.iter().map(|point| point.distance(center)).fold(0.0, f32::max) };
+
+        if boxed.radius < ritter.radius { boxed } else { ritter }
+    }
+}
+
+/// Quantize a position to unorm16 within `bounds`, as stored in `Unorm16x4` position streams.
+/// The fourth component is padding.
+pub fn quantize_position(position: Vec3, bounds: &Aabb) -> [u16; 4] {
+    let extent = bounds.max - bounds.min;
+    let normalized = ((position - bounds.min) / extent.max(Vec3::splat(f32::MIN_POSITIVE))).clamp(Vec3::ZERO, Vec3::ONE);
+    let [x, y, z] = (normalized * u16::MAX as f32).round().to_array().map(|value| value as u16);
+    [x, y, z, 0]
+}
+
+pub fn dequantize_position(position: [u16; 4], bounds: &Aabb) -> Vec3 {
+    let normalized = Vec3::new(position[0] as f32, position[1] as f32, position[2] as f32) / u16::MAX as f32;
+    bounds.min + normalized * (bounds.max - bounds.min)
+}
+
+/// Octahedral encoding of a unit vector as snorm16, as stored in `Snorm16x2` normal streams.
+/// A zero vector encodes as +Z.
+pub fn encode_octahedral(vector: Vec3) -> [i16; 2] {
+    let length = vector.x.abs() + vector.y.abs() + vector.z.abs();
+    if length == 0.0 {
+        return [0, 0];
+    }
+
+    let vector = vector / length;
+    let mut encoded = Vec2::new(vector.x, vector.y);
+    if vector.z < 0.0 {
+        encoded = (Vec2::ONE - Vec2::new(encoded.y, encoded.x).abs()) * encoded.signum();
+    }
+    (encoded.clamp(Vec2::NEG_ONE, Vec2::ONE) * i16::MAX as f32).round().to_array().map(|value| value as i16)
+}
+
+pub fn decode_octahedral(encoded: [i16; 2]) -> Vec3 {
+    let encoded = Vec2::new(encoded[0] as f32, encoded[1] as f32) / i16::MAX as f32;
+    let encoded = encoded.max(Vec2::NEG_ONE);
+    let mut vector = Vec3::new(encoded.x, encoded.y, 1.0 - encoded.x.abs() - encoded.y.abs());
+    // Fold the lower hemisphere back out of the corners
+    let fold = (-vector.z).max(0.0);
+    vector.x -= fold.copysign(vector.x);
+    vector.y -= fold.copysign(vector.y);
+    vector.normalize()
+}
+
+/// Convert to IEEE half precision, rounding to nearest even. Values beyond the half range become
+/// infinity.
+pub fn f16_from_f32(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = (bits >> 16) & 0x8000;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        // Infinity, or NaN with a quiet bit so it stays NaN
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return (sign | 0x7c00 | nan) as u16;
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return (sign | 0x7c00) as u16;
+    }
+
+    // Normal halves drop 13 mantissa bits; subnormals also shift the implicit bit in
+    let (half, shift) = if exponent > 0 {
+        ((exponent as u32) << 10 | mantissa >> 13, 13)
+    } else if exponent >= -10 {
+        let shift = (14 - exponent) as u32;
+        ((mantissa | 0x80_0000) >> shift, shift)
+    } else {
+        return sign as u16;
+    };
+
+    let full = if exponent > 0 { mantissa } else { mantissa | 0x80_0000 };
+    let remainder = full & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    // A carry out of the mantissa correctly moves on to the next exponent, or to infinity
+    let rounded = if remainder > halfway || (remainder == halfway && half & 1 == 1) { half + 1 } else { half };
+    (sign | rounded) as u16
+}
+
+pub fn f32_from_f16(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = (half >> 10) & 0x1f;
+    let mantissa = (half & 0x3ff) as u32;
+
+    match exponent {
+        0 => sign * mantissa as f32 * 2f32.powi(-24),
+        0x1f => f32::from_bits(((half as u32 & 0x8000) << 16) | 0x7f80_0000 | mantissa << 13),
+        _ => f32::from_bits(((half as u32 & 0x8000) << 16) | (exponent as u32 + 112) << 23 | mantissa << 13),
+    }
+}
+
+/// A range of a model's indices drawn with one material
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Submesh {
+    pub index_offset: u32,
+    pub index_count: u32,
+    /// Range of vertices referenced by the submesh's indices
+    pub vertex_offset: u32,
+    pub vertex_count: u32,
+    pub material: u32,
+    pub bounds: Aabb,
+    pub bounding_sphere: BoundingSphere,
+}
+
+/// A cluster of at most 64 vertices and 124 triangles, with bounds for culling it as a whole
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Meshlet {
+    /// First entry of the model's `meshlet_vertices`
+    pub vertex_offset: u32,
+    pub vertex_count: u32,
+    /// First triangle of the model's `meshlet_triangles`, in triangles
+    pub triangle_offset: u32,
+    pub triangle_count: u32,
+    /// Bounding sphere
+    pub center: Vec3,
+    pub radius: f32,
+    /// Normal cone: the meshlet is back-facing when
+    /// `dot(normalize(cone_apex - camera_position), cone_axis) >= cone_cutoff`
+    pub cone_apex: Vec3,
+    pub cone_axis: Vec3,
+    pub cone_cutoff: f32,
+}
+
+/// A simplified level of detail of the whole model
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lod {
+    /// First entry of the LOD submeshes chunk. A level has one submesh per submesh of the model, in
+    /// the same order, and their indices are contiguous.
+    pub submesh_offset: u32,
+    pub submesh_count: u32,
+    /// Largest distance of the simplified surface from the full detail one, in model units
+    pub error: f32,
+}
+
+/// `Node::parent` of root nodes
+pub const NO_PARENT: u32 = u32::MAX;
+
+/// A node of the model's hierarchy. Parents always come before their children.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Node {
+    /// Index of the parent node, or `NO_PARENT`
+    pub parent: u32,
+    /// First entry of the model's node meshes, which are indices into its submeshes
+    pub mesh_offset: u32,
+    pub mesh_count: u32,
+    /// Byte range of the node's name in the names chunk
+    pub name_offset: u32,
+    pub name_length: u32,
+    /// Column-major transform relative to the parent
+    pub transform: [f32; 16],
+}
+
+/// A perspective camera, looking from `position` towards `look_at` in its node's space
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    pub node: u32,
+    /// Full horizontal field of view in radians
+    pub horizontal_fov: f32,
+    /// Width over height, or 0 if the source doesn't specify one
+    pub aspect: f32,
+    pub near: f32,
+    pub far: f32,
+    pub position: Vec3,
+    pub up: Vec3,
+    pub look_at: Vec3,
+}
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightKind {
+    Directional = 1,
+    Point = 2,
+    Spot = 3,
+    Ambient = 4,
+    Area = 5,
+}
+
+impl LightKind {
+    pub fn from_raw(value: u32) -> Option<Self> {
+        Some(match value {
+            1 => LightKind::Directional,
+            2 => LightKind::Point,
+            3 => LightKind::Spot,
+            4 => LightKind::Ambient,
+            5 => LightKind::Area,
+            _ => return None,
+        })
+    }
+}
+
+/// A light source. Position and direction are in its node's space.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Light {
+    pub node: u32,
+    /// A `LightKind`
+    pub kind: u32,
+    /// Linear color, premultiplied by intensity
+    pub color: Vec3,
+    pub position: Vec3,
+    pub direction: Vec3,
+    /// Constant, linear and quadratic attenuation factors
+    pub attenuation: [f32; 3],
+    /// Spot light cone angles in radians
+    pub inner_cone: f32,
+    pub outer_cone: f32,
+}
+
+impl Light {
+    pub fn kind(&self) -> Option<LightKind> {
+        LightKind::from_raw(self.kind)
+    }
+}
+
+/// A joint of the model's skin. The `JOINTS` vertex stream indexes the model's joint list.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Joint {
+    pub node: u32,
+    /// Column-major transform from mesh space to the joint's space in the bind pose
+    pub inverse_bind: [f32; 16],
+}
+
+/// An animation clip, driving nodes through its channels
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Animation {
+    /// Byte range of the animation's name in the names chunk
+    pub name_offset: u32,
+    pub name_length: u32,
+    /// In seconds
+    pub duration: f32,
+    /// First entry of the animation channels chunk
+    pub channel_offset: u32,
+    pub channel_count: u32,
+}
+
+/// Keyframes of one node's translation, rotation and scale, as ranges of the key chunks. A
+/// component without keys keeps the node's own value.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnimationChannel {
+    pub node: u32,
+    pub translation_offset: u32,
+    pub translation_count: u32,
+    pub rotation_offset: u32,
+    pub rotation_count: u32,
+    pub scale_offset: u32,
+    pub scale_count: u32,
+}
+
+/// A translation or scale keyframe, with `time` in seconds
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vec3Key {
+    pub time: f32,
+    pub value: Vec3,
+}
+
+/// A rotation keyframe, with `time` in seconds and the rotation as an xyzw quaternion
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuatKey {
+    pub time: f32,
+    pub value: [f32; 4],
+}
+
+const _: () = assert!(size_of::<Aabb>() == 24);
+const _: () = assert!(size_of::<BoundingSphere>() == 16);
+const _: () = assert!(size_of::<Submesh>() == 60);
+const _: () = assert!(size_of::<Meshlet>() == 60);
+const _: () = assert!(size_of::<Lod>() == 12);
+const _: () = assert!(size_of::<Node>() == 84);
+const _: () = assert!(size_of::<Camera>() == 56);
+const _: () = assert!(size_of::<Light>() == 64);
+const _: () = assert!(size_of::<Joint>() == 68);
+const _: () = assert!(size_of::<Animation>() == 20);
+const _: () = assert!(size_of::<AnimationChannel>() == 28);
+const _: () = assert!(size_of::<Vec3Key>() == 16);
+const _: () = assert!(size_of::<QuatKey>() == 20);
+
+/// Plain-old-data types that chunk contents can be viewed as.
+///
+/// # Safety
+/// Implementors must have no padding, no invalid bit patterns, and the same layout as their
+/// little-endian serialized form.
+pub unsafe trait Pod: Copy + 'static {}
+
+unsafe impl Pod for u8 {}
+unsafe impl Pod for u16 {}
+unsafe impl Pod for u32 {}
+unsafe impl Pod for f32 {}
+unsafe impl Pod for [u8; 4] {}
+unsafe impl Pod for [i16; 2] {}
+unsafe impl Pod for [u16; 2] {}
+unsafe impl Pod for [u16; 4] {}
+unsafe impl Pod for Vec2 {}
+unsafe impl Pod for Vec3 {}
+unsafe impl Pod for Vec4 {}
+unsafe impl Pod for Aabb {}
+unsafe impl Pod for BoundingSphere {}
+unsafe impl Pod for Submesh {}
+unsafe impl Pod for Meshlet {}
+unsafe impl Pod for Lod {}
+unsafe impl Pod for Node {}
+unsafe impl Pod for Camera {}
+unsafe impl Pod for Light {}
+unsafe impl Pod for Joint {}
+unsafe impl Pod for Animation {}
+unsafe impl Pod for AnimationChannel {}
+unsafe impl Pod for Vec3Key {}
+unsafe impl Pod for QuatKey {}
+
+fn as_bytes<T: Pod>(values: &[T]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(values.as_ptr() as *const u8, size_of_val(values)) }
+}
+
+/// View `bytes` as a slice of `T` without copying when it is suitably aligned, copying otherwise
+fn cast_slice<T: Pod>(bytes: &[u8]) -> Cow<'_, [T]> {
+    debug_assert_eq!(bytes.len() % size_of::<T>(), 0);
+    let count = bytes.len() / size_of::<T>();
+
+    if cfg!(target_endian = "little") && bytes.as_ptr() as usize % align_of::<T>() == 0 {
+        return Cow::Borrowed(unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const T, count) });
+    }
+
+    let mut values = Vec::<T>::with_capacity(count);
+    unsafe {
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), values.as_mut_ptr() as *mut u8, bytes.len());
+        values.set_len(count);
+    }
+    Cow::Owned(values)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkHeader {
+    pub kind: ChunkKind,
+    pub semantic: StreamSemantic,
+    pub set: u16,
+    pub format: ElementFormat,
+    pub count: u32,
+    pub offset: u32,
+    pub size: u32,
+}
+
+/// `Uint16` when every index fits, `Uint32` otherwise. Models store their indices in this format,
+/// and meshes are uploaded in it.
+pub fn index_format_for(indices: &[u32]) -> ElementFormat {
+    if indices.iter().all(|index| *index <= u16::MAX as u32) { ElementFormat::Uint16 } else { ElementFormat::Uint32 }
+}
+
+/// Builds a model file chunk by chunk
+#[derive(Default)]
+pub struct ModelWriter {
+    chunks: Vec<(ChunkHeader, Vec<u8>)>,
+}
+
+impl ModelWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn vertex_stream<T: Pod>(&mut self, semantic: StreamSemantic, set: u16, format: ElementFormat, values: &[T]) -> &mut Self {
+        assert_eq!(format.size(), Some(size_of::<T>()), "{:?} does not match the stream's element type", format);
+        self.chunk(ChunkKind::VertexStream, semantic, set, format, values)
+    }
+
+    /// Indices are stored as 16-bit when every index fits, 32-bit otherwise; see `index_format_for`
+    pub fn indices(&mut self, indices: &[u32]) -> &mut Self {
+        self.index_chunk(ChunkKind::Indices, indices)
+    }
+
+    /// Levels of detail, with `submeshes` holding every level's submeshes and `indices` their
+    /// indices
+    pub fn lods(&mut self, lods: &[Lod], submeshes: &[Submesh], indices: &[u32]) -> &mut Self {
+        self.chunk(ChunkKind::Lods, StreamSemantic::None, 0, ElementFormat::Record, lods);
+        self.chunk(ChunkKind::LodSubmeshes, StreamSemantic::None, 0, ElementFormat::Record, submeshes);
+        self.index_chunk(ChunkKind::LodIndices, indices)
+    }
+
+    fn index_chunk(&mut self, kind: ChunkKind, indices: &[u32]) -> &mut Self {
+        match index_format_for(indices) {
+            ElementFormat::Uint16 => {
+                let narrow: Vec<u16> = indices.iter().map(|index| *index as u16).collect();
+                self.chunk(kind, StreamSemantic::None, 0, ElementFormat::Uint16, &narrow)
+            }
+            format => self.chunk(kind, StreamSemantic::None, 0, format, indices),
+        }
+    }
+
+    /// The format `indices` stored them in, if they've been written
+    pub fn index_format(&self) -> Option<ElementFormat> {
+        self.chunks.iter().find(|(header, _)| header.kind == ChunkKind::Indices).map(|(header, _)| header.format)
+    }
+
+    pub fn submeshes(&mut self, submeshes: &[Submesh]) -> &mut Self {
+        self.chunk(ChunkKind::Submeshes, StreamSemantic::None, 0, ElementFormat::Record, submeshes)
+    }
+
+    pub fn bounds(&mut self, bounds: Aabb) -> &mut Self {
+        self.chunk(ChunkKind::Bounds, StreamSemantic::None, 0, ElementFormat::Record, &[bounds])
+    }
+
+    pub fn bounding_sphere(&mut self, sphere: BoundingSphere) -> &mut Self {
+        self.chunk(ChunkKind::BoundingSphere, StreamSemantic::None, 0, ElementFormat::Record, &[sphere])
+    }
+
+    pub fn meshlets(&mut self, meshlets: &[Meshlet], vertices: &[u32], triangles: &[u8]) -> &mut Self {
+        self.chunk(ChunkKind::Meshlets, StreamSemantic::None, 0, ElementFormat::Record, meshlets);
+        self.chunk(ChunkKind::MeshletVertices, StreamSemantic::None, 0, ElementFormat::Uint32, vertices);
+        self.chunk(ChunkKind::MeshletTriangles, StreamSemantic::None, 0, ElementFormat::Uint8, triangles)
+    }
+
+    /// The node hierarchy, with `meshes` holding each node's submesh indices
+    pub fn nodes(&mut self, nodes: &[Node], meshes: &[u32]) -> &mut Self {
+        self.chunk(ChunkKind::Nodes, StreamSemantic::None, 0, ElementFormat::Record, nodes);
+        self.chunk(ChunkKind::NodeMeshes, StreamSemantic::None, 0, ElementFormat::Uint32, meshes)
+    }
+
+    /// UTF-8 names referenced by nodes and animations
+    pub fn names(&mut self, names: &str) -> &mut Self {
+        self.chunk(ChunkKind::Names, StreamSemantic::None, 0, ElementFormat::Uint8, names.as_bytes())
+    }
+
+    /// The import settings the model was built with, as TOML
+    pub fn import_settings(&mut self, settings: &str) -> &mut Self {
+        self.chunk(ChunkKind::ImportSettings, StreamSemantic::None, 0, ElementFormat::Uint8, settings.as_bytes())
+    }
+
+    pub fn cameras(&mut self, cameras: &[Camera]) -> &mut Self {
+        self.chunk(ChunkKind::Cameras, StreamSemantic::None, 0, ElementFormat::Record, cameras)
+    }
+
+    pub fn lights(&mut self, lights: &[Light]) -> &mut Self {
+        self.chunk(ChunkKind::Lights, StreamSemantic::None, 0, ElementFormat::Record, lights)
+    }
+
+    pub fn skin(&mut self, joints: &[Joint]) -> &mut Self {
+        self.chunk(ChunkKind::SkinJoints, StreamSemantic::None, 0, ElementFormat::Record, joints)
+    }
+
+    pub fn animations(
+        &mut self,
+        animations: &[Animation],
+        channels: &[AnimationChannel],
+        translations: &[Vec3Key],
+        rotations: &[QuatKey],
+        scales: &[Vec3Key],
+    ) -> &mut Self {
+        self.chunk(ChunkKind::Animations, StreamSemantic::None, 0, ElementFormat::Record, animations);
+        self.chunk(ChunkKind::AnimationChannels, StreamSemantic::None, 0, ElementFormat::Record, channels);
+        self.chunk(ChunkKind::TranslationKeys, StreamSemantic::None, 0, ElementFormat::Record, translations);
+        self.chunk(ChunkKind::RotationKeys, StreamSemantic::None, 0, ElementFormat::Record, rotations);
+        self.chunk(ChunkKind::ScaleKeys, StreamSemantic::None, 0, ElementFormat::Record, scales)
+    }
+
+    fn chunk<T: Pod>(&mut self, kind: ChunkKind, semantic: StreamSemantic, set: u16, format: ElementFormat, values: &[T]) -> &mut Self {
+        let header = ChunkHeader { kind, semantic, set, format, count: values.len() as u32, offset: 0, size: size_of_val(values) as u32 };
+        self.chunks.push((header, as_bytes(values).to_vec()));
+        self
+    }
+
+    pub fn finish(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&MAGIC);
+        data.extend_from_slice(&VERSION.to_le_bytes());
+        data.extend_from_slice(&(self.chunks.len() as u32).to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+
+        let mut offset = (HEADER_SIZE + self.chunks.len() * CHUNK_HEADER_SIZE).next_multiple_of(CHUNK_ALIGNMENT);
+        for (header, bytes) in &self.chunks {
+            data.extend_from_slice(&(header.kind as u32).to_le_bytes());
+            data.extend_from_slice(&(header.semantic as u16).to_le_bytes());
+            data.extend_from_slice(&header.set.to_le_bytes());
+            data.extend_from_slice(&(header.format as u32).to_le_bytes());
+            data.extend_from_slice(&header.count.to_le_bytes());
+            data.extend_from_slice(&(offset as u32).to_le_bytes());
+            data.extend_from_slice(&header.size.to_le_bytes());
+            offset = (offset + bytes.len()).next_multiple_of(CHUNK_ALIGNMENT);
+        }
+
+        for (_, bytes) in &self.chunks {
+            data.resize(data.len().next_multiple_of(CHUNK_ALIGNMENT), 0);
+            data.extend_from_slice(bytes);
+        }
+
+        data
+    }
+}
+
+/// Index data at its stored width
+#[derive(Debug, Clone)]
+pub enum Indices<'a> {
+    U16(Cow<'a, [u16]>),
+    U32(Cow<'a, [u32]>),
+}
+
+impl Indices<'_> {
+    pub fn len(&self) -> usize {
+        match self {
+            Indices::U16(indices) => indices.len(),
+            Indices::U32(indices) => indices.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Indices widened to 32 bits
+    pub fn to_u32(&self) -> Vec<u32> {
+        match self {
+            Indices::U16(indices) => indices.iter().map(|index| *index as u32).collect(),
+            Indices::U32(indices) => indices.to_vec(),
+        }
+    }
+}
+
+/// A vertex stream's raw contents
+#[derive(Debug, Clone, Copy)]
+pub struct StreamView<'a> {
+    pub semantic: StreamSemantic,
+    pub set: u16,
+    pub format: ElementFormat,
+    pub count: u32,
+    pub bytes: &'a [u8],
+}
+
+impl<'a> StreamView<'a> {
+    /// The stream's elements as `T`, borrowed when the data is aligned for `T`.
+    /// Returns None if `T` doesn't match the element size.
+    pub fn as_slice<T: Pod>(&self) -> Option<Cow<'a, [T]>> {
+        (self.format.size() == Some(size_of::<T>())).then(|| cast_slice(self.bytes))
+    }
+}
+
+/// A validated view over a serialized model. Accessors borrow from the underlying data when its
+/// alignment allows, which is always the case for the generated model constants.
+#[derive(Debug, Clone)]
+pub struct ModelView<'a> {
+    data: &'a [u8],
+    chunks: Vec<ChunkHeader>,
+}
+
+impl<'a> ModelView<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, ModelError> {
+        let read_u32 = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+        let read_u16 = |offset: usize| u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap());
+
+        if data.len() < HEADER_SIZE {
+            return Err(ModelError::Truncated { needed: HEADER_SIZE, available: data.len() });
+        }
+        if data[0..4] != MAGIC {
+            return Err(ModelError::BadMagic);
+        }
+        let version = read_u32(4);
+        if version != VERSION {
+            return Err(ModelError::UnsupportedVersion(version));
+        }
+
+        let chunk_count = read_u32(8) as usize;
+        let table_end = chunk_count
+            .checked_mul(CHUNK_HEADER_SIZE)
+            .and_then(|size| size.checked_add(HEADER_SIZE))
+            .ok_or_else(|| ModelError::InvalidChunk(format!("chunk count {} is too large", chunk_count)))?;
+        if data.len() < table_end {
+            return Err(ModelError::Truncated { needed: table_end, available: data.len() });
+        }
+
+        let mut chunks = Vec::with_capacity(chunk_count);
+        for index in 0..chunk_count {
+            let base = HEADER_SIZE + index * CHUNK_HEADER_SIZE;
+            let kind = ChunkKind::from_raw(read_u32(base))
+                .ok_or_else(|| ModelError::InvalidChunk(format!("chunk {} has unknown kind {}", index, read_u32(base))))?;
+            let semantic = StreamSemantic::from_raw(read_u16(base + 4))
+                .ok_or_else(|| ModelError::InvalidChunk(format!("chunk {} has unknown semantic {}", index, read_u16(base + 4))))?;
+            let format = ElementFormat::from_raw(read_u32(base + 8))
+                .ok_or_else(|| ModelError::InvalidChunk(format!("chunk {} has unknown format {}", index, read_u32(base + 8))))?;
+            let header = ChunkHeader {
+                kind,
+                semantic,
+                set: read_u16(base + 6),
+                format,
+                count: read_u32(base + 12),
+                offset: read_u32(base + 16),
+                size: read_u32(base + 20),
+            };
+
+            let element_size = match (header.kind, header.format) {
+                (ChunkKind::Submeshes | ChunkKind::LodSubmeshes, ElementFormat::Record) => size_of::<Submesh>(),
+                (ChunkKind::Lods, ElementFormat::Record) => size_of::<Lod>(),
+                (ChunkKind::Bounds, ElementFormat::Record) => size_of::<Aabb>(),
+                (ChunkKind::BoundingSphere, ElementFormat::Record) => size_of::<BoundingSphere>(),
+                (ChunkKind::Meshlets, ElementFormat::Record) => size_of::<Meshlet>(),
+                (ChunkKind::Nodes, ElementFormat::Record) => size_of::<Node>(),
+                (ChunkKind::Cameras, ElementFormat::Record) => size_of::<Camera>(),
+                (ChunkKind::Lights, ElementFormat::Record) => size_of::<Light>(),
+                (ChunkKind::SkinJoints, ElementFormat::Record) => size_of::<Joint>(),
+                (ChunkKind::Animations, ElementFormat::Record) => size_of::<Animation>(),
+                (ChunkKind::AnimationChannels, ElementFormat::Record) => size_of::<AnimationChannel>(),
+                (ChunkKind::TranslationKeys | ChunkKind::ScaleKeys, ElementFormat::Record) => size_of::<Vec3Key>(),
+                (ChunkKind::RotationKeys, ElementFormat::Record) => size_of::<QuatKey>(),
+                (ChunkKind::VertexStream | ChunkKind::Indices | ChunkKind::LodIndices | ChunkKind::MeshletVertices | ChunkKind::MeshletTriangles
+                    | ChunkKind::NodeMeshes | ChunkKind::Names | ChunkKind::ImportSettings, format)
+                    if format != ElementFormat::Record => format.size().unwrap(),
+                (kind, format) => return Err(ModelError::InvalidChunk(format!("{:?} chunk can't have format {:?}", kind, format))),
+            };
+            if header.count as usize * element_size != header.size as usize {
+                return Err(ModelError::InvalidChunk(format!(
+                    "{:?} chunk holds {} elements of {} bytes but is {} bytes long",
+                    header.kind, header.count, element_size, header.size
+                )));
+            }
+
+            let end = header.offset as usize + header.size as usize;
+            if end > data.len() {
+                return Err(ModelError::Truncated { needed: end, available: data.len() });
+            }
+            if header.offset as usize % CHUNK_ALIGNMENT != 0 {
+                return Err(ModelError::InvalidChunk(format!("{:?} chunk at offset {} is not aligned", header.kind, header.offset)));
+            }
+
+            chunks.push(header);
+        }
+
+        let view = Self { data, chunks };
+        let index_count = view.chunk(ChunkKind::Indices).map_or(0, |chunk| chunk.count as usize);
+        for submesh in view.submeshes().iter() {
+            check_range("submesh indices", submesh.index_offset, submesh.index_count, index_count)?;
+            check_range("submesh vertices", submesh.vertex_offset, submesh.vertex_count, view.vertex_count() as usize)?;
+        }
+        let lod_index_count = view.chunk(ChunkKind::LodIndices).map_or(0, |chunk| chunk.count as usize);
+        for submesh in view.records::<Submesh>(ChunkKind::LodSubmeshes).iter() {
+            check_range("LOD submesh indices", submesh.index_offset, submesh.index_count, lod_index_count)?;
+            check_range("LOD submesh vertices", submesh.vertex_offset, submesh.vertex_count, view.vertex_count() as usize)?;
+        }
+        for meshlet in view.meshlets().iter() {
+            check_range("meshlet vertices", meshlet.vertex_offset, meshlet.vertex_count, view.meshlet_vertices().len())?;
+            check_range("meshlet triangles", meshlet.triangle_offset, meshlet.triangle_count, view.meshlet_triangles().len() / 3)?;
+        }
+
+        Ok(view)
+    }
+
+    pub fn chunks(&self) -> &[ChunkHeader] {
+        &self.chunks
+    }
+
+    fn chunk(&self, kind: ChunkKind) -> Option<&ChunkHeader> {
+        self.chunks.iter().find(|chunk| chunk.kind == kind)
+    }
+
+    fn chunk_bytes(&self, chunk: &ChunkHeader) -> &'a [u8] {
+        &self.data[chunk.offset as usize..][..chunk.size as usize]
+    }
+
+    fn records<T: Pod>(&self, kind: ChunkKind) -> Cow<'a, [T]> {
+        self.chunk(kind).map_or(Cow::Borrowed(&[]), |chunk| cast_slice(self.chunk_bytes(chunk)))
+    }
+
+    /// Number of vertices, taken from the position stream
+    pub fn vertex_count(&self) -> u32 {
+        self.stream(StreamSemantic::Position, 0).map_or(0, |stream| stream.count)
+    }
+
+    pub fn streams(&self) -> impl Iterator<Item = StreamView<'a>> + '_ {
+        self.chunks.iter()
+            .filter(|chunk| chunk.kind == ChunkKind::VertexStream)
+            .map(|chunk| StreamView {
+                semantic: chunk.semantic,
+                set: chunk.set,
+                format: chunk.format,
+                count: chunk.count,
+                bytes: self.chunk_bytes(chunk),
+            })
+    }
+
+    pub fn stream(&self, semantic: StreamSemantic, set: u16) -> Option<StreamView<'a>> {
+        self.streams().find(|stream| stream.semantic == semantic && stream.set == set)
+    }
+
+    /// A vertex stream, checking that it has one element per vertex
+    fn attribute_stream(&self, semantic: StreamSemantic, set: u16) -> Result<Option<StreamView<'a>>, ModelError> {
+        let Some(stream) = self.stream(semantic, set) else {
+            return Ok(None);
+        };
+
+        if stream.count != self.vertex_count() {
+            return Err(ModelError::InvalidChunk(format!(
+                "{:?}{} stream has {} elements for {} vertices", semantic, set, stream.count, self.vertex_count()
+            )));
+        }
+
+        Ok(Some(stream))
+    }
+
+    /// A vertex stream's elements as `T`, checking that it has one element per vertex.
+    /// Returns None if the model has no such stream.
+    pub fn vertex_attribute<T: Pod>(&self, semantic: StreamSemantic, set: u16) -> Result<Option<Cow<'a, [T]>>, ModelError> {
+        let Some(stream) = self.attribute_stream(semantic, set)? else {
+            return Ok(None);
+        };
+
+        stream.as_slice::<T>()
+            .map(Some)
+            .ok_or_else(|| ModelError::InvalidChunk(format!(
+                "{:?}{} stream has format {:?}, expected {} byte elements", semantic, set, stream.format, size_of::<T>()
+            )))
+    }
+
+    /// Vertex positions, dequantized within the model's bounds when stored as unorm16
+    pub fn positions(&self) -> Result<Cow<'a, [Vec3]>, ModelError> {
+        let stream = self.stream(StreamSemantic::Position, 0).ok_or(ModelError::MissingChunk("position"))?;
+
+        match stream.format {
+            ElementFormat::Float32x3 => Ok(cast_slice(stream.bytes)),
+            ElementFormat::Unorm16x4 => {
+                let bounds = self.bounds().ok_or(ModelError::MissingChunk("bounds"))?;
+                Ok(Cow::Owned(cast_slice::<[u16; 4]>(stream.bytes).iter().map(|p| dequantize_position(*p, &bounds)).collect()))
+            }
+            format => Err(ModelError::InvalidChunk(format!("position stream has format {:?}", format))),
+        }
+    }
+
+    /// Vertex normals, decoded when stored octahedral. Returns None if the model has none.
+    pub fn normals(&self) -> Result<Option<Cow<'a, [Vec3]>>, ModelError> {
+        let Some(stream) = self.attribute_stream(StreamSemantic::Normal, 0)? else {
+            return Ok(None);
+        };
+
+        match stream.format {
+            ElementFormat::Float32x3 => Ok(Some(cast_slice(stream.bytes))),
+            ElementFormat::Snorm16x2 => {
+                Ok(Some(Cow::Owned(cast_slice::<[i16; 2]>(stream.bytes).iter().map(|n| decode_octahedral(*n)).collect())))
+            }
+            format => Err(ModelError::InvalidChunk(format!("normal stream has format {:?}", format))),
+        }
+    }
+
+    /// A texture coordinate set, widened when stored as half floats. Returns None if the model has
+    /// no such set.
+    pub fn tex_coords(&self, set: u16) -> Result<Option<Cow<'a, [Vec2]>>, ModelError> {
+        let Some(stream) = self.attribute_stream(StreamSemantic::TexCoord, set)? else {
+            return Ok(None);
+        };
+
+        match stream.format {
+            ElementFormat::Float32x2 => Ok(Some(cast_slice(stream.bytes))),
+            ElementFormat::Float16x2 => Ok(Some(Cow::Owned(
+                cast_slice::<[u16; 2]>(stream.bytes).iter().map(|uv| Vec2::new(f32_from_f16(uv[0]), f32_from_f16(uv[1]))).collect(),
+            ))),
+            format => Err(ModelError::InvalidChunk(format!("TexCoord{} stream has format {:?}", set, format))),
+        }
+    }
+
+    /// Number of consecutive sets, starting at 0, of a semantic
+    pub fn set_count(&self, semantic: StreamSemantic) -> u16 {
+        (0..).find(|set| self.stream(semantic, *set).is_none()).unwrap_or(0)
+    }
+
+    pub fn indices(&self) -> Result<Indices<'a>, ModelError> {
+        let chunk = self.chunk(ChunkKind::Indices).ok_or(ModelError::MissingChunk("index"))?;
+        self.index_chunk(chunk)
+    }
+
+    fn index_chunk(&self, chunk: &ChunkHeader) -> Result<Indices<'a>, ModelError> {
+        let bytes = self.chunk_bytes(chunk);
+
+        match chunk.format {
+            ElementFormat::Uint16 => Ok(Indices::U16(cast_slice(bytes))),
+            ElementFormat::Uint32 => Ok(Indices::U32(cast_slice(bytes))),
+            format => Err(ModelError::InvalidChunk(format!("{:?} chunk has format {:?}", chunk.kind, format))),
+        }
+    }
+
+    /// Empty when the model was built without levels of detail
+    pub fn lods(&self) -> Cow<'a, [Lod]> {
+        self.records(ChunkKind::Lods)
+    }
+
+    pub fn lod_submeshes(&self, lod: &Lod) -> Result<Cow<'a, [Submesh]>, ModelError> {
+        self.record_range(ChunkKind::LodSubmeshes, lod.submesh_offset, lod.submesh_count)
+    }
+
+    /// Indices of every level of detail, empty when there are none
+    pub fn lod_indices(&self) -> Result<Indices<'a>, ModelError> {
+        self.chunk(ChunkKind::LodIndices).map_or(Ok(Indices::U16(Cow::Borrowed(&[]))), |chunk| self.index_chunk(chunk))
+    }
+
+    pub fn submeshes(&self) -> Cow<'a, [Submesh]> {
+        self.records(ChunkKind::Submeshes)
+    }
+
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.records::<Aabb>(ChunkKind::Bounds).first().copied()
+    }
+
+    pub fn bounding_sphere(&self) -> Option<BoundingSphere> {
+        self.records::<BoundingSphere>(ChunkKind::BoundingSphere).first().copied()
+    }
+
+    /// Empty when the model was built without meshlets
+    pub fn meshlets(&self) -> Cow<'a, [Meshlet]> {
+        self.records(ChunkKind::Meshlets)
+    }
+
+    pub fn meshlet_vertices(&self) -> Cow<'a, [u32]> {
+        self.records(ChunkKind::MeshletVertices)
+    }
+
+    pub fn meshlet_triangles(&self) -> &'a [u8] {
+        self.chunk(ChunkKind::MeshletTriangles).map_or(&[], |chunk| self.chunk_bytes(chunk))
+    }
+
+    /// Empty when the model was written without a hierarchy
+    pub fn nodes(&self) -> Cow<'a, [Node]> {
+        self.records(ChunkKind::Nodes)
+    }
+
+    /// Records `offset..offset + count` of a chunk, checking that they're in range
+    fn record_range<T: Pod>(&self, kind: ChunkKind, offset: u32, count: u32) -> Result<Cow<'a, [T]>, ModelError> {
+        let records: Cow<'a, [T]> = self.records(kind);
+        let range = offset as usize..offset as usize + count as usize;
+        if range.end > records.len() {
+            return Err(ModelError::InvalidChunk(format!("{:?} {:?} are out of range", kind, range)));
+        }
+
+        Ok(match records {
+            Cow::Borrowed(records) => Cow::Borrowed(&records[range]),
+            Cow::Owned(records) => Cow::Owned(records[range].to_vec()),
+        })
+    }
+
+    fn name(&self, offset: u32, length: u32) -> Result<&'a str, ModelError> {
+        let names = self.chunk(ChunkKind::Names).map_or(&[][..], |chunk| self.chunk_bytes(chunk));
+        let bytes = names
+            .get(offset as usize..offset as usize + length as usize)
+            .ok_or_else(|| ModelError::InvalidChunk(format!("name at {} is out of range", offset)))?;
+
+        std::str::from_utf8(bytes).map_err(|e| ModelError::InvalidChunk(format!("name is not UTF-8: {}", e)))
+    }
+
+    /// The import settings the model was built with, as TOML. Returns None if it was built
+    /// without a settings file.
+    pub fn import_settings(&self) -> Result<Option<&'a str>, ModelError> {
+        let Some(chunk) = self.chunk(ChunkKind::ImportSettings) else {
+            return Ok(None);
+        };
+
+        std::str::from_utf8(self.chunk_bytes(chunk))
+            .map(Some)
+            .map_err(|e| ModelError::InvalidChunk(format!("import settings are not UTF-8: {}", e)))
+    }
+
+    /// Submesh indices of `node`
+    pub fn node_meshes(&self, node: &Node) -> Result<Cow<'a, [u32]>, ModelError> {
+        self.record_range(ChunkKind::NodeMeshes, node.mesh_offset, node.mesh_count)
+    }
+
+    pub fn node_name(&self, node: &Node) -> Result<&'a str, ModelError> {
+        self.name(node.name_offset, node.name_length)
+    }
+
+    pub fn cameras(&self) -> Cow<'a, [Camera]> {
+        self.records(ChunkKind::Cameras)
+    }
+
+    pub fn lights(&self) -> Cow<'a, [Light]> {
+        self.records(ChunkKind::Lights)
+    }
+
+    /// Empty when the model isn't skinned
+    pub fn skin(&self) -> Cow<'a, [Joint]> {
+        self.records(ChunkKind::SkinJoints)
+    }
+
+    pub fn animations(&self) -> Cow<'a, [Animation]> {
+        self.records(ChunkKind::Animations)
+    }
+
+    pub fn animation_name(&self, animation: &Animation) -> Result<&'a str, ModelError> {
+        self.name(animation.name_offset, animation.name_length)
+    }
+
+    pub fn animation_channels(&self, animation: &Animation) -> Result<Cow<'a, [AnimationChannel]>, ModelError> {
+        self.record_range(ChunkKind::AnimationChannels, animation.channel_offset, animation.channel_count)
+    }
+
+    pub fn translation_keys(&self, channel: &AnimationChannel) -> Result<Cow<'a, [Vec3Key]>, ModelError> {
+        self.record_range(ChunkKind::TranslationKeys, channel.translation_offset, channel.translation_count)
+    }
+
+    pub fn rotation_keys(&self, channel: &AnimationChannel) -> Result<Cow<'a, [QuatKey]>, ModelError> {
+        self.record_range(ChunkKind::RotationKeys, channel.rotation_offset, channel.rotation_count)
+    }
+
+    pub fn scale_keys(&self, channel: &AnimationChannel) -> Result<Cow<'a, [Vec3Key]>, ModelError> {
+        self.record_range(ChunkKind::ScaleKeys, channel.scale_offset, channel.scale_count)
+    }
+}
+
+/// Checks that elements `offset..offset + count` of `what` are within its `len` elements
+fn check_range(what: &str, offset: u32, count: u32, len: usize) -> Result<(), ModelError> {
+    match offset.checked_add(count) {
+        Some(end) if end as usize <= len => Ok(()),
+        _ => Err(ModelError::InvalidChunk(format!("{} {}+{} are out of range for {}", what, offset, count, len))),
+    }
+}
+
+/// A textured triangle in a single submesh, the model fixture of this crate's tests and its
+/// dependents'
+#[doc(hidden)]
+pub fn test_triangle_model() -> Vec<u8> {
+    let positions = [Vec3::ZERO, Vec3::X, Vec3::Y];
+    let uvs = [Vec2::ZERO, Vec2::X, Vec2::Y];
+    let bounds = Aabb::from_points(positions);
+    let bounding_sphere = BoundingSphere::from_points(&positions);
+
+    ModelWriter::new()
+        .vertex_stream(StreamSemantic::Position, 0, ElementFormat::Float32x3, &positions)
+        .vertex_stream(StreamSemantic::TexCoord, 0, ElementFormat::Float32x2, &uvs)
+        .indices(&[0, 1, 2])
+        .submeshes(&[Submesh { index_offset: 0, index_count: 3, vertex_offset: 0, vertex_count: 3, material: 0, bounds, bounding_sphere }])
+        .bounds(bounds)
+        .bounding_sphere(bounding_sphere)
+        .finish()
+}
+
+/// A `size` by `size` grid of quads over x and y, raised to `height(x, y)` and facing +z, as
+/// positions and indices for the tests of the model pipeline passes
+#[cfg(test)]
+pub(crate) fn test_grid(size: u32, height: impl Fn(f32, f32) -> f32) -> (Vec<Vec3>, Vec<u32>) {
+    let positions = (0..=size)
+        .flat_map(|y| (0..=size).map(move |x| (x as f32, y as f32)))
+        .map(|(x, y)| Vec3::new(x, y, height(x, y)))
+        .collect();
+    let indices = (0..size)
+        .flat_map(|y| (0..size).map(move |x| y * (size + 1) + x))
+        .flat_map(|corner| [corner, corner + 1, corner + size + 1, corner + size + 1, corner + 1, corner + size + 2])
+        .collect();
+
+    (positions, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        // Copy into u128 storage so the view can borrow
+        let data = test_triangle_model();
+        let mut aligned = vec![0u128; data.len().div_ceil(16)];
+        unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), aligned.as_mut_ptr() as *mut u8, data.len()) };
+        let data = unsafe { std::slice::from_raw_parts(aligned.as_ptr() as *const u8, data.len()) };
+
+        let view = ModelView::parse(data).expect("valid model rejected");
+        let positions = view.stream(StreamSemantic::Position, 0).unwrap().as_slice::<Vec3>().unwrap();
+
+        assert!(matches!(positions, Cow::Borrowed(_)));
+        assert_eq!(positions[1], Vec3::X);
+        assert_eq!(view.vertex_count(), 3);
+        assert!(matches!(view.indices().unwrap(), Indices::U16(_)));
+        assert_eq!(view.indices().unwrap().to_u32(), [0, 1, 2]);
+        assert_eq!(view.submeshes()[0].index_count, 3);
+        assert_eq!(view.bounds().unwrap().max, Vec3::new(1.0, 1.0, 0.0));
+        assert_eq!(view.bounding_sphere().unwrap(), view.submeshes()[0].bounding_sphere);
+        assert!(view.meshlets().is_empty());
+        assert!(view.stream(StreamSemantic::TexCoord, 0).unwrap().as_slice::<Vec3>().is_none());
+        assert_eq!(view.set_count(StreamSemantic::TexCoord), 1);
+        assert_eq!(view.set_count(StreamSemantic::Color), 0);
+        assert_eq!(view.vertex_attribute::<Vec2>(StreamSemantic::TexCoord, 0).unwrap().unwrap()[2], Vec2::Y);
+        assert!(view.vertex_attribute::<Vec2>(StreamSemantic::Normal, 0).unwrap().is_none());
+        assert!(view.vertex_attribute::<Vec3>(StreamSemantic::TexCoord, 0).is_err());
+    }
+
+    #[test]
+    fn test_unaligned_data_is_copied() {
+        let mut data = vec![0u8];
+        data.extend(test_triangle_model());
+        let view = ModelView::parse(&data[1..]).expect("valid model rejected");
+        let uvs = view.stream(StreamSemantic::TexCoord, 0).unwrap().as_slice::<Vec2>().unwrap();
+
+        assert!(matches!(uvs, Cow::Owned(_)));
+        assert_eq!(uvs[2], Vec2::Y);
+    }
+
+    #[test]
+    fn test_quantized_streams() {
+        let positions = [Vec3::new(-1.0, 0.0, 2.0), Vec3::new(3.0, 0.5, 2.0), Vec3::new(0.25, 1.0, 2.0)];
+        let normals = [Vec3::Z, Vec3::new(0.6, 0.0, -0.8), Vec3::new(-0.48, -0.6, -0.64)];
+        let uvs = [Vec2::ZERO, Vec2::new(0.5, 1.0), Vec2::new(-2.25, 1000.0)];
+        let bounds = Aabb::from_points(positions);
+
+        let data = ModelWriter::new()
+            .vertex_stream(StreamSemantic::Position, 0, ElementFormat::Unorm16x4, &positions.map(|p| quantize_position(p, &bounds)))
+            .vertex_stream(StreamSemantic::Normal, 0, ElementFormat::Snorm16x2, &normals.map(encode_octahedral))
+            .vertex_stream(StreamSemantic::TexCoord, 0, ElementFormat::Float16x2, &uvs.map(|uv| [f16_from_f32(uv.x), f16_from_f32(uv.y)]))
+            .indices(&[0, 1, 2])
+            .bounds(bounds)
+            .finish();
+        let view = ModelView::parse(&data).expect("valid model rejected");
+
+        for (decoded, position) in view.positions().unwrap().iter().zip(positions) {
+            assert!(decoded.distance(position) < 1e-4, "{} decoded as {}", position, decoded);
+        }
+        for (decoded, normal) in view.normals().unwrap().unwrap().iter().zip(normals) {
+            assert!(decoded.dot(normal) > 0.9999, "{} decoded as {}", normal, decoded);
+        }
+        assert_eq!(*view.tex_coords(0).unwrap().unwrap(), uvs);
+        assert!(view.tex_coords(1).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_bounding_sphere() {
+        assert_eq!(BoundingSphere::from_points(&[]), BoundingSphere { center: Vec3::ZERO, radius: 0.0 });
+
+        // Corners of a box, whose tightest sphere is centered on it
+        let corners: Vec<Vec3> = (0..8).map(|i| Vec3::new((i & 1) as f32 * 2.0, (i >> 1 & 1) as f32, (i >> 2) as f32 * 4.0)).collect();
+        let sphere = BoundingSphere::from_points(&corners);
+        assert!(sphere.center.distance(Vec3::new(1.0, 0.5, 2.0)) < 1e-5);
+        assert!((sphere.radius - Vec3::new(1.0, 0.5, 2.0).length()).abs() < 1e-5);
+
+        // Points on a circle with one far outlier, where the box center is a poor fit
+        let mut points: Vec<Vec3> = (0..32).map(|i| {
+            let angle = i as f32 / 32.0 * std::f32::consts::TAU;
+            Vec3::new(angle.cos(), angle.sin(), 0.0)
+        }).collect();
+        points.push(Vec3::new(3.0, 3.0, 0.0));
+        let sphere = BoundingSphere::from_points(&points);
+        assert!(points.iter().all(|point| point.distance(sphere.center) <= sphere.radius));
+        assert!(sphere.radius < 3.2, "{:?} is loose", sphere);
+    }
+
+    #[test]
+    fn test_f16() {
+        for value in [0.0, -0.0, 1.0, -2.5, 65504.0, 6.1035156e-5, 5.9604645e-8, f32::INFINITY] {
+            assert_eq!(f32_from_f16(f16_from_f32(value)).to_bits(), value.to_bits());
+        }
+        assert_eq!(f16_from_f32(1.0), 0x3c00);
+        // Ties round to even, and rounding up can carry into the exponent
+        assert_eq!(f16_from_f32(1.0 + 2f32.powi(-11)), 0x3c00);
+        assert_eq!(f16_from_f32(2.0 - 2f32.powi(-12)), 0x4000);
+        assert_eq!(f16_from_f32(1e6), 0x7c00);
+        assert_eq!(f16_from_f32(1e-9), 0);
+        assert!(f32_from_f16(f16_from_f32(f32::NAN)).is_nan());
+    }
+
+    #[test]
+    fn test_lods() {
+        let positions = [Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::ONE];
+        let bounds = Aabb::from_points(positions);
+        let bounding_sphere = BoundingSphere::from_points(&positions);
+        let submesh = Submesh { index_offset: 0, index_count: 6, vertex_offset: 0, vertex_count: 4, material: 0, bounds, bounding_sphere };
+        let lod_submesh = Submesh { index_count: 3, ..submesh };
+        let lod = Lod { submesh_offset: 0, submesh_count: 1, error: 0.25 };
+
+        let data = ModelWriter::new()
+            .vertex_stream(StreamSemantic::Position, 0, ElementFormat::Float32x3, &positions)
+            .indices(&[0, 1, 2, 2, 1, 3])
+            .submeshes(&[submesh])
+            .lods(&[lod], &[lod_submesh], &[0, 1, 3])
+            .finish();
+        let view = ModelView::parse(&data).expect("valid model rejected");
+
+        assert_eq!(*view.lods(), [lod]);
+        assert_eq!(*view.lod_submeshes(&lod).unwrap(), [lod_submesh]);
+        assert_eq!(view.lod_indices().unwrap().to_u32(), [0, 1, 3]);
+        assert!(view.lod_submeshes(&Lod { submesh_count: 2, ..lod }).is_err());
+
+        for bad_submesh in [Submesh { index_offset: 1, ..lod_submesh }, Submesh { index_offset: u32::MAX, ..lod_submesh }] {
+            let data = ModelWriter::new()
+                .vertex_stream(StreamSemantic::Position, 0, ElementFormat::Float32x3, &positions)
+                .indices(&[0, 1, 2, 2, 1, 3])
+                .submeshes(&[submesh])
+                .lods(&[lod], &[bad_submesh], &[0, 1, 3])
+                .finish();
+            assert!(matches!(ModelView::parse(&data), Err(ModelError::InvalidChunk(_))));
+        }
+
+        let data = test_triangle_model();
+        let without = ModelView::parse(&data).unwrap();
+        assert!(without.lods().is_empty());
+        assert!(without.lod_indices().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_meshlets() {
+        let positions = [Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::ONE];
+        let bounds = Aabb::from_points(positions);
+        let bounding_sphere = BoundingSphere::from_points(&positions);
+        let submesh = Submesh { index_offset: 0, index_count: 6, vertex_offset: 0, vertex_count: 4, material: 0, bounds, bounding_sphere };
+        let meshlet = Meshlet {
+            vertex_offset: 0,
+            vertex_count: 4,
+            triangle_offset: 0,
+            triangle_count: 2,
+            center: bounding_sphere.center,
+            radius: bounding_sphere.radius,
+            cone_apex: Vec3::ZERO,
+            cone_axis: Vec3::Z,
+            cone_cutoff: 1.0,
+        };
+        let with_meshlet = |meshlet: Meshlet, vertices: &[u32], triangles: &[u8]| {
+            ModelWriter::new()
+                .vertex_stream(StreamSemantic::Position, 0, ElementFormat::Float32x3, &positions)
+                .indices(&[0, 1, 2, 2, 1, 3])
+                .submeshes(&[submesh])
+                .meshlets(&[meshlet], vertices, triangles)
+                .bounds(bounds)
+                .bounding_sphere(bounding_sphere)
+                .finish()
+        };
+
+        let model = crate::Model::decode(None, &with_meshlet(meshlet, &[0, 1, 2, 3], &[0, 1, 2, 2, 1, 3])).expect("valid model rejected");
+        assert_eq!(model.meshlets, [meshlet]);
+        assert_eq!(model.meshlet_vertices, [0, 1, 2, 3]);
+        assert_eq!(model.meshlet_triangles, [0, 1, 2, 2, 1, 3]);
+
+        for bad_meshlet in [
+            Meshlet { vertex_offset: 1, ..meshlet },
+            Meshlet { vertex_offset: u32::MAX, ..meshlet },
+            Meshlet { triangle_count: 3, ..meshlet },
+            Meshlet { triangle_offset: u32::MAX, ..meshlet },
+        ] {
+            let data = with_meshlet(bad_meshlet, &[0, 1, 2, 3], &[0, 1, 2, 2, 1, 3]);
+            assert!(matches!(ModelView::parse(&data), Err(ModelError::InvalidChunk(_))));
+        }
+        let bad_vertex = with_meshlet(meshlet, &[0, 1, 2, 4], &[0, 1, 2, 2, 1, 3]);
+        assert!(matches!(crate::Model::decode(None, &bad_vertex), Err(ModelError::InvalidChunk(_))));
+        let bad_triangle = with_meshlet(meshlet, &[0, 1, 2, 3], &[0, 1, 2, 2, 1, 4]);
+        assert!(matches!(crate::Model::decode(None, &bad_triangle), Err(ModelError::InvalidChunk(_))));
+    }
+
+    #[test]
+    fn test_index_format() {
+        assert_eq!(index_format_for(&[0, 1, u16::MAX as u32]), ElementFormat::Uint16);
+        assert_eq!(index_format_for(&[0, 1, u16::MAX as u32 + 1]), ElementFormat::Uint32);
+
+        let mut writer = ModelWriter::new();
+        assert_eq!(writer.index_format(), None);
+        writer.lods(&[], &[], &[0, 1, 70_000]).indices(&[0, 1, 2]);
+        assert_eq!(writer.index_format(), Some(ElementFormat::Uint16));
+
+        let data = ModelWriter::new().indices(&[0, 1, 70_000]).finish();
+        assert!(matches!(ModelView::parse(&data).unwrap().indices().unwrap(), Indices::U32(_)));
+    }
+
+    #[test]
+    fn test_nodes() {
+        let identity = glam::Mat4::IDENTITY.to_cols_array();
+        let nodes = [
+            Node { parent: NO_PARENT, mesh_offset: 0, mesh_count: 0, name_offset: 0, name_length: 4, transform: identity },
+            Node { parent: 0, mesh_offset: 0, mesh_count: 2, name_offset: 4, name_length: 5, transform: identity },
+        ];
+        let mut data = vec![0u8];
+        data.extend(ModelWriter::new().nodes(&nodes, &[0, 1]).names("rootchild").finish());
+        let view = ModelView::parse(&data[1..]).expect("valid model rejected");
+
+        assert_eq!(*view.nodes(), nodes);
+        assert_eq!(view.node_name(&view.nodes()[1]).unwrap(), "child");
+        assert_eq!(*view.node_meshes(&view.nodes()[1]).unwrap(), [0, 1]);
+
+        let out_of_range = Node { mesh_offset: 1, name_length: 10, ..nodes[1] };
+        assert!(view.node_meshes(&out_of_range).is_err());
+        assert!(view.node_name(&out_of_range).is_err());
+    }
+
+    #[test]
+    fn test_import_settings() {
+        let data = ModelWriter::new().import_settings("scale = 0.01\nup_axis = \"z\"\n").finish();
+        let view = ModelView::parse(&data).expect("valid model rejected");
+        assert_eq!(view.import_settings().unwrap(), Some("scale = 0.01\nup_axis = \"z\"\n"));
+
+        let data = ModelWriter::new().names("").finish();
+        assert_eq!(ModelView::parse(&data).unwrap().import_settings().unwrap(), None);
+    }
+
+    #[test]
+    fn test_animations() {
+        let translations = [Vec3Key { time: 0.0, value: Vec3::ZERO }, Vec3Key { time: 1.0, value: Vec3::X }];
+        let rotations = [QuatKey { time: 0.5, value: [0.0, 0.0, 0.0, 1.0] }];
+        let channel = AnimationChannel {
+            node: 1,
+            translation_offset: 0,
+            translation_count: 2,
+            rotation_offset: 0,
+            rotation_count: 1,
+            scale_offset: 0,
+            scale_count: 0,
+        };
+        let animation = Animation { name_offset: 0, name_length: 4, duration: 1.0, channel_offset: 0, channel_count: 1 };
+        let light = Light {
+            node: 0,
+            kind: LightKind::Spot as u32,
+            color: Vec3::ONE,
+            position: Vec3::ZERO,
+            direction: Vec3::NEG_Z,
+            attenuation: [1.0, 0.0, 0.0],
+            inner_cone: 0.5,
+            outer_cone: 0.7,
+        };
+
+        let data = ModelWriter::new()
+            .names("walk")
+            .lights(&[light])
+            .animations(&[animation], &[channel], &translations, &rotations, &[])
+            .finish();
+        let view = ModelView::parse(&data).expect("valid model rejected");
+
+        let animation = view.animations()[0];
+        let channel = view.animation_channels(&animation).unwrap()[0];
+        assert_eq!(view.animation_name(&animation).unwrap(), "walk");
+        assert_eq!(*view.translation_keys(&channel).unwrap(), translations);
+        assert_eq!(*view.rotation_keys(&channel).unwrap(), rotations);
+        assert!(view.scale_keys(&channel).unwrap().is_empty());
+        assert_eq!(view.lights()[0].kind(), Some(LightKind::Spot));
+        assert!(view.cameras().is_empty());
+
+        let out_of_range = AnimationChannel { translation_offset: 1, ..channel };
+        assert!(view.translation_keys(&out_of_range).is_err());
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        let data = test_triangle_model();
+
+        assert_eq!(ModelView::parse(&data[..8]).unwrap_err(), ModelError::Truncated { needed: HEADER_SIZE, available: 8 });
+        assert!(matches!(ModelView::parse(&data[..data.len() - 4]), Err(ModelError::Truncated { .. })));
+
+        let mut bad_magic = data.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(ModelView::parse(&bad_magic).unwrap_err(), ModelError::BadMagic);
+
+        let mut bad_version = data.clone();
+        bad_version[4] = 99;
+        assert_eq!(ModelView::parse(&bad_version).unwrap_err(), ModelError::UnsupportedVersion(99));
+
+        let positions = [Vec3::ZERO, Vec3::X, Vec3::Y];
+        let bounds = Aabb::from_points(positions);
+        let bounding_sphere = BoundingSphere::from_points(&positions);
+        let submesh = Submesh { index_offset: 0, index_count: 3, vertex_offset: 0, vertex_count: 3, material: 0, bounds, bounding_sphere };
+        let with_submesh = |submesh: Submesh| {
+            ModelWriter::new()
+                .vertex_stream(StreamSemantic::Position, 0, ElementFormat::Float32x3, &positions)
+                .indices(&[0, 1, 2])
+                .submeshes(&[submesh])
+                .bounds(bounds)
+                .bounding_sphere(bounding_sphere)
+                .finish()
+        };
+        assert!(ModelView::parse(&with_submesh(submesh)).is_ok());
+        for bad_submesh in [
+            Submesh { index_offset: 1, ..submesh },
+            Submesh { index_offset: u32::MAX, index_count: 2, ..submesh },
+            Submesh { vertex_count: 4, ..submesh },
+            Submesh { vertex_offset: u32::MAX, vertex_count: 2, ..submesh },
+        ] {
+            assert!(matches!(ModelView::parse(&with_submesh(bad_submesh)), Err(ModelError::InvalidChunk(_))));
+        }
+    }
+}
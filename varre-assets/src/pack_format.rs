@@ -0,0 +1,404 @@
+// Asset packs: many assets in one file, shared by the build script (which writes them) and the
+// runtime (which memory-maps them and reads entries on demand).
+//
+// A pack is laid out as:
+//
+//   header   magic "VRPK", version: u32, entry count: u32, reserved: u32,
+//            table of contents offset/size: u64
+//   entries  each starting on a PACK_ALIGNMENT boundary
+//   table of contents
+//            entry count * (path offset/length: u32, compression: u32, reserved: u32,
+//            offset, stored size, size, content hash: u64), sorted by path
+//            followed by the UTF-8 paths
+//
+// Paths follow the layout of a `DirectorySource` without the extension, e.g. "models/cube". The
+// content hash is FNV-1a of the uncompressed data. All values are little-endian.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::io::Read;
+
+pub const PACK_MAGIC: [u8; 4] = *b"VRPK";
+pub const PACK_VERSION: u32 = 1;
+/// Entries start on this boundary, which satisfies `optimalBufferCopyOffsetAlignment` on every
+/// device, so uncompressed entries of a memory-mapped pack can be copied to the GPU as they are
+pub const PACK_ALIGNMENT: u64 = 256;
+
+const HEADER_SIZE: usize = 32;
+const TOC_ENTRY_SIZE: usize = 48;
+/// Largest ratio of an entry's size to its stored size. Zstd stays below it even on runs of one
+/// byte, and LZ4 far below, so larger sizes come from a corrupt table of contents.
+const MAX_COMPRESSION_RATIO: u64 = 1 << 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None = 0,
+    Zstd = 1,
+    Lz4 = 2,
+}
+
+impl Compression {
+    fn from_raw(value: u32) -> Option<Self> {
+        Some(match value {
+            0 => Compression::None,
+            1 => Compression::Zstd,
+            2 => Compression::Lz4,
+            _ => return None,
+        })
+    }
+
+    /// Parse a compression name as used in configuration: "none", "zstd" or "lz4"
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "none" => Some(Compression::None),
+            "zstd" => Some(Compression::Zstd),
+            "lz4" => Some(Compression::Lz4),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PackError {
+    BadMagic,
+    UnsupportedVersion(u32),
+    Truncated { needed: usize, available: usize },
+    Invalid(String),
+    /// An entry failed to decompress
+    Decompress { path: String, message: String },
+    /// An entry's data doesn't match its content hash
+    HashMismatch { path: String },
+}
+
+impl fmt::Display for PackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackError::BadMagic => write!(f, "not an asset pack (bad magic)"),
+            PackError::UnsupportedVersion(version) => write!(f, "unsupported asset pack version {}", version),
+            PackError::Truncated { needed, available } => {
+                write!(f, "asset pack is truncated: needed {} bytes, got {}", needed, available)
+            }
+            PackError::Invalid(message) => write!(f, "invalid asset pack: {}", message),
+            PackError::Decompress { path, message } => write!(f, "failed to decompress {:?}: {}", path, message),
+            PackError::HashMismatch { path } => write!(f, "{:?} doesn't match its content hash", path),
+        }
+    }
+}
+
+impl std::error::Error for PackError {}
+
+/// 64-bit FNV-1a hash of `data`
+pub fn content_hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3))
+}
+
+/// Builds a pack from entries added in any order
+#[derive(Default)]
+pub struct PackWriter {
+    entries: Vec<WriterEntry>,
+}
+
+struct WriterEntry {
+    path: String,
+    compression: Compression,
+    stored: Vec<u8>,
+    size: u64,
+    hash: u64,
+}
+
+impl PackWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an entry, compressed with `compression` unless that doesn't make it smaller. Panics if
+    /// an entry already has the same path.
+    pub fn add(&mut self, path: &str, data: &[u8], compression: Compression) {
+        assert!(self.entries.iter().all(|entry| entry.path != path), "asset pack already has an entry {:?}", path);
+
+        let compressed = match compression {
+            Compression::None => None,
+            Compression::Zstd => Some(ruzstd::encoding::compress_to_vec(data, ruzstd::encoding::CompressionLevel::Fastest)),
+            Compression::Lz4 => Some(lz4_flex::block::compress(data)),
+        };
+        let (compression, stored) = match compressed {
+            Some(compressed) if compressed.len() < data.len() => (compression, compressed),
+            _ => (Compression::None, data.to_vec()),
+        };
+
+        self.entries.push(WriterEntry {
+            path: path.to_string(),
+            compression,
+            stored,
+            size: data.len() as u64,
+            hash: content_hash(data),
+        });
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        self.entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut out = vec![0u8; HEADER_SIZE];
+        let mut offsets = Vec::with_capacity(self.entries.len());
+        for entry in &self.entries {
+            out.resize(out.len().next_multiple_of(PACK_ALIGNMENT as usize), 0);
+            offsets.push(out.len() as u64);
+            out.extend_from_slice(&entry.stored);
+        }
+
+        let toc_offset = out.len().next_multiple_of(8);
+        out.resize(toc_offset, 0);
+        let mut path_offset = 0u32;
+        for (entry, offset) in self.entries.iter().zip(&offsets) {
+            out.extend_from_slice(&path_offset.to_le_bytes());
+            out.extend_from_slice(&(entry.path.len() as u32).to_le_bytes());
+            out.extend_from_slice(&(entry.compression as u32).to_le_bytes());
+            out.extend_from_slice(&0u32.to_le_bytes());
+            out.extend_from_slice(&offset.to_le_bytes());
+            out.extend_from_slice(&(entry.stored.len() as u64).to_le_bytes());
+            out.extend_from_slice(&entry.size.to_le_bytes());
+            out.extend_from_slice(&entry.hash.to_le_bytes());
+            path_offset += entry.path.len() as u32;
+        }
+        for entry in &self.entries {
+            out.extend_from_slice(entry.path.as_bytes());
+        }
+        let toc_size = out.len() - toc_offset;
+
+        out[0..4].copy_from_slice(&PACK_MAGIC);
+        out[4..8].copy_from_slice(&PACK_VERSION.to_le_bytes());
+        out[8..12].copy_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        out[16..24].copy_from_slice(&(toc_offset as u64).to_le_bytes());
+        out[24..32].copy_from_slice(&(toc_size as u64).to_le_bytes());
+        out
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackEntry {
+    pub path: String,
+    pub compression: Compression,
+    /// Byte offset of the stored data from the start of the pack
+    pub offset: u64,
+    /// Size of the data as stored in the pack
+    pub stored_size: u64,
+    /// Size of the data once decompressed
+    pub size: u64,
+    pub hash: u64,
+}
+
+impl PackEntry {
+    /// The entry's data as stored in `pack`, which must be the data the index was parsed from
+    pub fn stored<'a>(&self, pack: &'a [u8]) -> &'a [u8] {
+        &pack[self.offset as usize..(self.offset + self.stored_size) as usize]
+    }
+
+    /// The entry's decompressed data, borrowed from `pack` if it isn't compressed
+    pub fn read<'a>(&self, pack: &'a [u8]) -> Result<Cow<'a, [u8]>, PackError> {
+        let data = match self.compression {
+            Compression::None => Cow::Borrowed(self.stored(pack)),
+            _ => {
+                let mut data = vec![0u8; self.size as usize];
+                self.decompress_into(pack, &mut data)?;
+                Cow::Owned(data)
+            }
+        };
+        self.verify(&data)?;
+        Ok(data)
+    }
+
+    /// Decompress the entry straight into `dst`, e.g. a mapped staging buffer, which must be
+    /// exactly `size` bytes
+    pub fn read_into(&self, pack: &[u8], dst: &mut [u8]) -> Result<(), PackError> {
+        if dst.len() as u64 != self.size {
+            return Err(PackError::Invalid(format!("{:?} is {} bytes, not {}", self.path, self.size, dst.len())));
+        }
+        self.decompress_into(pack, dst)?;
+        self.verify(dst)
+    }
+
+    fn decompress_into(&self, pack: &[u8], dst: &mut [u8]) -> Result<(), PackError> {
+        let stored = self.stored(pack);
+        let error = |message: String| PackError::Decompress { path: self.path.clone(), message };
+        match self.compression {
+            Compression::None => dst.copy_from_slice(stored),
+            Compression::Zstd => {
+                let mut decoder = ruzstd::decoding::StreamingDecoder::new(stored).map_err(|e| error(e.to_string()))?;
+                decoder.read_exact(dst).map_err(|e| error(e.to_string()))?;
+                if decoder.read(&mut [0]).map_err(|e| error(e.to_string()))? != 0 {
+                    return Err(error(format!("data is larger than {} bytes", self.size)));
+                }
+            }
+            Compression::Lz4 => {
+                let written = lz4_flex::block::decompress_into(stored, dst).map_err(|e| error(e.to_string()))?;
+                if written != dst.len() {
+                    return Err(error(format!("data is {} bytes, expected {}", written, self.size)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn verify(&self, data: &[u8]) -> Result<(), PackError> {
+        if content_hash(data) != self.hash {
+            return Err(PackError::HashMismatch { path: self.path.clone() });
+        }
+        Ok(())
+    }
+}
+
+/// The table of contents of a pack. Entries are read from the pack's data, which the index
+/// doesn't keep, so a memory map and its index can live side by side.
+#[derive(Debug, Clone, Default)]
+pub struct PackIndex {
+    entries: Vec<PackEntry>,
+}
+
+impl PackIndex {
+    pub fn parse(data: &[u8]) -> Result<Self, PackError> {
+        if data.len() < HEADER_SIZE {
+            return Err(PackError::Truncated { needed: HEADER_SIZE, available: data.len() });
+        }
+        if data[0..4] != PACK_MAGIC {
+            return Err(PackError::BadMagic);
+        }
+
+        let read_u32 = |bytes: &[u8], offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let read_u64 = |bytes: &[u8], offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+
+        let version = read_u32(data, 4);
+        if version != PACK_VERSION {
+            return Err(PackError::UnsupportedVersion(version));
+        }
+        let entry_count = read_u32(data, 8) as usize;
+        let toc_offset = read_u64(data, 16);
+        let toc_size = read_u64(data, 24);
+
+        let toc_end = toc_offset.checked_add(toc_size).ok_or_else(|| PackError::Invalid("table of contents is out of range".to_string()))?;
+        if toc_end > data.len() as u64 {
+            return Err(PackError::Truncated { needed: toc_end as usize, available: data.len() });
+        }
+        let toc = &data[toc_offset as usize..toc_end as usize];
+        let paths = toc.get(entry_count * TOC_ENTRY_SIZE..)
+            .ok_or_else(|| PackError::Invalid(format!("table of contents is too small for {} entries", entry_count)))?;
+
+        let mut entries = Vec::with_capacity(entry_count);
+        for record in toc[..entry_count * TOC_ENTRY_SIZE].chunks_exact(TOC_ENTRY_SIZE) {
+            let path_offset = read_u32(record, 0) as usize;
+            let path_length = read_u32(record, 4) as usize;
+            let path = paths.get(path_offset..path_offset + path_length)
+                .and_then(|path| std::str::from_utf8(path).ok())
+                .ok_or_else(|| PackError::Invalid("entry path is out of range or not UTF-8".to_string()))?;
+            let compression = Compression::from_raw(read_u32(record, 8))
+                .ok_or_else(|| PackError::Invalid(format!("{:?} has unknown compression {}", path, read_u32(record, 8))))?;
+
+            let entry = PackEntry {
+                path: path.to_string(),
+                compression,
+                offset: read_u64(record, 16),
+                stored_size: read_u64(record, 24),
+                size: read_u64(record, 32),
+                hash: read_u64(record, 40),
+            };
+            if entry.offset.checked_add(entry.stored_size).is_none_or(|end| end > data.len() as u64) {
+                return Err(PackError::Invalid(format!("{:?} is out of range", entry.path)));
+            }
+            if entry.compression == Compression::None && entry.stored_size != entry.size {
+                return Err(PackError::Invalid(format!("uncompressed {:?} has mismatched sizes", entry.path)));
+            }
+            if entry.size > entry.stored_size.saturating_mul(MAX_COMPRESSION_RATIO) {
+                return Err(PackError::Invalid(format!("{:?} is {} bytes, stored in {}", entry.path, entry.size, entry.stored_size)));
+            }
+            if entries.last().is_some_and(|last: &PackEntry| last.path >= entry.path) {
+                return Err(PackError::Invalid("entries aren't sorted by path".to_string()));
+            }
+            entries.push(entry);
+        }
+
+        Ok(Self { entries })
+    }
+
+    pub fn entries(&self) -> &[PackEntry] {
+        &self.entries
+    }
+
+    pub fn find(&self, path: &str) -> Option<&PackEntry> {
+        self.position(path).map(|position| &self.entries[position])
+    }
+
+    /// Index into `entries()` of the entry at `path`
+    pub fn position(&self, path: &str) -> Option<usize> {
+        self.entries.binary_search_by(|entry| entry.path.as_str().cmp(path)).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<u8> {
+        let mut writer = PackWriter::new();
+        writer.add("textures/noise", &[1, 2, 3], Compression::Zstd);
+        writer.add("models/plane", &[7; 1000], Compression::Lz4);
+        writer.add("models/cube", &[5; 1000], Compression::Zstd);
+        writer.add("shaders/empty", &[], Compression::None);
+        writer.finish()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = sample();
+        let index = PackIndex::parse(&data).expect("valid pack rejected");
+
+        let paths: Vec<&str> = index.entries().iter().map(|entry| entry.path.as_str()).collect();
+        assert_eq!(paths, ["models/cube", "models/plane", "shaders/empty", "textures/noise"]);
+        assert!(index.entries().iter().all(|entry| entry.offset % PACK_ALIGNMENT == 0));
+
+        let cube = index.find("models/cube").unwrap();
+        assert_eq!(cube.compression, Compression::Zstd);
+        assert!(cube.stored_size < cube.size);
+        assert_eq!(*cube.read(&data).unwrap(), [5; 1000]);
+
+        let plane = index.find("models/plane").unwrap();
+        assert_eq!(plane.compression, Compression::Lz4);
+        let mut staging = vec![0u8; plane.size as usize];
+        plane.read_into(&data, &mut staging).unwrap();
+        assert_eq!(staging, [7; 1000]);
+
+        // Compressing three bytes doesn't pay off, so they're stored and read in place
+        let noise = index.find("textures/noise").unwrap();
+        assert_eq!(noise.compression, Compression::None);
+        assert!(matches!(noise.read(&data), Ok(Cow::Borrowed([1, 2, 3]))));
+
+        assert_eq!(index.find("shaders/empty").unwrap().read(&data).unwrap().len(), 0);
+        assert!(index.find("models/missing").is_none());
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        let data = sample();
+        assert!(matches!(PackIndex::parse(&data[..16]), Err(PackError::Truncated { .. })));
+        assert!(matches!(PackIndex::parse(b"VRMD0000000000000000000000000000"), Err(PackError::BadMagic)));
+
+        let mut newer = data.clone();
+        newer[4] = 2;
+        assert!(matches!(PackIndex::parse(&newer), Err(PackError::UnsupportedVersion(2))));
+
+        let mut truncated_toc = data.clone();
+        truncated_toc.truncate(data.len() - 1);
+        assert!(matches!(PackIndex::parse(&truncated_toc), Err(PackError::Truncated { .. })));
+
+        // Claim the first entry, "models/cube", decompresses to far more than it could
+        let mut oversized = data.clone();
+        let size_offset = u64::from_le_bytes(data[16..24].try_into().unwrap()) as usize + 32;
+        oversized[size_offset..size_offset + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(PackIndex::parse(&oversized), Err(PackError::Invalid(_))));
+
+        // Flip a byte of the uncompressed entry
+        let mut corrupt = data.clone();
+        let noise = PackIndex::parse(&data).unwrap().find("textures/noise").unwrap().clone();
+        corrupt[noise.offset as usize] ^= 0xFF;
+        assert!(matches!(noise.read(&corrupt), Err(PackError::HashMismatch { .. })));
+        assert!(matches!(noise.read_into(&data, &mut [0; 2]), Err(PackError::Invalid(_))));
+    }
+}
@@ -0,0 +1,522 @@
+// KTX2 texture containers, shared by the build script (which writes them) and the runtime (which
+// reads them).
+//
+// A KTX2 file is laid out as:
+//
+//   identifier   «KTX 20»\r\n\x1A\n
+//   header       vkFormat, typeSize, pixelWidth, pixelHeight, pixelDepth, layerCount, faceCount,
+//                levelCount, supercompressionScheme: u32 each
+//   index        dfd offset/length: u32, key/value data offset/length: u32,
+//                supercompression global data offset/length: u64
+//   level index  levelCount * (byteOffset, byteLength, uncompressedByteLength): u64 each,
+//                starting with the largest mip
+//   data format descriptor, key/value data, supercompression global data
+//   mip levels   from the smallest to the largest, each holding every layer, face and z slice
+//
+// All values are little-endian. See https://registry.khronos.org/KTX/specs/2.0/ktxspec.v2.html
+
+use std::fmt;
+
+pub const KTX2_IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+
+const HEADER_SIZE: usize = 80;
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+/// Mip levels are aligned to 16 bytes, a multiple of every supported texel block size and of 4
+const LEVEL_ALIGNMENT: usize = 16;
+
+/// Raw VkFormat values of the formats textures are read and written in
+pub mod vk_format {
+    /// Used by Basis Universal textures, whose format is chosen when they're transcoded
+    pub const UNDEFINED: u32 = 0;
+    pub const R8_UNORM: u32 = 9;
+    pub const R8G8_UNORM: u32 = 16;
+    pub const R8G8B8A8_UNORM: u32 = 37;
+    pub const R8G8B8A8_SRGB: u32 = 43;
+    pub const B8G8R8A8_UNORM: u32 = 44;
+    pub const B8G8R8A8_SRGB: u32 = 50;
+    pub const A2B10G10R10_UNORM_PACK32: u32 = 64;
+    pub const R16_UNORM: u32 = 70;
+    pub const R16_SFLOAT: u32 = 76;
+    pub const R16G16_SFLOAT: u32 = 83;
+    pub const R16G16B16A16_UNORM: u32 = 91;
+    pub const R16G16B16A16_SFLOAT: u32 = 97;
+    pub const R32_SFLOAT: u32 = 100;
+    pub const R32G32B32A32_SFLOAT: u32 = 109;
+    pub const B10G11R11_UFLOAT_PACK32: u32 = 122;
+    pub const BC1_RGBA_UNORM_BLOCK: u32 = 133;
+    pub const BC1_RGBA_SRGB_BLOCK: u32 = 134;
+    pub const BC2_UNORM_BLOCK: u32 = 135;
+    pub const BC2_SRGB_BLOCK: u32 = 136;
+    pub const BC3_UNORM_BLOCK: u32 = 137;
+    pub const BC3_SRGB_BLOCK: u32 = 138;
+    pub const BC4_UNORM_BLOCK: u32 = 139;
+    pub const BC4_SNORM_BLOCK: u32 = 140;
+    pub const BC5_UNORM_BLOCK: u32 = 141;
+    pub const BC5_SNORM_BLOCK: u32 = 142;
+    pub const BC6H_UFLOAT_BLOCK: u32 = 143;
+    pub const BC6H_SFLOAT_BLOCK: u32 = 144;
+    pub const BC7_UNORM_BLOCK: u32 = 145;
+    pub const BC7_SRGB_BLOCK: u32 = 146;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Supercompression {
+    None = 0,
+    BasisLz = 1,
+    Zstandard = 2,
+    Zlib = 3,
+}
+
+impl Supercompression {
+    fn from_raw(value: u32) -> Option<Self> {
+        Some(match value {
+            0 => Supercompression::None,
+            1 => Supercompression::BasisLz,
+            2 => Supercompression::Zstandard,
+            3 => Supercompression::Zlib,
+            _ => return None,
+        })
+    }
+}
+
+/// Size of a format's texel blocks: 1x1 for uncompressed formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatInfo {
+    pub block_width: u32,
+    pub block_height: u32,
+    pub block_bytes: u32,
+    /// Whether the format's color values are sRGB encoded
+    pub srgb: bool,
+}
+
+impl FormatInfo {
+    /// Block layout of a supported format, or None for formats textures can't be stored in
+    pub const fn of(format: u32) -> Option<Self> {
+        let (block_width, block_height, block_bytes, srgb) = match format {
+            vk_format::R8_UNORM => (1, 1, 1, false),
+            vk_format::R8G8_UNORM | vk_format::R16_UNORM | vk_format::R16_SFLOAT => (1, 1, 2, false),
+            vk_format::R8G8B8A8_UNORM | vk_format::B8G8R8A8_UNORM | vk_format::A2B10G10R10_UNORM_PACK32
+            | vk_format::R16G16_SFLOAT | vk_format::R32_SFLOAT | vk_format::B10G11R11_UFLOAT_PACK32 => (1, 1, 4, false),
+            vk_format::R8G8B8A8_SRGB | vk_format::B8G8R8A8_SRGB => (1, 1, 4, true),
+            vk_format::R16G16B16A16_UNORM | vk_format::R16G16B16A16_SFLOAT => (1, 1, 8, false),
+            vk_format::R32G32B32A32_SFLOAT => (1, 1, 16, false),
+            vk_format::BC1_RGBA_UNORM_BLOCK | vk_format::BC4_UNORM_BLOCK | vk_format::BC4_SNORM_BLOCK => (4, 4, 8, false),
+            vk_format::BC1_RGBA_SRGB_BLOCK => (4, 4, 8, true),
+            vk_format::BC2_UNORM_BLOCK | vk_format::BC3_UNORM_BLOCK | vk_format::BC5_UNORM_BLOCK
+            | vk_format::BC5_SNORM_BLOCK | vk_format::BC6H_UFLOAT_BLOCK | vk_format::BC6H_SFLOAT_BLOCK
+            | vk_format::BC7_UNORM_BLOCK => (4, 4, 16, false),
+            vk_format::BC2_SRGB_BLOCK | vk_format::BC3_SRGB_BLOCK | vk_format::BC7_SRGB_BLOCK => (4, 4, 16, true),
+            _ => return None,
+        };
+        Some(Self { block_width, block_height, block_bytes, srgb })
+    }
+
+    /// Bytes of one image of a mip level
+    pub const fn image_size(&self, width: u32, height: u32) -> usize {
+        let blocks_x = width.div_ceil(self.block_width) as usize;
+        let blocks_y = height.div_ceil(self.block_height) as usize;
+        blocks_x * blocks_y * self.block_bytes as usize
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextureError {
+    BadIdentifier,
+    Truncated { needed: usize, available: usize },
+    UnsupportedFormat(u32),
+    UnsupportedSupercompression(u32),
+    Invalid(String),
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextureError::BadIdentifier => write!(f, "not a KTX2 file (bad identifier)"),
+            TextureError::Truncated { needed, available } => {
+                write!(f, "texture data is truncated: needed {} bytes, got {}", needed, available)
+            }
+            TextureError::UnsupportedFormat(format) => write!(f, "unsupported texture format {}", format),
+            TextureError::UnsupportedSupercompression(scheme) => {
+                write!(f, "unsupported supercompression scheme {}", scheme)
+            }
+            TextureError::Invalid(message) => write!(f, "invalid texture: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {}
+
+/// Dimensions and format of a texture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextureDesc {
+    pub vk_format: u32,
+    pub width: u32,
+    pub height: u32,
+    /// 0 for 1D and 2D textures
+    pub depth: u32,
+    /// 0 for textures that aren't arrays
+    pub layer_count: u32,
+    /// 6 for cube maps, 1 otherwise
+    pub face_count: u32,
+}
+
+impl TextureDesc {
+    pub fn new_2d(vk_format: u32, width: u32, height: u32) -> Self {
+        Self { vk_format, width, height, depth: 0, layer_count: 0, face_count: 1 }
+    }
+
+    /// Size of a mip level
+    pub fn level_extent(&self, level: u32) -> (u32, u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1), (self.depth >> level).max(1))
+    }
+
+    /// Number of images in each mip level: layers * faces * z slices
+    pub fn images_per_level(&self, level: u32) -> usize {
+        self.layer_count.max(1) as usize * self.face_count as usize * self.level_extent(level).2 as usize
+    }
+
+    /// Uncompressed size of a mip level, with every image, in `format`
+    pub fn level_size(&self, format: &FormatInfo, level: u32) -> usize {
+        let (width, height, _) = self.level_extent(level);
+        format.image_size(width, height) * self.images_per_level(level)
+    }
+}
+
+/// Write a KTX2 file without supercompression. `levels` are the data of each mip level, starting
+/// with the largest.
+pub fn write_ktx2(desc: &TextureDesc, levels: &[Vec<u8>]) -> Vec<u8> {
+    let format = FormatInfo::of(desc.vk_format).unwrap_or_else(|| panic!("can't write texture format {}", desc.vk_format));
+    for (level, data) in levels.iter().enumerate() {
+        assert_eq!(data.len(), desc.level_size(&format, level as u32), "mip level {} has the wrong size", level);
+    }
+
+    let dfd = basic_data_format_descriptor(desc.vk_format, &format);
+    let kvd = key_value(b"KTXwriter", b"varre-assets");
+
+    let level_index_size = levels.len() * LEVEL_INDEX_ENTRY_SIZE;
+    let dfd_offset = HEADER_SIZE + level_index_size;
+    let kvd_offset = dfd_offset + dfd.len();
+
+    // Levels are stored smallest first
+    let mut level_offsets = vec![0; levels.len()];
+    let mut offset = kvd_offset + kvd.len();
+    for level in (0..levels.len()).rev() {
+        offset = offset.next_multiple_of(LEVEL_ALIGNMENT);
+        level_offsets[level] = offset;
+        offset += levels[level].len();
+    }
+
+    // Block-compressed and 8-bit formats have a type size of 1
+    let type_size = match desc.vk_format {
+        vk_format::R16G16B16A16_SFLOAT => 2,
+        vk_format::R32G32B32A32_SFLOAT => 4,
+        _ => 1,
+    };
+
+    let mut data = Vec::with_capacity(offset);
+    data.extend_from_slice(&KTX2_IDENTIFIER);
+    for value in [
+        desc.vk_format, type_size, desc.width, desc.height, desc.depth, desc.layer_count, desc.face_count,
+        levels.len() as u32, Supercompression::None as u32,
+    ] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    for value in [dfd_offset as u32, dfd.len() as u32, kvd_offset as u32, kvd.len() as u32] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(&0u64.to_le_bytes());
+    data.extend_from_slice(&0u64.to_le_bytes());
+
+    for (level, bytes) in levels.iter().enumerate() {
+        for value in [level_offsets[level], bytes.len(), bytes.len()] {
+            data.extend_from_slice(&(value as u64).to_le_bytes());
+        }
+    }
+
+    data.extend_from_slice(&dfd);
+    data.extend_from_slice(&kvd);
+
+    for level in (0..levels.len()).rev() {
+        data.resize(level_offsets[level], 0);
+        data.extend_from_slice(&levels[level]);
+    }
+
+    data
+}
+
+/// A key/value data entry, padded to 4 bytes
+fn key_value(key: &[u8], value: &[u8]) -> Vec<u8> {
+    let length = key.len() + 1 + value.len() + 1;
+    let mut entry = Vec::new();
+    entry.extend_from_slice(&(length as u32).to_le_bytes());
+    entry.extend_from_slice(key);
+    entry.push(0);
+    entry.extend_from_slice(value);
+    entry.push(0);
+    entry.resize(entry.len().next_multiple_of(4), 0);
+    entry
+}
+
+/// A data format descriptor with one basic block, as required for formats other than
+/// VK_FORMAT_UNDEFINED
+fn basic_data_format_descriptor(vk_format: u32, format: &FormatInfo) -> Vec<u8> {
+    // Color models
+    const RGBSDA: u8 = 1;
+    const BC1A: u8 = 128;
+    const BC3: u8 = 130;
+    const BC4: u8 = 131;
+    const BC5: u8 = 132;
+    const BC6H: u8 = 133;
+    const BC7: u8 = 134;
+    // Channel qualifiers
+    const LINEAR: u8 = 0x10;
+    const SIGNED: u8 = 0x40;
+    const FLOAT: u8 = 0x80;
+    const ALPHA: u8 = 15;
+
+    // (bit offset, bit length, channel type, lower, upper)
+    let unorm8 = |channels: &[u8]| -> Vec<(u16, u8, u8, u32, u32)> {
+        channels.iter().enumerate().map(|(i, channel)| (i as u16 * 8, 8, *channel, 0, 255)).collect()
+    };
+    let float = |bits: u8, channels: &[u8]| -> Vec<(u16, u8, u8, u32, u32)> {
+        channels.iter()
+            .enumerate()
+            .map(|(i, channel)| (i as u16 * bits as u16, bits, channel | FLOAT | SIGNED, 0xBF80_0000, 0x3F80_0000))
+            .collect()
+    };
+    let block = |channel: u8| vec![(0, 128, channel, 0, u32::MAX)];
+
+    let (color_model, samples) = match vk_format {
+        vk_format::R8_UNORM => (RGBSDA, unorm8(&[0])),
+        vk_format::R8G8_UNORM => (RGBSDA, unorm8(&[0, 1])),
+        vk_format::R8G8B8A8_UNORM => (RGBSDA, unorm8(&[0, 1, 2, ALPHA])),
+        // Alpha is never sRGB encoded
+        vk_format::R8G8B8A8_SRGB => (RGBSDA, unorm8(&[0, 1, 2, ALPHA | LINEAR])),
+        vk_format::B8G8R8A8_UNORM => (RGBSDA, unorm8(&[2, 1, 0, ALPHA])),
+        vk_format::B8G8R8A8_SRGB => (RGBSDA, unorm8(&[2, 1, 0, ALPHA | LINEAR])),
+        vk_format::R16G16B16A16_SFLOAT => (RGBSDA, float(16, &[0, 1, 2, ALPHA])),
+        vk_format::R32G32B32A32_SFLOAT => (RGBSDA, float(32, &[0, 1, 2, ALPHA])),
+        vk_format::BC1_RGBA_UNORM_BLOCK | vk_format::BC1_RGBA_SRGB_BLOCK => (BC1A, vec![(0, 64, 1, 0, u32::MAX)]),
+        vk_format::BC3_UNORM_BLOCK | vk_format::BC3_SRGB_BLOCK => {
+            (BC3, vec![(0, 64, ALPHA | LINEAR, 0, u32::MAX), (64, 64, 0, 0, u32::MAX)])
+        }
+        vk_format::BC4_UNORM_BLOCK => (BC4, vec![(0, 64, 0, 0, u32::MAX)]),
+        vk_format::BC5_UNORM_BLOCK => (BC5, vec![(0, 64, 0, 0, u32::MAX), (64, 64, 1, 0, u32::MAX)]),
+        vk_format::BC6H_UFLOAT_BLOCK => (BC6H, vec![(0, 128, FLOAT, 0, 0x7F80_0000)]),
+        vk_format::BC7_UNORM_BLOCK | vk_format::BC7_SRGB_BLOCK => (BC7, block(0)),
+        _ => panic!("no data format descriptor for texture format {}", vk_format),
+    };
+
+    let transfer = if format.srgb { 2 } else { 1 };
+    let block_size = 24 + 16 * samples.len();
+
+    let mut dfd = Vec::with_capacity(4 + block_size);
+    dfd.extend_from_slice(&((4 + block_size) as u32).to_le_bytes());
+    // Khronos vendor, basic descriptor type
+    dfd.extend_from_slice(&0u32.to_le_bytes());
+    dfd.extend_from_slice(&2u16.to_le_bytes());
+    dfd.extend_from_slice(&(block_size as u16).to_le_bytes());
+    // BT.709 primaries, straight alpha
+    dfd.extend_from_slice(&[color_model, 1, transfer, 0]);
+    dfd.extend_from_slice(&[format.block_width as u8 - 1, format.block_height as u8 - 1, 0, 0]);
+    dfd.extend_from_slice(&[format.block_bytes as u8, 0, 0, 0, 0, 0, 0, 0]);
+    for (bit_offset, bit_length, channel, lower, upper) in samples {
+        dfd.extend_from_slice(&bit_offset.to_le_bytes());
+        dfd.push((bit_length as u16 - 1) as u8);
+        dfd.push(channel);
+        dfd.extend_from_slice(&[0; 4]);
+        dfd.extend_from_slice(&lower.to_le_bytes());
+        dfd.extend_from_slice(&upper.to_le_bytes());
+    }
+    dfd
+}
+
+/// Location of a mip level in the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ktx2Level {
+    pub offset: u64,
+    pub length: u64,
+    pub uncompressed_length: u64,
+}
+
+/// A validated view over a KTX2 file
+#[derive(Debug, Clone)]
+pub struct Ktx2View<'a> {
+    data: &'a [u8],
+    pub desc: TextureDesc,
+    pub type_size: u32,
+    pub supercompression: Supercompression,
+    levels: Vec<Ktx2Level>,
+    dfd: std::ops::Range<usize>,
+    kvd: std::ops::Range<usize>,
+    sgd: std::ops::Range<usize>,
+}
+
+impl<'a> Ktx2View<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, TextureError> {
+        if data.len() < HEADER_SIZE {
+            return Err(TextureError::Truncated { needed: HEADER_SIZE, available: data.len() });
+        }
+        if data[..12] != KTX2_IDENTIFIER {
+            return Err(TextureError::BadIdentifier);
+        }
+
+        let read_u32 = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+        let read_u64 = |offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+
+        let desc = TextureDesc {
+            vk_format: read_u32(12),
+            width: read_u32(20),
+            height: read_u32(24),
+            depth: read_u32(28),
+            layer_count: read_u32(32),
+            face_count: read_u32(36),
+        };
+        let type_size = read_u32(16);
+        // A level count of 0 asks the loader to generate mips; only the base level is stored
+        let level_count = read_u32(40).max(1) as usize;
+        let supercompression = Supercompression::from_raw(read_u32(44))
+            .ok_or(TextureError::UnsupportedSupercompression(read_u32(44)))?;
+
+        if desc.width == 0 || !(desc.face_count == 1 || desc.face_count == 6) {
+            return Err(TextureError::Invalid(format!("{}x{} texture with {} faces", desc.width, desc.height, desc.face_count)));
+        }
+        if level_count > 32 {
+            return Err(TextureError::Invalid(format!("{} mip levels", level_count)));
+        }
+
+        let range = |offset: u64, length: u64| -> Result<std::ops::Range<usize>, TextureError> {
+            let end = offset.checked_add(length).filter(|end| *end <= data.len() as u64)
+                .ok_or(TextureError::Truncated { needed: offset.saturating_add(length) as usize, available: data.len() })?;
+            Ok(offset as usize..end as usize)
+        };
+
+        let dfd = range(read_u32(48) as u64, read_u32(52) as u64)?;
+        let kvd = range(read_u32(56) as u64, read_u32(60) as u64)?;
+        let sgd = range(read_u64(64), read_u64(72))?;
+
+        let index_end = HEADER_SIZE + level_count * LEVEL_INDEX_ENTRY_SIZE;
+        if data.len() < index_end {
+            return Err(TextureError::Truncated { needed: index_end, available: data.len() });
+        }
+
+        let mut levels = Vec::with_capacity(level_count);
+        for level in 0..level_count {
+            let base = HEADER_SIZE + level * LEVEL_INDEX_ENTRY_SIZE;
+            let entry = Ktx2Level { offset: read_u64(base), length: read_u64(base + 8), uncompressed_length: read_u64(base + 16) };
+            range(entry.offset, entry.length)?;
+            levels.push(entry);
+        }
+
+        let view = Self { data, desc, type_size, supercompression, levels, dfd, kvd, sgd };
+
+        // Sizes of supercompressed levels are only known once they're inflated
+        if let (Supercompression::None, Some(format)) = (supercompression, FormatInfo::of(desc.vk_format)) {
+            for (level, entry) in view.levels.iter().enumerate() {
+                let expected = view.desc.level_size(&format, level as u32);
+                if entry.length as usize != expected {
+                    return Err(TextureError::Invalid(format!("mip level {} is {} bytes, expected {}", level, entry.length, expected)));
+                }
+            }
+        }
+
+        Ok(view)
+    }
+
+    /// Block layout of the texture's format, or an error if it isn't supported
+    pub fn format_info(&self) -> Result<FormatInfo, TextureError> {
+        FormatInfo::of(self.desc.vk_format).ok_or(TextureError::UnsupportedFormat(self.desc.vk_format))
+    }
+
+    pub fn levels(&self) -> &[Ktx2Level] {
+        &self.levels
+    }
+
+    /// Stored bytes of a mip level, still supercompressed if the file is
+    pub fn level_data(&self, level: usize) -> &'a [u8] {
+        let entry = &self.levels[level];
+        &self.data[entry.offset as usize..(entry.offset + entry.length) as usize]
+    }
+
+    pub fn data_format_descriptor(&self) -> &'a [u8] {
+        &self.data[self.dfd.clone()]
+    }
+
+    pub fn supercompression_global_data(&self) -> &'a [u8] {
+        &self.data[self.sgd.clone()]
+    }
+
+    /// Key/value pairs, with values including their terminating NUL if they're strings
+    pub fn key_values(&self) -> Vec<(&'a str, &'a [u8])> {
+        let mut pairs = Vec::new();
+        let mut data = &self.data[self.kvd.clone()];
+        while data.len() >= 4 {
+            let length = u32::from_le_bytes(data[..4].try_into().unwrap()) as usize;
+            let Some(entry) = data.get(4..4 + length) else { break };
+            if let Some(split) = entry.iter().position(|b| *b == 0)
+                && let Ok(key) = std::str::from_utf8(&entry[..split])
+            {
+                pairs.push((key, &entry[split + 1..]));
+            }
+            data = data.get((4 + length).next_multiple_of(4)..).unwrap_or(&[]);
+        }
+        pairs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<u8> {
+        let desc = TextureDesc::new_2d(vk_format::R8G8B8A8_SRGB, 4, 2);
+        write_ktx2(&desc, &[vec![1; 32], vec![2; 8], vec![3; 4]])
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = sample();
+        let view = Ktx2View::parse(&data).expect("valid texture rejected");
+
+        assert_eq!(view.desc, TextureDesc::new_2d(vk_format::R8G8B8A8_SRGB, 4, 2));
+        assert_eq!(view.supercompression, Supercompression::None);
+        assert_eq!(view.levels().len(), 3);
+        assert_eq!(view.level_data(1), [2; 8]);
+        assert!(view.levels()[2].offset < view.levels()[0].offset, "smaller mips should come first");
+        assert!(view.levels().iter().all(|level| level.offset % LEVEL_ALIGNMENT as u64 == 0));
+        assert_eq!(view.key_values(), [("KTXwriter", &b"varre-assets\0"[..])]);
+        // Total size, then a basic descriptor block for RGBSDA with sRGB transfer and four samples
+        let dfd = view.data_format_descriptor();
+        assert_eq!(dfd.len(), 4 + 24 + 4 * 16);
+        assert_eq!(dfd[12..14], [1, 1]);
+        assert_eq!(dfd[14], 2);
+    }
+
+    #[test]
+    fn test_block_compressed_sizes() {
+        let bc7 = FormatInfo::of(vk_format::BC7_SRGB_BLOCK).unwrap();
+        assert_eq!(bc7.image_size(5, 4), 2 * 16);
+        assert_eq!(bc7.image_size(1, 1), 16);
+
+        let desc = TextureDesc::new_2d(vk_format::BC5_UNORM_BLOCK, 8, 8);
+        let data = write_ktx2(&desc, &[vec![0; 64], vec![0; 16], vec![0; 16], vec![0; 16]]);
+        assert_eq!(Ktx2View::parse(&data).unwrap().levels().len(), 4);
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        let data = sample();
+
+        assert_eq!(Ktx2View::parse(&data[..40]).unwrap_err(), TextureError::Truncated { needed: HEADER_SIZE, available: 40 });
+        assert!(matches!(Ktx2View::parse(&data[..data.len() - 1]), Err(TextureError::Truncated { .. })));
+
+        let mut bad_identifier = data.clone();
+        bad_identifier[1] = b'X';
+        assert_eq!(Ktx2View::parse(&bad_identifier).unwrap_err(), TextureError::BadIdentifier);
+
+        // Claim the texture is twice as wide, so the stored levels are too small
+        let mut wrong_size = data.clone();
+        wrong_size[20] = 8;
+        assert!(matches!(Ktx2View::parse(&wrong_size), Err(TextureError::Invalid(_))));
+    }
+}
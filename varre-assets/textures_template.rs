@@ -0,0 +1,66 @@
+// Template for generated textures.rs
+// This file is read by build.rs and prepended to the generated texture constants
+
+pub use crate::texture_format::{FormatInfo, Ktx2View, TextureDesc, TextureError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextureID {
+    // TextureID variants will be generated here by build.rs
+}
+
+/// How a texture's values are interpreted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureKind {
+    /// sRGB encoded color, sampled as linear through an sRGB format
+    Color,
+    /// Linear data such as occlusion, roughness and metalness
+    Linear,
+    /// Tangent-space normals in RG; z is reconstructed in the shader
+    Normal,
+    /// Linear floating point color
+    Hdr,
+}
+
+/// A texture imported from the textures directory, with its full mip chain
+#[derive(Debug, Clone, Copy)]
+pub struct Texture {
+    pub id: TextureID,
+    /// Path of the source image relative to the textures directory, with `/` separators
+    pub name: &'static str,
+    pub kind: TextureKind,
+    pub width: u32,
+    pub height: u32,
+    pub mip_levels: u32,
+    /// Raw VkFormat value of the stored mips
+    pub vk_format: u32,
+}
+
+impl Texture {
+    pub fn view(&self) -> Ktx2View<'static> {
+        Ktx2View::parse(self.id.data())
+            .unwrap_or_else(|e| panic!("Texture {:?} was written by this build and must decode: {}", self.id, e))
+    }
+}
+
+pub mod textures {
+    #[allow(unused_imports)]
+    use super::{Texture, TextureID, TextureKind};
+
+    // Texture constants will be generated here by build.rs
+}
+
+impl TextureID {
+    /// Find the texture imported from `path`, matching trailing path components so material
+    /// references like `../textures/bricks.png` resolve to `bricks.png`
+    pub fn from_path(path: &str) -> Option<TextureID> {
+        let path = path.replace('\\', "/");
+        TextureID::all()
+            .iter()
+            .copied()
+            .filter(|id| {
+                let name = id.texture().name;
+                path == name || path.ends_with(&format!("/{}", name))
+            })
+            .max_by_key(|id| id.texture().name.len())
+    }
+}
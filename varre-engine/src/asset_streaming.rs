@@ -0,0 +1,531 @@
+use ash::vk;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use varre_assets::texture_format::{vk_format, write_ktx2};
+use varre_assets::{AssetKind, Assets, Ktx2View, Model, ModelID, TextureDesc};
+use crate::DeviceContext;
+use crate::memory_utils::record_copy_buffer;
+use crate::mesh_utils::{model_upload_size, VulkanMesh};
+use crate::texture_loader::{decode_ktx2, decode_texture, TextureImage};
+use crate::texture_utils::{format_supported, VulkanTexture};
+
+/// A mesh requested from an `AssetStreamer`, drawable once it's `AssetState::Ready`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MeshHandle(usize);
+
+/// A texture requested from an `AssetStreamer`, sampleable once it's `AssetState::Ready`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureHandle(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssetState<'a> {
+    /// Being decoded or uploaded; the placeholder is drawn instead
+    Pending,
+    /// Resident on the GPU
+    Ready,
+    /// Failed to load, with why; the placeholder is drawn for good
+    Failed(&'a str),
+}
+
+enum Slot<T> {
+    /// Being decoded, or decoded and waiting for upload budget
+    Decoding,
+    /// Created, with its copies submitted in the upload that holds this slot
+    Uploading(T),
+    Ready(T),
+    Failed(String),
+}
+
+impl<T> Slot<T> {
+    fn state(&self) -> AssetState<'_> {
+        match self {
+            Slot::Decoding | Slot::Uploading(_) => AssetState::Pending,
+            Slot::Ready(_) => AssetState::Ready,
+            Slot::Failed(error) => AssetState::Failed(error),
+        }
+    }
+
+    fn ready(&self) -> Option<&T> {
+        match self {
+            Slot::Ready(asset) => Some(asset),
+            _ => None,
+        }
+    }
+
+    /// Mark an uploading asset ready, returning it
+    fn finish_upload(&mut self) -> Option<&mut T> {
+        if let Slot::Uploading(asset) = std::mem::replace(self, Slot::Decoding) {
+            *self = Slot::Ready(asset);
+        }
+        match self {
+            Slot::Ready(asset) => Some(asset),
+            _ => None,
+        }
+    }
+}
+
+enum Job {
+    Mesh(usize, String),
+    Texture(usize, String),
+}
+
+enum Decoded {
+    Mesh(usize, Result<Box<Model>, String>),
+    Texture(usize, Result<TextureImage, String>),
+}
+
+impl Decoded {
+    /// Bytes staged for upload if this decoded successfully
+    fn upload_size(&self) -> vk::DeviceSize {
+        match self {
+            Decoded::Mesh(_, Ok(model)) => model_upload_size(model),
+            Decoded::Texture(_, Ok(image)) => image.data.len() as vk::DeviceSize,
+            _ => 0,
+        }
+    }
+}
+
+/// Worker threads decoding models and textures from `Assets`
+struct Decoder {
+    jobs: Option<Sender<(Job, Arc<Assets>)>>,
+    results: Receiver<Decoded>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Decoder {
+    /// Start `worker_count` workers. Textures are transcoded to formats in `supported` where they
+    /// have a choice.
+    fn new(worker_count: usize, supported: Arc<HashSet<vk::Format>>) -> Self {
+        let (job_sender, job_receiver) = mpsc::channel::<(Job, Arc<Assets>)>();
+        let (result_sender, results) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        let workers = (0..worker_count.max(1))
+            .map(|index| {
+                let jobs = job_receiver.clone();
+                let results = result_sender.clone();
+                let supported = supported.clone();
+                std::thread::Builder::new()
+                    .name(format!("varre-asset-decoder-{}", index))
+                    .spawn(move || {
+                        loop {
+                            // The lock is only held while waiting, so workers take turns receiving
+                            let job = jobs.lock().unwrap().recv();
+                            let Ok((job, assets)) = job else { break };
+                            if results.send(decode(job, &assets, &supported)).is_err() {
+                                break;
+                            }
+                        }
+                    })
+                    .expect("failed to spawn asset decoder thread")
+            })
+            .collect();
+
+        Self { jobs: Some(job_sender), results, workers }
+    }
+
+    fn submit(&self, job: Job, assets: Arc<Assets>) {
+        self.jobs.as_ref().unwrap().send((job, assets)).expect("asset decoder threads exited");
+    }
+
+    /// Stop accepting jobs and wait for the workers to finish the ones already queued
+    fn shutdown(&mut self) {
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn decode(job: Job, assets: &Assets, supported: &HashSet<vk::Format>) -> Decoded {
+    match job {
+        Job::Mesh(slot, name) => Decoded::Mesh(slot, assets.load_model(&name).map(Box::new).map_err(|e| e.to_string())),
+        Job::Texture(slot, name) => Decoded::Texture(
+            slot,
+            assets
+                .read(AssetKind::Texture, &name)
+                .map_err(|e| e.to_string())
+                .and_then(|data| decode_texture(&data, &|format| supported.contains(&format)).map_err(|e| e.to_string())),
+        ),
+    }
+}
+
+/// A submitted batch of uploads and the slots waiting on it
+struct Upload {
+    cmd: vk::CommandBuffer,
+    fence: vk::Fence,
+    meshes: Vec<usize>,
+    textures: Vec<usize>,
+}
+
+/// Bytes `AssetStreamer::update` stages per call unless `set_upload_budget` says otherwise
+pub const DEFAULT_UPLOAD_BUDGET: vk::DeviceSize = 32 << 20;
+
+/// Loads meshes and textures in the background. Requests return a handle straight away; worker
+/// threads decode the asset, and `update` creates its GPU resources and submits their copies on
+/// the graphics queue, staging at most the upload budget per call. Until the copies complete, the
+/// handle is pending and render contexts draw the placeholder.
+pub struct AssetStreamer {
+    assets: Arc<Assets>,
+    decoder: Decoder,
+    /// Decoded assets waiting for upload budget, oldest first
+    decoded: VecDeque<Decoded>,
+    upload_budget: vk::DeviceSize,
+    requests: HashMap<(AssetKind, String), usize>,
+    meshes: Vec<Slot<VulkanMesh>>,
+    textures: Vec<Slot<VulkanTexture>>,
+    placeholder_mesh: VulkanMesh,
+    placeholder_texture: VulkanTexture,
+    command_pool: vk::CommandPool,
+    uploads: Vec<Upload>,
+    /// Command buffers and fences of completed uploads, for reuse
+    free_uploads: Vec<(vk::CommandBuffer, vk::Fence)>,
+}
+
+impl AssetStreamer {
+    /// Start a streamer with a worker per spare CPU core. The placeholders are uploaded before
+    /// this returns.
+    pub fn new(device_context: &DeviceContext, assets: Assets, queue_family_index: u32) -> Self {
+        let worker_count = std::thread::available_parallelism().map_or(1, |count| count.get().saturating_sub(1));
+
+        // Probe every core format, so workers can choose what textures are transcoded to without
+        // the device
+        let supported_formats: Arc<HashSet<vk::Format>> = Arc::new(
+            (1..=vk::Format::ASTC_12X12_SRGB_BLOCK.as_raw())
+                .map(vk::Format::from_raw)
+                .filter(|format| format_supported(device_context, *format))
+                .collect(),
+        );
+
+        let command_pool_create_info = vk::CommandPoolCreateInfo::default()
+            .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
+            .queue_family_index(queue_family_index);
+        let command_pool = unsafe {
+            device_context.device.create_command_pool(&command_pool_create_info, None).expect("failed to create command pool")
+        };
+
+        // The generated cube is always compiled in and tiny, and a white texel samples as if
+        // there were no texture
+        let placeholder_mesh = VulkanMesh::from_model(device_context, &ModelID::CUBE.load());
+        let white = write_ktx2(&TextureDesc::new_2d(vk_format::R8G8B8A8_UNORM, 1, 1), &[vec![255; 4]]);
+        let white = decode_ktx2(&Ktx2View::parse(&white).unwrap(), &|_| true).unwrap();
+        let placeholder_texture = VulkanTexture::new(device_context, &white).expect("failed to create placeholder texture");
+
+        let mut streamer = Self {
+            assets: Arc::new(assets),
+            decoder: Decoder::new(worker_count, supported_formats),
+            decoded: VecDeque::new(),
+            upload_budget: DEFAULT_UPLOAD_BUDGET,
+            requests: HashMap::new(),
+            meshes: Vec::new(),
+            textures: Vec::new(),
+            placeholder_mesh,
+            placeholder_texture,
+            command_pool,
+            uploads: Vec::new(),
+            free_uploads: Vec::new(),
+        };
+
+        let (cmd, fence) = streamer.acquire_upload(device_context);
+        submit_upload(device_context, cmd, fence, &[&streamer.placeholder_mesh], &[&streamer.placeholder_texture]);
+        unsafe { device_context.device.wait_for_fences(&[fence], true, u64::MAX).unwrap() };
+        streamer.placeholder_mesh.release_staging(device_context);
+        streamer.placeholder_texture.release_staging(device_context);
+        streamer.uploads.push(Upload { cmd, fence, meshes: Vec::new(), textures: Vec::new() });
+        streamer.retire_uploads(device_context);
+
+        streamer
+    }
+
+    /// Replace where later requests are read from. Assets already requested are kept.
+    pub fn set_assets(&mut self, assets: Assets) {
+        self.assets = Arc::new(assets);
+    }
+
+    /// Limit the bytes `update` stages and copies per call. An asset larger than the budget is
+    /// still uploaded, on its own.
+    pub fn set_upload_budget(&mut self, bytes: vk::DeviceSize) {
+        self.upload_budget = bytes;
+    }
+
+    /// Start loading the model named `name`, e.g. `ModelID::name()`. Requesting the same model
+    /// again returns the same handle.
+    pub fn load_mesh(&mut self, name: &str) -> MeshHandle {
+        MeshHandle(self.request(AssetKind::Model, name))
+    }
+
+    /// Start loading the texture named `name`, e.g. `TextureID::name()`. Requesting the same
+    /// texture again returns the same handle.
+    pub fn load_texture(&mut self, name: &str) -> TextureHandle {
+        TextureHandle(self.request(AssetKind::Texture, name))
+    }
+
+    fn request(&mut self, kind: AssetKind, name: &str) -> usize {
+        if let Some(&slot) = self.requests.get(&(kind, name.to_string())) {
+            return slot;
+        }
+
+        let (slot, job) = match kind {
+            AssetKind::Texture => {
+                self.textures.push(Slot::Decoding);
+                (self.textures.len() - 1, Job::Texture(self.textures.len() - 1, name.to_string()))
+            }
+            _ => {
+                self.meshes.push(Slot::Decoding);
+                (self.meshes.len() - 1, Job::Mesh(self.meshes.len() - 1, name.to_string()))
+            }
+        };
+        self.decoder.submit(job, self.assets.clone());
+        self.requests.insert((kind, name.to_string()), slot);
+        slot
+    }
+
+    pub fn mesh_state(&self, handle: MeshHandle) -> AssetState<'_> {
+        self.meshes[handle.0].state()
+    }
+
+    pub fn texture_state(&self, handle: TextureHandle) -> AssetState<'_> {
+        self.textures[handle.0].state()
+    }
+
+    /// The mesh, if it's resident
+    pub fn mesh(&self, handle: MeshHandle) -> Option<&VulkanMesh> {
+        self.meshes[handle.0].ready()
+    }
+
+    /// The mesh if it's resident, and the placeholder mesh otherwise
+    pub fn mesh_or_placeholder(&self, handle: MeshHandle) -> &VulkanMesh {
+        self.mesh(handle).unwrap_or(&self.placeholder_mesh)
+    }
+
+    /// The texture, if it's resident
+    pub fn texture(&self, handle: TextureHandle) -> Option<&VulkanTexture> {
+        self.textures[handle.0].ready()
+    }
+
+    /// The texture if it's resident, and a white placeholder otherwise
+    pub fn texture_or_placeholder(&self, handle: TextureHandle) -> &VulkanTexture {
+        self.texture(handle).unwrap_or(&self.placeholder_texture)
+    }
+
+    /// Whether every requested asset is ready or has failed
+    pub fn is_idle(&self) -> bool {
+        self.meshes.iter().all(|slot| slot.state() != AssetState::Pending)
+            && self.textures.iter().all(|slot| slot.state() != AssetState::Pending)
+    }
+
+    /// Mark finished uploads ready, and create and submit the uploads of decoded assets, up to the
+    /// upload budget; the rest wait for later calls. Called once per frame, from the thread that
+    /// owns the device context.
+    pub fn update(&mut self, device_context: &DeviceContext) {
+        self.retire_uploads(device_context);
+        self.decoded.extend(self.decoder.results.try_iter());
+
+        let mut meshes = Vec::new();
+        let mut textures = Vec::new();
+        let mut staged: vk::DeviceSize = 0;
+        while let Some(size) = self.decoded.front().map(Decoded::upload_size) {
+            // Something is always uploaded, so an asset over the budget can't stall the queue
+            if staged > 0 && staged + size > self.upload_budget {
+                break;
+            }
+            staged += size;
+
+            match self.decoded.pop_front().unwrap() {
+                Decoded::Mesh(slot, Ok(model)) => {
+                    self.meshes[slot] = Slot::Uploading(VulkanMesh::from_model(device_context, &model));
+                    meshes.push(slot);
+                }
+                Decoded::Texture(slot, Ok(image)) => match VulkanTexture::new(device_context, &image) {
+                    Ok(texture) => {
+                        self.textures[slot] = Slot::Uploading(texture);
+                        textures.push(slot);
+                    }
+                    Err(e) => self.textures[slot] = Slot::Failed(e.to_string()),
+                },
+                Decoded::Mesh(slot, Err(e)) => self.meshes[slot] = Slot::Failed(e),
+                Decoded::Texture(slot, Err(e)) => self.textures[slot] = Slot::Failed(e),
+            }
+        }
+
+        if meshes.is_empty() && textures.is_empty() {
+            return;
+        }
+
+        let (cmd, fence) = self.acquire_upload(device_context);
+        let uploading_meshes: Vec<&VulkanMesh> = meshes.iter().filter_map(|&slot| match &self.meshes[slot] {
+            Slot::Uploading(mesh) => Some(mesh),
+            _ => None,
+        }).collect();
+        let uploading_textures: Vec<&VulkanTexture> = textures.iter().filter_map(|&slot| match &self.textures[slot] {
+            Slot::Uploading(texture) => Some(texture),
+            _ => None,
+        }).collect();
+        submit_upload(device_context, cmd, fence, &uploading_meshes, &uploading_textures);
+        self.uploads.push(Upload { cmd, fence, meshes, textures });
+    }
+
+    /// A command buffer and unsignaled fence for an upload
+    fn acquire_upload(&mut self, device_context: &DeviceContext) -> (vk::CommandBuffer, vk::Fence) {
+        self.free_uploads.pop().unwrap_or_else(|| unsafe {
+            let command_buffer_allocate_info = vk::CommandBufferAllocateInfo::default()
+                .command_buffer_count(1)
+                .command_pool(self.command_pool)
+                .level(vk::CommandBufferLevel::PRIMARY);
+            let cmd = device_context.device.allocate_command_buffers(&command_buffer_allocate_info).expect("failed to allocate command buffer")[0];
+            let fence = device_context.device.create_fence(&vk::FenceCreateInfo::default(), None).expect("failed to create fence");
+            (cmd, fence)
+        })
+    }
+
+    /// Mark the slots of completed uploads ready, free their staging buffers and recycle their
+    /// command buffers
+    fn retire_uploads(&mut self, device_context: &DeviceContext) {
+        let mut index = 0;
+        while index < self.uploads.len() {
+            let signaled = unsafe { device_context.device.get_fence_status(self.uploads[index].fence) }.unwrap_or(false);
+            if !signaled {
+                index += 1;
+                continue;
+            }
+
+            let upload = self.uploads.swap_remove(index);
+            for slot in upload.meshes {
+                if let Some(mesh) = self.meshes[slot].finish_upload() {
+                    mesh.release_staging(device_context);
+                }
+            }
+            for slot in upload.textures {
+                if let Some(texture) = self.textures[slot].finish_upload() {
+                    texture.release_staging(device_context);
+                }
+            }
+            unsafe {
+                device_context.device.reset_fences(&[upload.fence]).unwrap();
+                device_context.device.reset_command_buffer(upload.cmd, vk::CommandBufferResetFlags::empty()).unwrap();
+            }
+            self.free_uploads.push((upload.cmd, upload.fence));
+        }
+    }
+
+    /// Stop the workers and destroy every streamed asset. The device must be idle.
+    pub fn destroy(&mut self, device_context: &DeviceContext) {
+        self.decoder.shutdown();
+        self.retire_uploads(device_context);
+
+        for slot in self.meshes.drain(..) {
+            if let Slot::Uploading(mesh) | Slot::Ready(mesh) = slot {
+                mesh.destroy(device_context);
+            }
+        }
+        for slot in self.textures.drain(..) {
+            if let Slot::Uploading(texture) | Slot::Ready(texture) = slot {
+                texture.destroy(device_context);
+            }
+        }
+        self.placeholder_mesh.destroy(device_context);
+        self.placeholder_texture.destroy(device_context);
+
+        unsafe {
+            for upload in self.uploads.drain(..) {
+                device_context.device.destroy_fence(upload.fence, None);
+            }
+            for (_, fence) in self.free_uploads.drain(..) {
+                device_context.device.destroy_fence(fence, None);
+            }
+            device_context.device.destroy_command_pool(self.command_pool, None);
+        }
+    }
+}
+
+/// Record the copies of `meshes` and `textures` into `cmd`, followed by a barrier making them
+/// visible to later draws on the queue, and submit it signaling `fence`
+fn submit_upload(device_context: &DeviceContext, cmd: vk::CommandBuffer, fence: vk::Fence, meshes: &[&VulkanMesh], textures: &[&VulkanTexture]) {
+    unsafe {
+        let command_buffer_begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        device_context.device.begin_command_buffer(cmd, &command_buffer_begin_info).unwrap();
+
+        for mesh in meshes {
+            record_copy_buffer(device_context, cmd, mesh.vertex_staging_buffer, mesh.vertex_buffer, mesh.vertex_buffer_size);
+            record_copy_buffer(device_context, cmd, mesh.index_staging_buffer, mesh.index_buffer, mesh.index_buffer_size);
+        }
+        for texture in textures {
+            texture.record_upload(device_context, cmd);
+        }
+
+        let memory_barrier = [vk::MemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::COPY)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::VERTEX_INPUT | vk::PipelineStageFlags2::ALL_GRAPHICS | vk::PipelineStageFlags2::COMPUTE_SHADER)
+            .dst_access_mask(vk::AccessFlags2::VERTEX_ATTRIBUTE_READ | vk::AccessFlags2::INDEX_READ | vk::AccessFlags2::SHADER_READ)];
+        let dependency_info = vk::DependencyInfo::default().memory_barriers(&memory_barrier);
+        device_context.device.cmd_pipeline_barrier2(cmd, &dependency_info);
+
+        device_context.device.end_command_buffer(cmd).unwrap();
+
+        let command_buffers = [cmd];
+        let submit_info = vk::SubmitInfo::default().command_buffers(&command_buffers);
+        device_context
+            .device
+            .queue_submit(device_context.graphics_queue, &[submit_info], fence)
+            .expect("failed to submit upload command buffer");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+    use std::time::Duration;
+    use varre_assets::model_format::test_triangle_model;
+    use varre_assets::{AssetError, AssetSource};
+
+    /// A single triangle named "triangle"
+    struct TriangleSource;
+
+    impl AssetSource for TriangleSource {
+        fn read(&self, kind: AssetKind, name: &str) -> Result<Cow<'_, [u8]>, AssetError> {
+            if kind != AssetKind::Model || name != "triangle" {
+                return Err(AssetError::NotFound { kind, name: name.to_string() });
+            }
+            Ok(Cow::Owned(test_triangle_model()))
+        }
+
+        fn names(&self, kind: AssetKind) -> Vec<String> {
+            if kind == AssetKind::Model { vec!["triangle".to_string()] } else { Vec::new() }
+        }
+    }
+
+    #[test]
+    fn test_decoder() {
+        let supported = Arc::new(HashSet::from([vk::Format::R8G8B8A8_UNORM]));
+        let mut decoder = Decoder::new(2, supported);
+        let assets = Arc::new(Assets::empty().with_source(TriangleSource));
+
+        decoder.submit(Job::Mesh(0, "triangle".to_string()), assets.clone());
+        decoder.submit(Job::Mesh(1, "missing".to_string()), assets.clone());
+        decoder.submit(Job::Texture(2, "triangle".to_string()), assets);
+
+        let mut results: Vec<Decoded> = (0..3).map(|_| decoder.results.recv_timeout(Duration::from_secs(10)).expect("decoder stalled")).collect();
+        results.sort_by_key(|decoded| match decoded {
+            Decoded::Mesh(slot, _) | Decoded::Texture(slot, _) => *slot,
+        });
+
+        assert!(matches!(&results[0], Decoded::Mesh(0, Ok(model)) if model.verts.len() == 3));
+        assert!(matches!(&results[1], Decoded::Mesh(1, Err(_))));
+        assert!(matches!(&results[2], Decoded::Texture(2, Err(_))));
+
+        decoder.shutdown();
+        assert!(decoder.workers.is_empty());
+    }
+
+    #[test]
+    fn test_failed_state() {
+        let slot: Slot<()> = Slot::Failed("asset not found".to_string());
+        assert_eq!(slot.state(), AssetState::Failed("asset not found"));
+        assert_eq!(Decoded::Mesh(0, Err("asset not found".to_string())).upload_size(), 0);
+    }
+}
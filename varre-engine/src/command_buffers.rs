@@ -25,6 +25,21 @@ pub fn record_image_layout_transition(device: &Device, cmd : vk::CommandBuffer,
    }
 }
 
+/// Push `data` as the whole push constant block described by `range`.
+/// Panics if the size of `T` doesn't match the reflected block size.
+pub fn cmd_push_constants<T: Copy>(device_context: &DeviceContext, cmd: vk::CommandBuffer, pipeline_layout: vk::PipelineLayout, range: &vk::PushConstantRange, data: &T) {
+   assert_eq!(
+       size_of::<T>(), range.size as usize,
+       "push constant data is {} bytes but the shader's push constant block is {} bytes",
+       size_of::<T>(), range.size
+   );
+
+   unsafe {
+       let bytes = std::slice::from_raw_parts(data as *const T as *const u8, size_of::<T>());
+       device_context.device.cmd_push_constants(cmd, pipeline_layout, range.stage_flags, range.offset, bytes);
+   }
+}
+
 pub(crate) fn record_mesh_draw_setup(device_context: &DeviceContext, cmd: &vk::CommandBuffer, mesh: &VulkanMesh) {
    // Setup vertex and index buffers
 
@@ -1,32 +1,47 @@
+mod asset_streaming;
 mod command_buffers;
 mod geometry;
+mod material_utils;
 mod memory_utils;
 mod mesh_utils;
 mod physical_device_utils;
+pub mod primitives;
 mod render_context;
+mod shader_cache;
+mod shader_program;
 mod shader_utils;
+mod texture_loader;
+mod texture_utils;
 mod vulkan_window;
 mod extensions;
 
 use crate::mesh_utils::VulkanMesh;
 use crate::render_context::mesh_simple::MeshSimpleRenderContext;
+use crate::render_context::meshlet::MeshletRenderContext;
 use ash::vk::SurfaceKHR;
 use ash::{
     Device, Entry, Instance,
-    ext::{debug_utils, shader_object},
+    ext::{debug_utils, mesh_shader, shader_object},
     khr::{surface, swapchain},
     vk,
 };
 use physical_device_utils::*;
 use raw_window_handle::{RawDisplayHandle, RawWindowHandle};
 use render_context::RenderContext;
+pub use asset_streaming::{AssetState, AssetStreamer, MeshHandle, TextureHandle, DEFAULT_UPLOAD_BUDGET};
+pub use material_utils::{GpuMaterial, GpuMaterials, NO_TEXTURE};
 pub use render_context::RenderContextType;
+pub use shader_cache::{ShaderCache, SHADER_CACHE_ENV};
+pub use shader_program::{ShaderProgram, ShaderProgramRegistry};
+pub use shader_utils::{Specialization, SpecializationValue};
+pub use texture_loader::{decode_dds, decode_ktx2, decode_texture, TextureImage};
+pub use texture_utils::VulkanTexture;
 use render_context::triangle::TriangleRenderContext;
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::ffi::CStr;
 use std::{error::Error, ffi, os::raw::c_char};
-use varre_assets::{ModelID, ShaderID};
+use varre_assets::{Assets, ModelID, ShaderID};
 use crate::extensions::unified_image_layouts;
 
 pub const NUM_FRAMES_IN_FLIGHT: usize = 3;
@@ -113,6 +128,7 @@ fn create_device(
     instance: &Instance,
     physical_device: vk::PhysicalDevice,
     queue_family_indices: QueueFamilyIndices,
+    features: DeviceFeatures,
     headless: bool,
 ) -> Result<Device, Box<dyn Error>> {
     let mut queue_create_infos: Vec<vk::DeviceQueueCreateInfo> = vec![];
@@ -145,6 +161,7 @@ fn create_device(
     let device_extension_names_raw: Vec<*const c_char> = [shader_object::NAME.as_ptr(), unified_image_layouts::NAME.as_ptr()]
         .into_iter()
         .chain((!headless).then_some(swapchain::NAME.as_ptr()))
+        .chain(features.mesh_shader.then_some(mesh_shader::NAME.as_ptr()))
         .collect();
 
     let mut shader_object_features =
@@ -156,18 +173,35 @@ fn create_device(
     let mut vulkan11_features =
         vk::PhysicalDeviceVulkan11Features::default().shader_draw_parameters(true);
 
+    let mut vulkan12_features = vk::PhysicalDeviceVulkan12Features::default()
+        .descriptor_indexing(features.descriptor_indexing)
+        .runtime_descriptor_array(features.descriptor_indexing)
+        .descriptor_binding_partially_bound(features.descriptor_indexing)
+        .descriptor_binding_variable_descriptor_count(features.descriptor_indexing)
+        .shader_sampled_image_array_non_uniform_indexing(features.descriptor_indexing)
+        .shader_storage_buffer_array_non_uniform_indexing(features.descriptor_indexing);
+
     let mut vulkan13_features = vk::PhysicalDeviceVulkan13Features::default()
         .synchronization2(true)
         .dynamic_rendering(true);
 
-    let device_create_info = vk::DeviceCreateInfo::default()
+    let mut mesh_shader_features = vk::PhysicalDeviceMeshShaderFeaturesEXT::default()
+        .mesh_shader(true)
+        .task_shader(true);
+
+    let mut device_create_info = vk::DeviceCreateInfo::default()
         .queue_create_infos(&queue_create_infos)
         .enabled_extension_names(&device_extension_names_raw)
         .push_next(&mut shader_object_features)
         .push_next(&mut unified_image_layouts_features)
         .push_next(&mut vulkan11_features)
+        .push_next(&mut vulkan12_features)
         .push_next(&mut vulkan13_features);
 
+    if features.mesh_shader {
+        device_create_info = device_create_info.push_next(&mut mesh_shader_features);
+    }
+
     unsafe {
         instance
             .create_device(physical_device, &device_create_info, None)
@@ -186,6 +220,9 @@ pub struct DeviceContext {
     pub surface_loader: surface::Instance,
     pub swapchain_loader: swapchain::Device,
     pub shader_object_loader: Option<shader_object::Device>,
+    pub mesh_shader_loader: Option<mesh_shader::Device>,
+    pub features: DeviceFeatures,
+    pub shader_cache: ShaderCache,
 }
 
 pub struct VulkanEngine {
@@ -202,6 +239,8 @@ pub struct VulkanEngine {
     debug_utils: Option<(debug_utils::Instance, vk::DebugUtilsMessengerEXT)>,
 
     render_context: Option<Box<dyn RenderContext>>,
+    shader_programs: ShaderProgramRegistry,
+    asset_streamer: AssetStreamer,
 }
 
 impl VulkanEngine {
@@ -232,6 +271,8 @@ impl VulkanEngine {
 
         let queue_family_indices = QueueFamilyIndices::new(&queue_family_properties);
 
+        let features = DeviceFeatures::query(&instance, physical_device);
+
         let debug_utils = enable_validation.then(|| {
             let debug_info = vk::DebugUtilsMessengerCreateInfoEXT::default()
                 .message_severity(
@@ -262,6 +303,7 @@ impl VulkanEngine {
             &instance,
             physical_device,
             queue_family_indices,
+            features,
             display_handle.is_none(),
         )?;
 
@@ -269,6 +311,8 @@ impl VulkanEngine {
         let surface_loader = surface::Instance::new(&entry, &instance);
         let swapchain_loader = swapchain::Device::new(&instance, &device);
         let shader_object_loader = Some(shader_object::Device::new(&instance, &device));
+        let mesh_shader_loader = features.mesh_shader.then(|| mesh_shader::Device::new(&instance, &device));
+        let shader_cache = ShaderCache::new(&instance, &device, physical_device);
 
         let graphics_queue = unsafe {
             Device::get_device_queue(
@@ -287,6 +331,9 @@ impl VulkanEngine {
             surface_loader,
             swapchain_loader,
             shader_object_loader,
+            mesh_shader_loader,
+            features,
+            shader_cache,
         };
 
 
@@ -315,6 +362,8 @@ impl VulkanEngine {
         let one_time_command_buffer = command_buffers[0];
         let draw_command_buffers = command_buffers[1..][..3].try_into()?;
 
+        let asset_streamer = AssetStreamer::new(&device_context, Assets::default(), queue_family_indices.graphics_general.unwrap());
+
         Ok(VulkanEngine {
             device_context,
 
@@ -326,6 +375,8 @@ impl VulkanEngine {
             frame_index: 0,
             debug_utils,
             render_context: None,
+            shader_programs: ShaderProgramRegistry::default(),
+            asset_streamer,
         })
     }
 
@@ -333,17 +384,37 @@ impl VulkanEngine {
         match context_type {
             RenderContextType::Triangle => {
                 self.render_context =
-                    Some(Box::new(TriangleRenderContext::new(&self.device_context)));
+                    Some(Box::new(TriangleRenderContext::new(&self.device_context, &mut self.shader_programs)));
             }
             RenderContextType::MeshSimple => {
                 self.render_context =
-                    Some(Box::new(MeshSimpleRenderContext::new(&self.device_context)));
+                    Some(Box::new(MeshSimpleRenderContext::new(&self.device_context, &mut self.shader_programs, &mut self.asset_streamer)));
+            }
+            RenderContextType::Meshlet => {
+                self.render_context =
+                    Some(Box::new(MeshletRenderContext::new(&self.device_context, &mut self.shader_programs)));
             }
         }
 
         self.setup_render_context();
     }
 
+    /// Look up a shader program created by a render context, or registered by the application
+    pub fn shader_program(&self, name: &str) -> Option<std::rc::Rc<ShaderProgram>> {
+        self.shader_programs.get(name)
+    }
+
+    /// Streams meshes and textures in the background for render contexts and the application
+    pub fn asset_streamer(&mut self) -> &mut AssetStreamer {
+        &mut self.asset_streamer
+    }
+
+    /// Read assets requested from now on from `assets`, e.g. with a `DirectorySource` or
+    /// `PackSource` added
+    pub fn set_assets(&mut self, assets: Assets) {
+        self.asset_streamer.set_assets(assets);
+    }
+
     pub fn add_window(
         &mut self,
         display_handle: RawDisplayHandle,
@@ -409,8 +480,9 @@ impl VulkanEngine {
     }
 
     pub fn draw(&mut self) {
+        self.asset_streamer.update(&self.device_context);
         let cmd = self.get_next_command_buffer();
-        self.window.as_mut().unwrap().render_frame(&self.device_context, cmd, self.render_context.as_mut().unwrap())
+        self.window.as_mut().unwrap().render_frame(&self.device_context, cmd, self.render_context.as_mut().unwrap(), &self.asset_streamer)
     }
 
     pub fn setup_render_context(&mut self) {
@@ -460,11 +532,21 @@ impl Drop for VulkanEngine {
                     .destroy_swapchain(swapchain.vk_swapchain, None);
             }
 
+            // Destroy shader programs
+            self.shader_programs.destroy(&self.device_context);
+
+            // Stop streaming and destroy streamed assets
+            self.asset_streamer.destroy(&self.device_context);
+
             // Destroy command pool (this also frees command buffers)
             self.device_context
                 .device
                 .destroy_command_pool(self.command_pool, None);
 
+            // Persist and destroy the pipeline cache
+            self.device_context.shader_cache.save(&self.device_context.device);
+            self.device_context.shader_cache.destroy(&self.device_context.device);
+
             // Destroy device
             self.device_context.device.destroy_device(None);
 
@@ -517,7 +599,9 @@ mod tests {
 
         let queue_family_indices = QueueFamilyIndices::new(&queue_family_properties);
 
-        create_device(&instance, physical_device, queue_family_indices, false)
+        let features = DeviceFeatures::query(&instance, physical_device);
+
+        create_device(&instance, physical_device, queue_family_indices, features, false)
             .expect("Failed to create VarreEngine device");
     }
 
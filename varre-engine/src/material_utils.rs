@@ -0,0 +1,149 @@
+use ash::vk;
+use std::collections::HashMap;
+use varre_assets::{AlphaMode, Material, MaterialID};
+use crate::DeviceContext;
+use crate::memory_utils::create_buffer_with_data;
+
+/// Texture index of a material slot without a texture
+pub const NO_TEXTURE: u32 = u32::MAX;
+
+pub const MATERIAL_DOUBLE_SIDED: u32 = 1 << 0;
+pub const MATERIAL_ALPHA_MASK: u32 = 1 << 1;
+pub const MATERIAL_ALPHA_BLEND: u32 = 1 << 2;
+
+/// A material as read from a std430 storage buffer by
+///
+/// ```slang
+/// struct GpuMaterial {
+///     float4 baseColor;
+///     float3 emissive;
+///     float metallic;
+///     float roughness;
+///     float alphaCutoff;
+///     uint flags;
+///     uint baseColorTexture;
+///     uint metallicRoughnessTexture;
+///     uint normalTexture;
+///     uint occlusionTexture;
+///     uint emissiveTexture;
+/// };
+/// ```
+///
+/// Texture fields index the texture array bound alongside the materials, or are `NO_TEXTURE`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpuMaterial {
+    pub base_color: [f32; 4],
+    pub emissive: [f32; 3],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub alpha_cutoff: f32,
+    /// `MATERIAL_*` flags
+    pub flags: u32,
+    pub base_color_texture: u32,
+    pub metallic_roughness_texture: u32,
+    pub normal_texture: u32,
+    pub occlusion_texture: u32,
+    pub emissive_texture: u32,
+}
+
+const _: () = assert!(size_of::<GpuMaterial>() == 64);
+
+impl GpuMaterial {
+    /// Pack `material`, mapping each of its texture references to an index with `texture_index`.
+    /// References it can't map are left without a texture.
+    pub fn new(material: &Material, texture_index: &impl Fn(&str) -> Option<u32>) -> Self {
+        let texture = |path: Option<&str>| path.and_then(texture_index).unwrap_or(NO_TEXTURE);
+
+        let mut flags = match material.alpha_mode {
+            AlphaMode::Opaque => 0,
+            AlphaMode::Mask => MATERIAL_ALPHA_MASK,
+            AlphaMode::Blend => MATERIAL_ALPHA_BLEND,
+        };
+        if material.double_sided {
+            flags |= MATERIAL_DOUBLE_SIDED;
+        }
+
+        Self {
+            base_color: material.base_color.to_array(),
+            emissive: material.emissive.to_array(),
+            metallic: material.metallic,
+            roughness: material.roughness,
+            alpha_cutoff: material.alpha_cutoff,
+            flags,
+            base_color_texture: texture(material.base_color_texture),
+            metallic_roughness_texture: texture(material.metallic_roughness_texture),
+            normal_texture: texture(material.normal_texture),
+            occlusion_texture: texture(material.occlusion_texture),
+            emissive_texture: texture(material.emissive_texture),
+        }
+    }
+}
+
+/// A set of materials packed for a storage buffer, indexed in the order they were given
+#[derive(Debug, Default)]
+pub struct GpuMaterials {
+    pub materials: Vec<GpuMaterial>,
+    indices: HashMap<MaterialID, u32>,
+}
+
+impl GpuMaterials {
+    /// Pack `ids`, skipping duplicates
+    pub fn new(ids: &[MaterialID], texture_index: impl Fn(&str) -> Option<u32>) -> Self {
+        let mut materials = Self::default();
+        for id in ids {
+            if !materials.indices.contains_key(id) {
+                materials.indices.insert(*id, materials.materials.len() as u32);
+                materials.materials.push(GpuMaterial::new(id.material(), &texture_index));
+            }
+        }
+        materials
+    }
+
+    /// Index of a material in the buffer, for passing to shaders
+    pub fn index(&self, id: MaterialID) -> Option<u32> {
+        self.indices.get(&id).copied()
+    }
+
+    /// Create a host-visible storage buffer holding the materials
+    pub fn create_buffer(&self, device_context: &DeviceContext) -> (vk::Buffer, vk::DeviceMemory) {
+        create_buffer_with_data(device_context, &self.materials, vk::BufferUsageFlags::STORAGE_BUFFER)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gpu_materials() {
+        let ids = [MaterialID::all()[0], MaterialID::all()[0]];
+        let material = ids[0].material();
+        let materials = GpuMaterials::new(&ids, |_| Some(3));
+
+        assert_eq!(materials.materials.len(), 1);
+        assert_eq!(materials.index(ids[0]), Some(0));
+        assert_eq!(materials.materials[0].base_color, material.base_color.to_array());
+        assert_eq!(materials.materials[0].roughness, material.roughness);
+        assert_eq!(materials.materials[0].flags & MATERIAL_DOUBLE_SIDED != 0, material.double_sided);
+
+        let expected_texture = if material.base_color_texture.is_some() { 3 } else { NO_TEXTURE };
+        assert_eq!(materials.materials[0].base_color_texture, expected_texture);
+    }
+
+    #[test]
+    fn test_gpu_material_flags() {
+        let material = Material {
+            alpha_mode: AlphaMode::Mask,
+            double_sided: true,
+            base_color_texture: Some("base.png"),
+            normal_texture: Some("normal.png"),
+            ..*MaterialID::all()[0].material()
+        };
+        let packed = GpuMaterial::new(&material, &|path| (path == "base.png").then_some(7));
+
+        assert_eq!(packed.flags, MATERIAL_ALPHA_MASK | MATERIAL_DOUBLE_SIDED);
+        assert_eq!(packed.base_color_texture, 7);
+        assert_eq!(packed.normal_texture, NO_TEXTURE);
+    }
+}
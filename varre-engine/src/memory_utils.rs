@@ -45,4 +45,38 @@ pub fn create_buffer(device_context: &DeviceContext, size: vk::DeviceSize, usage
     unsafe { device_context.device.bind_buffer_memory(buffer, device_memory, 0).expect("failed to bind buffer memory!") };
 
     (buffer, device_memory)
-}
\ No newline at end of file
+}
+
+/// Create a host-visible buffer holding a copy of `data`
+pub fn create_buffer_with_data<T: Copy>(device_context: &DeviceContext, data: &[T], usage: vk::BufferUsageFlags) -> (vk::Buffer, vk::DeviceMemory) {
+    // Zero-sized buffers are invalid, so empty data still gets a minimal allocation
+    let size = size_of_val(data).max(4) as vk::DeviceSize;
+    let (buffer, memory) = create_buffer(device_context, size, usage, vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT);
+
+    unsafe {
+        let ptr = device_context.device.map_memory(memory, 0, size, vk::MemoryMapFlags::empty()).expect("failed to map buffer memory!");
+        std::ptr::copy_nonoverlapping(data.as_ptr() as *const u8, ptr as *mut u8, size_of_val(data));
+        device_context.device.unmap_memory(memory);
+    }
+
+    (buffer, memory)
+}
+
+/// Create an image and bind it to a new allocation with `memory_properties`
+pub fn create_image(device_context: &DeviceContext, image_create_info: &vk::ImageCreateInfo, memory_properties: vk::MemoryPropertyFlags) -> (vk::Image, vk::DeviceMemory) {
+    let image = unsafe { device_context.device.create_image(image_create_info, None).expect("failed to create image!") };
+
+    let memory_reqs = unsafe { device_context.device.get_image_memory_requirements(image) };
+    let physical_device_memory_properties = unsafe { device_context.instance.get_physical_device_memory_properties(device_context.physical_device) };
+    let memory_type_index = find_memory_type_index(memory_reqs, physical_device_memory_properties, memory_properties).expect("failed to find suitable memory type");
+
+    let memory_allocate_info = vk::MemoryAllocateInfo::default()
+        .allocation_size(memory_reqs.size)
+        .memory_type_index(memory_type_index);
+
+    let device_memory = unsafe { device_context.device.allocate_memory(&memory_allocate_info, None).expect("failed to allocate memory!") };
+
+    unsafe { device_context.device.bind_image_memory(image, device_memory, 0).expect("failed to bind image memory!") };
+
+    (image, device_memory)
+}
@@ -1,29 +1,160 @@
 use ash::util::Align;
 use ash::vk;
-use glam::Vec3;
+use glam::{Vec2, Vec3, Vec4};
+use std::error::Error;
+use varre_assets::model_format::{index_format_for, ElementFormat};
 use crate::memory_utils::create_buffer;
 
+/// A vertex attribute stream provided by a mesh. Each stream is bound at the binding matching its
+/// index in the mesh's stream list.
+#[derive(Debug, Clone, Copy)]
+pub struct VertexStream {
+    pub semantic: &'static str,
+    pub format: vk::Format,
+    pub stride: u32,
+}
+
+const TEXCOORD_SEMANTICS: [&str; 8] = [
+    "TEXCOORD0", "TEXCOORD1", "TEXCOORD2", "TEXCOORD3", "TEXCOORD4", "TEXCOORD5", "TEXCOORD6", "TEXCOORD7",
+];
+const COLOR_SEMANTICS: [&str; 8] = ["COLOR0", "COLOR1", "COLOR2", "COLOR3", "COLOR4", "COLOR5", "COLOR6", "COLOR7"];
+
+/// Offset of each stream in the shared vertex buffer
+const STREAM_ALIGNMENT: usize = 16;
+
+/// Reinterpret a slice of plain vertex data as bytes
+fn slice_bytes<T: Copy>(data: &[T]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, size_of_val(data)) }
+}
+
+/// The model's vertex streams with their data, in binding order. Sets beyond the supported
+/// semantics are skipped.
+fn model_vertex_streams(model: &varre_assets::Model) -> Vec<(VertexStream, &[u8])> {
+    let mut streams = vec![(
+        VertexStream { semantic: "POSITION", format: vk::Format::R32G32B32_SFLOAT, stride: size_of::<Vec3>() as u32 },
+        slice_bytes(&model.verts),
+    )];
+
+    if !model.normals.is_empty() {
+        streams.push((
+            VertexStream { semantic: "NORMAL", format: vk::Format::R32G32B32_SFLOAT, stride: size_of::<Vec3>() as u32 },
+            slice_bytes(&model.normals),
+        ));
+    }
+    if !model.tangents.is_empty() {
+        streams.push((
+            VertexStream { semantic: "TANGENT", format: vk::Format::R32G32B32A32_SFLOAT, stride: size_of::<Vec4>() as u32 },
+            slice_bytes(&model.tangents),
+        ));
+    }
+    for (semantic, uvs) in TEXCOORD_SEMANTICS.iter().zip(&model.uvs) {
+        streams.push((
+            VertexStream { semantic, format: vk::Format::R32G32_SFLOAT, stride: size_of::<Vec2>() as u32 },
+            slice_bytes(uvs),
+        ));
+    }
+    for (semantic, colors) in COLOR_SEMANTICS.iter().zip(&model.colors) {
+        streams.push((
+            VertexStream { semantic, format: vk::Format::R8G8B8A8_UNORM, stride: size_of::<[u8; 4]>() as u32 },
+            slice_bytes(colors),
+        ));
+    }
+    if !model.joints.is_empty() {
+        streams.push((
+            VertexStream { semantic: "JOINTS", format: vk::Format::R16G16B16A16_UINT, stride: size_of::<[u16; 4]>() as u32 },
+            slice_bytes(&model.joints),
+        ));
+        streams.push((
+            VertexStream { semantic: "WEIGHTS", format: vk::Format::R16G16B16A16_UNORM, stride: size_of::<[u16; 4]>() as u32 },
+            slice_bytes(&model.weights),
+        ));
+    }
+
+    streams
+}
+
+/// A level of detail of a mesh, as a range of its index buffer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeshLod {
+    pub first_index: u32,
+    pub index_count: u32,
+    /// Largest distance of the level's surface from the full detail one, in model units
+    pub error: f32,
+}
+
 pub struct VulkanMesh {
     pub vertex_staging_buffer: vk::Buffer,
     vertex_staging_buffer_memory: vk::DeviceMemory,
+    /// Every vertex stream, each starting at its entry in `vertex_buffer_offsets`
     pub vertex_buffer: vk::Buffer,
     vertex_buffer_memory: vk::DeviceMemory,
     pub vertex_buffer_size: vk::DeviceSize,
+    vertex_streams: Vec<VertexStream>,
+    vertex_stream_offsets: Vec<vk::DeviceSize>,
     pub index_staging_buffer: vk::Buffer,
     index_staging_buffer_memory: vk::DeviceMemory,
     pub index_buffer: vk::Buffer,
     index_buffer_memory: vk::DeviceMemory,
     pub index_buffer_size: vk::DeviceSize,
     pub index_count: u32,
+    /// 16-bit when every index fits, 32-bit otherwise
+    pub index_type: vk::IndexType,
+    /// Every level of detail, starting with the full detail mesh, all in the one index buffer
+    pub lods: Vec<MeshLod>,
+    /// Index ranges of the full detail mesh drawn with one material each, with their own bounds
+    pub submeshes: Vec<varre_assets::Submesh>,
+    pub bounds: varre_assets::Aabb,
+    pub bounding_sphere: varre_assets::BoundingSphere,
 }
 
 impl VulkanMesh {
+    /// The vertex streams this mesh provides, in binding order
+    pub fn vertex_streams(&self) -> &[VertexStream] {
+        &self.vertex_streams
+    }
+
+    /// Vertex buffers in binding order, matching `vertex_streams`
+    pub fn vertex_buffers(&self) -> Vec<vk::Buffer> {
+        vec![self.vertex_buffer; self.vertex_streams.len()]
+    }
+
+    /// Offsets into `vertex_buffers`, matching `vertex_streams`
+    pub fn vertex_buffer_offsets(&self) -> &[vk::DeviceSize] {
+        &self.vertex_stream_offsets
+    }
+
+    /// The least detailed level whose error covers at most `max_pixels` on screen at `distance` from
+    /// the camera. `pixels_per_unit` is the size in pixels of one unit at distance 1, i.e. the
+    /// viewport height over `2 * tan(fov_y / 2)`.
+    pub fn select_lod(&self, distance: f32, pixels_per_unit: f32, max_pixels: f32) -> &MeshLod {
+        select_lod(&self.lods, distance, pixels_per_unit, max_pixels)
+    }
 
     pub fn from_model(device_context: &crate::DeviceContext, model: &varre_assets::Model) -> Self {
         unsafe {
 
-            let vertex_buffer_size = (model.verts.len() * std::mem::size_of::<Vec3>()) as vk::DeviceSize;
-            let index_buffer_size = (model.indices.len() * std::mem::size_of::<u32>()) as vk::DeviceSize;
+            let streams = model_vertex_streams(model);
+            let mut vertex_data = Vec::new();
+            let mut vertex_stream_offsets = Vec::with_capacity(streams.len());
+            for (_, data) in &streams {
+                vertex_data.resize(vertex_data.len().next_multiple_of(STREAM_ALIGNMENT), 0);
+                vertex_stream_offsets.push(vertex_data.len() as vk::DeviceSize);
+                vertex_data.extend_from_slice(data);
+            }
+
+            let vertex_buffer_size = vertex_data.len() as vk::DeviceSize;
+
+            // Levels of detail follow the full detail indices
+            let lods = model_lods(model);
+            let indices: Vec<u32> = model.indices.iter().chain(&model.lod_indices).copied().collect();
+            let (index_type, index_data) = match index_format_for(&indices) {
+                ElementFormat::Uint16 => {
+                    let narrow: Vec<u16> = indices.iter().map(|index| *index as u16).collect();
+                    (vk::IndexType::UINT16, slice_bytes(&narrow).to_vec())
+                }
+                _ => (vk::IndexType::UINT32, slice_bytes(&indices).to_vec()),
+            };
+            let index_buffer_size = index_data.len() as vk::DeviceSize;
 
             let (vertex_staging_buffer, vertex_staging_buffer_memory) = create_buffer(device_context, vertex_buffer_size, vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::VERTEX_BUFFER, vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT);
             let (index_staging_buffer, index_staging_buffer_memory) = create_buffer(device_context, index_buffer_size, vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::INDEX_BUFFER, vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT);
@@ -33,20 +164,20 @@ impl VulkanMesh {
             let vertex_ptr = device_context.device.map_memory(vertex_staging_buffer_memory, 0, vertex_buffer_size, vk::MemoryMapFlags::empty()).unwrap();
 
             let mut vertex_slice = Align::new(vertex_ptr,
-            align_of::<Vec3>() as u64,
+            align_of::<u8>() as u64,
             vertex_buffer_size as u64);
 
-            vertex_slice.copy_from_slice(&model.verts);
+            vertex_slice.copy_from_slice(&vertex_data);
 
             device_context.device.unmap_memory(vertex_staging_buffer_memory);
 
             let index_ptr = device_context.device.map_memory(index_staging_buffer_memory, 0, index_buffer_size, vk::MemoryMapFlags::empty()).unwrap();
 
             let mut index_slice = Align::new(index_ptr,
-            align_of::<u32>() as u64,
+            align_of::<u8>() as u64,
             index_buffer_size as u64);
 
-            index_slice.copy_from_slice(&model.indices);
+            index_slice.copy_from_slice(&index_data);
 
             device_context.device.unmap_memory(index_staging_buffer_memory);
 
@@ -56,13 +187,332 @@ impl VulkanMesh {
                 vertex_buffer,
                 vertex_buffer_memory,
                 vertex_buffer_size,
+                vertex_streams: streams.iter().map(|(stream, _)| *stream).collect(),
+                vertex_stream_offsets,
                 index_staging_buffer,
                 index_staging_buffer_memory,
                 index_buffer,
                 index_buffer_memory,
                 index_buffer_size,
-                index_count: model.indices.len() as u32
+                index_count: model.indices.len() as u32,
+                index_type,
+                lods,
+                submeshes: model.submeshes.clone(),
+                bounds: model.bounds,
+                bounding_sphere: model.bounding_sphere,
+            }
+        }
+    }
+
+    /// Free the staging buffers once the copies out of them have completed. The mesh can't be
+    /// uploaded again afterwards.
+    pub fn release_staging(&mut self, device_context: &crate::DeviceContext) {
+        unsafe {
+            for (buffer, memory) in [
+                (self.vertex_staging_buffer, self.vertex_staging_buffer_memory),
+                (self.index_staging_buffer, self.index_staging_buffer_memory),
+            ] {
+                device_context.device.destroy_buffer(buffer, None);
+                device_context.device.free_memory(memory, None);
+            }
+        }
+        self.vertex_staging_buffer = vk::Buffer::null();
+        self.vertex_staging_buffer_memory = vk::DeviceMemory::null();
+        self.index_staging_buffer = vk::Buffer::null();
+        self.index_staging_buffer_memory = vk::DeviceMemory::null();
+    }
+
+    pub fn destroy(&self, device_context: &crate::DeviceContext) {
+        unsafe {
+            for (buffer, memory) in [
+                (self.vertex_staging_buffer, self.vertex_staging_buffer_memory),
+                (self.vertex_buffer, self.vertex_buffer_memory),
+                (self.index_staging_buffer, self.index_staging_buffer_memory),
+                (self.index_buffer, self.index_buffer_memory),
+            ] {
+                device_context.device.destroy_buffer(buffer, None);
+                device_context.device.free_memory(memory, None);
             }
         }
     }
 }
+
+/// Bytes `VulkanMesh::from_model` stages for `model`, at most
+pub(crate) fn model_upload_size(model: &varre_assets::Model) -> vk::DeviceSize {
+    let vertex_size: usize = model_vertex_streams(model).iter().map(|(_, data)| data.len().next_multiple_of(STREAM_ALIGNMENT)).sum();
+    let index_size = (model.indices.len() + model.lod_indices.len()) * size_of::<u32>();
+    (vertex_size + index_size) as vk::DeviceSize
+}
+
+/// The model's levels of detail as ranges of its indices followed by its LOD indices
+fn model_lods(model: &varre_assets::Model) -> Vec<MeshLod> {
+    let full = MeshLod { first_index: 0, index_count: model.indices.len() as u32, error: 0.0 };
+    let simplified = model.lods.iter().map(|lod| {
+        // Clamped to the LOD indices, so a malformed level draws less instead of reading past them
+        let end = lod.submeshes.iter()
+            .map(|submesh| submesh.index_offset as usize + submesh.index_count as usize)
+            .max()
+            .unwrap_or(0)
+            .min(model.lod_indices.len());
+        let start = lod.submeshes.iter().map(|submesh| submesh.index_offset as usize).min().unwrap_or(0).min(end);
+        MeshLod { first_index: (model.indices.len() + start) as u32, index_count: (end - start) as u32, error: lod.error }
+    });
+
+    std::iter::once(full).chain(simplified).collect()
+}
+
+fn select_lod(lods: &[MeshLod], distance: f32, pixels_per_unit: f32, max_pixels: f32) -> &MeshLod {
+    lods.iter()
+        .rev()
+        .find(|lod| lod.error * pixels_per_unit <= max_pixels * distance)
+        .unwrap_or(&lods[0])
+}
+
+/// A meshlet as read by meshlet.slang
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GpuMeshlet {
+    pub vertex_offset: u32,
+    pub vertex_count: u32,
+    pub triangle_offset: u32,
+    pub triangle_count: u32,
+}
+
+/// A model's meshlets in the layout meshlet.slang reads: each entry of `triangles` packs three
+/// 8-bit meshlet-local indices, and `vertices` maps local indices to model vertices.
+#[derive(Debug, Default)]
+pub struct GpuMeshlets {
+    pub meshlets: Vec<GpuMeshlet>,
+    pub vertices: Vec<u32>,
+    pub triangles: Vec<u32>,
+}
+
+impl GpuMeshlets {
+    pub fn from_model(model: &varre_assets::Model) -> Self {
+        let meshlets = model.meshlets
+            .iter()
+            .map(|meshlet| GpuMeshlet {
+                vertex_offset: meshlet.vertex_offset,
+                vertex_count: meshlet.vertex_count,
+                triangle_offset: meshlet.triangle_offset,
+                triangle_count: meshlet.triangle_count,
+            })
+            .collect();
+
+        let triangles = model.meshlet_triangles
+            .chunks_exact(3)
+            .map(|t| t[0] as u32 | (t[1] as u32) << 8 | (t[2] as u32) << 16)
+            .collect();
+
+        Self { meshlets, vertices: model.meshlet_vertices.clone(), triangles }
+    }
+}
+
+/// Vertex input state for `cmd_set_vertex_input`
+pub struct VertexInputLayout {
+    pub bindings: Vec<vk::VertexInputBindingDescription2EXT<'static>>,
+    pub attributes: Vec<vk::VertexInputAttributeDescription2EXT<'static>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NumericType {
+    Float,
+    Double,
+    SInt,
+    UInt,
+}
+
+/// The numeric type a vertex attribute format is read as in the shader
+fn format_numeric_type(format: vk::Format) -> Option<NumericType> {
+    match format {
+        vk::Format::R32_SFLOAT | vk::Format::R32G32_SFLOAT | vk::Format::R32G32B32_SFLOAT | vk::Format::R32G32B32A32_SFLOAT
+        | vk::Format::R16_SFLOAT | vk::Format::R16G16_SFLOAT | vk::Format::R16G16B16_SFLOAT | vk::Format::R16G16B16A16_SFLOAT
+        | vk::Format::R16G16_UNORM | vk::Format::R16G16B16A16_UNORM | vk::Format::R16G16_SNORM | vk::Format::R16G16B16A16_SNORM
+        | vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SNORM | vk::Format::R8G8_SNORM
+        | vk::Format::A2B10G10R10_UNORM_PACK32 | vk::Format::A2B10G10R10_SNORM_PACK32 => Some(NumericType::Float),
+        vk::Format::R64_SFLOAT | vk::Format::R64G64_SFLOAT | vk::Format::R64G64B64_SFLOAT | vk::Format::R64G64B64A64_SFLOAT => Some(NumericType::Double),
+        vk::Format::R32_SINT | vk::Format::R32G32_SINT | vk::Format::R32G32B32_SINT | vk::Format::R32G32B32A32_SINT
+        | vk::Format::R16G16B16A16_SINT | vk::Format::R8G8B8A8_SINT => Some(NumericType::SInt),
+        vk::Format::R32_UINT | vk::Format::R32G32_UINT | vk::Format::R32G32B32_UINT | vk::Format::R32G32B32A32_UINT
+        | vk::Format::R16G16B16A16_UINT | vk::Format::R8G8B8A8_UINT => Some(NumericType::UInt),
+        _ => None,
+    }
+}
+
+/// Split a semantic into its name and index, e.g. "TEXCOORD1" -> ("TEXCOORD", 1).
+/// A missing index is index 0, and "UV" is treated as an alias of "TEXCOORD".
+fn parse_semantic(semantic: &str) -> (String, u32) {
+    let semantic = semantic.to_uppercase();
+    let name = semantic.trim_end_matches(|c: char| c.is_ascii_digit());
+    let index = semantic[name.len()..].parse().unwrap_or(0);
+    let name = if name == "UV" { "TEXCOORD" } else { name };
+
+    (name.to_string(), index)
+}
+
+/// Build the vertex input state for `shader` by matching each of its inputs against the mesh's
+/// vertex streams by semantic.
+pub fn build_vertex_input_layout(shader: &varre_assets::Shader, streams: &[VertexStream]) -> Result<VertexInputLayout, Box<dyn Error>> {
+    let mut bindings: Vec<vk::VertexInputBindingDescription2EXT<'static>> = Vec::new();
+    let mut attributes = Vec::new();
+
+    for input in shader.inputs {
+        let semantic = parse_semantic(input.semantic);
+
+        let (binding, stream) = streams
+            .iter()
+            .enumerate()
+            .find(|(_, stream)| parse_semantic(stream.semantic) == semantic)
+            .ok_or_else(|| {
+                let available: Vec<&str> = streams.iter().map(|s| s.semantic).collect();
+                format!(
+                    "{:?} reads {} at location {}, but the mesh only provides [{}]",
+                    shader.id, input.semantic, input.location, available.join(", ")
+                )
+            })?;
+
+        let input_format = vk::Format::from_raw(input.format as i32);
+        if format_numeric_type(input_format) != format_numeric_type(stream.format) || format_numeric_type(stream.format).is_none() {
+            return Err(format!(
+                "{:?} reads {} at location {} as {:?}, which is incompatible with the mesh's {:?} stream",
+                shader.id, input.semantic, input.location, input_format, stream.format
+            ).into());
+        }
+
+        if !bindings.iter().any(|b| b.binding == binding as u32) {
+            bindings.push(vk::VertexInputBindingDescription2EXT::default()
+                .binding(binding as u32)
+                .stride(stream.stride)
+                .input_rate(vk::VertexInputRate::VERTEX)
+                .divisor(1));
+        }
+
+        attributes.push(vk::VertexInputAttributeDescription2EXT::default()
+            .binding(binding as u32)
+            .location(input.location)
+            .format(stream.format)
+            .offset(0));
+    }
+
+    Ok(VertexInputLayout { bindings, attributes })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use varre_assets::ShaderInterfaceVariable;
+
+    const STREAMS: [VertexStream; 2] = [
+        VertexStream { semantic: "POSITION", format: vk::Format::R32G32B32_SFLOAT, stride: 12 },
+        VertexStream { semantic: "TEXCOORD0", format: vk::Format::R32G32_SFLOAT, stride: 8 },
+    ];
+
+    fn shader_with_inputs(inputs: &'static [ShaderInterfaceVariable]) -> varre_assets::Shader {
+        let mut shader = *varre_assets::ShaderID::all()[0].shader();
+        shader.inputs = inputs;
+        shader
+    }
+
+    #[test]
+    fn test_build_vertex_input_layout() {
+        const INPUTS: [ShaderInterfaceVariable; 2] = [
+            ShaderInterfaceVariable { location: 0, format: 106, semantic: "POSITION" },
+            ShaderInterfaceVariable { location: 1, format: 103, semantic: "UV" },
+        ];
+
+        let layout = build_vertex_input_layout(&shader_with_inputs(&INPUTS), &STREAMS)
+            .expect("matching streams were rejected");
+
+        assert_eq!(layout.bindings.len(), 2);
+        assert_eq!(layout.attributes[1].binding, 1);
+        assert_eq!(layout.attributes[1].location, 1);
+        assert_eq!(layout.attributes[1].format, vk::Format::R32G32_SFLOAT);
+    }
+
+    #[test]
+    fn test_build_vertex_input_layout_mismatch() {
+        const MISSING: [ShaderInterfaceVariable; 1] = [
+            ShaderInterfaceVariable { location: 2, format: 106, semantic: "NORMAL" },
+        ];
+        const WRONG_TYPE: [ShaderInterfaceVariable; 1] = [
+            ShaderInterfaceVariable { location: 0, format: 107, semantic: "POSITION" },
+        ];
+
+        assert!(build_vertex_input_layout(&shader_with_inputs(&MISSING), &STREAMS).is_err());
+        assert!(build_vertex_input_layout(&shader_with_inputs(&WRONG_TYPE), &STREAMS).is_err());
+    }
+
+    fn test_model() -> varre_assets::Model {
+        varre_assets::Model {
+            id: Some(varre_assets::ModelID::all()[0]),
+            verts: vec![Vec3::ZERO; 4],
+            indices: vec![0, 1, 2, 2, 1, 3],
+            normals: Vec::new(),
+            tangents: Vec::new(),
+            uvs: Vec::new(),
+            colors: Vec::new(),
+            joints: Vec::new(),
+            weights: Vec::new(),
+            submeshes: Vec::new(),
+            bounds: varre_assets::Aabb { min: Vec3::ZERO, max: Vec3::ONE },
+            bounding_sphere: varre_assets::BoundingSphere { center: Vec3::splat(0.5), radius: 0.75f32.sqrt() },
+            meshlets: vec![varre_assets::Meshlet {
+                vertex_offset: 0,
+                vertex_count: 4,
+                triangle_offset: 0,
+                triangle_count: 2,
+                center: Vec3::ZERO,
+                radius: 1.0,
+                cone_apex: Vec3::ZERO,
+                cone_axis: Vec3::Z,
+                cone_cutoff: 1.0,
+            }],
+            meshlet_vertices: vec![0, 1, 2, 3],
+            meshlet_triangles: vec![0, 1, 2, 2, 1, 3],
+            lods: Vec::new(),
+            lod_indices: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_gpu_meshlets_from_model() {
+        let meshlets = GpuMeshlets::from_model(&test_model());
+
+        assert_eq!(meshlets.meshlets, [GpuMeshlet { vertex_offset: 0, vertex_count: 4, triangle_offset: 0, triangle_count: 2 }]);
+        assert_eq!(meshlets.vertices, [0, 1, 2, 3]);
+        assert_eq!(meshlets.triangles, [0x02_01_00, 0x03_01_02]);
+    }
+
+    #[test]
+    fn test_lods() {
+        let mut model = test_model();
+        let (bounds, bounding_sphere) = (model.bounds, model.bounding_sphere);
+        let submesh = |index_offset, index_count| varre_assets::Submesh {
+            index_offset,
+            index_count,
+            vertex_offset: 0,
+            vertex_count: 4,
+            material: 0,
+            bounds,
+            bounding_sphere,
+        };
+        model.lods = vec![
+            varre_assets::ModelLod { submeshes: vec![submesh(0, 3)], error: 0.01 },
+            varre_assets::ModelLod { submeshes: vec![submesh(3, 3)], error: 0.5 },
+        ];
+        model.lod_indices = vec![0, 1, 3, 0, 1, 2];
+
+        let lods = model_lods(&model);
+        assert_eq!(lods[0], MeshLod { first_index: 0, index_count: 6, error: 0.0 });
+        assert_eq!(lods[2], MeshLod { first_index: 9, index_count: 3, error: 0.5 });
+
+        let mut malformed = model.clone();
+        malformed.lods[1].submeshes = vec![submesh(u32::MAX, 3)];
+        assert_eq!(model_lods(&malformed)[2], MeshLod { first_index: 12, index_count: 0, error: 0.5 });
+
+        // 100 pixels per unit: 0.01 units cover a pixel at distance 1, 0.5 units at distance 50
+        assert_eq!(select_lod(&lods, 0.5, 100.0, 1.0).error, 0.0);
+        assert_eq!(select_lod(&lods, 1.0, 100.0, 1.0).error, 0.01);
+        assert_eq!(select_lod(&lods, 50.0, 100.0, 1.0).error, 0.5);
+    }
+}
@@ -43,6 +43,47 @@ impl QueueFamilyIndices {
         }
     }
 }
+/// Optional device features, enabled at device creation when the physical device supports them
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DeviceFeatures {
+    /// Runtime-sized, partially bound descriptor arrays indexed non-uniformly in shaders
+    pub descriptor_indexing: bool,
+    /// `VK_EXT_mesh_shader` with both task and mesh stages
+    pub mesh_shader: bool,
+}
+
+impl DeviceFeatures {
+    pub fn query(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> Self {
+        let mesh_shader_extension = unsafe { instance.enumerate_device_extension_properties(physical_device) }
+            .unwrap_or_default()
+            .iter()
+            .any(|extension| extension.extension_name_as_c_str() == Ok(ash::ext::mesh_shader::NAME));
+
+        let mut vulkan12_features = vk::PhysicalDeviceVulkan12Features::default();
+        let mut mesh_shader_features = vk::PhysicalDeviceMeshShaderFeaturesEXT::default();
+        let mut features2 = vk::PhysicalDeviceFeatures2::default()
+            .push_next(&mut vulkan12_features);
+        // Feature structures of unsupported extensions must not be chained
+        if mesh_shader_extension {
+            features2 = features2.push_next(&mut mesh_shader_features);
+        }
+
+        unsafe { instance.get_physical_device_features2(physical_device, &mut features2) };
+
+        Self {
+            descriptor_indexing: vulkan12_features.descriptor_indexing == vk::TRUE
+                && vulkan12_features.runtime_descriptor_array == vk::TRUE
+                && vulkan12_features.descriptor_binding_partially_bound == vk::TRUE
+                && vulkan12_features.descriptor_binding_variable_descriptor_count == vk::TRUE
+                && vulkan12_features.shader_sampled_image_array_non_uniform_indexing == vk::TRUE
+                && vulkan12_features.shader_storage_buffer_array_non_uniform_indexing == vk::TRUE,
+            mesh_shader: mesh_shader_extension
+                && mesh_shader_features.mesh_shader == vk::TRUE
+                && mesh_shader_features.task_shader == vk::TRUE,
+        }
+    }
+}
+
 pub fn find_memorytype_index(
     device_context: &DeviceContext,
     memory_req: &vk::MemoryRequirements,
@@ -0,0 +1,463 @@
+use glam::{Vec2, Vec3, Vec4};
+use std::collections::HashMap;
+use std::f32::consts::{PI, TAU};
+use varre_assets::{Aabb, BoundingSphere, Model, Submesh};
+
+// Shapes are centered on the origin with +Y up, and have normals, tangents and one set of texture
+// coordinates, like imported models. Triangles wind counter-clockwise around their outward normal.
+// Texture coordinates run right and down as seen from outside, wrapping once around shapes of
+// revolution, with a seam at -X.
+
+/// A sphere of `segments` columns by `rings` rows of quads, with poles on the Y axis. At least 3
+/// segments and 2 rings.
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> Model {
+    let rings = rings.max(2);
+    let profile: Vec<ProfilePoint> = (0..=rings)
+        .map(|ring| {
+            let v = ring as f32 / rings as f32;
+            let normal = from_pole(v * PI);
+            ProfilePoint { position: normal * radius, normal, v }
+        })
+        .collect();
+
+    let mut mesh = MeshBuilder::default();
+    mesh.lathe(&profile, segments);
+    mesh.finish()
+}
+
+/// A sphere made by splitting each triangle of an icosahedron into four `subdivisions` times,
+/// which spreads its vertices more evenly than `uv_sphere`
+pub fn ico_sphere(radius: f32, subdivisions: u32) -> Model {
+    // Golden ratio rectangles in the three axis planes
+    let t = (1.0 + 5f32.sqrt()) / 2.0;
+    let mut directions: Vec<Vec3> = [
+        (-1.0, t, 0.0), (1.0, t, 0.0), (-1.0, -t, 0.0), (1.0, -t, 0.0),
+        (0.0, -1.0, t), (0.0, 1.0, t), (0.0, -1.0, -t), (0.0, 1.0, -t),
+        (t, 0.0, -1.0), (t, 0.0, 1.0), (-t, 0.0, -1.0), (-t, 0.0, 1.0),
+    ]
+    .map(|(x, y, z)| Vec3::new(x, y, z).normalize())
+    .to_vec();
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        // Edges are shared by two triangles, which must share the vertex splitting them too
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut midpoint = |a: u32, b: u32| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                directions.push((directions[a as usize] + directions[b as usize]).normalize());
+                directions.len() as u32 - 1
+            })
+        };
+        triangles = triangles.iter()
+            .flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    // Texture coordinates are mapped as on `uv_sphere`. Triangles spanning the seam get copies of
+    // their vertices on the low side with u past 1, and the poles, which have no longitude, get a
+    // copy per triangle with the triangle's.
+    let is_pole = |direction: Vec3| direction.x.abs() < 1e-6 && direction.z.abs() < 1e-6;
+    let mut mesh = MeshBuilder::default();
+    let mut vertices: HashMap<(u32, u32), u32> = HashMap::new();
+    for triangle in triangles {
+        let corners = triangle.map(|index| directions[index as usize]);
+        let mut u = corners.map(|direction| (direction.z.atan2(-direction.x) / TAU).rem_euclid(1.0));
+        let around: Vec<usize> = (0..3).filter(|corner| !is_pole(corners[*corner])).collect();
+        let (low, high) = around.iter().fold((1.0f32, 0.0f32), |(low, high), corner| (low.min(u[*corner]), high.max(u[*corner])));
+        if high - low > 0.5 {
+            for corner in &around {
+                if u[*corner] < 0.5 {
+                    u[*corner] += 1.0;
+                }
+            }
+        }
+        let longitude = around.iter().map(|corner| u[*corner]).sum::<f32>() / around.len() as f32;
+
+        let indices = [0, 1, 2].map(|corner| {
+            let direction = corners[corner];
+            let u = if is_pole(direction) { longitude } else { u[corner] };
+            *vertices.entry((triangle[corner], u.to_bits())).or_insert_with(|| {
+                let uv = Vec2::new(u, direction.y.clamp(-1.0, 1.0).acos() / PI);
+                mesh.vertex(direction * radius, direction, uv)
+            })
+        });
+        mesh.triangle(indices);
+    }
+    mesh.finish()
+}
+
+/// A plane in XZ facing +Y, of `columns` by `rows` quads
+pub fn plane(size: Vec2, columns: u32, rows: u32) -> Model {
+    let mut mesh = MeshBuilder::default();
+    mesh.grid(Vec3::ZERO, Vec3::Y, Vec3::X * size.x, Vec3::Z * size.y, columns, rows);
+    mesh.finish()
+}
+
+/// A box with its own vertices, normals and texture coordinates on each face, so that it shades
+/// flat and each face shows the whole texture. Each face has `segments` by `segments` quads.
+pub fn cube(size: Vec3, segments: u32) -> Model {
+    // Each face's outward direction, then the directions its texture runs right and down in
+    let faces = [
+        (Vec3::X, Vec3::NEG_Z, Vec3::NEG_Y),
+        (Vec3::NEG_X, Vec3::Z, Vec3::NEG_Y),
+        (Vec3::Y, Vec3::X, Vec3::Z),
+        (Vec3::NEG_Y, Vec3::X, Vec3::NEG_Z),
+        (Vec3::Z, Vec3::X, Vec3::NEG_Y),
+        (Vec3::NEG_Z, Vec3::NEG_X, Vec3::NEG_Y),
+    ];
+
+    let mut mesh = MeshBuilder::default();
+    for (normal, right, down) in faces {
+        mesh.grid(normal * size * 0.5, normal, right * size, down * size, segments, segments);
+    }
+    mesh.finish()
+}
+
+/// A capped cylinder along Y, of `segments` around by `height_segments` along its side
+pub fn cylinder(radius: f32, height: f32, segments: u32, height_segments: u32) -> Model {
+    let height_segments = height_segments.max(1);
+    let profile: Vec<ProfilePoint> = (0..=height_segments)
+        .map(|row| {
+            let v = row as f32 / height_segments as f32;
+            ProfilePoint { position: Vec2::new(radius, height * (0.5 - v)), normal: Vec2::X, v }
+        })
+        .collect();
+
+    let mut mesh = MeshBuilder::default();
+    mesh.lathe(&profile, segments);
+    mesh.disk(height * 0.5, Vec3::Y, radius, segments);
+    mesh.disk(-height * 0.5, Vec3::NEG_Y, radius, segments);
+    mesh.finish()
+}
+
+/// A cone along Y with its apex at the top and a capped base, of `segments` around by
+/// `height_segments` along its side
+pub fn cone(radius: f32, height: f32, segments: u32, height_segments: u32) -> Model {
+    let height_segments = height_segments.max(1);
+    let normal = Vec2::new(height, radius).normalize();
+    let profile: Vec<ProfilePoint> = (0..=height_segments)
+        .map(|row| {
+            let v = row as f32 / height_segments as f32;
+            ProfilePoint { position: Vec2::new(radius * v, height * (0.5 - v)), normal, v }
+        })
+        .collect();
+
+    let mut mesh = MeshBuilder::default();
+    mesh.lathe(&profile, segments);
+    mesh.disk(-height * 0.5, Vec3::NEG_Y, radius, segments);
+    mesh.finish()
+}
+
+/// A torus around Y, with `major_segments` around the ring and `minor_segments` around the tube.
+/// Texture coordinates run around the tube from its top, outwards first.
+pub fn torus(major_radius: f32, minor_radius: f32, major_segments: u32, minor_segments: u32) -> Model {
+    let minor_segments = minor_segments.max(3);
+    let profile: Vec<ProfilePoint> = (0..=minor_segments)
+        .map(|row| {
+            let v = row as f32 / minor_segments as f32;
+            let normal = from_pole(v * TAU);
+            ProfilePoint { position: Vec2::new(major_radius, 0.0) + normal * minor_radius, normal, v }
+        })
+        .collect();
+
+    let mut mesh = MeshBuilder::default();
+    mesh.lathe(&profile, major_segments);
+    mesh.finish()
+}
+
+/// A cylinder along Y with hemispherical ends, `height` tall in all, of `segments` around and
+/// `rings` rows in each hemisphere. Texture coordinates run down the capsule in proportion to
+/// distance along its surface.
+pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32) -> Model {
+    let rings = rings.max(1);
+    let half_length = (height * 0.5 - radius).max(0.0);
+
+    // Each hemisphere ends on the equator, so the rows between them form the cylinder
+    let mut profile: Vec<ProfilePoint> = Vec::with_capacity(2 * rings as usize + 2);
+    for (center, first_ring) in [(half_length, 0), (-half_length, rings)] {
+        for ring in first_ring..=first_ring + rings {
+            let normal = from_pole(ring as f32 / rings as f32 * PI / 2.0);
+            profile.push(ProfilePoint { position: Vec2::new(0.0, center) + normal * radius, normal, v: 0.0 });
+        }
+    }
+    let mut length = 0.0;
+    for row in 1..profile.len() {
+        length += profile[row].position.distance(profile[row - 1].position);
+        profile[row].v = length;
+    }
+    for point in &mut profile {
+        point.v /= length;
+    }
+
+    let mut mesh = MeshBuilder::default();
+    mesh.lathe(&profile, segments);
+    mesh.finish()
+}
+
+/// A point of the outline revolved by `MeshBuilder::lathe`, with `position` and `normal` as
+/// (distance from the Y axis, height)
+struct ProfilePoint {
+    position: Vec2,
+    normal: Vec2,
+    v: f32,
+}
+
+/// Vertices and triangles of a shape under construction
+#[derive(Default)]
+struct MeshBuilder {
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<Vec2>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuilder {
+    fn vertex(&mut self, position: Vec3, normal: Vec3, uv: Vec2) -> u32 {
+        self.positions.push(position);
+        self.normals.push(normal);
+        self.uvs.push(uv);
+        self.positions.len() as u32 - 1
+    }
+
+    /// Add a triangle unless it's degenerate, as at the poles of shapes of revolution, where the
+    /// corners only differ by rounding
+    fn triangle(&mut self, triangle: [u32; 3]) {
+        let [a, b, c] = triangle.map(|index| self.positions[index as usize]);
+        let edges = [a.distance(b), b.distance(c), c.distance(a)];
+        let longest = edges.iter().fold(0.0f32, |longest, edge| longest.max(*edge));
+        if edges.iter().all(|edge| *edge > longest * 1e-4) {
+            self.indices.extend_from_slice(&triangle);
+        }
+    }
+
+    /// Triangulate `columns` by `rows` quads of vertices laid out row by row from `first`, with
+    /// columns running right and rows down as seen from the front
+    fn quads(&mut self, first: u32, columns: u32, rows: u32) {
+        for row in 0..rows {
+            for column in 0..columns {
+                let top_left = first + row * (columns + 1) + column;
+                let bottom_left = top_left + columns + 1;
+                self.triangle([top_left, bottom_left, top_left + 1]);
+                self.triangle([top_left + 1, bottom_left, bottom_left + 1]);
+            }
+        }
+    }
+
+    /// A flat grid around `center` facing `normal`, spanning `right` and `down`
+    fn grid(&mut self, center: Vec3, normal: Vec3, right: Vec3, down: Vec3, columns: u32, rows: u32) {
+        let (columns, rows) = (columns.max(1), rows.max(1));
+        let first = self.positions.len() as u32;
+        for row in 0..=rows {
+            for column in 0..=columns {
+                let uv = Vec2::new(column as f32 / columns as f32, row as f32 / rows as f32);
+                self.vertex(center + right * (uv.x - 0.5) + down * (uv.y - 0.5), normal, uv);
+            }
+        }
+        self.quads(first, columns, rows);
+    }
+
+    /// Revolve `profile`, listed from top to bottom, around the Y axis in `segments` steps of at
+    /// least 3. The first and last columns are at the same place, with u of 0 and 1.
+    fn lathe(&mut self, profile: &[ProfilePoint], segments: u32) {
+        let segments = segments.max(3);
+        let first = self.positions.len() as u32;
+        for point in profile {
+            for column in 0..=segments {
+                let u = column as f32 / segments as f32;
+                let around = around(u);
+                let position = around * point.position.x + Vec3::Y * point.position.y;
+                let normal = (around * point.normal.x + Vec3::Y * point.normal.y).normalize();
+                self.vertex(position, normal, Vec2::new(u, point.v));
+            }
+        }
+        self.quads(first, segments, profile.len() as u32 - 1);
+    }
+
+    /// A disk at `height` facing `normal`, +Y or -Y, textured as if the texture were laid over it
+    /// from that side
+    fn disk(&mut self, height: f32, normal: Vec3, radius: f32, segments: u32) {
+        let segments = segments.max(3);
+        let flip = normal.y.signum();
+        let center = self.vertex(Vec3::Y * height, normal, Vec2::splat(0.5));
+        for column in 0..segments {
+            let around = around(column as f32 / segments as f32);
+            self.vertex(around * radius + Vec3::Y * height, normal, Vec2::new(0.5 + around.x * 0.5, 0.5 + around.z * 0.5 * flip));
+        }
+        for column in 0..segments {
+            let (a, b) = (center + 1 + column, center + 1 + (column + 1) % segments);
+            // The ring runs counter-clockwise seen from +Y
+            self.triangle(if flip > 0.0 { [center, a, b] } else { [center, b, a] });
+        }
+    }
+
+    fn finish(self) -> Model {
+        let tangents = tangents(&self.positions, &self.normals, &self.uvs, &self.indices);
+        let bounds = Aabb::from_points(self.positions.iter().copied());
+        let bounding_sphere = BoundingSphere::from_points(&self.positions);
+        let submesh = Submesh {
+            index_offset: 0,
+            index_count: self.indices.len() as u32,
+            vertex_offset: 0,
+            vertex_count: self.positions.len() as u32,
+            material: 0,
+            bounds,
+            bounding_sphere,
+        };
+
+        Model {
+            id: None,
+            verts: self.positions,
+            indices: self.indices,
+            normals: self.normals,
+            tangents,
+            uvs: vec![self.uvs],
+            colors: Vec::new(),
+            joints: Vec::new(),
+            weights: Vec::new(),
+            submeshes: vec![submesh],
+            bounds,
+            bounding_sphere,
+            meshlets: Vec::new(),
+            meshlet_vertices: Vec::new(),
+            meshlet_triangles: Vec::new(),
+            lods: Vec::new(),
+            lod_indices: Vec::new(),
+        }
+    }
+}
+
+/// Direction from the Y axis at `u` of the way around, starting at -X and running right as seen
+/// from outside
+fn around(u: f32) -> Vec3 {
+    let angle = u * TAU;
+    Vec3::new(-angle.cos(), 0.0, angle.sin())
+}
+
+/// Direction in a profile at `angle` from +Y, turning away from the axis
+fn from_pole(angle: f32) -> Vec2 {
+    Vec2::new(angle.sin(), angle.cos())
+}
+
+/// Per-vertex tangents along increasing u, with the handedness of increasing v in w, as the
+/// model pipeline stores them
+fn tangents(positions: &[Vec3], normals: &[Vec3], uvs: &[Vec2], indices: &[u32]) -> Vec<Vec4> {
+    let mut u_directions = vec![Vec3::ZERO; positions.len()];
+    let mut v_directions = vec![Vec3::ZERO; positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|index| index as usize);
+        let (edge1, edge2) = (positions[b] - positions[a], positions[c] - positions[a]);
+        let (uv1, uv2) = (uvs[b] - uvs[a], uvs[c] - uvs[a]);
+        let determinant = uv1.perp_dot(uv2);
+        if determinant.abs() < f32::EPSILON {
+            continue;
+        }
+        let u_direction = (edge1 * uv2.y - edge2 * uv1.y) / determinant;
+        let v_direction = (edge2 * uv1.x - edge1 * uv2.x) / determinant;
+        for index in [a, b, c] {
+            u_directions[index] += u_direction;
+            v_directions[index] += v_direction;
+        }
+    }
+
+    normals.iter()
+        .zip(u_directions.iter().zip(&v_directions))
+        .map(|(normal, (u_direction, v_direction))| {
+            let tangent = (*u_direction - *normal * normal.dot(*u_direction)).try_normalize().unwrap_or_else(|| normal.any_orthonormal_vector());
+            let handedness = if normal.cross(tangent).dot(*v_direction) < 0.0 { -1.0 } else { 1.0 };
+            tangent.extend(handedness)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shapes() -> Vec<(&'static str, Model)> {
+        vec![
+            ("uv_sphere", uv_sphere(1.0, 16, 8)),
+            ("ico_sphere", ico_sphere(1.0, 2)),
+            ("plane", plane(Vec2::new(2.0, 1.0), 4, 2)),
+            ("cube", cube(Vec3::new(1.0, 2.0, 3.0), 2)),
+            ("cylinder", cylinder(0.5, 2.0, 12, 3)),
+            ("cone", cone(0.5, 1.0, 12, 2)),
+            ("torus", torus(1.0, 0.25, 24, 8)),
+            ("capsule", capsule(0.5, 2.0, 12, 4)),
+        ]
+    }
+
+    #[test]
+    fn test_shapes_are_well_formed() {
+        for (name, model) in shapes() {
+            let vertex_count = model.verts.len();
+            assert!(model.indices.len() % 3 == 0 && !model.indices.is_empty(), "{}", name);
+            assert!(model.indices.iter().all(|index| (*index as usize) < vertex_count), "{} index out of range", name);
+            assert_eq!(model.normals.len(), vertex_count, "{}", name);
+            assert_eq!(model.tangents.len(), vertex_count, "{}", name);
+            assert_eq!(model.uvs[0].len(), vertex_count, "{}", name);
+            assert_eq!(model.submeshes[0].index_count as usize, model.indices.len(), "{}", name);
+
+            for ((normal, tangent), uv) in model.normals.iter().zip(&model.tangents).zip(&model.uvs[0]) {
+                assert!((normal.length() - 1.0).abs() < 1e-4, "{} normal {} isn't unit length", name, normal);
+                assert!(normal.dot(tangent.truncate()).abs() < 1e-4, "{} tangent {} isn't perpendicular to {}", name, tangent, normal);
+                // Triangles spanning the ico sphere's seam continue past u of 1
+                let max_u = if name == "ico_sphere" { 2.0 } else { 1.0 };
+                assert!(uv.cmpge(Vec2::ZERO).all() && uv.x <= max_u && uv.y <= 1.0, "{} uv {} is out of range", name, uv);
+            }
+
+            // Every triangle winds counter-clockwise around its vertices' normals
+            for triangle in model.indices.chunks_exact(3) {
+                let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|index| model.verts[index as usize]);
+                let normal: Vec3 = triangle.iter().map(|index| model.normals[*index as usize]).sum();
+                assert!((b - a).cross(c - a).dot(normal) > 0.0, "{} triangle {:?} faces inwards", name, triangle);
+            }
+
+            for position in &model.verts {
+                assert!(position.cmpge(model.bounds.min).all() && position.cmple(model.bounds.max).all(), "{}", name);
+                assert!(position.distance(model.bounding_sphere.center) <= model.bounding_sphere.radius + 1e-5, "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_shape_dimensions() {
+        let sphere = uv_sphere(2.0, 16, 8);
+        assert!(sphere.verts.iter().all(|position| (position.length() - 2.0).abs() < 1e-5));
+        assert!(sphere.bounds.max.abs_diff_eq(Vec3::splat(2.0), 1e-5));
+
+        let sphere = ico_sphere(2.0, 1);
+        assert!(sphere.verts.iter().all(|position| (position.length() - 2.0).abs() < 1e-5));
+        assert_eq!(sphere.indices.len(), 80 * 3);
+
+        let plane = plane(Vec2::new(2.0, 1.0), 4, 2);
+        assert_eq!((plane.verts.len(), plane.indices.len()), (15, 16 * 3));
+        assert_eq!(plane.bounds, Aabb { min: Vec3::new(-1.0, 0.0, -0.5), max: Vec3::new(1.0, 0.0, 0.5) });
+
+        let cube = cube(Vec3::new(1.0, 2.0, 3.0), 1);
+        assert_eq!((cube.verts.len(), cube.indices.len()), (24, 12 * 3));
+        assert_eq!(cube.bounds, Aabb { min: Vec3::new(-0.5, -1.0, -1.5), max: Vec3::new(0.5, 1.0, 1.5) });
+        // The front face reads left to right, top to bottom
+        let front = cube.normals.iter().position(|normal| *normal == Vec3::Z).unwrap();
+        assert_eq!(cube.verts[front], Vec3::new(-0.5, 1.0, 1.5));
+        assert_eq!(cube.uvs[0][front], Vec2::ZERO);
+        assert!(cube.tangents[front].abs_diff_eq(Vec4::new(1.0, 0.0, 0.0, -1.0), 1e-5));
+
+        let capsule = capsule(0.5, 3.0, 12, 4);
+        assert!((capsule.bounds.max.y - 1.5).abs() < 1e-5 && (capsule.bounds.min.y + 1.5).abs() < 1e-5);
+
+        let torus = torus(1.0, 0.25, 24, 8);
+        assert!((torus.bounds.max.x - 1.25).abs() < 1e-5 && (torus.bounds.max.y - 0.25).abs() < 1e-5);
+        for (position, normal) in torus.verts.iter().zip(&torus.normals) {
+            let tube_center = (*position * Vec3::new(1.0, 0.0, 1.0)).normalize();
+            assert!((*position - tube_center).normalize().abs_diff_eq(*normal, 1e-4));
+        }
+    }
+}
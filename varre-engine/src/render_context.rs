@@ -1,12 +1,15 @@
 pub mod triangle;
 pub mod mesh_simple;
+pub mod meshlet;
 
 use ash::vk;
-use crate::DeviceContext;
+use crate::{AssetStreamer, DeviceContext};
 
 pub enum RenderContextType {
     Triangle,
     MeshSimple,
+    /// Requires `VK_EXT_mesh_shader`
+    Meshlet,
 }
 
 pub trait RenderContext {
@@ -14,5 +17,7 @@ pub trait RenderContext {
         
     }
     fn record_setup(&self, device_context: &DeviceContext, cmd : vk::CommandBuffer);
-    fn record_draw(&self, device_context: &DeviceContext, cmd : vk::CommandBuffer, img: vk::Image, img_view: vk::ImageView, depth_img: vk::Image, depth_view: vk::ImageView, area: vk::Rect2D);
+    /// Record the frame's draws. Streamed assets that aren't resident yet are drawn with
+    /// `asset_streamer`'s placeholders.
+    fn record_draw(&self, device_context: &DeviceContext, asset_streamer: &AssetStreamer, cmd : vk::CommandBuffer, img: vk::Image, img_view: vk::ImageView, depth_img: vk::Image, depth_view: vk::ImageView, area: vk::Rect2D);
 }
\ No newline at end of file
@@ -1,46 +1,40 @@
 use std::ptr::null;
-use crate::DeviceContext;
+use crate::{AssetStreamer, DeviceContext, MeshHandle};
 use crate::command_buffers::record_image_layout_transition;
-use crate::mesh_utils::VulkanMesh;
+use crate::mesh_utils::build_vertex_input_layout;
 use crate::render_context::RenderContext;
-use crate::shader_utils::{create_shader_object, make_descriptor_set_layouts};
+use crate::shader_program::{ShaderProgram, ShaderProgramRegistry};
+use std::rc::Rc;
 use ash::vk;
 use ash::vk::{CommandBuffer, Extent2D, Image, ImageView, PipelineBindPoint, Rect2D, SampleCountFlags};
-use glam::Vec3;
 use varre_assets::{ModelID, ShaderID};
-use crate::memory_utils::{create_buffer, record_copy_buffer};
+use varre_assets::layouts::basic_model::UBO;
+use crate::memory_utils::create_buffer;
 
-struct UBO {
-    model: glam::Mat4,
-    view: glam::Mat4,
-    proj: glam::Mat4,
-}
+const CAMERA_POSITION: glam::Vec3 = glam::Vec3::new(2.0, 2.0, 2.0);
+const FIELD_OF_VIEW_DEGREES: f32 = 45.0;
+/// Largest simplification error allowed on screen when picking the mesh's level of detail
+const MAX_LOD_ERROR_PIXELS: f32 = 1.0;
 
 pub struct MeshSimpleRenderContext {
-    vertex_shader: vk::ShaderEXT,
-    fragment_shader: vk::ShaderEXT,
-    mesh: VulkanMesh,
+    program: Rc<ShaderProgram>,
+    /// Streamed in the background; the placeholder is drawn until it's resident
+    mesh: MeshHandle,
     descriptor_pool: vk::DescriptorPool,
     descriptor_set: vk::DescriptorSet,
-    pipeline_layout: vk::PipelineLayout,
     uniform_buffer: vk::Buffer,
     uniform_buffer_memory: vk::DeviceMemory,
 }
 
 impl MeshSimpleRenderContext {
-    pub fn new(device_context: &DeviceContext) -> Self {
+    pub fn new(device_context: &DeviceContext, programs: &mut ShaderProgramRegistry, asset_streamer: &mut AssetStreamer) -> Self {
         unsafe {
 
-            let vert_shader_data = ShaderID::BASIC_MODEL_VERTEX.shader();
-            let frag_shader_data = ShaderID::BASIC_MODEL_FRAGMENT.shader();
-            let descriptor_set_layouts = make_descriptor_set_layouts(device_context, &[vert_shader_data, frag_shader_data]);
-            
-            let vertex_shader = create_shader_object(device_context, vert_shader_data, &descriptor_set_layouts);
-
-            let fragment_shader = create_shader_object(device_context, frag_shader_data, &descriptor_set_layouts);
+            let program = programs
+                .get_or_create(device_context, "basic_model", &[ShaderID::BASIC_MODEL_VERTEX, ShaderID::BASIC_MODEL_FRAGMENT])
+                .expect("failed to create basic_model shader program");
 
-            let model = ModelID::CUBE.load();
-            let mesh = VulkanMesh::from_model(device_context, &model);
+            let mesh = asset_streamer.load_mesh(ModelID::CUBE.name());
 
             let pool_sizes = [vk::DescriptorPoolSize::default()
                 .descriptor_count(32)
@@ -57,24 +51,17 @@ impl MeshSimpleRenderContext {
 
             let descriptor_set_alloc_info = vk::DescriptorSetAllocateInfo::default()
                 .descriptor_pool(descriptor_pool)
-                .set_layouts(&descriptor_set_layouts);
+                .set_layouts(program.descriptor_set_layouts());
 
             let descriptor_sets = device_context.device.allocate_descriptor_sets(&descriptor_set_alloc_info).unwrap();
 
-            let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo::default()
-                .set_layouts(&descriptor_set_layouts);
-
-            let pipeline_layout = device_context.device.create_pipeline_layout(&pipeline_layout_create_info, None).unwrap();
-
             let (uniform_buffer, uniform_buffer_memory) = create_buffer(device_context, size_of::<UBO>() as vk::DeviceSize, vk::BufferUsageFlags::UNIFORM_BUFFER, vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT);
 
             Self {
-                vertex_shader,
-                fragment_shader,
+                program,
                 mesh,
                 descriptor_pool,
                 descriptor_set: descriptor_sets[0],
-                pipeline_layout,
                 uniform_buffer,
                 uniform_buffer_memory,
             }
@@ -85,20 +72,19 @@ impl MeshSimpleRenderContext {
 impl RenderContext for MeshSimpleRenderContext {
     fn on_swapchain_resized(&self, new_size: Extent2D) {}
 
-    fn record_setup(&self, device_context: &DeviceContext, cmd: CommandBuffer) {
+    fn record_setup(&self, device_context: &DeviceContext, _cmd: CommandBuffer) {
         unsafe {
-            record_copy_buffer(device_context, cmd, self.mesh.vertex_staging_buffer, self.mesh.vertex_buffer, self.mesh.vertex_buffer_size);
-            record_copy_buffer(device_context, cmd, self.mesh.index_staging_buffer, self.mesh.index_buffer, self.mesh.index_buffer_size);
-
             let uboData_c= device_context.device.map_memory(self.uniform_buffer_memory, 0, size_of::<UBO>() as vk::DeviceSize, vk::MemoryMapFlags::empty()).unwrap();
 
-            let uboData = &mut *(uboData_c as *mut UBO);
+            let mut uboData = UBO::zeroed();
 
             uboData.model = glam::Mat4::IDENTITY;
-            uboData.view = glam::Mat4::look_at_lh(glam::Vec3::new(2.0, 2.0, 2.0), glam::Vec3::new(0.0, 0.0, 0.0), glam::Vec3::new(0.0, 0.0, 1.0));
-            uboData.proj = glam::Mat4::perspective_lh(f32::to_radians(45.0), 1920 as f32 / 1080 as f32, 0.1, 10.0);
+            uboData.view = glam::Mat4::look_at_lh(CAMERA_POSITION, glam::Vec3::new(0.0, 0.0, 0.0), glam::Vec3::new(0.0, 0.0, 1.0));
+            uboData.proj = glam::Mat4::perspective_lh(f32::to_radians(FIELD_OF_VIEW_DEGREES), 1920 as f32 / 1080 as f32, 0.1, 10.0);
             uboData.proj.col_mut(1).y *= -1.0;
 
+            (uboData_c as *mut UBO).write(uboData);
+
             device_context.device.unmap_memory(self.uniform_buffer_memory);
 
             let ubo_descriptor = [vk::DescriptorBufferInfo { buffer: self.uniform_buffer, offset: 0, range: size_of::<UBO>() as vk::DeviceSize }];
@@ -119,6 +105,7 @@ impl RenderContext for MeshSimpleRenderContext {
     fn record_draw(
         &self,
         device_context: &DeviceContext,
+        asset_streamer: &AssetStreamer,
         cmd: CommandBuffer,
         img: Image,
         img_view: ImageView,
@@ -126,6 +113,10 @@ impl RenderContext for MeshSimpleRenderContext {
         depth_view: ImageView,
         area: Rect2D,
     ) {
+        let mesh = asset_streamer.mesh_or_placeholder(self.mesh);
+        let vertex_input_layout = build_vertex_input_layout(ShaderID::BASIC_MODEL_VERTEX.shader(), mesh.vertex_streams())
+            .expect("mesh does not provide the vertex shader's inputs");
+
         unsafe {
 
             // Begin rendering
@@ -163,13 +154,11 @@ impl RenderContext for MeshSimpleRenderContext {
 
             // Set render state
             {
-                let shaders = [self.vertex_shader, self.fragment_shader];
-                let stages = [vk::ShaderStageFlags::VERTEX, vk::ShaderStageFlags::FRAGMENT];
+                self.program.bind(device_context, cmd);
                 let shader_object_loader = device_context
                     .shader_object_loader
                     .as_ref()
                     .expect("shader_object_loader not available");
-                shader_object_loader.cmd_bind_shaders(cmd, &stages, &shaders);
 
                 // Setting viewport, scissor, and rasterizer discard is required before draw w/ shader object.
                 let viewport = [vk::Viewport::default()
@@ -189,20 +178,8 @@ impl RenderContext for MeshSimpleRenderContext {
                     .device
                     .cmd_set_rasterizer_discard_enable(cmd, false);
 
-                let vertex_input_binding_descriptions = [vk::VertexInputBindingDescription2EXT::default()
-                    .binding(0)
-                    .stride(std::mem::size_of::<Vec3>() as u32)
-                    .input_rate(vk::VertexInputRate::VERTEX)
-                    .divisor(1)];
-
-                let vertex_attribute_descriptions = [vk::VertexInputAttributeDescription2EXT::default()
-                    .binding(0)
-                    .location(0)
-                    .format(vk::Format::R32G32B32_SFLOAT)
-                    .offset(0)];
-
                 // Setting vertex input, primitive topology, primitive restart, and polygon mode is required before draw w/ shader object, if a vertex shader is bound.
-                shader_object_loader.cmd_set_vertex_input(cmd, &vertex_input_binding_descriptions, &vertex_attribute_descriptions);
+                shader_object_loader.cmd_set_vertex_input(cmd, &vertex_input_layout.bindings, &vertex_input_layout.attributes);
                 shader_object_loader
                     .cmd_set_primitive_topology(cmd, vk::PrimitiveTopology::TRIANGLE_LIST);
                 shader_object_loader.cmd_set_primitive_restart_enable(cmd, false);
@@ -235,17 +212,20 @@ impl RenderContext for MeshSimpleRenderContext {
                 let color_write_mask = [vk::ColorComponentFlags::RGBA];
                 shader_object_loader.cmd_set_color_write_mask(cmd, 0, &color_write_mask);
 
-                let vertex_buffers = [self.mesh.vertex_buffer];
-                let offsets = [0];
+                let vertex_buffers = mesh.vertex_buffers();
+                let offsets = mesh.vertex_buffer_offsets();
                 let dynamic_offsets : &[u32] = &[];
 
-                shader_object_loader.cmd_bind_vertex_buffers2(cmd, 0, &vertex_buffers, &offsets, None, None);
-                device_context.device.cmd_bind_index_buffer(cmd, self.mesh.index_buffer, 0, vk::IndexType::UINT32);
-                device_context.device.cmd_bind_descriptor_sets(cmd, PipelineBindPoint::GRAPHICS, self.pipeline_layout, 0, &[self.descriptor_set], &dynamic_offsets);
+                shader_object_loader.cmd_bind_vertex_buffers2(cmd, 0, &vertex_buffers, offsets, None, None);
+                device_context.device.cmd_bind_index_buffer(cmd, mesh.index_buffer, 0, mesh.index_type);
+                device_context.device.cmd_bind_descriptor_sets(cmd, PipelineBindPoint::GRAPHICS, self.program.pipeline_layout(), 0, &[self.descriptor_set], &dynamic_offsets);
 
             }
 
-            device_context.device.cmd_draw_indexed(cmd, self.mesh.index_count, 1, 0, 0, 0);
+            // The model sits at the origin
+            let pixels_per_unit = area.extent.height as f32 / (2.0 * (FIELD_OF_VIEW_DEGREES.to_radians() / 2.0).tan());
+            let lod = mesh.select_lod(CAMERA_POSITION.length(), pixels_per_unit, MAX_LOD_ERROR_PIXELS);
+            device_context.device.cmd_draw_indexed(cmd, lod.index_count, 1, lod.first_index, 0, 0);
 
             device_context.device.cmd_end_rendering(cmd);
         }
@@ -0,0 +1,239 @@
+use crate::{AssetStreamer, DeviceContext};
+use crate::memory_utils::{create_buffer, create_buffer_with_data};
+use crate::mesh_utils::GpuMeshlets;
+use crate::render_context::RenderContext;
+use crate::shader_program::{ShaderProgram, ShaderProgramRegistry};
+use std::rc::Rc;
+use ash::vk;
+use ash::vk::{CommandBuffer, Image, ImageView, PipelineBindPoint, Rect2D, SampleCountFlags};
+use varre_assets::{ModelID, ShaderID};
+use varre_assets::layouts::meshlet::UBO;
+
+/// Draws a model as meshlets with a mesh shader, coloring each meshlet differently.
+/// Requires `VK_EXT_mesh_shader`.
+pub struct MeshletRenderContext {
+    program: Rc<ShaderProgram>,
+    meshlet_count: u32,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+    uniform_buffer: vk::Buffer,
+    uniform_buffer_memory: vk::DeviceMemory,
+    // Positions, meshlets, meshlet vertices and meshlet triangles, at bindings 1 to 4
+    storage_buffers: [vk::Buffer; 4],
+    storage_buffer_memory: [vk::DeviceMemory; 4],
+}
+
+impl MeshletRenderContext {
+    pub fn new(device_context: &DeviceContext, programs: &mut ShaderProgramRegistry) -> Self {
+        assert!(device_context.features.mesh_shader, "the meshlet render context requires VK_EXT_mesh_shader");
+
+        unsafe {
+            let program = programs
+                .get_or_create(device_context, "meshlet", &[ShaderID::MESHLET_MESH, ShaderID::MESHLET_FRAGMENT])
+                .expect("failed to create meshlet shader program");
+
+            let model = ModelID::CUBE.load();
+            assert!(!model.meshlets.is_empty(), "model has no meshlets; enable the varre-assets `meshlets` feature");
+            let meshlets = GpuMeshlets::from_model(&model);
+
+            let usage = vk::BufferUsageFlags::STORAGE_BUFFER;
+            let (positions, positions_memory) = create_buffer_with_data(device_context, &model.verts, usage);
+            let (meshlet_buffer, meshlet_memory) = create_buffer_with_data(device_context, &meshlets.meshlets, usage);
+            let (vertices, vertices_memory) = create_buffer_with_data(device_context, &meshlets.vertices, usage);
+            let (triangles, triangles_memory) = create_buffer_with_data(device_context, &meshlets.triangles, usage);
+
+            let pool_sizes = [
+                vk::DescriptorPoolSize::default()
+                    .descriptor_count(1)
+                    .ty(vk::DescriptorType::UNIFORM_BUFFER),
+                vk::DescriptorPoolSize::default()
+                    .descriptor_count(4)
+                    .ty(vk::DescriptorType::STORAGE_BUFFER),
+            ];
+
+            let pool_create_info = vk::DescriptorPoolCreateInfo::default()
+                .pool_sizes(&pool_sizes)
+                .max_sets(1);
+
+            let descriptor_pool = device_context.device.create_descriptor_pool(&pool_create_info, None).unwrap();
+
+            let descriptor_set_alloc_info = vk::DescriptorSetAllocateInfo::default()
+                .descriptor_pool(descriptor_pool)
+                .set_layouts(program.descriptor_set_layouts());
+
+            let descriptor_sets = device_context.device.allocate_descriptor_sets(&descriptor_set_alloc_info).unwrap();
+
+            let (uniform_buffer, uniform_buffer_memory) = create_buffer(device_context, size_of::<UBO>() as vk::DeviceSize, vk::BufferUsageFlags::UNIFORM_BUFFER, vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT);
+
+            Self {
+                program,
+                meshlet_count: meshlets.meshlets.len() as u32,
+                descriptor_pool,
+                descriptor_set: descriptor_sets[0],
+                uniform_buffer,
+                uniform_buffer_memory,
+                storage_buffers: [positions, meshlet_buffer, vertices, triangles],
+                storage_buffer_memory: [positions_memory, meshlet_memory, vertices_memory, triangles_memory],
+            }
+        }
+    }
+}
+
+impl RenderContext for MeshletRenderContext {
+    fn record_setup(&self, device_context: &DeviceContext, _cmd: CommandBuffer) {
+        unsafe {
+            let ubo_ptr = device_context.device.map_memory(self.uniform_buffer_memory, 0, size_of::<UBO>() as vk::DeviceSize, vk::MemoryMapFlags::empty()).unwrap();
+
+            let mut ubo = UBO::zeroed();
+
+            ubo.model = glam::Mat4::IDENTITY;
+            ubo.view = glam::Mat4::look_at_lh(glam::Vec3::new(2.0, 2.0, 2.0), glam::Vec3::new(0.0, 0.0, 0.0), glam::Vec3::new(0.0, 0.0, 1.0));
+            ubo.proj = glam::Mat4::perspective_lh(f32::to_radians(45.0), 1920 as f32 / 1080 as f32, 0.1, 10.0);
+            ubo.proj.col_mut(1).y *= -1.0;
+
+            (ubo_ptr as *mut UBO).write(ubo);
+
+            device_context.device.unmap_memory(self.uniform_buffer_memory);
+
+            let ubo_descriptor = [vk::DescriptorBufferInfo { buffer: self.uniform_buffer, offset: 0, range: size_of::<UBO>() as vk::DeviceSize }];
+            let storage_descriptors: Vec<_> = self.storage_buffers
+                .iter()
+                .map(|buffer| [vk::DescriptorBufferInfo { buffer: *buffer, offset: 0, range: vk::WHOLE_SIZE }])
+                .collect();
+
+            let write_descriptor_sets: Vec<_> = std::iter::once(
+                vk::WriteDescriptorSet::default()
+                    .dst_set(self.descriptor_set)
+                    .dst_binding(0)
+                    .descriptor_count(1)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(&ubo_descriptor))
+                .chain(storage_descriptors.iter().enumerate().map(|(index, descriptor)| {
+                    vk::WriteDescriptorSet::default()
+                        .dst_set(self.descriptor_set)
+                        .dst_binding(index as u32 + 1)
+                        .descriptor_count(1)
+                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                        .buffer_info(descriptor)
+                }))
+                .collect();
+
+            device_context.device.update_descriptor_sets(&write_descriptor_sets, &[]);
+        }
+    }
+
+    fn record_draw(
+        &self,
+        device_context: &DeviceContext,
+        _asset_streamer: &AssetStreamer,
+        cmd: CommandBuffer,
+        _img: Image,
+        img_view: ImageView,
+        _depth_img: Image,
+        depth_view: ImageView,
+        area: Rect2D,
+    ) {
+        unsafe {
+            // Begin rendering
+            {
+                let clear_color = vk::ClearValue {
+                    color: vk::ClearColorValue {
+                        float32: [0.0, 0.0, 0.0, 1.0],
+                    },
+                };
+
+                let attachment_info = [vk::RenderingAttachmentInfo::default()
+                    .image_view(img_view)
+                    .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                    .load_op(vk::AttachmentLoadOp::CLEAR)
+                    .store_op(vk::AttachmentStoreOp::STORE)
+                    .clear_value(clear_color)];
+
+                let depth_attachment_info = vk::RenderingAttachmentInfo::default()
+                    .image_view(depth_view)
+                    .image_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                    .load_op(vk::AttachmentLoadOp::CLEAR)
+                    .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                    .clear_value(clear_color);
+
+                let rendering_info = vk::RenderingInfo::default()
+                    .render_area(area)
+                    .layer_count(1)
+                    .color_attachments(&attachment_info)
+                    .depth_attachment(&depth_attachment_info);
+
+                device_context
+                    .device
+                    .cmd_begin_rendering(cmd, &rendering_info);
+            }
+
+            // Set render state
+            {
+                self.program.bind(device_context, cmd);
+                let shader_object_loader = device_context
+                    .shader_object_loader
+                    .as_ref()
+                    .expect("shader_object_loader not available");
+
+                // Setting viewport, scissor, and rasterizer discard is required before draw w/ shader object.
+                let viewport = [vk::Viewport::default()
+                    .width(area.extent.width as f32)
+                    .height(area.extent.height as f32)
+                    .min_depth(0.0)
+                    .max_depth(1.0)];
+
+                device_context
+                    .device
+                    .cmd_set_viewport_with_count(cmd, &viewport);
+                let scissor = [vk::Rect2D::default().extent(area.extent)];
+                device_context
+                    .device
+                    .cmd_set_scissor_with_count(cmd, &scissor);
+                device_context
+                    .device
+                    .cmd_set_rasterizer_discard_enable(cmd, false);
+
+                // Vertex input, topology and primitive restart state is not used without a vertex shader.
+
+                // Required w/ shader object if rasterizer discard is disabled.
+                shader_object_loader
+                    .cmd_set_rasterization_samples(cmd, vk::SampleCountFlags::TYPE_1);
+                let sample_mask = [0x1];
+                shader_object_loader.cmd_set_sample_mask(
+                    cmd,
+                    SampleCountFlags::TYPE_1,
+                    &sample_mask,
+                );
+                shader_object_loader.cmd_set_alpha_to_coverage_enable(cmd, false);
+                shader_object_loader.cmd_set_polygon_mode(cmd, vk::PolygonMode::FILL);
+                device_context.device.cmd_set_line_width(cmd, 1.0);
+                shader_object_loader.cmd_set_cull_mode(cmd, vk::CullModeFlags::BACK);
+                shader_object_loader.cmd_set_front_face(cmd, vk::FrontFace::CLOCKWISE);
+                shader_object_loader.cmd_set_depth_test_enable(cmd, true);
+                shader_object_loader.cmd_set_depth_bounds_test_enable(cmd, false);
+                shader_object_loader.cmd_set_depth_bias_enable(cmd, false);
+                shader_object_loader.cmd_set_stencil_test_enable(cmd, false);
+                shader_object_loader.cmd_set_depth_compare_op(cmd, vk::CompareOp::GREATER);
+
+                shader_object_loader.cmd_set_depth_write_enable(cmd, true);
+
+                // Required per bound color target
+                let color_blend_enable = [vk::FALSE];
+                shader_object_loader.cmd_set_color_blend_enable(cmd, 0, &color_blend_enable);
+                let color_write_mask = [vk::ColorComponentFlags::RGBA];
+                shader_object_loader.cmd_set_color_write_mask(cmd, 0, &color_write_mask);
+
+                device_context.device.cmd_bind_descriptor_sets(cmd, PipelineBindPoint::GRAPHICS, self.program.pipeline_layout(), 0, &[self.descriptor_set], &[]);
+            }
+
+            // One mesh shader workgroup per meshlet
+            let mesh_shader_loader = device_context
+                .mesh_shader_loader
+                .as_ref()
+                .expect("mesh_shader_loader not available");
+            mesh_shader_loader.cmd_draw_mesh_tasks(cmd, self.meshlet_count, 1, 1);
+
+            device_context.device.cmd_end_rendering(cmd);
+        }
+    }
+}
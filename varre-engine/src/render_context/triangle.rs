@@ -2,30 +2,22 @@ use ash::vk;
 use ash::vk::{CommandBuffer, SampleCountFlags};
 use varre_assets::ShaderID;
 use crate::command_buffers::record_image_layout_transition;
-use crate::DeviceContext;
+use crate::{AssetStreamer, DeviceContext};
 use crate::render_context::RenderContext;
-use crate::shader_utils::{create_shader_object, make_descriptor_set_layouts};
+use crate::shader_program::{ShaderProgram, ShaderProgramRegistry};
+use std::rc::Rc;
 
 pub struct TriangleRenderContext {
-    triangle_vert: vk::ShaderEXT,
-    triangle_frag: vk::ShaderEXT,
+    program: Rc<ShaderProgram>,
 }
 
 impl TriangleRenderContext {
-    pub fn new(device_context: &DeviceContext) -> Self {
-      
-        let vert_shader = ShaderID::SHADER_TRIANGLE_VERTEX.shader();
-        let frag_shader = ShaderID::SHADER_TRIANGLE_FRAGMENT.shader();
-
-        let descriptor_set_layouts = make_descriptor_set_layouts(device_context, &[vert_shader, frag_shader]);
-
-        let triangle_vert = create_shader_object(device_context, vert_shader, &descriptor_set_layouts);
-        
-        let triangle_frag = create_shader_object(device_context, frag_shader, &descriptor_set_layouts);
-        
-        Self{
-           triangle_vert, triangle_frag
-        }
+    pub fn new(device_context: &DeviceContext, programs: &mut ShaderProgramRegistry) -> Self {
+        let program = programs
+            .get_or_create(device_context, "triangle", &[ShaderID::SHADER_TRIANGLE_VERTEX, ShaderID::SHADER_TRIANGLE_FRAGMENT])
+            .expect("failed to create triangle shader program");
+
+        Self { program }
     }
 }
 
@@ -33,7 +25,7 @@ impl RenderContext for TriangleRenderContext {
     fn record_setup(&self, device_context: &DeviceContext, cmd: CommandBuffer) {
     }
 
-    fn record_draw(&self, device_context: &DeviceContext, cmd : vk::CommandBuffer, img: vk::Image, img_view: vk::ImageView, _: vk::Image, _: vk::ImageView, area: vk::Rect2D) {
+    fn record_draw(&self, device_context: &DeviceContext, _: &AssetStreamer, cmd : vk::CommandBuffer, img: vk::Image, img_view: vk::ImageView, _: vk::Image, _: vk::ImageView, area: vk::Rect2D) {
         unsafe {
 
             // Begin rendering
@@ -57,11 +49,9 @@ impl RenderContext for TriangleRenderContext {
 
             // Set render state
             {
-                let shaders = [self.triangle_vert, self.triangle_frag];
-                let stages = [vk::ShaderStageFlags::VERTEX, vk::ShaderStageFlags::FRAGMENT];
+                self.program.bind(device_context, cmd);
                 let shader_object_loader = device_context.shader_object_loader.as_ref()
                     .expect("shader_object_loader not available");
-                shader_object_loader.cmd_bind_shaders(cmd, &stages, &shaders);
 
                 // Setting viewport, scissor, and rasterizer discard is required before draw w/ shader object.
                 let viewport = [vk::Viewport::default().width(area.extent.width as f32).height(area.extent.height as f32)];
@@ -0,0 +1,195 @@
+use ash::vk;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Environment variable overriding the cache directory, which is `varre/shaders` in the user's
+/// cache directory by default. Setting it to an empty value disables the on-disk cache.
+pub const SHADER_CACHE_ENV: &str = "VARRE_SHADER_CACHE";
+
+/// Persistent cache of compiled shader-object binaries and pipeline cache data.
+///
+/// Entries live in a per-device directory named after the device UUID and driver version, so a
+/// driver update or a different GPU never sees binaries it didn't produce. Shader binaries are
+/// further keyed by a hash of the SPIR-V and everything else that affects compilation.
+pub struct ShaderCache {
+    directory: Option<PathBuf>,
+    pipeline_cache: vk::PipelineCache,
+}
+
+impl ShaderCache {
+    pub fn new(instance: &ash::Instance, device: &ash::Device, physical_device: vk::PhysicalDevice) -> Self {
+        let mut id_properties = vk::PhysicalDeviceIDProperties::default();
+        let mut properties2 = vk::PhysicalDeviceProperties2::default().push_next(&mut id_properties);
+        unsafe { instance.get_physical_device_properties2(physical_device, &mut properties2) };
+        let driver_version = properties2.properties.driver_version;
+
+        let device_key: String = id_properties.device_uuid.iter().map(|b| format!("{:02x}", b)).collect();
+        let root = match std::env::var_os(SHADER_CACHE_ENV) {
+            Some(path) if path.is_empty() => None,
+            Some(path) => Some(PathBuf::from(path)),
+            None => user_cache_directory().map(|cache| cache.join("varre").join("shaders")),
+        };
+
+        let directory = root
+            .map(|root| root.join(format!("{}-{:08x}", device_key, driver_version)))
+            .filter(|directory| create_private_dir(directory).is_ok());
+
+        // Drivers validate the header of the initial data and ignore it if it doesn't match
+        let initial_data = directory.as_ref()
+            .and_then(|directory| fs::read(directory.join("pipeline_cache.bin")).ok())
+            .unwrap_or_default();
+
+        let pipeline_cache_create_info = vk::PipelineCacheCreateInfo::default()
+            .initial_data(&initial_data);
+
+        let pipeline_cache = unsafe {
+            device.create_pipeline_cache(&pipeline_cache_create_info, None)
+                .or_else(|_| device.create_pipeline_cache(&vk::PipelineCacheCreateInfo::default(), None))
+                .expect("failed to create pipeline cache")
+        };
+
+        Self { directory, pipeline_cache }
+    }
+
+    /// The pipeline cache to pass to pipeline creation
+    pub fn pipeline_cache(&self) -> vk::PipelineCache {
+        self.pipeline_cache
+    }
+
+    /// Load a shader binary stored under `key`. The returned bytes are 16-byte aligned, as
+    /// required for `ShaderCodeTypeEXT::BINARY` code.
+    pub fn load_shader_binary(&self, key: u64) -> Option<AlignedBytes> {
+        let directory = self.directory.as_ref()?;
+        let data = fs::read(directory.join(format!("{:016x}.bin", key))).ok()?;
+
+        Some(AlignedBytes::new(&data))
+    }
+
+    /// Store a shader binary under `key`, replacing any previous entry
+    pub fn store_shader_binary(&self, key: u64, data: &[u8]) {
+        if let Some(directory) = &self.directory {
+            write_atomically(directory.join(format!("{:016x}.bin", key)), data);
+        }
+    }
+
+    /// Remove the shader binary stored under `key`, e.g. after the driver rejected it
+    pub fn remove_shader_binary(&self, key: u64) {
+        if let Some(directory) = &self.directory {
+            let _ = fs::remove_file(directory.join(format!("{:016x}.bin", key)));
+        }
+    }
+
+    /// Write the pipeline cache contents to disk
+    pub fn save(&self, device: &ash::Device) {
+        if let Some(directory) = &self.directory {
+            if let Ok(data) = unsafe { device.get_pipeline_cache_data(self.pipeline_cache) } {
+                write_atomically(directory.join("pipeline_cache.bin"), &data);
+            }
+        }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe { device.destroy_pipeline_cache(self.pipeline_cache, None) };
+    }
+}
+
+/// The user's cache directory: `$XDG_CACHE_HOME`, falling back to `~/.cache`, on Unix,
+/// `~/Library/Caches` on macOS and `%LOCALAPPDATA%` on Windows
+fn user_cache_directory() -> Option<PathBuf> {
+    let var = |name| std::env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+
+    if cfg!(windows) {
+        var("LOCALAPPDATA")
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|home| home.join("Library").join("Caches"))
+    } else {
+        var("XDG_CACHE_HOME")
+            .filter(|cache| cache.is_absolute())
+            .or_else(|| var("HOME").map(|home| home.join(".cache")))
+    }
+}
+
+/// Create `directory` and any missing parents accessible to the current user only, as the driver
+/// loads whatever binaries it finds there
+fn create_private_dir(directory: &Path) -> std::io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(directory)
+}
+
+/// Write to a temporary file and rename it into place so a crash never leaves a truncated entry
+fn write_atomically(path: PathBuf, data: &[u8]) {
+    let temp_path = path.with_extension("tmp");
+    if fs::write(&temp_path, data).is_ok() {
+        let _ = fs::rename(&temp_path, &path);
+    }
+}
+
+/// Byte buffer with 16-byte alignment
+pub struct AlignedBytes {
+    words: Vec<u128>,
+    len: usize,
+}
+
+impl AlignedBytes {
+    fn new(data: &[u8]) -> Self {
+        let mut words = vec![0u128; data.len().div_ceil(16)];
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), words.as_mut_ptr() as *mut u8, data.len());
+        }
+
+        Self { words, len: data.len() }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.words.as_ptr() as *const u8, self.len) }
+    }
+}
+
+/// 64-bit FNV-1a hash of `parts`, used to key cache entries. Each part is prefixed with its length
+/// so that bytes can't move between adjacent parts without changing the key.
+pub fn cache_key(parts: &[&[u8]]) -> u64 {
+    let hash = |hash: u64, bytes: &[u8]| bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    parts.iter().fold(0xcbf2_9ce4_8422_2325, |key, part| hash(hash(key, &(part.len() as u64).to_le_bytes()), part))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aligned_bytes() {
+        let data: Vec<u8> = (0..37).collect();
+        let aligned = AlignedBytes::new(&data);
+
+        assert_eq!(aligned.as_bytes(), data.as_slice());
+        assert_eq!(aligned.as_bytes().as_ptr() as usize % 16, 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_create_private_dir() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = std::env::temp_dir().join(format!("varre-shader-cache-{}", std::process::id()));
+        let directory = root.join("device");
+        create_private_dir(&directory).unwrap();
+
+        for path in [&root, &directory] {
+            assert_eq!(fs::metadata(path).unwrap().permissions().mode() & 0o777, 0o700);
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_cache_key() {
+        assert_eq!(cache_key(&[b"a"]), 0x529a_4ddc_8ff5_6bbf);
+        assert_ne!(cache_key(&[b"ab", b"c"]), cache_key(&[b"a", b"bc"]));
+        assert_eq!(cache_key(&[b"spirv", &1u32.to_le_bytes()]), cache_key(&[b"spirv", &1u32.to_le_bytes()]));
+        assert_ne!(cache_key(&[b"spirv", &1u32.to_le_bytes()]), cache_key(&[b"spirv", &2u32.to_le_bytes()]));
+    }
+}
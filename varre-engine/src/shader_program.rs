@@ -0,0 +1,206 @@
+use ash::vk;
+use std::collections::HashMap;
+use std::error::Error;
+use std::rc::Rc;
+use varre_assets::ShaderID;
+use crate::DeviceContext;
+use crate::command_buffers::cmd_push_constants;
+use crate::shader_utils::{
+    create_linked_shader_objects, create_pipeline_layout, create_shader_object, make_descriptor_set_layouts,
+    merge_descriptor_bindings, merge_push_constant_ranges, Specialization, ToVkShaderStage,
+};
+
+/// A set of shader stages used together, along with the descriptor set layouts, push constant
+/// ranges and pipeline layout they were created against.
+pub struct ShaderProgram {
+    name: String,
+    shader_ids: Vec<ShaderID>,
+    stages: Vec<vk::ShaderStageFlags>,
+    shaders: Vec<vk::ShaderEXT>,
+    descriptor_set_layouts: Vec<vk::DescriptorSetLayout>,
+    push_constant_ranges: Vec<vk::PushConstantRange>,
+    pipeline_layout: vk::PipelineLayout,
+}
+
+impl ShaderProgram {
+    /// Create a program from its stages, in pipeline order. Multiple graphics stages are created
+    /// as linked shader objects; `specialization` values are applied to whichever stages declare
+    /// them.
+    pub fn new(
+        device_context: &DeviceContext,
+        name: &str,
+        shader_ids: &[ShaderID],
+        specialization: &Specialization,
+    ) -> Result<Self, Box<dyn Error>> {
+        if shader_ids.is_empty() {
+            return Err(format!("Shader program {} has no stages", name).into());
+        }
+
+        let shaders: Vec<&varre_assets::Shader> = shader_ids.iter().map(|id| id.shader()).collect();
+        let constants: Vec<_> = shaders.iter().map(|shader| shader.specialization_constants).collect();
+        let specializations = specialization.split(&constants)
+            .map_err(|e| format!("Shader program {}: {}", name, e))?;
+
+        let descriptor_sets = merge_descriptor_bindings(&shaders)
+            .map_err(|e| format!("Shader program {}: {}", name, e))?;
+        let descriptor_set_layouts = make_descriptor_set_layouts(device_context, &descriptor_sets)
+            .map_err(|e| format!("Shader program {}: {}", name, e))?;
+        let push_constant_ranges = merge_push_constant_ranges(&shaders);
+        let pipeline_layout = create_pipeline_layout(device_context, &descriptor_set_layouts, &push_constant_ranges);
+
+        let shader_objects = if shaders.len() == 1 {
+            create_shader_object(device_context, shaders[0], &descriptor_sets, &descriptor_set_layouts, &push_constant_ranges, &specializations[0])
+                .map(|shader| vec![shader])
+        } else {
+            let stages: Vec<_> = shaders.iter().copied().zip(specializations.iter()).collect();
+            create_linked_shader_objects(device_context, &stages, &descriptor_sets, &descriptor_set_layouts, &push_constant_ranges)
+        };
+
+        let program = Self {
+            name: name.to_string(),
+            shader_ids: shader_ids.to_vec(),
+            stages: shaders.iter().map(|shader| shader.stage.to_vk()).collect(),
+            shaders: Vec::new(),
+            descriptor_set_layouts,
+            push_constant_ranges,
+            pipeline_layout,
+        };
+
+        match shader_objects {
+            Ok(shader_objects) => Ok(Self { shaders: shader_objects, ..program }),
+            Err(e) => {
+                program.destroy(device_context);
+                Err(format!("Shader program {}: {}", name, e).into())
+            }
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn shader_ids(&self) -> &[ShaderID] {
+        &self.shader_ids
+    }
+
+    pub fn descriptor_set_layouts(&self) -> &[vk::DescriptorSetLayout] {
+        &self.descriptor_set_layouts
+    }
+
+    pub fn push_constant_ranges(&self) -> &[vk::PushConstantRange] {
+        &self.push_constant_ranges
+    }
+
+    pub fn pipeline_layout(&self) -> vk::PipelineLayout {
+        self.pipeline_layout
+    }
+
+    /// The shader object created for one of the program's stages
+    pub fn shader(&self, id: ShaderID) -> Option<vk::ShaderEXT> {
+        self.shader_ids.iter().position(|s| *s == id).map(|index| self.shaders[index])
+    }
+
+    /// Bind every stage of the program. For graphics programs, the vertex and mesh shading stages
+    /// the program doesn't use are unbound, so a mesh program can follow a vertex program in the
+    /// same command buffer and vice versa.
+    pub fn bind(&self, device_context: &DeviceContext, cmd: vk::CommandBuffer) {
+        let shader_object_loader = device_context.shader_object_loader.as_ref()
+            .expect("shader_object_loader not available");
+
+        let mut stages = self.stages.clone();
+        let mut shaders = self.shaders.clone();
+
+        if !self.stages.contains(&vk::ShaderStageFlags::COMPUTE) {
+            let mut unused_stages = vec![vk::ShaderStageFlags::VERTEX];
+            if device_context.features.mesh_shader {
+                unused_stages.extend([vk::ShaderStageFlags::TASK_EXT, vk::ShaderStageFlags::MESH_EXT]);
+            }
+
+            for stage in unused_stages.into_iter().filter(|stage| !self.stages.contains(stage)) {
+                stages.push(stage);
+                shaders.push(vk::ShaderEXT::null());
+            }
+        }
+
+        unsafe { shader_object_loader.cmd_bind_shaders(cmd, &stages, &shaders) };
+    }
+
+    /// Push `data` as the program's whole push constant block.
+    /// Panics if the program has no push constants or the size of `T` doesn't match.
+    pub fn push_constants<T: Copy>(&self, device_context: &DeviceContext, cmd: vk::CommandBuffer, data: &T) {
+        let range = self.push_constant_ranges.first()
+            .unwrap_or_else(|| panic!("shader program {} has no push constants", self.name));
+
+        cmd_push_constants(device_context, cmd, self.pipeline_layout, range, data);
+    }
+
+    pub fn destroy(&self, device_context: &DeviceContext) {
+        let shader_object_loader = device_context.shader_object_loader.as_ref()
+            .expect("shader_object_loader not available");
+
+        unsafe {
+            for shader in &self.shaders {
+                shader_object_loader.destroy_shader(*shader, None);
+            }
+            device_context.device.destroy_pipeline_layout(self.pipeline_layout, None);
+            for layout in &self.descriptor_set_layouts {
+                device_context.device.destroy_descriptor_set_layout(*layout, None);
+            }
+        }
+    }
+}
+
+/// Shader programs shared between render contexts, looked up by name
+#[derive(Default)]
+pub struct ShaderProgramRegistry {
+    programs: HashMap<String, Rc<ShaderProgram>>,
+}
+
+impl ShaderProgramRegistry {
+    pub fn get(&self, name: &str) -> Option<Rc<ShaderProgram>> {
+        self.programs.get(name).cloned()
+    }
+
+    /// Look up a program by name, creating it from `shader_ids` with default specialization if
+    /// it hasn't been created yet
+    pub fn get_or_create(
+        &mut self,
+        device_context: &DeviceContext,
+        name: &str,
+        shader_ids: &[ShaderID],
+    ) -> Result<Rc<ShaderProgram>, Box<dyn Error>> {
+        if let Some(program) = self.get(name) {
+            if program.shader_ids() != shader_ids {
+                return Err(format!(
+                    "Shader program {} is already registered with stages {:?}",
+                    name, program.shader_ids()
+                ).into());
+            }
+            return Ok(program);
+        }
+
+        let program = ShaderProgram::new(device_context, name, shader_ids, &Specialization::default())?;
+        self.register(device_context, program)
+    }
+
+    /// Add a program under its name. If the name is already taken the program is destroyed and
+    /// an error is returned.
+    pub fn register(&mut self, device_context: &DeviceContext, program: ShaderProgram) -> Result<Rc<ShaderProgram>, Box<dyn Error>> {
+        if self.programs.contains_key(program.name()) {
+            program.destroy(device_context);
+            return Err(format!("A shader program named {} is already registered", program.name()).into());
+        }
+
+        let program = Rc::new(program);
+        self.programs.insert(program.name().to_string(), program.clone());
+        Ok(program)
+    }
+
+    /// Destroy every registered program. Programs still referenced elsewhere must not be used
+    /// afterwards.
+    pub fn destroy(&mut self, device_context: &DeviceContext) {
+        for (_, program) in self.programs.drain() {
+            program.destroy(device_context);
+        }
+    }
+}
@@ -1,7 +1,13 @@
 use ash::vk;
+use ash::vk::Handle;
 use ash::ext::shader_object;
-use std::ffi::CStr;
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::ffi::CString;
+use varre_assets::{SpecializationConstant, SpecializationConstantType};
 use crate::DeviceContext;
+use crate::shader_cache::cache_key;
 
 // Helper trait for converting ShaderStage to Vulkan flags
 pub trait ToVkShaderStage {
@@ -64,74 +70,859 @@ fn get_next_stages(stage: vk::ShaderStageFlags) -> vk::ShaderStageFlags {
     }
 }
 
-/// Convert varre_assets descriptor bindings to Vulkan descriptor bindings (without set index)
-fn convert_binding(b: &varre_assets::VkDescriptorSetLayoutBinding) -> vk::DescriptorSetLayoutBinding {
-    vk::DescriptorSetLayoutBinding::default()
-        .binding(b.binding)
-        .descriptor_type(vk::DescriptorType::from_raw(b.descriptor_type as i32))
-        .descriptor_count(b.descriptor_count)
-        .stage_flags(vk::ShaderStageFlags::from_raw(b.stage_flags))
+/// Descriptor count used for runtime-sized descriptor arrays. This is the upper bound of the
+/// variable-sized binding; the actual count is chosen when the set is allocated.
+pub const UNBOUNDED_DESCRIPTOR_COUNT: u32 = 1024;
+
+/// A descriptor binding merged across every stage that uses it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MergedBinding {
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    pub descriptor_count: u32,
+    pub stage_flags: vk::ShaderStageFlags,
+    pub unbounded: bool,
+}
+
+impl MergedBinding {
+    fn to_vk(&self) -> vk::DescriptorSetLayoutBinding<'static> {
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(self.binding)
+            .descriptor_type(self.descriptor_type)
+            .descriptor_count(if self.unbounded { UNBOUNDED_DESCRIPTOR_COUNT } else { self.descriptor_count })
+            .stage_flags(self.stage_flags)
+    }
+
+    fn binding_flags(&self) -> vk::DescriptorBindingFlags {
+        if self.unbounded {
+            vk::DescriptorBindingFlags::PARTIALLY_BOUND | vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT
+        } else {
+            vk::DescriptorBindingFlags::empty()
+        }
+    }
 }
 
-pub fn make_descriptor_set_layouts(device_context: &DeviceContext, shaders: &[&varre_assets::Shader]) -> Vec<vk::DescriptorSetLayout> {
-    // Group bindings by set index across all shaders
-    use std::collections::BTreeMap;
-    let mut sets: BTreeMap<u32, Vec<vk::DescriptorSetLayoutBinding>> = BTreeMap::new();
+/// Merge the reflected bindings of shaders that are used together, keyed by (set, binding).
+/// Stages sharing a binding are OR-ed together; a binding declared with a different type or count
+/// by two stages is an error. The result has one entry per set index from 0 to the highest set
+/// used, with empty entries for sets no shader declares.
+pub fn merge_descriptor_bindings(shaders: &[&varre_assets::Shader]) -> Result<Vec<Vec<MergedBinding>>, Box<dyn Error>> {
+    let mut sets: BTreeMap<u32, BTreeMap<u32, MergedBinding>> = BTreeMap::new();
 
     for shader in shaders {
         for binding in shader.descriptor_set_layout_bindings {
-            sets.entry(binding.set)
-                .or_insert_with(Vec::new)
-                .push(convert_binding(binding));
+            let incoming = MergedBinding {
+                binding: binding.binding,
+                descriptor_type: vk::DescriptorType::from_raw(binding.descriptor_type as i32),
+                descriptor_count: binding.descriptor_count,
+                stage_flags: vk::ShaderStageFlags::from_raw(binding.stage_flags),
+                unbounded: binding.unbounded,
+            };
+
+            match sets.entry(binding.set).or_default().entry(binding.binding) {
+                Entry::Vacant(entry) => {
+                    entry.insert(incoming);
+                }
+                Entry::Occupied(mut entry) => {
+                    let existing = entry.get_mut();
+                    if existing.descriptor_type != incoming.descriptor_type
+                        || existing.descriptor_count != incoming.descriptor_count
+                        || existing.unbounded != incoming.unbounded
+                    {
+                        return Err(format!(
+                            "Conflicting declarations of set {} binding {}: {:?}[{}] in {:?} and {:?}[{}] in {:?}",
+                            binding.set, binding.binding,
+                            existing.descriptor_type, existing.descriptor_count, existing.stage_flags,
+                            incoming.descriptor_type, incoming.descriptor_count, shader.id
+                        ).into());
+                    }
+                    existing.stage_flags |= incoming.stage_flags;
+                }
+            }
         }
     }
 
-    if sets.is_empty() {
-        return Vec::new();
+    let set_count = sets.keys().next_back().map_or(0, |last| last + 1);
+    let mut merged: Vec<Vec<MergedBinding>> = (0..set_count)
+        .map(|set| sets.remove(&set).map(|b| b.into_values().collect()).unwrap_or_default())
+        .collect();
+
+    // A variable-sized binding must be the highest binding in its set
+    for (set, bindings) in merged.iter_mut().enumerate() {
+        if let Some(unbounded) = bindings.iter().find(|b| b.unbounded) {
+            if bindings.iter().any(|b| b.binding > unbounded.binding) {
+                return Err(format!(
+                    "Runtime-sized array at set {} binding {} must be the highest binding in its set",
+                    set, unbounded.binding
+                ).into());
+            }
+        }
     }
 
-    // Create a descriptor set layout for each set, in order
+    Ok(merged)
+}
+
+/// Create a descriptor set layout per set of bindings from `merge_descriptor_bindings`
+pub fn make_descriptor_set_layouts(device_context: &DeviceContext, sets: &[Vec<MergedBinding>]) -> Result<Vec<vk::DescriptorSetLayout>, Box<dyn Error>> {
+    let uses_descriptor_indexing = sets.iter().flatten().any(|b| b.unbounded);
+    if uses_descriptor_indexing && !device_context.features.descriptor_indexing {
+        return Err("Shaders use runtime-sized descriptor arrays, but the device does not support descriptor indexing".into());
+    }
+
+    // Create a descriptor set layout for each set index, in order, so layout indices match set numbers
     let mut layouts = Vec::new();
-    for (_set_index, bindings) in sets {
-        let layout_create_info = vk::DescriptorSetLayoutCreateInfo::default()
+    for merged_bindings in sets {
+        let bindings: Vec<_> = merged_bindings.iter().map(MergedBinding::to_vk).collect();
+        let binding_flags: Vec<_> = merged_bindings.iter().map(MergedBinding::binding_flags).collect();
+
+        let mut binding_flags_create_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::default()
+            .binding_flags(&binding_flags);
+
+        let mut layout_create_info = vk::DescriptorSetLayoutCreateInfo::default()
             .bindings(&bindings);
+        if uses_descriptor_indexing {
+            layout_create_info = layout_create_info.push_next(&mut binding_flags_create_info);
+        }
 
         let layout = unsafe {
-            device_context.device.create_descriptor_set_layout(&layout_create_info, None).unwrap()
+            device_context.device.create_descriptor_set_layout(&layout_create_info, None)?
         };
 
         layouts.push(layout);
     }
 
-    layouts
+    Ok(layouts)
+}
+
+/// Merge the reflected push constant blocks of shaders that are used together into a single range.
+/// Shader objects bound together must be created with identical push constant ranges, so every
+/// stage shares one range spanning all of the blocks.
+pub fn merge_push_constant_ranges(shaders: &[&varre_assets::Shader]) -> Vec<vk::PushConstantRange> {
+    shaders
+        .iter()
+        .filter_map(|shader| shader.push_constant_range)
+        .map(|range| vk::PushConstantRange::default()
+            .offset(range.offset)
+            .size(range.size)
+            .stage_flags(vk::ShaderStageFlags::from_raw(range.stage_flags)))
+        .reduce(|merged, range| {
+            let start = merged.offset.min(range.offset);
+            let end = (merged.offset + merged.size).max(range.offset + range.size);
+            merged
+                .offset(start)
+                .size(end - start)
+                .stage_flags(merged.stage_flags | range.stage_flags)
+        })
+        .into_iter()
+        .collect()
+}
+
+pub fn create_pipeline_layout(
+    device_context: &DeviceContext,
+    descriptor_set_layouts: &[vk::DescriptorSetLayout],
+    push_constant_ranges: &[vk::PushConstantRange],
+) -> vk::PipelineLayout {
+    let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo::default()
+        .set_layouts(descriptor_set_layouts)
+        .push_constant_ranges(push_constant_ranges);
+
+    unsafe {
+        device_context.device.create_pipeline_layout(&pipeline_layout_create_info, None)
+            .expect("failed to create pipeline layout")
+    }
+}
+
+/// A typed value for one of a shader's specialization constants
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpecializationValue {
+    Bool(bool),
+    Int32(i32),
+    UInt32(u32),
+    Int64(i64),
+    UInt64(u64),
+    Float32(f32),
+    Float64(f64),
+}
+
+impl SpecializationValue {
+    fn ty(&self) -> SpecializationConstantType {
+        match self {
+            SpecializationValue::Bool(_) => SpecializationConstantType::Bool,
+            SpecializationValue::Int32(_) => SpecializationConstantType::Int32,
+            SpecializationValue::UInt32(_) => SpecializationConstantType::UInt32,
+            SpecializationValue::Int64(_) => SpecializationConstantType::Int64,
+            SpecializationValue::UInt64(_) => SpecializationConstantType::UInt64,
+            SpecializationValue::Float32(_) => SpecializationConstantType::Float32,
+            SpecializationValue::Float64(_) => SpecializationConstantType::Float64,
+        }
+    }
+
+    fn write_bytes(&self, data: &mut Vec<u8>) {
+        match *self {
+            // Boolean specialization constants are consumed as VkBool32
+            SpecializationValue::Bool(v) => data.extend_from_slice(&(v as vk::Bool32).to_ne_bytes()),
+            SpecializationValue::Int32(v) => data.extend_from_slice(&v.to_ne_bytes()),
+            SpecializationValue::UInt32(v) => data.extend_from_slice(&v.to_ne_bytes()),
+            SpecializationValue::Int64(v) => data.extend_from_slice(&v.to_ne_bytes()),
+            SpecializationValue::UInt64(v) => data.extend_from_slice(&v.to_ne_bytes()),
+            SpecializationValue::Float32(v) => data.extend_from_slice(&v.to_ne_bytes()),
+            SpecializationValue::Float64(v) => data.extend_from_slice(&v.to_ne_bytes()),
+        }
+    }
+}
+
+impl From<bool> for SpecializationValue {
+    fn from(v: bool) -> Self { SpecializationValue::Bool(v) }
+}
+
+impl From<i32> for SpecializationValue {
+    fn from(v: i32) -> Self { SpecializationValue::Int32(v) }
+}
+
+impl From<u32> for SpecializationValue {
+    fn from(v: u32) -> Self { SpecializationValue::UInt32(v) }
+}
+
+impl From<i64> for SpecializationValue {
+    fn from(v: i64) -> Self { SpecializationValue::Int64(v) }
+}
+
+impl From<u64> for SpecializationValue {
+    fn from(v: u64) -> Self { SpecializationValue::UInt64(v) }
+}
+
+impl From<f32> for SpecializationValue {
+    fn from(v: f32) -> Self { SpecializationValue::Float32(v) }
+}
+
+impl From<f64> for SpecializationValue {
+    fn from(v: f64) -> Self { SpecializationValue::Float64(v) }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SpecializationKey {
+    Name(String),
+    Id(u32),
+}
+
+impl SpecializationKey {
+    fn matches(&self, constant: &SpecializationConstant) -> bool {
+        match self {
+            SpecializationKey::Name(name) => constant.name == name,
+            SpecializationKey::Id(id) => constant.id == *id,
+        }
+    }
+}
+
+/// Values for a shader's specialization constants, addressed by name or by SpecId.
+/// Constants that are not set keep the default value compiled into the shader.
+#[derive(Debug, Clone, Default)]
+pub struct Specialization {
+    values: Vec<(SpecializationKey, SpecializationValue)>,
+}
+
+/// Specialization values packed into the layout expected by vk::SpecializationInfo
+pub struct SpecializationData {
+    map_entries: Vec<vk::SpecializationMapEntry>,
+    data: Vec<u8>,
+}
+
+impl SpecializationData {
+    pub fn info(&self) -> vk::SpecializationInfo<'_> {
+        vk::SpecializationInfo::default()
+            .map_entries(&self.map_entries)
+            .data(&self.data)
+    }
+}
+
+impl Specialization {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(mut self, name: &str, value: impl Into<SpecializationValue>) -> Self {
+        self.values.push((SpecializationKey::Name(name.to_string()), value.into()));
+        self
+    }
+
+    pub fn set_id(mut self, id: u32, value: impl Into<SpecializationValue>) -> Self {
+        self.values.push((SpecializationKey::Id(id), value.into()));
+        self
+    }
+
+    /// Split the values between several stages used together: each stage gets the values that
+    /// address one of its constants. Errors if a value addresses no stage's constants.
+    pub fn split(&self, stages: &[&[SpecializationConstant]]) -> Result<Vec<Specialization>, Box<dyn Error>> {
+        let mut split = vec![Specialization::default(); stages.len()];
+
+        for (key, value) in &self.values {
+            let mut matched = false;
+            for (constants, specialization) in stages.iter().zip(split.iter_mut()) {
+                if constants.iter().any(|c| key.matches(c)) {
+                    specialization.values.push((key.clone(), *value));
+                    matched = true;
+                }
+            }
+
+            if !matched {
+                return Err(format!("no stage declares a specialization constant matching {:?}", key).into());
+            }
+        }
+
+        Ok(split)
+    }
+
+    /// Validate the values against a shader's reflected specialization constants and pack them.
+    pub fn resolve(&self, constants: &[SpecializationConstant]) -> Result<SpecializationData, Box<dyn Error>> {
+        let mut map_entries: Vec<vk::SpecializationMapEntry> = Vec::with_capacity(self.values.len());
+        let mut data = Vec::new();
+
+        for (key, value) in &self.values {
+            let constant = constants
+                .iter()
+                .find(|c| key.matches(c))
+                .ok_or_else(|| {
+                    let available: Vec<String> = constants.iter().map(|c| format!("{} (id {})", c.name, c.id)).collect();
+                    format!("no specialization constant matches {:?}; available: [{}]", key, available.join(", "))
+                })?;
+
+            if constant.ty != value.ty() {
+                return Err(format!(
+                    "specialization constant {} (id {}) is {:?}, but a {:?} value was provided",
+                    constant.name, constant.id, constant.ty, value.ty()
+                ).into());
+            }
+
+            if map_entries.iter().any(|e| e.constant_id == constant.id) {
+                return Err(format!(
+                    "specialization constant {} (id {}) was set more than once",
+                    constant.name, constant.id
+                ).into());
+            }
+
+            let offset = data.len();
+            value.write_bytes(&mut data);
+
+            map_entries.push(vk::SpecializationMapEntry::default()
+                .constant_id(constant.id)
+                .offset(offset as u32)
+                .size(data.len() - offset));
+        }
+
+        Ok(SpecializationData { map_entries, data })
+    }
+}
+
+/// Check that `shaders` form a valid chain of graphics stages, and that each stage's inputs are
+/// written by the previous stage's outputs at the same location with a compatible type.
+pub fn validate_linked_stages(shaders: &[&varre_assets::Shader]) -> Result<(), Box<dyn Error>> {
+    for pair in shaders.windows(2) {
+        let (producer, consumer) = (pair[0], pair[1]);
+
+        if !get_next_stages(producer.stage.to_vk()).contains(consumer.stage.to_vk()) {
+            return Err(format!(
+                "{:?} ({:?}) cannot be followed by {:?} ({:?})",
+                producer.id, producer.stage, consumer.id, consumer.stage
+            ).into());
+        }
+
+        for input in consumer.inputs {
+            let output = producer.outputs
+                .iter()
+                .find(|output| output.location == input.location)
+                .ok_or_else(|| format!(
+                    "{:?} reads {} at location {}, which {:?} does not write",
+                    consumer.id, input.semantic, input.location, producer.id
+                ))?;
+
+            let compatible = match (interface_format(output.format), interface_format(input.format)) {
+                (Some((output_type, output_components)), Some((input_type, input_components))) => {
+                    output_type == input_type && output_components >= input_components
+                }
+                _ => false,
+            };
+
+            if !compatible {
+                return Err(format!(
+                    "{:?} writes {:?} at location {}, but {:?} reads it as {:?}",
+                    producer.id, vk::Format::from_raw(output.format as i32), output.location,
+                    consumer.id, vk::Format::from_raw(input.format as i32)
+                ).into());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Decode an interface variable format assigned by the asset build into its component type
+/// (identified by the single-component format) and component count. Formats of one component
+/// type are evenly spaced by component count, e.g. R32_SFLOAT = 100, R32G32_SFLOAT = 103, ...
+fn interface_format(format: u32) -> Option<(u32, u32)> {
+    const COMPONENT_TYPES: [(u32, u32); 5] = [
+        (98, 3),  // VK_FORMAT_R32_UINT
+        (99, 3),  // VK_FORMAT_R32_SINT
+        (100, 3), // VK_FORMAT_R32_SFLOAT
+        (112, 3), // VK_FORMAT_R64_SFLOAT
+        (76, 7),  // VK_FORMAT_R16_SFLOAT
+    ];
+
+    COMPONENT_TYPES.iter().find_map(|&(first, stride)| {
+        (format >= first && format < first + 4 * stride && (format - first) % stride == 0)
+            .then(|| (first, (format - first) / stride + 1))
+    })
+}
+
+/// Per-stage state referenced by a vk::ShaderCreateInfoEXT
+struct PreparedShader<'a> {
+    shader: &'a varre_assets::Shader,
+    entry_point: CString,
+    next_stage: vk::ShaderStageFlags,
+    flags: vk::ShaderCreateFlagsEXT,
+    specialization_data: SpecializationData,
+    cache_key: u64,
 }
 
+impl<'a> PreparedShader<'a> {
+    fn new(
+        shader: &'a varre_assets::Shader,
+        next_stage: vk::ShaderStageFlags,
+        flags: vk::ShaderCreateFlagsEXT,
+        descriptor_sets: &[Vec<MergedBinding>],
+        push_constant_ranges: &[vk::PushConstantRange],
+        specialization: &Specialization,
+    ) -> Result<Self, Box<dyn Error>> {
+        let entry_point = CString::new(shader.entry_point)?;
+        let specialization_data = specialization.resolve(shader.specialization_constants)
+            .map_err(|e| format!("{:?}: {}", shader.id, e))?;
+        let cache_key = shader_cache_key(shader, next_stage, flags, descriptor_sets, push_constant_ranges, &specialization_data);
+
+        Ok(Self { shader, entry_point, next_stage, flags, specialization_data, cache_key })
+    }
+}
+
+/// Create a standalone shader object. `descriptor_sets` are the merged bindings
+/// `descriptor_set_layouts` were created from.
 pub fn create_shader_object(
     device_context: &DeviceContext,
     shader: &varre_assets::Shader,
-    descriptor_set_layouts: &Vec<vk::DescriptorSetLayout>
-) -> vk::ShaderEXT {
+    descriptor_sets: &[Vec<MergedBinding>],
+    descriptor_set_layouts: &Vec<vk::DescriptorSetLayout>,
+    push_constant_ranges: &[vk::PushConstantRange],
+    specialization: &Specialization,
+) -> Result<vk::ShaderEXT, Box<dyn Error>> {
+    let next_stage = get_next_stages(shader.stage.to_vk());
+    // A standalone mesh shader is used without a task shader
+    let flags = if shader.stage.to_vk() == vk::ShaderStageFlags::MESH_EXT {
+        vk::ShaderCreateFlagsEXT::NO_TASK_SHADER
+    } else {
+        vk::ShaderCreateFlagsEXT::empty()
+    };
+    let prepared = PreparedShader::new(shader, next_stage, flags, descriptor_sets, push_constant_ranges, specialization)?;
+    let cache_key = prepared.cache_key;
+
+    let shaders = create_shaders(
+        device_context,
+        &[prepared],
+        vk::ShaderCreateFlagsEXT::empty(),
+        descriptor_set_layouts,
+        push_constant_ranges,
+        &[cache_key],
+    )?;
+
+    Ok(shaders[0])
+}
+
+/// Create a full set of stages (e.g. vertex + fragment) as linked shader objects in a single
+/// `create_shaders` call, letting the driver optimize across stage boundaries. `stages` must be in
+/// pipeline order, and the returned shaders must be bound together. `descriptor_sets` are the merged
+/// bindings `descriptor_set_layouts` were created from.
+pub fn create_linked_shader_objects(
+    device_context: &DeviceContext,
+    stages: &[(&varre_assets::Shader, &Specialization)],
+    descriptor_sets: &[Vec<MergedBinding>],
+    descriptor_set_layouts: &[vk::DescriptorSetLayout],
+    push_constant_ranges: &[vk::PushConstantRange],
+) -> Result<Vec<vk::ShaderEXT>, Box<dyn Error>> {
+    let shaders: Vec<&varre_assets::Shader> = stages.iter().map(|(shader, _)| *shader).collect();
+    validate_linked_stages(&shaders)?;
+
+    let prepared = stages
+        .iter()
+        .enumerate()
+        .map(|(index, (shader, specialization))| {
+            let next_stage = stages.get(index + 1)
+                .map_or(vk::ShaderStageFlags::empty(), |(next, _)| next.stage.to_vk());
+            let has_task_stage = index > 0 && stages[index - 1].0.stage.to_vk() == vk::ShaderStageFlags::TASK_EXT;
+            let flags = if shader.stage.to_vk() == vk::ShaderStageFlags::MESH_EXT && !has_task_stage {
+                vk::ShaderCreateFlagsEXT::NO_TASK_SHADER
+            } else {
+                vk::ShaderCreateFlagsEXT::empty()
+            };
+            PreparedShader::new(shader, next_stage, flags, descriptor_sets, push_constant_ranges, specialization)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Linked binaries are only valid together, so every stage's key covers the whole set
+    let linked_key: Vec<u8> = prepared.iter().flat_map(|p| p.cache_key.to_le_bytes()).collect();
+    let cache_keys: Vec<u64> = (0..prepared.len() as u32)
+        .map(|index| cache_key(&[&linked_key, &index.to_le_bytes()]))
+        .collect();
+
+    create_shaders(
+        device_context,
+        &prepared,
+        vk::ShaderCreateFlagsEXT::LINK_STAGE,
+        descriptor_set_layouts,
+        push_constant_ranges,
+        &cache_keys,
+    )
+}
+
+/// Create shader objects, reusing the driver binaries cached under `cache_keys` when they are all
+/// available and falling back to SPIR-V when the driver rejects them.
+fn create_shaders(
+    device_context: &DeviceContext,
+    prepared: &[PreparedShader],
+    flags: vk::ShaderCreateFlagsEXT,
+    descriptor_set_layouts: &[vk::DescriptorSetLayout],
+    push_constant_ranges: &[vk::PushConstantRange],
+    cache_keys: &[u64],
+) -> Result<Vec<vk::ShaderEXT>, Box<dyn Error>> {
     let shader_object_loader = device_context.shader_object_loader.as_ref()
         .expect("shader_object_loader not available");
-    let stage = shader.stage.to_vk();
-    let next_stage = get_next_stages(stage);
+    let shader_cache = &device_context.shader_cache;
 
-    // Convert entry point to CString (owned, null-terminated)
-    let entry_point_string = format!("{}\0", shader.entry_point);
-    let entry_point = CStr::from_bytes_with_nul(entry_point_string.as_bytes())
-        .expect("Invalid entry point");
+    let specialization_infos: Vec<_> = prepared.iter().map(|p| p.specialization_data.info()).collect();
+    let create_infos: Vec<_> = prepared
+        .iter()
+        .zip(&specialization_infos)
+        .map(|(p, specialization_info)| vk::ShaderCreateInfoEXT::default()
+            .flags(flags | p.flags)
+            .stage(p.shader.stage.to_vk())
+            .name(&p.entry_point)
+            .next_stage(p.next_stage)
+            .set_layouts(descriptor_set_layouts)
+            .push_constant_ranges(push_constant_ranges)
+            .specialization_info(specialization_info))
+        .collect();
 
     unsafe {
-        let shader_create_info = vk::ShaderCreateInfoEXT::default()
-            .stage(stage)
-            .code_type(vk::ShaderCodeTypeEXT::SPIRV)
-            .code(shader.spv)
-            .name(entry_point)
-            .next_stage(next_stage)
-            .set_layouts(descriptor_set_layouts.as_slice());
-
-        shader_object_loader
-            .create_shaders(&[shader_create_info], None)
-            .expect("failed to create shaders")[0]
+        // Try the driver binaries cached by a previous run first
+        let binaries: Option<Vec<_>> = cache_keys.iter().map(|key| shader_cache.load_shader_binary(*key)).collect();
+        if let Some(binaries) = binaries {
+            let binary_create_infos: Vec<_> = create_infos
+                .iter()
+                .zip(&binaries)
+                .map(|(create_info, binary)| create_info
+                    .code_type(vk::ShaderCodeTypeEXT::BINARY)
+                    .code(binary.as_bytes()))
+                .collect();
+
+            match shader_object_loader.create_shaders(&binary_create_infos, None) {
+                Ok(shaders) => return Ok(shaders),
+                // The binaries came from a different driver build; recompile from SPIR-V below
+                Err((shaders, vk::Result::INCOMPATIBLE_SHADER_BINARY_EXT)) => {
+                    destroy_shaders(device_context, &shaders);
+                    cache_keys.iter().for_each(|key| shader_cache.remove_shader_binary(*key));
+                }
+                Err((shaders, e)) => {
+                    destroy_shaders(device_context, &shaders);
+                    return Err(e.into());
+                }
+            }
+        }
+
+        let spirv_create_infos: Vec<_> = create_infos
+            .iter()
+            .zip(prepared)
+            .map(|(create_info, p)| create_info
+                .code_type(vk::ShaderCodeTypeEXT::SPIRV)
+                .code(p.shader.spv))
+            .collect();
+
+        let shaders = shader_object_loader
+            .create_shaders(&spirv_create_infos, None)
+            .map_err(|(shaders, e)| {
+                destroy_shaders(device_context, &shaders);
+                e
+            })?;
+
+        for (shader, key) in shaders.iter().zip(cache_keys) {
+            if let Ok(binary) = shader_object_loader.get_shader_binary_data(*shader) {
+                shader_cache.store_shader_binary(*key, &binary);
+            }
+        }
+
+        Ok(shaders)
+    }
+}
+
+/// Destroy the shaders that were created before a `create_shaders` call failed
+fn destroy_shaders(device_context: &DeviceContext, shaders: &[vk::ShaderEXT]) {
+    let shader_object_loader = device_context.shader_object_loader.as_ref()
+        .expect("shader_object_loader not available");
+
+    for shader in shaders.iter().filter(|shader| !shader.is_null()) {
+        unsafe { shader_object_loader.destroy_shader(*shader, None) };
+    }
+}
+
+/// Key a shader's cached binary by its SPIR-V and everything else that affects how the driver
+/// compiles it, including the merged descriptor set layouts it's created against, which other
+/// stages of its program contribute to. The device and driver are accounted for by the cache
+/// directory.
+fn shader_cache_key(
+    shader: &varre_assets::Shader,
+    next_stage: vk::ShaderStageFlags,
+    flags: vk::ShaderCreateFlagsEXT,
+    descriptor_sets: &[Vec<MergedBinding>],
+    push_constant_ranges: &[vk::PushConstantRange],
+    specialization_data: &SpecializationData,
+) -> u64 {
+    // Every list is prefixed with its length so that entries can't shift between lists
+    let mut layout = Vec::new();
+    layout.extend_from_slice(&(descriptor_sets.len() as u32).to_le_bytes());
+    for bindings in descriptor_sets {
+        layout.extend_from_slice(&(bindings.len() as u32).to_le_bytes());
+        for binding in bindings {
+            let vk_binding = binding.to_vk();
+            for value in [
+                vk_binding.binding,
+                vk_binding.descriptor_type.as_raw() as u32,
+                vk_binding.descriptor_count,
+                vk_binding.stage_flags.as_raw(),
+                binding.binding_flags().as_raw(),
+            ] {
+                layout.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+    layout.extend_from_slice(&(push_constant_ranges.len() as u32).to_le_bytes());
+    for range in push_constant_ranges {
+        for value in [range.offset, range.size, range.stage_flags.as_raw()] {
+            layout.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    let mut specialization = Vec::new();
+    specialization.extend_from_slice(&(specialization_data.map_entries.len() as u32).to_le_bytes());
+    for entry in &specialization_data.map_entries {
+        specialization.extend_from_slice(&entry.constant_id.to_le_bytes());
+        specialization.extend_from_slice(&entry.offset.to_le_bytes());
+    }
+    specialization.extend_from_slice(&specialization_data.data);
+
+    cache_key(&[
+        shader.spv,
+        shader.entry_point.as_bytes(),
+        &shader.stage.to_vk().as_raw().to_le_bytes(),
+        &next_stage.as_raw().to_le_bytes(),
+        &flags.as_raw().to_le_bytes(),
+        &layout,
+        &specialization,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use varre_assets::{Shader, ShaderStage};
+
+    const CONSTANTS: [SpecializationConstant; 2] = [
+        SpecializationConstant { id: 0, name: "USE_FOG", ty: SpecializationConstantType::Bool, default_value: 0 },
+        SpecializationConstant { id: 3, name: "LIGHT_COUNT", ty: SpecializationConstantType::UInt32, default_value: 4 },
+    ];
+
+    /// A shader of `stage` without code, bindings or interface, for tests to fill in
+    fn test_shader(stage: ShaderStage) -> Shader {
+        Shader {
+            id: varre_assets::ShaderID::BASIC_MODEL_VERTEX,
+            spv: &[],
+            stage,
+            entry_point: "main",
+            descriptor_set_layout_bindings: &[],
+            specialization_constants: &[],
+            push_constant_range: None,
+            inputs: &[],
+            outputs: &[],
+        }
+    }
+
+    #[test]
+    fn test_resolve_specialization() {
+        let data = Specialization::new()
+            .set("LIGHT_COUNT", 8u32)
+            .set_id(0, true)
+            .resolve(&CONSTANTS)
+            .expect("valid specialization was rejected");
+
+        assert_eq!(data.map_entries.len(), 2);
+        assert_eq!(data.map_entries[0].constant_id, 3);
+        assert_eq!(data.map_entries[1].constant_id, 0);
+        assert_eq!(data.map_entries[1].offset, 4);
+        assert_eq!(data.map_entries[1].size, size_of::<vk::Bool32>());
+        assert_eq!(data.data.len(), 8);
+    }
+
+    #[test]
+    fn test_split_specialization() {
+        const FRAGMENT_CONSTANTS: [SpecializationConstant; 1] = [
+            SpecializationConstant { id: 0, name: "USE_FOG", ty: SpecializationConstantType::Bool, default_value: 0 },
+        ];
+
+        let specialization = Specialization::new().set("USE_FOG", true).set("LIGHT_COUNT", 2u32);
+        let split = specialization.split(&[&CONSTANTS, &FRAGMENT_CONSTANTS]).expect("valid specialization was rejected");
+        assert_eq!(split[0].values.len(), 2);
+        assert_eq!(split[1].values.len(), 1);
+
+        assert!(Specialization::new().set("MISSING", 1u32).split(&[&CONSTANTS, &FRAGMENT_CONSTANTS]).is_err());
+    }
+
+    #[test]
+    fn test_merge_push_constant_ranges() {
+        let mut vertex = test_shader(ShaderStage::Vertex);
+        vertex.push_constant_range = Some(varre_assets::VkPushConstantRange { offset: 0, size: 64, stage_flags: vk::ShaderStageFlags::VERTEX.as_raw() });
+        let mut fragment = test_shader(ShaderStage::Fragment);
+        fragment.push_constant_range = Some(varre_assets::VkPushConstantRange { offset: 64, size: 16, stage_flags: vk::ShaderStageFlags::FRAGMENT.as_raw() });
+        let no_block = test_shader(ShaderStage::Vertex);
+
+        let ranges = merge_push_constant_ranges(&[&vertex, &no_block, &fragment]);
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0].offset, 0);
+        assert_eq!(ranges[0].size, 80);
+        assert_eq!(ranges[0].stage_flags, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT);
+
+        assert!(merge_push_constant_ranges(&[&no_block]).is_empty());
+    }
+
+    #[test]
+    fn test_merge_descriptor_bindings() {
+        const fn binding(set: u32, binding: u32, descriptor_type: vk::DescriptorType, stage: vk::ShaderStageFlags) -> varre_assets::VkDescriptorSetLayoutBinding {
+            varre_assets::VkDescriptorSetLayoutBinding {
+                set,
+                binding,
+                descriptor_type: descriptor_type.as_raw() as u32,
+                descriptor_count: 1,
+                stage_flags: stage.as_raw(),
+                unbounded: false,
+            }
+        }
+
+        const VERTEX_BINDINGS: [varre_assets::VkDescriptorSetLayoutBinding; 1] = [
+            binding(0, 0, vk::DescriptorType::UNIFORM_BUFFER, vk::ShaderStageFlags::VERTEX),
+        ];
+        const FRAGMENT_BINDINGS: [varre_assets::VkDescriptorSetLayoutBinding; 2] = [
+            binding(0, 0, vk::DescriptorType::UNIFORM_BUFFER, vk::ShaderStageFlags::FRAGMENT),
+            binding(2, 1, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT),
+        ];
+        const CONFLICTING_BINDINGS: [varre_assets::VkDescriptorSetLayoutBinding; 1] = [
+            binding(0, 0, vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::FRAGMENT),
+        ];
+
+        let mut vertex = test_shader(ShaderStage::Vertex);
+        vertex.descriptor_set_layout_bindings = &VERTEX_BINDINGS;
+        let mut fragment = test_shader(ShaderStage::Fragment);
+        fragment.descriptor_set_layout_bindings = &FRAGMENT_BINDINGS;
+        let mut conflicting = test_shader(ShaderStage::Fragment);
+        conflicting.descriptor_set_layout_bindings = &CONFLICTING_BINDINGS;
+
+        let sets = merge_descriptor_bindings(&[&vertex, &fragment]).expect("compatible bindings were rejected");
+        assert_eq!(sets.len(), 3);
+        assert_eq!(sets[0].len(), 1);
+        assert_eq!(sets[0][0].stage_flags, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT);
+        assert!(sets[1].is_empty());
+        assert_eq!(sets[2][0].binding, 1);
+
+        assert!(merge_descriptor_bindings(&[&vertex, &conflicting]).is_err());
+    }
+
+    #[test]
+    fn test_shader_cache_key_covers_linked_layout() {
+        const fn binding(set: u32, binding: u32, stage: vk::ShaderStageFlags, unbounded: bool) -> varre_assets::VkDescriptorSetLayoutBinding {
+            varre_assets::VkDescriptorSetLayoutBinding {
+                set,
+                binding,
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER.as_raw() as u32,
+                descriptor_count: 1,
+                stage_flags: stage.as_raw(),
+                unbounded,
+            }
+        }
+
+        const VERTEX_BINDINGS: [varre_assets::VkDescriptorSetLayoutBinding; 1] = [
+            binding(0, 0, vk::ShaderStageFlags::VERTEX, false),
+        ];
+        const FRAGMENT_BINDINGS: [varre_assets::VkDescriptorSetLayoutBinding; 1] = [
+            binding(0, 1, vk::ShaderStageFlags::FRAGMENT, false),
+        ];
+        const SHARED_BINDINGS: [varre_assets::VkDescriptorSetLayoutBinding; 1] = [
+            binding(0, 0, vk::ShaderStageFlags::FRAGMENT, false),
+        ];
+        const UNBOUNDED_BINDINGS: [varre_assets::VkDescriptorSetLayoutBinding; 1] = [
+            binding(0, 1, vk::ShaderStageFlags::FRAGMENT, true),
+        ];
+        const LATER_SET_BINDINGS: [varre_assets::VkDescriptorSetLayoutBinding; 1] = [
+            binding(1, 1, vk::ShaderStageFlags::FRAGMENT, false),
+        ];
+
+        let mut vertex = test_shader(ShaderStage::Vertex);
+        vertex.descriptor_set_layout_bindings = &VERTEX_BINDINGS;
+        let specialization = Specialization::new().resolve(&[]).expect("empty specialization was rejected");
+
+        // The vertex shader's key with each fragment shader linked to it
+        let key = |fragment_bindings: &'static [varre_assets::VkDescriptorSetLayoutBinding]| {
+            let mut fragment = test_shader(ShaderStage::Fragment);
+            fragment.descriptor_set_layout_bindings = fragment_bindings;
+            let sets = merge_descriptor_bindings(&[&vertex, &fragment]).expect("compatible bindings were rejected");
+            shader_cache_key(&vertex, vk::ShaderStageFlags::FRAGMENT, vk::ShaderCreateFlagsEXT::empty(), &sets, &[], &specialization)
+        };
+
+        let keys = [key(&FRAGMENT_BINDINGS), key(&SHARED_BINDINGS), key(&UNBOUNDED_BINDINGS), key(&LATER_SET_BINDINGS), key(&[])];
+        assert_eq!(key(&FRAGMENT_BINDINGS), keys[0]);
+        for (index, a) in keys.iter().enumerate() {
+            assert!(keys[index + 1..].iter().all(|b| a != b), "keys {:?} aren't distinct", keys);
+        }
+    }
+
+    #[test]
+    fn test_validate_linked_stages() {
+        use varre_assets::ShaderInterfaceVariable;
+
+        const VERTEX_OUTPUTS: [ShaderInterfaceVariable; 1] = [
+            ShaderInterfaceVariable { location: 0, format: 106, semantic: "COLOR" }, // R32G32B32_SFLOAT
+        ];
+        const FRAGMENT_INPUTS: [ShaderInterfaceVariable; 1] = [
+            ShaderInterfaceVariable { location: 0, format: 103, semantic: "COLOR" }, // R32G32_SFLOAT
+        ];
+        const WIDER_INPUTS: [ShaderInterfaceVariable; 1] = [
+            ShaderInterfaceVariable { location: 0, format: 109, semantic: "COLOR" }, // R32G32B32A32_SFLOAT
+        ];
+        const INTEGER_INPUTS: [ShaderInterfaceVariable; 1] = [
+            ShaderInterfaceVariable { location: 0, format: 104, semantic: "COLOR" }, // R32G32B32_UINT
+        ];
+        const MISSING_INPUTS: [ShaderInterfaceVariable; 1] = [
+            ShaderInterfaceVariable { location: 1, format: 100, semantic: "FOG" },
+        ];
+
+        let mut vertex = test_shader(ShaderStage::Vertex);
+        vertex.outputs = &VERTEX_OUTPUTS;
+        let mut fragment = test_shader(ShaderStage::Fragment);
+        fragment.inputs = &FRAGMENT_INPUTS;
+
+        assert!(validate_linked_stages(&[&vertex, &fragment]).is_ok());
+        assert!(validate_linked_stages(&[&fragment, &vertex]).is_err());
+
+        for inputs in [&WIDER_INPUTS, &INTEGER_INPUTS, &MISSING_INPUTS] {
+            fragment.inputs = inputs;
+            assert!(validate_linked_stages(&[&vertex, &fragment]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_resolve_specialization_errors() {
+        assert!(Specialization::new().set("MISSING", 1u32).resolve(&CONSTANTS).is_err());
+        assert!(Specialization::new().set("LIGHT_COUNT", 1.0f32).resolve(&CONSTANTS).is_err());
+        assert!(Specialization::new().set("LIGHT_COUNT", 1u32).set_id(3, 2u32).resolve(&CONSTANTS).is_err());
     }
 }
\ No newline at end of file
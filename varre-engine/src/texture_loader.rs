@@ -0,0 +1,834 @@
+use ash::vk;
+use std::borrow::Cow;
+use std::error::Error;
+use std::io::Read;
+use std::path::Path;
+use varre_assets::texture_format::{vk_format, Supercompression, KTX2_IDENTIFIER};
+use varre_assets::{FormatInfo, Ktx2View, TextureDesc};
+
+/// Buffer offsets of copies into block-compressed images must be multiples of the block size
+const STAGING_ALIGNMENT: usize = 16;
+
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+
+/// Color models of Basis Universal textures in a KTX2 data format descriptor
+const KHR_DF_MODEL_ETC1S: u8 = 163;
+const KHR_DF_MODEL_UASTC: u8 = 166;
+
+/// Texture data decoded from a file, laid out for copying from a staging buffer
+#[derive(Debug)]
+pub struct TextureImage {
+    /// Dimensions and format of `data`, which may differ from the file's if it was transcoded
+    pub desc: TextureDesc,
+    pub mip_levels: u32,
+    pub data: Vec<u8>,
+    /// Copies from `data` into every mip level and array layer
+    pub regions: Vec<vk::BufferImageCopy>,
+}
+
+impl TextureImage {
+    fn new(desc: TextureDesc, mip_levels: u32) -> Self {
+        Self { desc, mip_levels, data: Vec::new(), regions: Vec::new() }
+    }
+
+    pub fn format(&self) -> vk::Format {
+        vk::Format::from_raw(self.desc.vk_format as i32)
+    }
+
+    /// Array layers of the image, six per cube
+    pub fn array_layers(&self) -> u32 {
+        self.desc.layer_count.max(1) * self.desc.face_count
+    }
+
+    /// Append the images of mip `level` for `layer_count` layers starting at `base_layer`
+    fn push_subresource(&mut self, bytes: &[u8], level: u32, base_layer: u32, layer_count: u32) {
+        let (width, height, depth) = self.desc.level_extent(level);
+        let offset = self.data.len().next_multiple_of(STAGING_ALIGNMENT);
+        self.data.resize(offset, 0);
+        self.data.extend_from_slice(bytes);
+
+        self.regions.push(
+            vk::BufferImageCopy::default()
+                .buffer_offset(offset as vk::DeviceSize)
+                .image_subresource(
+                    vk::ImageSubresourceLayers::default()
+                        .aspect_mask(vk::ImageAspectFlags::COLOR)
+                        .mip_level(level)
+                        .base_array_layer(base_layer)
+                        .layer_count(layer_count),
+                )
+                .image_extent(vk::Extent3D { width, height, depth }),
+        );
+    }
+}
+
+/// Read a KTX2 or DDS file. `supported` reports whether the device can sample a format, which
+/// picks the format Basis Universal textures are transcoded to.
+pub fn load_texture_file(path: &Path, supported: &dyn Fn(vk::Format) -> bool) -> Result<TextureImage, Box<dyn Error>> {
+    let data = std::fs::read(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    decode_texture(&data, supported).map_err(|e| format!("{}: {}", path.display(), e).into())
+}
+
+/// Decode KTX2 or DDS data, telling them apart by their magic numbers
+pub fn decode_texture(data: &[u8], supported: &dyn Fn(vk::Format) -> bool) -> Result<TextureImage, Box<dyn Error>> {
+    if data.starts_with(&KTX2_IDENTIFIER) {
+        decode_ktx2(&Ktx2View::parse(data)?, supported)
+    } else if data.starts_with(DDS_MAGIC) {
+        decode_dds(data)
+    } else {
+        Err("neither a KTX2 nor a DDS file".into())
+    }
+}
+
+/// Inflate a KTX2 file's mip levels, transcoding Basis Universal textures, both UASTC and
+/// BasisLZ-supercompressed ETC1S, to BC7 or RGBA8.
+///
+/// Zstandard supercompression is supported for any format but ETC1S.
+pub fn decode_ktx2(ktx2: &Ktx2View, supported: &dyn Fn(vk::Format) -> bool) -> Result<TextureImage, Box<dyn Error>> {
+    let mut desc = ktx2.desc;
+    let color_model = ktx2.data_format_descriptor().get(12).copied();
+    let basis = desc.vk_format == vk_format::UNDEFINED
+        && matches!(color_model, Some(KHR_DF_MODEL_ETC1S | KHR_DF_MODEL_UASTC));
+
+    let transcode = basis.then(|| BasisTarget::select(ktx2.data_format_descriptor(), supported));
+    if let Some(target) = &transcode {
+        desc.vk_format = target.vk_format;
+    }
+    let format = FormatInfo::of(desc.vk_format).ok_or(format!("unsupported texture format {}", desc.vk_format))?;
+
+    // ETC1S levels share the codebooks in the supercompression global data, so the whole texture
+    // is transcoded at once rather than level by level
+    let mut etc1s_levels = match &transcode {
+        Some(target) if color_model == Some(KHR_DF_MODEL_ETC1S) => {
+            Some(target.transcode_etc1s(&basis_file(ktx2)?, &desc, ktx2.levels().len() as u32)?)
+        }
+        _ => None,
+    };
+
+    let mut image = TextureImage::new(desc, ktx2.levels().len() as u32);
+    for (level, entry) in ktx2.levels().iter().enumerate() {
+        let bytes = if let Some(levels) = &mut etc1s_levels {
+            Cow::Owned(std::mem::take(&mut levels[level]))
+        } else {
+            let stored = ktx2.level_data(level);
+            let bytes = match ktx2.supercompression {
+                Supercompression::None => Cow::Borrowed(stored),
+                Supercompression::Zstandard => Cow::Owned(inflate_zstd(stored, entry.uncompressed_length as usize)?),
+                scheme => return Err(format!("{:?} supercompression isn't supported", scheme).into()),
+            };
+            match &transcode {
+                Some(target) => Cow::Owned(target.transcode_uastc(&bytes, &desc, level as u32)?),
+                None => bytes,
+            }
+        };
+
+        let expected = desc.level_size(&format, level as u32);
+        if bytes.len() != expected {
+            return Err(format!("mip level {} is {} bytes, expected {}", level, bytes.len(), expected).into());
+        }
+        image.push_subresource(&bytes, level as u32, 0, image.array_layers());
+    }
+
+    Ok(image)
+}
+
+fn inflate_zstd(data: &[u8], uncompressed_length: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut decoder = ruzstd::decoding::StreamingDecoder::new(data)?;
+    let mut inflated = Vec::with_capacity(uncompressed_length);
+    decoder.read_to_end(&mut inflated)?;
+    if inflated.len() != uncompressed_length {
+        return Err(format!("zstd data inflated to {} bytes, expected {}", inflated.len(), uncompressed_length).into());
+    }
+    Ok(inflated)
+}
+
+/// Rearrange a BasisLZ-supercompressed ETC1S texture into a .basis file, the form the
+/// basis-universal transcoder reads ETC1S in. The file holds the header, a description of each
+/// slice, the codebooks and tables from the supercompression global data, then the slices. Each
+/// image of each level is an RGB slice, followed by an alpha slice if the texture has alpha.
+fn basis_file(ktx2: &Ktx2View) -> Result<Vec<u8>, Box<dyn Error>> {
+    // Header flags and texture types
+    const ETC1S: usize = 1;
+    const HAS_ALPHA_SLICES: usize = 4;
+    const SRGB: usize = 16;
+    const TEXTURE_2D: usize = 0;
+    const TEXTURE_2D_ARRAY: usize = 1;
+    const CUBEMAP_ARRAY: usize = 2;
+
+    if ktx2.supercompression != Supercompression::BasisLz {
+        return Err(format!("ETC1S textures must be BasisLZ supercompressed, not {:?}", ktx2.supercompression).into());
+    }
+    let desc = &ktx2.desc;
+    if desc.depth > 1 {
+        return Err("BasisLZ 3D textures aren't supported".into());
+    }
+
+    // Global data: endpoint and selector counts, byte lengths of the endpoints, selectors, tables
+    // and extended data, then a description of each image, from the largest level down
+    let sgd = ktx2.supercompression_global_data();
+    let read = |offset: usize, size: usize| {
+        sgd.get(offset..offset + size)
+            .map(|bytes| bytes.iter().rev().fold(0, |value, byte| value << 8 | *byte as usize))
+            .ok_or("BasisLZ global data is truncated")
+    };
+    let (endpoint_count, selector_count) = (read(0, 2)?, read(2, 2)?);
+    let lengths = [read(4, 4)?, read(8, 4)?, read(12, 4)?, read(16, 4)?];
+    let images_per_level = desc.images_per_level(0);
+    let codebooks_start = 20 + images_per_level * ktx2.levels().len() * 20;
+    let codebooks = sgd.get(codebooks_start..codebooks_start + lengths.iter().sum::<usize>())
+        .ok_or("BasisLZ global data is truncated")?;
+
+    // (level, image, slice flags, data), with RGB and alpha slices told apart by flag 1
+    let has_alpha = read(20 + 16, 4)? > 0;
+    let mut slices: Vec<(usize, usize, usize, &[u8])> = Vec::new();
+    for level in 0..ktx2.levels().len() {
+        let data = ktx2.level_data(level);
+        for image in 0..images_per_level {
+            let image_desc = 20 + (level * images_per_level + image) * 20;
+            let [rgb_offset, rgb_length, alpha_offset, alpha_length] = [4, 8, 12, 16].map(|offset| read(image_desc + offset, 4));
+            let slice = |offset: usize, length: usize| {
+                data.get(offset..offset + length).ok_or(format!("BasisLZ slice of mip level {} is out of bounds", level))
+            };
+
+            slices.push((level, image, 0, slice(rgb_offset?, rgb_length?)?));
+            if (alpha_length? > 0) != has_alpha {
+                return Err(format!("BasisLZ image {} of mip level {} has alpha unlike the first", image, level).into());
+            }
+            if has_alpha {
+                slices.push((level, image, 1, slice(alpha_offset?, alpha_length?)?));
+            }
+        }
+    }
+
+    const HEADER_SIZE: usize = 77;
+    const SLICE_DESC_SIZE: usize = 23;
+    let codebooks_offset = HEADER_SIZE + slices.len() * SLICE_DESC_SIZE;
+    let offsets: Vec<usize> = lengths.iter()
+        .scan(codebooks_offset, |offset, length| Some(std::mem::replace(offset, *offset + length)))
+        .collect();
+
+    let mut body = Vec::new();
+    let mut slice_offset = codebooks_offset + codebooks.len();
+    for (level, image, flags, data) in &slices {
+        let (width, height, _) = desc.level_extent(*level as u32);
+        let (width, height) = (width as usize, height as usize);
+        write_packed(&mut body, &[
+            (*image, 3), (*level, 1), (*flags, 1), (width, 2), (height, 2), (width.div_ceil(4), 2), (height.div_ceil(4), 2),
+            (slice_offset, 4), (data.len(), 4), (crc16(data) as usize, 2),
+        ])?;
+        slice_offset += data.len();
+    }
+    body.extend_from_slice(codebooks);
+    for (_, _, _, data) in &slices {
+        body.extend_from_slice(data);
+    }
+
+    let srgb = ktx2.data_format_descriptor().get(14) == Some(&2);
+    let flags = ETC1S | if has_alpha { HAS_ALPHA_SLICES } else { 0 } | if srgb { SRGB } else { 0 };
+    let texture_type = match (desc.face_count, desc.layer_count) {
+        (6, _) => CUBEMAP_ARRAY,
+        (_, 0) => TEXTURE_2D,
+        _ => TEXTURE_2D_ARRAY,
+    };
+
+    // Everything after the header's checksum: data size and checksum, slice and image counts,
+    // texture format, flags, texture type, frame duration, reserved and user data, codebook and
+    // table locations, slice descriptions and extended data
+    let mut header = Vec::with_capacity(HEADER_SIZE - 8);
+    write_packed(&mut header, &[
+        (body.len(), 4), (crc16(&body) as usize, 2), (slices.len(), 3), (images_per_level, 3),
+        (0, 1), (flags, 2), (texture_type, 1), (0, 3), (0, 4), (0, 4), (0, 4),
+        (endpoint_count, 2), (offsets[0], 4), (lengths[0], 3),
+        (selector_count, 2), (offsets[1], 4), (lengths[1], 3),
+        (offsets[2], 4), (lengths[2], 4),
+        (HEADER_SIZE, 4),
+        (offsets[3], 4), (lengths[3], 4),
+    ])?;
+
+    let mut file = Vec::with_capacity(HEADER_SIZE + body.len());
+    // Signature "sB", version 0x13, header size and checksum
+    write_packed(&mut file, &[(0x4273, 2), (0x13, 2), (HEADER_SIZE, 2), (crc16(&header) as usize, 2)])?;
+    file.extend_from_slice(&header);
+    file.extend_from_slice(&body);
+    Ok(file)
+}
+
+/// Append little-endian `(value, size in bytes)` fields
+fn write_packed(out: &mut Vec<u8>, fields: &[(usize, usize)]) -> Result<(), Box<dyn Error>> {
+    for (value, size) in fields {
+        if *size < size_of::<usize>() && value >> (size * 8) != 0 {
+            return Err(format!("{} doesn't fit the {} bytes .basis files store it in", value, size).into());
+        }
+        out.extend_from_slice(&value.to_le_bytes()[..*size]);
+    }
+    Ok(())
+}
+
+/// Checksum of .basis files (CRC-16/GENIBUS)
+fn crc16(data: &[u8]) -> u16 {
+    !data.iter().fold(!0u16, |crc, byte| {
+        let q = *byte as u16 ^ (crc >> 8);
+        let k = (q >> 4) ^ q;
+        (crc << 8) ^ k ^ (k << 5) ^ (k << 12)
+    })
+}
+
+/// Format a Basis Universal texture is transcoded to: BC7 where the device supports it, RGBA8
+/// otherwise
+struct BasisTarget {
+    vk_format: u32,
+    /// Whether a UASTC texture has alpha. ETC1S textures have alpha slices instead.
+    has_alpha: bool,
+}
+
+impl BasisTarget {
+    fn select(dfd: &[u8], supported: &dyn Fn(vk::Format) -> bool) -> Self {
+        // Basic descriptor block: transfer function at byte 14, first sample's channel at 31
+        let srgb = dfd.get(14) == Some(&2);
+        // UASTC channel ids: 0 RGB, 3 RGBA, 4 RRR, 5 RRRG, 6 RG
+        let has_alpha = matches!(dfd.get(31).map(|channel| channel & 0xF), Some(3 | 5));
+
+        let bc7 = if srgb { vk_format::BC7_SRGB_BLOCK } else { vk_format::BC7_UNORM_BLOCK };
+        let rgba8 = if srgb { vk_format::R8G8B8A8_SRGB } else { vk_format::R8G8B8A8_UNORM };
+        let vk_format = if supported(vk::Format::from_raw(bc7 as i32)) { bc7 } else { rgba8 };
+        Self { vk_format, has_alpha }
+    }
+
+    #[cfg(feature = "basis-universal")]
+    fn is_bc7(&self) -> bool {
+        matches!(self.vk_format, vk_format::BC7_SRGB_BLOCK | vk_format::BC7_UNORM_BLOCK)
+    }
+
+    #[cfg(feature = "basis-universal")]
+    fn init() {
+        static INIT: std::sync::Once = std::sync::Once::new();
+        INIT.call_once(basis_universal::transcoder_init);
+    }
+
+    #[cfg(feature = "basis-universal")]
+    fn transcode_uastc(&self, data: &[u8], desc: &TextureDesc, level: u32) -> Result<Vec<u8>, Box<dyn Error>> {
+        use basis_universal::{DecodeFlags, LowLevelUastcTranscoder, SliceParametersUastc, TranscoderBlockFormat};
+
+        Self::init();
+
+        let (width, height, _) = desc.level_extent(level);
+        let (num_blocks_x, num_blocks_y) = (width.div_ceil(4), height.div_ceil(4));
+        let slice_size = num_blocks_x as usize * num_blocks_y as usize * 16;
+        if data.len() != slice_size * desc.images_per_level(level) {
+            return Err(format!("UASTC mip level {} is {} bytes, expected {}", level, data.len(), slice_size * desc.images_per_level(level)).into());
+        }
+
+        let block_format = if self.is_bc7() { TranscoderBlockFormat::BC7 } else { TranscoderBlockFormat::RGBA32 };
+        let transcoder = LowLevelUastcTranscoder::new();
+
+        let mut transcoded = Vec::new();
+        for slice in data.chunks_exact(slice_size) {
+            let parameters = SliceParametersUastc {
+                num_blocks_x,
+                num_blocks_y,
+                has_alpha: self.has_alpha,
+                original_width: width,
+                original_height: height,
+            };
+            let image = transcoder.transcode_slice(slice, parameters, DecodeFlags::HIGH_QUALITY, block_format)
+                .map_err(|_| format!("failed to transcode UASTC mip level {}", level))?;
+            transcoded.extend_from_slice(&image);
+        }
+        Ok(transcoded)
+    }
+
+    /// Transcode every level of the .basis file from `basis_file`, returning each level's images
+    #[cfg(feature = "basis-universal")]
+    fn transcode_etc1s(&self, basis_file: &[u8], desc: &TextureDesc, level_count: u32) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+        use basis_universal::{TranscodeParameters, Transcoder, TranscoderTextureFormat};
+
+        Self::init();
+
+        let format = if self.is_bc7() { TranscoderTextureFormat::BC7_RGBA } else { TranscoderTextureFormat::RGBA32 };
+        let mut transcoder = Transcoder::new();
+        transcoder.prepare_transcoding(basis_file).map_err(|_| "failed to read the ETC1S codebooks")?;
+
+        let levels = (0..level_count)
+            .map(|level| {
+                let mut transcoded = Vec::new();
+                for image in 0..desc.images_per_level(level) as u32 {
+                    let parameters = TranscodeParameters { image_index: image, level_index: level, ..Default::default() };
+                    let image = transcoder.transcode_image_level(basis_file, format, parameters)
+                        .map_err(|_| format!("failed to transcode ETC1S mip level {}", level))?;
+                    transcoded.extend_from_slice(&image);
+                }
+                Ok(transcoded)
+            })
+            .collect();
+        transcoder.end_transcoding();
+        levels
+    }
+
+    #[cfg(not(feature = "basis-universal"))]
+    fn transcode_uastc(&self, _data: &[u8], _desc: &TextureDesc, _level: u32) -> Result<Vec<u8>, Box<dyn Error>> {
+        Err("UASTC textures need the basis-universal feature".into())
+    }
+
+    #[cfg(not(feature = "basis-universal"))]
+    fn transcode_etc1s(&self, _basis_file: &[u8], _desc: &TextureDesc, _level_count: u32) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+        Err("ETC1S textures need the basis-universal feature".into())
+    }
+}
+
+/// Parse a DDS file, with or without the DX10 header extension.
+///
+/// DDS stores every mip of one array layer before the next layer, so each layer and level gets
+/// its own copy region.
+pub fn decode_dds(data: &[u8]) -> Result<TextureImage, Box<dyn Error>> {
+    const HEADER_SIZE: usize = 128;
+    const DX10_HEADER_SIZE: usize = 20;
+    // dwFlags
+    const DDSD_MIPMAPCOUNT: u32 = 0x2_0000;
+    // ddspf.dwFlags
+    const DDPF_ALPHAPIXELS: u32 = 0x1;
+    const DDPF_FOURCC: u32 = 0x4;
+    const DDPF_RGB: u32 = 0x40;
+    const DDPF_LUMINANCE: u32 = 0x2_0000;
+    // dwCaps2
+    const DDSCAPS2_CUBEMAP: u32 = 0x200;
+    const DDSCAPS2_CUBEMAP_ALLFACES: u32 = 0xFC00;
+    const DDSCAPS2_VOLUME: u32 = 0x20_0000;
+    // DX10 header
+    const D3D10_RESOURCE_DIMENSION_TEXTURE1D: u32 = 2;
+    const D3D10_RESOURCE_DIMENSION_TEXTURE3D: u32 = 4;
+    const D3D10_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+    if data.len() < HEADER_SIZE || !data.starts_with(DDS_MAGIC) {
+        return Err("not a DDS file".into());
+    }
+    let read_u32 = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+
+    let flags = read_u32(8);
+    let height = read_u32(12);
+    let width = read_u32(16);
+    let depth = read_u32(24);
+    let mip_levels = if flags & DDSD_MIPMAPCOUNT != 0 { read_u32(28).max(1) } else { 1 };
+    let pixel_flags = read_u32(80);
+    let four_cc = &data[84..88];
+    let caps2 = read_u32(112);
+
+    let mut desc = TextureDesc::new_2d(0, width, height);
+    let mut data_offset = HEADER_SIZE;
+
+    if pixel_flags & DDPF_FOURCC != 0 && four_cc == b"DX10" {
+        if data.len() < HEADER_SIZE + DX10_HEADER_SIZE {
+            return Err("DDS file is truncated in its DX10 header".into());
+        }
+        let dxgi_format = read_u32(128);
+        desc.vk_format = dxgi_to_vk_format(dxgi_format).ok_or(format!("unsupported DXGI format {}", dxgi_format))?;
+        match read_u32(132) {
+            D3D10_RESOURCE_DIMENSION_TEXTURE1D => desc.height = 0,
+            D3D10_RESOURCE_DIMENSION_TEXTURE3D => desc.depth = depth.max(1),
+            _ => {}
+        }
+        if read_u32(136) & D3D10_RESOURCE_MISC_TEXTURECUBE != 0 {
+            desc.face_count = 6;
+        }
+        let array_size = read_u32(140);
+        if array_size > 1 {
+            desc.layer_count = array_size;
+        }
+        data_offset += DX10_HEADER_SIZE;
+    } else {
+        desc.vk_format = if pixel_flags & DDPF_FOURCC != 0 {
+            four_cc_to_vk_format(four_cc).ok_or(format!("unsupported DDS FourCC {:?}", String::from_utf8_lossy(four_cc)))?
+        } else {
+            let bit_count = read_u32(88);
+            let masks = [read_u32(92), read_u32(96), read_u32(100), if pixel_flags & DDPF_ALPHAPIXELS != 0 { read_u32(104) } else { 0 }];
+            match (pixel_flags & (DDPF_RGB | DDPF_LUMINANCE), bit_count, masks) {
+                (DDPF_RGB, 32, [0xFF, 0xFF00, 0xFF_0000, _]) => vk_format::R8G8B8A8_UNORM,
+                (DDPF_RGB, 32, [0xFF_0000, 0xFF00, 0xFF, _]) => vk_format::B8G8R8A8_UNORM,
+                (DDPF_LUMINANCE, 8, [0xFF, _, _, 0]) => vk_format::R8_UNORM,
+                _ => return Err(format!("unsupported DDS pixel format: {} bits with masks {:x?}", bit_count, masks).into()),
+            }
+        };
+        if caps2 & DDSCAPS2_CUBEMAP != 0 {
+            if caps2 & DDSCAPS2_CUBEMAP_ALLFACES != DDSCAPS2_CUBEMAP_ALLFACES {
+                return Err("DDS cube maps must have all six faces".into());
+            }
+            desc.face_count = 6;
+        }
+        if caps2 & DDSCAPS2_VOLUME != 0 {
+            desc.depth = depth.max(1);
+        }
+    }
+
+    if width == 0 || height == 0 {
+        return Err(format!("DDS texture is {}x{}", width, height).into());
+    }
+    // A full mip chain halves the largest extent down to 1
+    let max_mip_levels = 32 - width.max(height).max(desc.depth).leading_zeros();
+    if mip_levels > max_mip_levels {
+        return Err(format!("{}x{} DDS texture has {} mip levels, more than its {}", width, height, mip_levels, max_mip_levels).into());
+    }
+
+    let format = FormatInfo::of(desc.vk_format).ok_or(format!("unsupported texture format {}", desc.vk_format))?;
+    let mut image = TextureImage::new(desc, mip_levels);
+    let mut offset = data_offset;
+    for layer in 0..image.array_layers() {
+        for level in 0..mip_levels {
+            let (width, height, depth) = desc.level_extent(level);
+            let size = format.image_size(width, height) * depth as usize;
+            let bytes = data.get(offset..offset + size)
+                .ok_or(format!("DDS file is truncated: layer {} mip level {} ends at byte {} of {}", layer, level, offset + size, data.len()))?;
+            image.push_subresource(bytes, level, layer, 1);
+            offset += size;
+        }
+    }
+
+    Ok(image)
+}
+
+fn dxgi_to_vk_format(dxgi_format: u32) -> Option<u32> {
+    Some(match dxgi_format {
+        2 => vk_format::R32G32B32A32_SFLOAT,
+        10 => vk_format::R16G16B16A16_SFLOAT,
+        11 => vk_format::R16G16B16A16_UNORM,
+        24 => vk_format::A2B10G10R10_UNORM_PACK32,
+        26 => vk_format::B10G11R11_UFLOAT_PACK32,
+        28 => vk_format::R8G8B8A8_UNORM,
+        29 => vk_format::R8G8B8A8_SRGB,
+        34 => vk_format::R16G16_SFLOAT,
+        41 => vk_format::R32_SFLOAT,
+        49 => vk_format::R8G8_UNORM,
+        54 => vk_format::R16_SFLOAT,
+        56 => vk_format::R16_UNORM,
+        61 => vk_format::R8_UNORM,
+        71 => vk_format::BC1_RGBA_UNORM_BLOCK,
+        72 => vk_format::BC1_RGBA_SRGB_BLOCK,
+        74 => vk_format::BC2_UNORM_BLOCK,
+        75 => vk_format::BC2_SRGB_BLOCK,
+        77 => vk_format::BC3_UNORM_BLOCK,
+        78 => vk_format::BC3_SRGB_BLOCK,
+        80 => vk_format::BC4_UNORM_BLOCK,
+        81 => vk_format::BC4_SNORM_BLOCK,
+        83 => vk_format::BC5_UNORM_BLOCK,
+        84 => vk_format::BC5_SNORM_BLOCK,
+        87 => vk_format::B8G8R8A8_UNORM,
+        91 => vk_format::B8G8R8A8_SRGB,
+        95 => vk_format::BC6H_UFLOAT_BLOCK,
+        96 => vk_format::BC6H_SFLOAT_BLOCK,
+        98 => vk_format::BC7_UNORM_BLOCK,
+        99 => vk_format::BC7_SRGB_BLOCK,
+        _ => return None,
+    })
+}
+
+fn four_cc_to_vk_format(four_cc: &[u8]) -> Option<u32> {
+    Some(match four_cc {
+        b"DXT1" => vk_format::BC1_RGBA_UNORM_BLOCK,
+        b"DXT2" | b"DXT3" => vk_format::BC2_UNORM_BLOCK,
+        b"DXT4" | b"DXT5" => vk_format::BC3_UNORM_BLOCK,
+        b"ATI1" | b"BC4U" => vk_format::BC4_UNORM_BLOCK,
+        b"BC4S" => vk_format::BC4_SNORM_BLOCK,
+        b"ATI2" | b"BC5U" => vk_format::BC5_UNORM_BLOCK,
+        b"BC5S" => vk_format::BC5_SNORM_BLOCK,
+        // D3DFORMAT values stored as FourCCs
+        [36, 0, 0, 0] => vk_format::R16G16B16A16_UNORM,
+        [113, 0, 0, 0] => vk_format::R16G16B16A16_SFLOAT,
+        [116, 0, 0, 0] => vk_format::R32G32B32A32_SFLOAT,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use varre_assets::texture_format::write_ktx2;
+
+    /// A KTX2 file of a Basis Universal texture, with a sample of each of `channels` in its data
+    /// format descriptor
+    fn basis_ktx2(desc: &TextureDesc, color_model: u8, channels: &[u8], supercompression: Supercompression, sgd: &[u8], levels: &[Vec<u8>]) -> Vec<u8> {
+        let block_size = 24 + 16 * channels.len();
+        let mut dfd = Vec::new();
+        dfd.extend_from_slice(&((4 + block_size) as u32).to_le_bytes());
+        dfd.extend_from_slice(&0u32.to_le_bytes());
+        dfd.extend_from_slice(&2u16.to_le_bytes());
+        dfd.extend_from_slice(&(block_size as u16).to_le_bytes());
+        dfd.extend_from_slice(&[color_model, 1, 1, 0, 3, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        for channel in channels {
+            dfd.extend_from_slice(&[0, 0, 127, *channel, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        }
+
+        let dfd_offset = 80 + 24 * levels.len();
+        let sgd_offset = (dfd_offset + dfd.len()).next_multiple_of(8);
+        let mut data = Vec::new();
+        data.extend_from_slice(&KTX2_IDENTIFIER);
+        for value in [
+            vk_format::UNDEFINED, 1, desc.width, desc.height, desc.depth, desc.layer_count, desc.face_count,
+            levels.len() as u32, supercompression as u32, dfd_offset as u32, dfd.len() as u32, 0, 0,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&(sgd_offset as u64).to_le_bytes());
+        data.extend_from_slice(&(sgd.len() as u64).to_le_bytes());
+
+        let mut offset = sgd_offset + sgd.len();
+        for level in levels {
+            // BasisLZ levels have no uncompressed length
+            let uncompressed_length = if supercompression == Supercompression::BasisLz { 0 } else { level.len() };
+            for value in [offset, level.len(), uncompressed_length] {
+                data.extend_from_slice(&(value as u64).to_le_bytes());
+            }
+            offset += level.len();
+        }
+
+        data.extend_from_slice(&dfd);
+        data.resize(sgd_offset, 0);
+        data.extend_from_slice(sgd);
+        levels.iter().for_each(|level| data.extend_from_slice(level));
+        data
+    }
+
+    /// A little-endian field of a .basis file
+    fn basis_field(basis: &[u8], offset: usize, size: usize) -> usize {
+        basis[offset..offset + size].iter().rev().fold(0, |value, byte| value << 8 | *byte as usize)
+    }
+
+    /// A DDS header for a `width`x`height` texture with `mip_levels` mips
+    fn dds_header(width: u32, height: u32, mip_levels: u32, four_cc: &[u8; 4], caps2: u32) -> Vec<u8> {
+        let mut header = vec![0u8; 128];
+        let mut write = |offset: usize, value: u32| header[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        write(4, 124);
+        write(8, 0x1 | 0x2 | 0x4 | 0x1000 | 0x2_0000);
+        write(12, height);
+        write(16, width);
+        write(28, mip_levels);
+        write(76, 32);
+        write(80, 0x4);
+        write(112, caps2);
+        header[..4].copy_from_slice(DDS_MAGIC);
+        header[84..88].copy_from_slice(four_cc);
+        header
+    }
+
+    #[test]
+    fn test_decode_dds_cube() {
+        // A BC1 cube map with 8x8 and 4x4 mips: 4 + 1 blocks of 8 bytes per face
+        let mut data = dds_header(8, 8, 2, b"DXT1", 0x200 | 0xFC00);
+        for face in 0..6u8 {
+            data.extend(std::iter::repeat_n(face, 40));
+        }
+
+        let image = decode_dds(&data).expect("valid DDS rejected");
+        assert_eq!(image.format(), vk::Format::BC1_RGBA_UNORM_BLOCK);
+        assert_eq!(image.array_layers(), 6);
+        assert_eq!(image.mip_levels, 2);
+        assert_eq!(image.regions.len(), 12);
+
+        // Layer-major in the file, with each subresource realigned for the copy
+        let region = image.regions[3];
+        assert_eq!((region.image_subresource.base_array_layer, region.image_subresource.mip_level), (1, 1));
+        assert_eq!(region.image_extent, vk::Extent3D { width: 4, height: 4, depth: 1 });
+        assert!(image.regions.iter().all(|r| r.buffer_offset % STAGING_ALIGNMENT as u64 == 0));
+        assert_eq!(image.data[region.buffer_offset as usize..][..8], [1; 8]);
+
+        assert!(decode_dds(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_decode_dds_invalid_extent() {
+        // 8x8 has 4 mip levels at most
+        assert!(decode_dds(&dds_header(8, 8, 5, b"DXT1", 0)).is_err());
+        assert!(decode_dds(&dds_header(8, 8, u32::MAX, b"DXT1", 0)).is_err());
+        assert!(decode_dds(&dds_header(0, 8, 1, b"DXT1", 0)).is_err());
+        assert!(decode_dds(&dds_header(8, 0, 1, b"DXT1", 0)).is_err());
+
+        let mut data = dds_header(8, 8, 4, b"DXT1", 0);
+        data.extend(std::iter::repeat_n(0, 8 * (4 + 1 + 1 + 1)));
+        assert_eq!(decode_dds(&data).expect("valid DDS rejected").mip_levels, 4);
+    }
+
+    #[test]
+    fn test_decode_dds_dx10() {
+        let mut data = dds_header(2, 2, 1, b"DX10", 0);
+        for value in [29u32, 3, 0, 3, 0] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend(std::iter::repeat_n(7, 16 * 3));
+
+        let image = decode_dds(&data).expect("valid DDS rejected");
+        assert_eq!(image.format(), vk::Format::R8G8B8A8_SRGB);
+        assert_eq!(image.desc.layer_count, 3);
+        assert_eq!(image.regions.len(), 3);
+    }
+
+    #[test]
+    fn test_decode_ktx2() {
+        let desc = TextureDesc::new_2d(vk_format::R8G8B8A8_UNORM, 2, 2);
+        let data = write_ktx2(&desc, &[vec![1; 16], vec![2; 4]]);
+        let image = decode_ktx2(&Ktx2View::parse(&data).unwrap(), &|_| true).expect("valid KTX2 rejected");
+
+        assert_eq!(image.desc, desc);
+        assert_eq!(image.regions.len(), 2);
+        assert_eq!(image.regions[1].buffer_offset, 16);
+        assert_eq!(image.data[16..], [2; 4]);
+    }
+
+    #[test]
+    fn test_basis_file() {
+        // Two layers of two levels, each image with an RGB and an alpha slice filled with its index
+        let desc = TextureDesc { vk_format: vk_format::UNDEFINED, width: 8, height: 4, depth: 0, layer_count: 2, face_count: 1 };
+        let codebooks: [&[u8]; 4] = [&[1; 3], &[2; 5], &[3; 7], &[]];
+        let mut sgd: Vec<u8> = [3u16, 5].iter().flat_map(|count| count.to_le_bytes()).collect();
+        sgd.extend(codebooks.iter().flat_map(|codebook| (codebook.len() as u32).to_le_bytes()));
+
+        let mut levels = vec![Vec::new(); 2];
+        let mut slice_index = 0;
+        for (level, data) in levels.iter_mut().enumerate() {
+            for _ in 0..2 {
+                let mut image_desc = vec![0];
+                for length in [6 >> level, 4 >> level] {
+                    image_desc.extend([data.len() as u32, length]);
+                    data.extend(std::iter::repeat_n(slice_index, length as usize));
+                    slice_index += 1;
+                }
+                sgd.extend(image_desc.iter().flat_map(|value| value.to_le_bytes()));
+            }
+        }
+        codebooks.iter().for_each(|codebook| sgd.extend_from_slice(codebook));
+
+        let ktx2 = basis_ktx2(&desc, KHR_DF_MODEL_ETC1S, &[0, 15], Supercompression::BasisLz, &sgd, &levels);
+        let basis = basis_file(&Ktx2View::parse(&ktx2).unwrap()).expect("valid BasisLZ texture rejected");
+        let field = |offset: usize, size: usize| basis_field(&basis, offset, size);
+
+        assert_eq!((field(0, 2), field(2, 2), field(4, 2)), (0x4273, 0x13, 77));
+        assert_eq!(field(6, 2), crc16(&basis[8..77]) as usize);
+        assert_eq!((field(8, 4), field(12, 2)), (basis.len() - 77, crc16(&basis[77..]) as usize));
+        // 8 slices of 2 images, ETC1S with alpha slices, 2D array
+        assert_eq!((field(14, 3), field(17, 3), field(20, 1), field(21, 2), field(23, 1)), (8, 2, 0, 1 | 4, 1));
+
+        assert_eq!((field(39, 2), field(48, 2)), (3, 5));
+        for (codebook, (offset, length)) in codebooks.iter().zip([(41, (45, 3)), (50, (54, 3)), (57, (61, 4)), (69, (73, 4))]) {
+            assert_eq!(&basis[field(offset, 4)..][..field(length.0, length.1)], *codebook);
+        }
+
+        // (image, level, flags, width, height, blocks x, blocks y) of each slice
+        let slices = [(0, 0, 0, 8, 4, 2, 1), (0, 0, 1, 8, 4, 2, 1), (1, 0, 0, 8, 4, 2, 1), (1, 0, 1, 8, 4, 2, 1), (0, 1, 0, 4, 2, 1, 1)];
+        assert_eq!(field(65, 4), 77);
+        for (index, expected) in slices.iter().enumerate() {
+            let desc = 77 + index * 23;
+            let field = |offset: usize, size: usize| field(desc + offset, size);
+            assert_eq!((field(0, 3), field(3, 1), field(4, 1), field(5, 2), field(7, 2), field(9, 2), field(11, 2)), *expected);
+            let data = &basis[field(13, 4)..][..field(17, 4)];
+            assert!(!data.is_empty() && data.iter().all(|byte| *byte as usize == index));
+            assert_eq!(field(21, 2), crc16(data) as usize);
+        }
+
+        assert_eq!(crc16(b"123456789"), 0xD64E);
+        let zstd = basis_ktx2(&desc, KHR_DF_MODEL_ETC1S, &[0], Supercompression::Zstandard, &[], &levels);
+        assert!(decode_ktx2(&Ktx2View::parse(&zstd).unwrap(), &|_| true).is_err());
+    }
+
+    /// A .basis file of an 8x8 texture with alpha and mips
+    #[cfg(feature = "basis-universal")]
+    fn encode_basis(format: basis_universal::BasisTextureFormat) -> Vec<u8> {
+        use basis_universal::{Compressor, CompressorParams};
+
+        basis_universal::encoder_init();
+        let pixels: Vec<u8> = (0..64u8).flat_map(|i| [i * 4, 255 - i * 4, i % 8 * 32, i * 2 + 100]).collect();
+        let mut params = CompressorParams::new();
+        params.set_basis_format(format);
+        params.set_generate_mipmaps(true);
+        params.source_image_mut(0).init(&pixels, 8, 8, 4);
+
+        let mut compressor = Compressor::new(1);
+        unsafe {
+            assert!(compressor.init(&params));
+            compressor.process().expect("failed to encode the test texture");
+        }
+        compressor.basis_file().to_vec()
+    }
+
+    /// Repack a .basis file of one 2D image as KTX2, ETC1S as BasisLZ and UASTC as is
+    #[cfg(feature = "basis-universal")]
+    fn basis_to_ktx2(basis: &[u8]) -> Vec<u8> {
+        let field = |offset: usize, size: usize| basis_field(basis, offset, size);
+        let etc1s = field(20, 1) == 0;
+        let has_alpha = field(21, 2) & 4 != 0;
+
+        // Each level's slices and a description of its image: flags, then RGB and alpha slice offsets
+        // and lengths
+        let first_slice = field(65, 4);
+        let level_count = (0..field(14, 3)).map(|slice| field(first_slice + slice * 23 + 3, 1)).max().unwrap() + 1;
+        let mut levels = vec![Vec::new(); level_count];
+        let mut image_descs = vec![[0u32; 5]; level_count];
+        for slice in 0..field(14, 3) {
+            let desc = first_slice + slice * 23;
+            let (level, alpha) = (field(desc + 3, 1), field(desc + 4, 1) & 1);
+            let data = &basis[field(desc + 13, 4)..][..field(desc + 17, 4)];
+            image_descs[level][1 + alpha * 2] = levels[level].len() as u32;
+            image_descs[level][2 + alpha * 2] = data.len() as u32;
+            levels[level].extend_from_slice(data);
+        }
+
+        let desc = TextureDesc::new_2d(vk_format::UNDEFINED, field(first_slice + 5, 2) as u32, field(first_slice + 7, 2) as u32);
+        if etc1s {
+            let codebooks = [(41, field(45, 3)), (50, field(54, 3)), (57, field(61, 4)), (69, field(73, 4))];
+            let mut sgd: Vec<u8> = [field(39, 2) as u16, field(48, 2) as u16].iter().flat_map(|count| count.to_le_bytes()).collect();
+            sgd.extend(codebooks.iter().flat_map(|(_, length)| (*length as u32).to_le_bytes()));
+            sgd.extend(image_descs.iter().flatten().flat_map(|value| value.to_le_bytes()));
+            for (offset, length) in codebooks {
+                sgd.extend_from_slice(&basis[field(offset, 4)..][..length]);
+            }
+            let channels: &[u8] = if has_alpha { &[0, 15] } else { &[0] };
+            basis_ktx2(&desc, KHR_DF_MODEL_ETC1S, channels, Supercompression::BasisLz, &sgd, &levels)
+        } else {
+            basis_ktx2(&desc, KHR_DF_MODEL_UASTC, &[if has_alpha { 3 } else { 0 }], Supercompression::None, &[], &levels)
+        }
+    }
+
+    /// Decode `basis` repacked as KTX2 to both BC7 and RGBA8, checking each level against the
+    /// transcoder's own output for the .basis file
+    #[cfg(feature = "basis-universal")]
+    fn assert_transcodes_like_basis(basis: &[u8], decode_flags: Option<basis_universal::DecodeFlags>) {
+        use basis_universal::{TranscodeParameters, Transcoder, TranscoderTextureFormat};
+
+        let ktx2 = basis_to_ktx2(basis);
+        let mut transcoder = Transcoder::new();
+        transcoder.prepare_transcoding(basis).expect("invalid .basis file");
+
+        for bc7 in [true, false] {
+            let image = decode_ktx2(&Ktx2View::parse(&ktx2).unwrap(), &|_| bc7).expect("valid Basis Universal texture rejected");
+            let expected_format = if bc7 { vk::Format::BC7_UNORM_BLOCK } else { vk::Format::R8G8B8A8_UNORM };
+            assert_eq!(image.format(), expected_format);
+            assert!(image.mip_levels > 1);
+
+            let format = if bc7 { TranscoderTextureFormat::BC7_RGBA } else { TranscoderTextureFormat::RGBA32 };
+            for (level, region) in image.regions.iter().enumerate() {
+                let parameters = TranscodeParameters { image_index: 0, level_index: level as u32, decode_flags, ..Default::default() };
+                let expected = transcoder.transcode_image_level(basis, format, parameters).expect("failed to transcode the .basis file");
+                assert_eq!(image.data[region.buffer_offset as usize..][..expected.len()], expected[..], "mip level {}", level);
+            }
+        }
+        transcoder.end_transcoding();
+    }
+
+    #[test]
+    #[cfg(feature = "basis-universal")]
+    fn test_decode_ktx2_uastc() {
+        let basis = encode_basis(basis_universal::BasisTextureFormat::UASTC4x4);
+        assert_transcodes_like_basis(&basis, Some(basis_universal::DecodeFlags::HIGH_QUALITY));
+    }
+
+    #[test]
+    #[cfg(feature = "basis-universal")]
+    fn test_decode_ktx2_basis_lz() {
+        let basis = encode_basis(basis_universal::BasisTextureFormat::ETC1S);
+        assert_transcodes_like_basis(&basis, None);
+    }
+
+    #[test]
+    fn test_inflate_zstd() {
+        let level = vec![5u8; 256];
+        let compressed = ruzstd::encoding::compress_to_vec(&level[..], ruzstd::encoding::CompressionLevel::Fastest);
+
+        assert_eq!(inflate_zstd(&compressed, 256).unwrap(), level);
+        assert!(inflate_zstd(&compressed, 128).is_err());
+    }
+}
@@ -0,0 +1,259 @@
+use ash::vk;
+use std::error::Error;
+use std::path::Path;
+use varre_assets::{AssetKind, Assets, Ktx2View, TextureDesc, TextureID};
+use crate::command_buffers::record_image_layout_transition;
+use crate::DeviceContext;
+use crate::memory_utils::{create_buffer, create_image};
+use crate::texture_loader::{decode_ktx2, decode_texture, load_texture_file, TextureImage};
+
+/// A sampled image with every mip level of a texture. Created with its data in a staging buffer;
+/// `record_upload` copies it into the image.
+pub struct VulkanTexture {
+    pub image: vk::Image,
+    image_memory: vk::DeviceMemory,
+    pub view: vk::ImageView,
+    pub sampler: vk::Sampler,
+    pub format: vk::Format,
+    pub extent: vk::Extent3D,
+    pub mip_levels: u32,
+    /// Array layers of the image, six per cube
+    pub array_layers: u32,
+    staging_buffer: vk::Buffer,
+    staging_buffer_memory: vk::DeviceMemory,
+    copy_regions: Vec<vk::BufferImageCopy>,
+}
+
+impl VulkanTexture {
+    pub fn from_texture(device_context: &DeviceContext, id: TextureID) -> Result<Self, Box<dyn Error>> {
+        Self::from_ktx2(device_context, &id.texture().view())
+    }
+
+    pub fn from_ktx2(device_context: &DeviceContext, ktx2: &Ktx2View) -> Result<Self, Box<dyn Error>> {
+        Self::new(device_context, &decode_ktx2(ktx2, &|format| format_supported(device_context, format))?)
+    }
+
+    /// Load a texture from `assets` by name, e.g. one dropped into a `DirectorySource` or packed
+    /// into a `PackSource`
+    pub fn from_asset(device_context: &DeviceContext, assets: &Assets, name: &str) -> Result<Self, Box<dyn Error>> {
+        let data = assets.read(AssetKind::Texture, name)?;
+        Self::new(device_context, &decode_texture(&data, &|format| format_supported(device_context, format))?)
+    }
+
+    /// Load a KTX2 or DDS file from disk
+    pub fn load(device_context: &DeviceContext, path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        Self::new(device_context, &load_texture_file(path.as_ref(), &|format| format_supported(device_context, format))?)
+    }
+
+    /// Create the image and stage `texture` for `record_upload`. Fails if the device can't sample
+    /// the texture's format.
+    pub fn new(device_context: &DeviceContext, texture: &TextureImage) -> Result<Self, Box<dyn Error>> {
+        let desc = texture.desc;
+        let format = texture.format();
+        if !format_supported(device_context, format) {
+            return Err(format!("the device can't sample {:?} images", format).into());
+        }
+
+        let extent = vk::Extent3D { width: desc.width, height: desc.height.max(1), depth: desc.depth.max(1) };
+        let mip_levels = texture.mip_levels;
+        let array_layers = texture.array_layers();
+
+        // Zero-sized buffers are invalid
+        let staging_size = texture.data.len().max(4) as vk::DeviceSize;
+        let (staging_buffer, staging_buffer_memory) = create_buffer(device_context, staging_size, vk::BufferUsageFlags::TRANSFER_SRC, vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT);
+
+        unsafe {
+            let ptr = device_context.device.map_memory(staging_buffer_memory, 0, staging_size, vk::MemoryMapFlags::empty()).unwrap();
+            std::ptr::copy_nonoverlapping(texture.data.as_ptr(), ptr as *mut u8, texture.data.len());
+            device_context.device.unmap_memory(staging_buffer_memory);
+        }
+
+        let image_create_info = vk::ImageCreateInfo::default()
+            .flags(if desc.face_count == 6 { vk::ImageCreateFlags::CUBE_COMPATIBLE } else { vk::ImageCreateFlags::empty() })
+            .image_type(image_type(&desc))
+            .extent(extent)
+            .mip_levels(mip_levels)
+            .array_layers(array_layers)
+            .format(format)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let (image, image_memory) = create_image(device_context, &image_create_info, vk::MemoryPropertyFlags::DEVICE_LOCAL);
+
+        let view_create_info = vk::ImageViewCreateInfo::default()
+            .image(image)
+            .view_type(image_view_type(&desc))
+            .format(format)
+            .subresource_range(subresource_range(mip_levels, array_layers));
+
+        // Frees what was created so far when the view or sampler can't be created
+        let destroy_image_and_staging = || unsafe {
+            device_context.device.destroy_image(image, None);
+            device_context.device.free_memory(image_memory, None);
+            device_context.device.destroy_buffer(staging_buffer, None);
+            device_context.device.free_memory(staging_buffer_memory, None);
+        };
+
+        let view = unsafe { device_context.device.create_image_view(&view_create_info, None) }
+            .inspect_err(|_| destroy_image_and_staging())?;
+
+        // Formats such as 32-bit floats may not support linear filtering
+        let format_properties = unsafe { device_context.instance.get_physical_device_format_properties(device_context.physical_device, format) };
+        let filter = if format_properties.optimal_tiling_features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR) {
+            vk::Filter::LINEAR
+        } else {
+            vk::Filter::NEAREST
+        };
+
+        let sampler_create_info = vk::SamplerCreateInfo::default()
+            .mag_filter(filter)
+            .min_filter(filter)
+            .mipmap_mode(if filter == vk::Filter::LINEAR { vk::SamplerMipmapMode::LINEAR } else { vk::SamplerMipmapMode::NEAREST })
+            .address_mode_u(vk::SamplerAddressMode::REPEAT)
+            .address_mode_v(vk::SamplerAddressMode::REPEAT)
+            .address_mode_w(vk::SamplerAddressMode::REPEAT)
+            .max_lod(mip_levels as f32);
+
+        let sampler = unsafe { device_context.device.create_sampler(&sampler_create_info, None) }
+            .inspect_err(|_| {
+                unsafe { device_context.device.destroy_image_view(view, None) };
+                destroy_image_and_staging();
+            })?;
+
+        Ok(Self {
+            image,
+            image_memory,
+            view,
+            sampler,
+            format,
+            extent,
+            mip_levels,
+            array_layers,
+            staging_buffer,
+            staging_buffer_memory,
+            copy_regions: texture.regions.clone(),
+        })
+    }
+
+    /// Copy the staged mip levels into the image and leave it ready for sampling
+    pub fn record_upload(&self, device_context: &DeviceContext, cmd: vk::CommandBuffer) {
+        let subresource_range = subresource_range(self.mip_levels, self.array_layers);
+
+        record_image_layout_transition(
+            &device_context.device,
+            cmd,
+            self.image,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::AccessFlags2::NONE,
+            vk::AccessFlags2::TRANSFER_WRITE,
+            vk::PipelineStageFlags2::TOP_OF_PIPE,
+            vk::PipelineStageFlags2::COPY,
+            subresource_range,
+        );
+
+        unsafe {
+            device_context.device.cmd_copy_buffer_to_image(cmd, self.staging_buffer, self.image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &self.copy_regions);
+        }
+
+        record_image_layout_transition(
+            &device_context.device,
+            cmd,
+            self.image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::AccessFlags2::TRANSFER_WRITE,
+            vk::AccessFlags2::SHADER_SAMPLED_READ,
+            vk::PipelineStageFlags2::COPY,
+            vk::PipelineStageFlags2::ALL_GRAPHICS | vk::PipelineStageFlags2::COMPUTE_SHADER,
+            subresource_range,
+        );
+    }
+
+    /// Free the staging buffer once the upload has completed. The texture can't be uploaded again
+    /// afterwards.
+    pub fn release_staging(&mut self, device_context: &DeviceContext) {
+        unsafe {
+            device_context.device.destroy_buffer(self.staging_buffer, None);
+            device_context.device.free_memory(self.staging_buffer_memory, None);
+        }
+        self.staging_buffer = vk::Buffer::null();
+        self.staging_buffer_memory = vk::DeviceMemory::null();
+    }
+
+    /// Descriptor info for binding the texture as a combined image sampler
+    pub fn descriptor_image_info(&self) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo::default()
+            .sampler(self.sampler)
+            .image_view(self.view)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    }
+
+    pub fn destroy(&self, device_context: &DeviceContext) {
+        unsafe {
+            device_context.device.destroy_sampler(self.sampler, None);
+            device_context.device.destroy_image_view(self.view, None);
+            device_context.device.destroy_image(self.image, None);
+            device_context.device.free_memory(self.image_memory, None);
+            device_context.device.destroy_buffer(self.staging_buffer, None);
+            device_context.device.free_memory(self.staging_buffer_memory, None);
+        }
+    }
+}
+
+fn subresource_range(mip_levels: u32, array_layers: u32) -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRange::default()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(mip_levels)
+        .base_array_layer(0)
+        .layer_count(array_layers)
+}
+
+fn image_type(desc: &TextureDesc) -> vk::ImageType {
+    if desc.depth > 0 {
+        vk::ImageType::TYPE_3D
+    } else if desc.height == 0 {
+        vk::ImageType::TYPE_1D
+    } else {
+        vk::ImageType::TYPE_2D
+    }
+}
+
+fn image_view_type(desc: &TextureDesc) -> vk::ImageViewType {
+    match (image_type(desc), desc.face_count, desc.layer_count > 0) {
+        (vk::ImageType::TYPE_3D, _, _) => vk::ImageViewType::TYPE_3D,
+        (vk::ImageType::TYPE_1D, _, false) => vk::ImageViewType::TYPE_1D,
+        (vk::ImageType::TYPE_1D, _, true) => vk::ImageViewType::TYPE_1D_ARRAY,
+        (_, 6, false) => vk::ImageViewType::CUBE,
+        (_, 6, true) => vk::ImageViewType::CUBE_ARRAY,
+        (_, _, false) => vk::ImageViewType::TYPE_2D,
+        (_, _, true) => vk::ImageViewType::TYPE_2D_ARRAY,
+    }
+}
+
+/// Whether optimally tiled images of `format` can be uploaded and sampled
+pub(crate) fn format_supported(device_context: &DeviceContext, format: vk::Format) -> bool {
+    let properties = unsafe { device_context.instance.get_physical_device_format_properties(device_context.physical_device, format) };
+    properties.optimal_tiling_features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE | vk::FormatFeatureFlags::TRANSFER_DST)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use varre_assets::texture_format::vk_format;
+
+    #[test]
+    fn test_image_view_type() {
+        let desc = TextureDesc { face_count: 6, ..TextureDesc::new_2d(vk_format::BC7_SRGB_BLOCK, 8, 8) };
+
+        assert_eq!(image_view_type(&desc), vk::ImageViewType::CUBE);
+        assert_eq!(image_view_type(&TextureDesc { layer_count: 3, ..desc }), vk::ImageViewType::CUBE_ARRAY);
+        assert_eq!(image_view_type(&TextureDesc::new_2d(vk_format::R8G8B8A8_SRGB, 4, 4)), vk::ImageViewType::TYPE_2D);
+        assert_eq!(image_view_type(&TextureDesc { height: 0, layer_count: 2, ..desc }), vk::ImageViewType::TYPE_1D_ARRAY);
+        assert_eq!(image_view_type(&TextureDesc { depth: 4, ..desc }), vk::ImageViewType::TYPE_3D);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::DeviceContext;
+use crate::{AssetStreamer, DeviceContext};
 use crate::command_buffers::record_image_layout_transition;
 use crate::physical_device_utils::find_memorytype_index;
 use crate::render_context::RenderContext;
@@ -167,6 +167,7 @@ impl VulkanWindow {
         device_context: &DeviceContext,
         cmd: vk::CommandBuffer,
         render_context: &Box<dyn RenderContext>,
+        asset_streamer: &AssetStreamer,
     ) {
         unsafe {
             let frame_fence = self.frame_fences[self.frame_index];
@@ -239,7 +240,7 @@ impl VulkanWindow {
                     .level_count(1),
             );
 
-            render_context.record_draw(device_context, cmd, self.swapchain_images[present_index as usize], self.swapchain_image_views[present_index as usize], self.depth_image, self.depth_image_view, vk::Rect2D::default().extent(self.extent));
+            render_context.record_draw(device_context, asset_streamer, cmd, self.swapchain_images[present_index as usize], self.swapchain_image_views[present_index as usize], self.depth_image, self.depth_image_view, vk::Rect2D::default().extent(self.extent));
 
             record_image_layout_transition(&device_context.device, cmd, self.swapchain_images[present_index as usize], vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, vk::ImageLayout::PRESENT_SRC_KHR, vk::AccessFlags2::COLOR_ATTACHMENT_WRITE, vk::AccessFlags2::NONE, vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT, vk::PipelineStageFlags2::BOTTOM_OF_PIPE, vk::ImageSubresourceRange::default()
                 .aspect_mask(vk::ImageAspectFlags::COLOR)